@@ -0,0 +1,373 @@
+use crate::{
+    errors::CliError,
+    models::{auth::RouteGroup, config::Config, notification::NotificationContent},
+    subsystems::{
+        queue::{DeliveryEvent, NotificationQueue},
+        sinks::Sinks,
+    },
+};
+use prowl::Priority;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{sleep, timeout, Duration},
+};
+
+pub(crate) const USAGE: &str = "Usage:
+  grafana-prowl-notifier [config.json]
+  grafana-prowl-notifier send --title <title> [--message <message>] [--priority <priority>]
+                              [--url <url>] [--receiver <name>] [--config <config.json>]
+  grafana-prowl-notifier tail [--lines <n>] [--no-follow] [--server <host:port>]
+                              [--config <config.json>]";
+
+/// How many times `send` retries before giving up, it shouldn't hang a script.
+const SEND_RETRIES: usize = 2;
+const SEND_TIMEOUT: Duration = Duration::from_secs(60);
+const TAIL_POLL: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub(crate) enum Command {
+    /// Run the webhook server, the default.
+    Serve {
+        config: Option<String>,
+    },
+    /// Send one notification and exit.
+    Send(SendArgs),
+    /// Print a running server's delivery events.
+    Tail(TailArgs),
+    Help,
+}
+
+#[derive(Debug)]
+pub(crate) struct SendArgs {
+    config: Option<String>,
+    title: String,
+    message: String,
+    priority: Priority,
+    url: Option<String>,
+    receiver: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct TailArgs {
+    config: Option<String>,
+    /// Defaults to the listener serving the API from the config.
+    server: Option<String>,
+    lines: usize,
+    follow: bool,
+}
+
+/// `args` without the program name.
+pub(crate) fn parse(args: Vec<String>) -> Result<Command, CliError> {
+    let mut args = args.into_iter();
+    match args.next() {
+        Some(x) if x == "send" => parse_send(args).map(Command::Send),
+        Some(x) if x == "tail" => parse_tail(args).map(Command::Tail),
+        Some(x) if x == "help" || x == "--help" || x == "-h" => Ok(Command::Help),
+        Some(x) if x.starts_with('-') => Err(CliError::UnknownArgument(x)),
+        config => match args.next() {
+            Some(x) => Err(CliError::UnknownArgument(x)),
+            None => Ok(Command::Serve { config }),
+        },
+    }
+}
+
+fn parse_send(mut args: impl Iterator<Item = String>) -> Result<SendArgs, CliError> {
+    let mut send = SendArgs {
+        config: None,
+        title: String::new(),
+        message: String::new(),
+        priority: Priority::Normal,
+        url: None,
+        receiver: None,
+    };
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| CliError::MissingValue(flag.clone()))
+        };
+        match flag.as_str() {
+            "--config" => send.config = Some(value()?),
+            "--title" => send.title = value()?,
+            "--message" => send.message = value()?,
+            "--priority" => send.priority = parse_priority(&value()?)?,
+            "--url" => send.url = Some(value()?),
+            "--receiver" => send.receiver = Some(value()?),
+            _ => return Err(CliError::UnknownArgument(flag)),
+        }
+    }
+    if send.title.is_empty() {
+        return Err(CliError::MissingTitle);
+    }
+    Ok(send)
+}
+
+fn parse_tail(mut args: impl Iterator<Item = String>) -> Result<TailArgs, CliError> {
+    let mut tail = TailArgs {
+        config: None,
+        server: None,
+        lines: 20,
+        follow: true,
+    };
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| CliError::MissingValue(flag.clone()))
+        };
+        match flag.as_str() {
+            "--config" => tail.config = Some(value()?),
+            "--server" => tail.server = Some(value()?),
+            "--lines" => {
+                let lines = value()?;
+                tail.lines = lines
+                    .parse()
+                    .map_err(|_| CliError::UnknownArgument(lines))?;
+            }
+            "--no-follow" => tail.follow = false,
+            _ => return Err(CliError::UnknownArgument(flag)),
+        }
+    }
+    Ok(tail)
+}
+
+fn parse_priority(value: &str) -> Result<Priority, CliError> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| CliError::BadPriority(value.to_string()))
+}
+
+/// Sends through the same queue as the server, returning the exit code.
+pub(crate) async fn send(args: SendArgs) -> i32 {
+    let config = Config::load(args.config);
+    let receivers = match &args.receiver {
+        Some(name) => match config.receivers().get(name) {
+            Some(receiver) => vec![receiver.clone()],
+            None => {
+                eprintln!("{}", CliError::UnknownReceiver(name.clone()));
+                return 2;
+            }
+        },
+        None => config.default_receivers(),
+    };
+    let content = NotificationContent::new(
+        Some(args.priority),
+        args.url,
+        config.app_name().to_string(),
+        args.title,
+        args.message,
+    );
+
+    let retry_secs = Duration::from_secs(*config.linear_retry_secs());
+    let (sender, reciever) = NotificationQueue::new(
+        Sinks::new(&config),
+        retry_secs,
+        Some(SEND_RETRIES),
+        *config.test_mode(),
+        *config.delivery_workers(),
+    )
+    .into_parts();
+    if let Err(e) = sender.add_for_each(&receivers, &content) {
+        eprintln!("Failed to queue notification. {e}");
+        return 1;
+    }
+    drop(sender);
+    match timeout(SEND_TIMEOUT, reciever.async_loop()).await {
+        Ok(0) => 0,
+        Ok(_) => {
+            eprintln!("Failed to send notification, see the log for why.");
+            1
+        }
+        Err(_) => {
+            eprintln!("Timed out sending notification after {SEND_TIMEOUT:?}.");
+            1
+        }
+    }
+}
+
+/// Prints the last delivery events from `/api/deliveries`, then polls for new ones.
+pub(crate) async fn tail(args: TailArgs) -> i32 {
+    let config = Config::load(args.config);
+    let server = args.server.unwrap_or_else(|| {
+        let listeners = config.listeners_or_default();
+        let listener = listeners
+            .iter()
+            .find(|x| x.serves(RouteGroup::Api))
+            .unwrap_or(&listeners[0]);
+        local_address(listener.bind_host())
+    });
+    let credentials = config.auth_for(RouteGroup::Api).credentials();
+
+    let mut since = 0;
+    let mut first = true;
+    loop {
+        let path = format!("/api/deliveries?since={since}");
+        let events = match get_deliveries(&server, &path, &credentials).await {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("{e}");
+                return 1;
+            }
+        };
+        let skip = match first {
+            true => events.len().saturating_sub(args.lines),
+            false => 0,
+        };
+        for event in &events[skip..] {
+            println!("{event}");
+        }
+        if let Some(last) = events.last() {
+            since = last.id();
+        }
+        if !args.follow {
+            return 0;
+        }
+        first = false;
+        sleep(TAIL_POLL).await;
+    }
+}
+
+/// Where to reach a server listening on `bind_host` from the same host.
+fn local_address(bind_host: &str) -> String {
+    if let Some(port) = bind_host.strip_prefix("0.0.0.0:") {
+        format!("127.0.0.1:{port}")
+    } else if let Some(port) = bind_host.strip_prefix("[::]:") {
+        format!("[::1]:{port}")
+    } else {
+        bind_host.to_string()
+    }
+}
+
+async fn get_deliveries(
+    server: &str,
+    path: &str,
+    credentials: &Option<(String, String)>,
+) -> Result<Vec<DeliveryEvent>, CliError> {
+    let mut request = format!("GET {path} HTTP/1.1\r\nHost: {server}\r\nConnection: close\r\n");
+    if let Some((name, value)) = credentials {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str("\r\n");
+
+    let mut stream = TcpStream::connect(server)
+        .await
+        .map_err(CliError::Unreachable)?;
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(CliError::Unreachable)?;
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .await
+        .map_err(CliError::Unreachable)?;
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status_line = head.lines().next().unwrap_or_default();
+    if !status_line.contains(" 200 ") {
+        return Err(CliError::BadResponse(status_line.to_string()));
+    }
+    serde_json::from_str(body).map_err(|_| CliError::BadResponse(status_line.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_serve() {
+        assert!(matches!(
+            parse(args(&[])),
+            Ok(Command::Serve { config: None })
+        ));
+        match parse(args(&["/config/config.json"])) {
+            Ok(Command::Serve { config }) => {
+                assert_eq!(config, Some("/config/config.json".to_string()))
+            }
+            x => panic!("Expected serve, got {x:?}"),
+        }
+        assert!(matches!(parse(args(&["--help"])), Ok(Command::Help)));
+        assert!(matches!(
+            parse(args(&["a.json", "b.json"])),
+            Err(CliError::UnknownArgument(_))
+        ));
+    }
+
+    #[test]
+    fn parse_send_args() {
+        let command = parse(args(&[
+            "send",
+            "--title",
+            "Backup done",
+            "--priority",
+            "High",
+            "--receiver",
+            "family",
+        ]));
+        let send = match command {
+            Ok(Command::Send(send)) => send,
+            x => panic!("Expected send, got {x:?}"),
+        };
+        assert_eq!(send.title, "Backup done");
+        assert_eq!(send.message, "");
+        assert!(matches!(send.priority, Priority::High));
+        assert_eq!(send.receiver, Some("family".to_string()));
+        assert_eq!(send.config, None);
+
+        assert!(matches!(
+            parse(args(&["send", "--message", "hi"])),
+            Err(CliError::MissingTitle)
+        ));
+        assert!(matches!(
+            parse(args(&["send", "--title"])),
+            Err(CliError::MissingValue(_))
+        ));
+        assert!(matches!(
+            parse(args(&["send", "--title", "x", "--priority", "Loud"])),
+            Err(CliError::BadPriority(_))
+        ));
+    }
+
+    #[test]
+    fn parse_tail_args() {
+        match parse(args(&["tail"])) {
+            Ok(Command::Tail(tail)) => {
+                assert_eq!(tail.lines, 20);
+                assert!(tail.follow);
+                assert_eq!(tail.server, None);
+            }
+            x => panic!("Expected tail, got {x:?}"),
+        }
+        match parse(args(&["tail", "--lines", "5", "--no-follow"])) {
+            Ok(Command::Tail(tail)) => {
+                assert_eq!(tail.lines, 5);
+                assert!(!tail.follow);
+            }
+            x => panic!("Expected tail, got {x:?}"),
+        }
+        assert!(matches!(
+            parse(args(&["tail", "--lines", "many"])),
+            Err(CliError::UnknownArgument(_))
+        ));
+        assert_eq!(local_address("0.0.0.0:3333"), "127.0.0.1:3333");
+        assert_eq!(local_address("[::]:3333"), "[::1]:3333");
+        assert_eq!(local_address("10.0.0.2:3333"), "10.0.0.2:3333");
+    }
+
+    #[tokio::test]
+    async fn send_in_test_mode() {
+        let send = |receiver: Option<&str>| SendArgs {
+            config: Some("src/resources/test-max-config.json".to_string()),
+            title: "Backup done".to_string(),
+            message: String::new(),
+            priority: Priority::Normal,
+            url: None,
+            receiver: receiver.map(|x| x.to_string()),
+        };
+        assert_eq!(super::send(send(None)).await, 0);
+        assert_eq!(super::send(send(Some("family"))).await, 0);
+        assert_eq!(super::send(send(Some("nobody"))).await, 2);
+    }
+}
@@ -0,0 +1,14 @@
+use chrono::{DateTime, Utc};
+
+/// Source of the current time, so time based subsystems can be tested without sleeping.
+pub(crate) trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
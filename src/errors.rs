@@ -12,12 +12,42 @@ pub(crate) enum RequestError {
     BadMessage(std::str::Utf8Error),
     #[error("HTTP Request did not have the content-length header")]
     NoContentLength,
+    #[error("HTTP Request had content-length headers that disagree")]
+    ConflictingContentLength,
+    #[error("HTTP Request had a malformed or incomplete chunked body")]
+    BadChunk,
+    #[error("HTTP Request body is over the {0} byte limit")]
+    BodyTooLarge(usize),
+    #[error("HTTP Request headers are over the {0} byte limit")]
+    HeadTooLarge(usize),
     #[error("Sender said they had {0} bytes, but only sent {1} bytes.")]
     BadContentLength(usize, usize),
     #[error("The HTTP request did not have a request line.")]
     NoRequestLine,
     #[error("The HTTP request-line was not properly formatted.")]
     RequestLineParse,
+    #[error("The connection closed or went idle before a request started.")]
+    ConnectionClosed,
+    #[error("The HTTP request was not sent in time.")]
+    Timeout,
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum CliError {
+    #[error("Unknown argument '{0}'.")]
+    UnknownArgument(String),
+    #[error("{0} needs a value.")]
+    MissingValue(String),
+    #[error("--title is required.")]
+    MissingTitle,
+    #[error("Unknown priority '{0}', expected VeryLow, Moderate, Normal, High or Emergency.")]
+    BadPriority(String),
+    #[error("No receiver named '{0}' in the config.")]
+    UnknownReceiver(String),
+    #[error("Failed to reach the server. {0}")]
+    Unreachable(std::io::Error),
+    #[error("The server answered '{0}'.")]
+    BadResponse(String),
 }
 
 #[derive(Debug, Error)]
@@ -26,8 +56,8 @@ pub(crate) enum GrafanaWebhookError {
     QueueError(AddNotificationError),
     #[error("JSON from Grafana could not be parsed. {0}")]
     BadJson(serde_json::Error),
-    #[error("Wrong method, expected POST but got {0}")]
-    WrongMethod(String),
+    #[error("Expected application/json but got {0}. {1}")]
+    NotJson(String, serde_json::Error),
 }
 
 #[derive(Debug, Error)]
@@ -35,7 +65,7 @@ pub(crate) enum AddNotificationError {
     #[error("Failed to create prowl notification. {0}")]
     Creation(prowl::CreationError),
     #[error("Failed to queue notification to be sent. {0}")]
-    Queue(Box<prowl_queue::AddError>),
+    Queue(QueueAddError),
 }
 
 impl From<prowl::CreationError> for AddNotificationError {
@@ -44,8 +74,159 @@ impl From<prowl::CreationError> for AddNotificationError {
     }
 }
 
-impl From<Box<prowl_queue::AddError>> for AddNotificationError {
-    fn from(error: Box<prowl_queue::AddError>) -> Self {
+impl From<QueueAddError> for AddNotificationError {
+    fn from(error: QueueAddError) -> Self {
         Self::Queue(error)
     }
 }
+
+#[derive(Debug, Error)]
+pub(crate) enum DeliveryError {
+    /// The service could not be reached, so it is worth trying again.
+    #[error("Failed to reach {0}. {1}")]
+    Send(&'static str, reqwest::Error),
+    #[error("{0} answered {1}. {2}")]
+    Api(&'static str, reqwest::StatusCode, String),
+    /// The service is down or rate limiting, so it is worth trying again too.
+    #[error("{0} is unavailable, it answered {1}. {2}")]
+    Unavailable(&'static str, reqwest::StatusCode, String),
+    #[error("Failed to reach the mail server. {0}")]
+    MailConnection(String),
+    #[error("Failed to write the email. {0}")]
+    Email(String),
+    /// 4xx replies are temporary, 5xx ones aren't.
+    #[error("The mail server answered {0}. {1}")]
+    Mail(u16, String),
+}
+
+impl DeliveryError {
+    pub(crate) fn is_retryable(&self) -> bool {
+        match self {
+            DeliveryError::Api(..) | DeliveryError::Email(_) => false,
+            DeliveryError::Mail(code, _) => *code < 500,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum QueueAddError {
+    #[error("The notification queue is no longer running.")]
+    Closed,
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum ConfigError {
+    #[error("Failed to open {0}. {1}")]
+    Open(String, std::io::Error),
+    #[error("Failed to parse {0}. {1}")]
+    Parse(String, serde_json::Error),
+    #[error("{0} can't be changed by a reload, restart instead.")]
+    NeedsRestart(&'static str),
+    #[error("Route sends to receiver '{0}', which is not in `receivers`.")]
+    UnknownReceiver(String),
+    #[error(
+        "priority_destinations has '{0}', which isn't VeryLow, Moderate, Normal, High or Emergency."
+    )]
+    UnknownPriority(String),
+    #[error("Route {0} needs either a `receiver` or `api_keys`.")]
+    RouteTarget(usize),
+    #[error("Receiver '{0}' has no API keys.")]
+    NoApiKeys(String),
+    #[error("Receiver '{0}' sends email, but there is no `smtp` server.")]
+    NoSmtp(String),
+    #[error("Receiver '{0}' has a max_priority for a key that isn't one of its api_keys.")]
+    UnknownCapKey(String),
+    #[error("max_connections must be at least 1.")]
+    NoConnections,
+    #[error("delivery_workers must be at least 1.")]
+    NoWorkers,
+    #[error("dashboard_user and dashboard_password must be set together.")]
+    HalfDashboardLogin,
+    #[error("dashboard_page_size must be at least 1.")]
+    NoPageSize,
+    #[error("Webhook path '{0}' must be under /webhooks/, and only end with *.")]
+    BadWebhookPath(String),
+    #[error(
+        "url_rewrite rule {0:?} needs a `from`, or a `base` starting with http:// or https://."
+    )]
+    BadUrlRewrite(crate::models::url_rewrite::UrlRewrite),
+    #[error("forward_urls must start with http:// or https://, not '{0}'.")]
+    BadForwardUrl(String),
+    #[error("Tenant '{0}' needs an auth_token, prowl_api_keys and a name without a /.")]
+    BadTenant(String),
+    #[error("Listener on '{0}' has no route groups.")]
+    NoListenerGroups(String),
+    #[error("{0}: {1}")]
+    BadSchedule(String, ScheduleError),
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum SilenceError {
+    #[error("Set an alertname, fingerprint or matchers to silence.")]
+    NothingToSilence,
+    #[error("Silence ends before it starts.")]
+    EndsBeforeStart,
+    #[error("Silence lasts too long.")]
+    TooLong,
+    #[error("{0}")]
+    Matcher(#[from] MatcherError),
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum ScheduleError {
+    #[error("Cron '{0}' has {1} fields, expected 5: minute hour day-of-month month day-of-week.")]
+    CronFieldCount(String, usize),
+    #[error("Cron '{0}' has {1} '{2}', which must be within {3}-{4}.")]
+    BadCronField(String, &'static str, String, u32, u32),
+    #[error("Cron '{0}' could not be parsed. {1}")]
+    BadCron(String, cron_parser::ParseError),
+    #[error("'{0}' is not a duration, use minutes or a string like 90m, 2h30m or 1d.")]
+    BadDuration(String),
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum MatcherError {
+    #[error("Matcher '{0}' is not formatted like `name=value`, `name` or `!name`.")]
+    Syntax(String),
+    #[error("Unknown matcher target '{0}'.")]
+    UnknownTarget(String),
+    #[error("Matcher has an invalid regex. {0}")]
+    Regex(regex::Error),
+    #[error("Matcher '{0}' can't be used in a silence, use =, !=, =~ or !~.")]
+    NotSilenceable(String),
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum CidrError {
+    #[error("'{0}' is not an address or CIDR like 10.0.0.0/8.")]
+    Syntax(String),
+    #[error("CIDR '{0}' has a prefix over {1}.")]
+    Prefix(String, u8),
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum BulkError {
+    #[error("At least one matcher is required.")]
+    NoMatchers,
+    #[error("minutes must be at least 1, or a duration of a minute or more.")]
+    BadDuration,
+    #[error("minutes is too long.")]
+    TooLong,
+    #[error("{0}")]
+    Matcher(#[from] MatcherError),
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum AppriseError {
+    #[error("Receiver `url` isn't an Apprise URL like `pover://user@token`.")]
+    NotAUrl,
+    #[error("Apprise {0}:// URLs aren't supported, use prowl, pover, ntfy, ntfys, gotify, gotifys, tgram, discord, slack, mailto or mailtos.")]
+    UnknownScheme(String),
+    #[error("Apprise {0}:// URLs need {1}.")]
+    Missing(String, &'static str),
+    #[error("Apprise {0}:// URLs with {1} aren't supported.")]
+    Unsupported(String, &'static str),
+    #[error("A receiver has both `url` and `api_keys` or `sink`, use one or the other.")]
+    Both,
+}
@@ -1,51 +1,211 @@
+mod cli;
+mod clock;
 mod errors;
 mod models;
 mod subsystems;
 #[cfg(test)]
 mod test;
 
-use models::{config::Config, fingerprint::Fingerprints};
-use prowl_queue::{LinearRetry, ProwlQueue, ProwlQueueOptions, RetryMethod};
-use std::net::TcpListener;
+use clock::{Clock, SystemClock};
+use models::{
+    config::Config, digest::Digest, fingerprint::Fingerprints, silence::Silences, volume::Volume,
+};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::time::Duration;
+use subsystems::{queue::NotificationQueue, reload::SharedConfig, sinks::Sinks};
+use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{broadcast, Mutex};
+use tokio::time::{timeout, Duration};
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
 
+    let config_path = match cli::parse(std::env::args().skip(1).collect()) {
+        Ok(cli::Command::Serve { config }) => config,
+        Ok(cli::Command::Send(args)) => std::process::exit(cli::send(args).await),
+        Ok(cli::Command::Tail(args)) => std::process::exit(cli::tail(args).await),
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
     // Migrate data if needed
-    let config = Config::load(std::env::args().nth(1));
+    let config = Config::load(config_path);
     let _ = Fingerprints::migrate_v1(&config);
 
     // Build dependencies
-    let listener = TcpListener::bind(config.bind_host())
-        .unwrap_or_else(|_| panic!("Faild to bind to {}", config.bind_host()));
-    log::info!("Listening on {}", config.bind_host());
+    let mut listeners = vec![];
+    for listener in config.listeners_or_default() {
+        let bound = TcpListener::bind(listener.bind_host())
+            .await
+            .unwrap_or_else(|_| panic!("Faild to bind to {}", listener.bind_host()));
+        log::info!(
+            "Listening on {} for {:?}",
+            listener.bind_host(),
+            listener.groups()
+        );
+        listeners.push((bound, listener));
+    }
     let fingerprints = Fingerprints::load_or_default(&config);
+    let fingerprint_count = fingerprints.len();
     let fingerprints = Arc::new(Mutex::new(fingerprints));
+    let silences = Arc::new(Mutex::new(Silences::load_or_default(&config)));
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let volume = Arc::new(Mutex::new(Volume::new(clock.now())));
+    let digest = Arc::new(Mutex::new(Digest::default()));
 
     let retry_secs = config.linear_retry_secs();
     let retry_secs = Duration::from_secs(*retry_secs);
-    let retry_method = LinearRetry::new(retry_secs, None);
-    let retry_method = RetryMethod::Linear(retry_method);
-    let options = ProwlQueueOptions::new(retry_method);
-    let (sender, reciever) = ProwlQueue::new(options).into_parts();
+    let sinks = Sinks::new(&config);
+    let (sender, reciever) = NotificationQueue::new(
+        sinks.clone(),
+        retry_secs,
+        None,
+        *config.test_mode(),
+        *config.delivery_workers(),
+    )
+    .with_fallbacks(config.fallback_receivers())
+    .into_parts();
+
+    let (shutdown_sender, _) = broadcast::channel(1);
+    let shared_config = SharedConfig::new(config.clone());
 
     // Run tasks
-    if !*config.test_mode() {
-        tokio::spawn(reciever.async_loop());
-    }
-    tokio::spawn(subsystems::realert_every::main_loop(
-        config.clone(),
-        sender.clone(),
+    let queue = tokio::spawn(reciever.async_loop());
+    let pollers = vec![
+        tokio::spawn(subsystems::realert_every::main_loop(
+            shared_config.clone(),
+            sender.clone(),
+            fingerprints.clone(),
+            silences.clone(),
+            clock.clone(),
+            shutdown_sender.subscribe(),
+        )),
+        tokio::spawn(subsystems::realert_cron::main_loop(
+            shared_config.clone(),
+            sender.clone(),
+            fingerprints.clone(),
+            silences.clone(),
+            clock.clone(),
+            shutdown_sender.subscribe(),
+        )),
+        tokio::spawn(subsystems::guardrails::main_loop(
+            config.clone(),
+            sender.clone(),
+            fingerprints.clone(),
+            clock.clone(),
+            shutdown_sender.subscribe(),
+        )),
+        tokio::spawn(subsystems::scheduled::main_loop(
+            config.clone(),
+            sender.clone(),
+            clock.clone(),
+            shutdown_sender.subscribe(),
+        )),
+        tokio::spawn(subsystems::volume::main_loop(
+            config.clone(),
+            sender.clone(),
+            volume.clone(),
+            clock.clone(),
+            shutdown_sender.subscribe(),
+        )),
+        tokio::spawn(subsystems::digest::main_loop(
+            config.clone(),
+            sender.clone(),
+            digest.clone(),
+            shutdown_sender.subscribe(),
+        )),
+        tokio::spawn(subsystems::archive::main_loop(
+            config.clone(),
+            fingerprints.clone(),
+            clock.clone(),
+            shutdown_sender.subscribe(),
+        )),
+        tokio::spawn(subsystems::expiry::main_loop(
+            config.clone(),
+            fingerprints.clone(),
+            clock.clone(),
+            shutdown_sender.subscribe(),
+        )),
+        tokio::spawn(subsystems::prune::main_loop(
+            config.clone(),
+            fingerprints.clone(),
+            clock.clone(),
+            shutdown_sender.subscribe(),
+        )),
+        tokio::spawn(subsystems::health::main_loop(
+            config.clone(),
+            sinks.clone(),
+            sender.breakers().clone(),
+            shutdown_sender.subscribe(),
+        )),
+        tokio::spawn(subsystems::reload::main_loop(
+            shared_config.clone(),
+            shutdown_sender.subscribe(),
+        )),
+    ];
+    subsystems::lifecycle::notify_started(&config, &sender, fingerprint_count);
+    let handler = subsystems::server::Handler::new(
+        shared_config,
+        sender,
         fingerprints.clone(),
-    ));
-    tokio::spawn(subsystems::realert_cron::main_loop(
-        config.clone(),
-        sender.clone(),
-        fingerprints.clone(),
-    ));
-    subsystems::server::main_loop(listener, config, sender, fingerprints).await;
+        silences,
+        volume,
+        digest,
+        clock,
+    );
+    let servers: Vec<_> = listeners
+        .into_iter()
+        .map(|(bound, listener)| {
+            tokio::spawn(subsystems::server::main_loop(
+                bound,
+                handler.for_listener(listener),
+                shutdown_sender.subscribe(),
+            ))
+        })
+        .collect();
+    drop(handler);
+
+    shutdown_signal().await;
+    let _ = shutdown_sender.send(());
+
+    let drain_timeout = Duration::from_secs(*config.drain_timeout_secs());
+    let drain = async {
+        // Stop accepting, then stop the pollers so nothing else is queued.
+        for server in servers {
+            let _ = server.await;
+        }
+        for poller in pollers {
+            let _ = poller.await;
+        }
+        log::debug!("Stopped accepting connections and pollers");
+
+        // Every sender has been dropped, so the queue exits once it is empty.
+        if timeout(drain_timeout, queue).await.is_err() {
+            log::warn!("Notification queue did not drain within {drain_timeout:?}");
+        }
+    };
+    tokio::select! {
+        _ = drain => {}
+        _ = shutdown_signal() => log::warn!("Received a second signal, exiting without draining"),
+    }
+
+    fingerprints.lock().await.save(&config);
+    log::debug!("Saved fingerprints");
+    subsystems::lifecycle::notify_stopping(&config, sinks.prowl()).await;
+    std::process::exit(0);
+}
+
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => log::info!("Received SIGINT, shutting down"),
+        _ = terminate.recv() => log::info!("Received SIGTERM, shutting down"),
+    }
 }
@@ -0,0 +1,240 @@
+use crate::models::http;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+/// Which part of the server a path belongs to, each with its own `Auth`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RouteGroup {
+    Webhooks,
+    Api,
+    Ui,
+}
+
+impl RouteGroup {
+    pub(crate) fn of(path: &str) -> Self {
+        if path.starts_with("/webhooks/") {
+            RouteGroup::Webhooks
+        } else if path.starts_with("/api/") || path == "/metrics" {
+            RouteGroup::Api
+        } else {
+            RouteGroup::Ui
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub(crate) struct AuthGroups {
+    webhooks: Option<Auth>,
+    api: Option<Auth>,
+    ui: Option<Auth>,
+}
+
+impl AuthGroups {
+    pub(crate) fn get(&self, group: RouteGroup) -> &Option<Auth> {
+        match group {
+            RouteGroup::Webhooks => &self.webhooks,
+            RouteGroup::Api => &self.api,
+            RouteGroup::Ui => &self.ui,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Auth {
+    /// Anyone can make requests.
+    None,
+    /// `Authorization: Bearer <token>`, or the whole value of `header` if set.
+    Bearer {
+        token: String,
+        header: Option<String>,
+    },
+    /// `Authorization: Basic` with this user and password.
+    Basic { user: String, password: String },
+    /// A hex HMAC-SHA256 of the body, optionally prefixed with `sha256=`.
+    Hmac {
+        secret: String,
+        #[serde(default = "default_hmac_header")]
+        header: String,
+    },
+    /// TLS is terminated by a proxy, which verifies the client certificate and
+    /// passes its subject in `header`. The proxy must drop the header from clients.
+    Mtls {
+        #[serde(default = "default_mtls_header")]
+        header: String,
+        subjects: Vec<String>,
+    },
+}
+
+fn default_hmac_header() -> String {
+    "X-Signature".to_string()
+}
+
+fn default_mtls_header() -> String {
+    "X-Client-Cert-Subject".to_string()
+}
+
+impl Auth {
+    pub(crate) fn authorize(&self, request: &http::Request) -> bool {
+        match self {
+            Auth::None => true,
+            Auth::Bearer { token, header } => {
+                let sent = match header {
+                    Some(header) => request.header(header).map(|x| x.as_str()),
+                    None => request
+                        .header("Authorization")
+                        .and_then(|x| x.strip_prefix("Bearer ")),
+                };
+                sent.is_some_and(|x| constant_time_eq(x.as_bytes(), token.as_bytes()))
+            }
+            Auth::Basic { user, password } => request
+                .header("Authorization")
+                .and_then(|x| x.strip_prefix("Basic "))
+                .and_then(|x| base64::decode(x.trim()).ok())
+                .is_some_and(|x| constant_time_eq(&x, format!("{user}:{password}").as_bytes())),
+            Auth::Hmac { secret, header } => {
+                let signature = request
+                    .header(header)
+                    .map(|x| x.trim())
+                    .map(|x| x.strip_prefix("sha256=").unwrap_or(x))
+                    .and_then(|x| hex::decode(x).ok());
+                let signature = match signature {
+                    Some(x) => x,
+                    None => return false,
+                };
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+                    .expect("HMAC accepts keys of any length");
+                mac.update(request.body().as_bytes());
+                mac.verify_slice(&signature).is_ok()
+            }
+            Auth::Mtls { header, subjects } => request
+                .header(header)
+                .is_some_and(|x| subjects.iter().any(|s| s == x.trim())),
+        }
+    }
+
+    /// The header a client sends to pass, for methods that don't depend on the request.
+    pub(crate) fn credentials(&self) -> Option<(String, String)> {
+        match self {
+            Auth::Bearer { token, header } => Some(match header {
+                Some(header) => (header.clone(), token.clone()),
+                None => ("Authorization".to_string(), format!("Bearer {token}")),
+            }),
+            Auth::Basic { user, password } => Some((
+                "Authorization".to_string(),
+                format!("Basic {}", base64::encode(format!("{user}:{password}"))),
+            )),
+            Auth::None | Auth::Hmac { .. } | Auth::Mtls { .. } => None,
+        }
+    }
+
+    /// The `WWW-Authenticate` value for a 401. Methods without one get a 403.
+    pub(crate) fn challenge(&self) -> Option<&'static str> {
+        match self {
+            Auth::Bearer { .. } => Some("Bearer"),
+            Auth::Basic { .. } => Some("Basic realm=\"grafana-prowl-notifier\""),
+            Auth::None | Auth::Hmac { .. } | Auth::Mtls { .. } => None,
+        }
+    }
+}
+
+/// Compares without returning early, so the time taken doesn't hint at the secret.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::TestStream;
+
+    async fn request(headers: &str, body: &str) -> http::Request {
+        let message = format!(
+            "POST / HTTP/1.1\r\n{headers}\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let mut stream = TestStream::new(message.as_bytes());
+        http::Request::from_stream(&mut stream)
+            .await
+            .expect("Failed to build request")
+    }
+
+    fn sign(secret: &str, body: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("Failed to create HMAC");
+        mac.update(body.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn test_route_group() {
+        assert_eq!(RouteGroup::of("/webhooks/grafana"), RouteGroup::Webhooks);
+        assert_eq!(RouteGroup::of("/api/groups"), RouteGroup::Api);
+        assert_eq!(RouteGroup::of("/metrics"), RouteGroup::Api);
+        assert_eq!(RouteGroup::of("/"), RouteGroup::Ui);
+        assert_eq!(RouteGroup::of("/delete/fingerprint"), RouteGroup::Ui);
+    }
+
+    #[tokio::test]
+    async fn test_authorize() {
+        let bearer = Auth::Bearer {
+            token: "s3cret".to_string(),
+            header: None,
+        };
+        let header = Auth::Bearer {
+            token: "s3cret".to_string(),
+            header: Some("X-Webhook-Token".to_string()),
+        };
+        let basic = Auth::Basic {
+            user: "admin".to_string(),
+            password: "hunter2".to_string(),
+        };
+        let hmac: Auth = serde_json::from_str(r#"{"type": "hmac", "secret": "key"}"#)
+            .expect("Failed to parse hmac");
+        let mtls: Auth = serde_json::from_str(r#"{"type": "mtls", "subjects": ["CN=grafana"]}"#)
+            .expect("Failed to parse mtls");
+        let good = sign("key", "{}");
+        let wrong_key = sign("other", "{}");
+
+        for (auth, headers, expected) in [
+            (&Auth::None, "X-Other: value".to_string(), true),
+            (&bearer, "Authorization: Bearer s3cret".to_string(), true),
+            (&bearer, "Authorization: Bearer s3cre".to_string(), false),
+            (&header, "x-webhook-token: s3cret".to_string(), true),
+            (&header, "Authorization: Bearer s3cret".to_string(), false),
+            (
+                &basic,
+                format!("Authorization: Basic {}", base64::encode("admin:hunter2")),
+                true,
+            ),
+            (
+                &basic,
+                format!("Authorization: Basic {}", base64::encode("admin:hunter")),
+                false,
+            ),
+            (&basic, "Authorization: Basic !!!".to_string(), false),
+            (&hmac, format!("X-Signature: {good}"), true),
+            (&hmac, format!("X-Signature: sha256={good}"), true),
+            (&hmac, format!("X-Signature: {wrong_key}"), false),
+            (&hmac, "X-Signature: zz".to_string(), false),
+            (&mtls, "X-Client-Cert-Subject: CN=grafana".to_string(), true),
+            (&mtls, "X-Client-Cert-Subject: CN=other".to_string(), false),
+            (&mtls, "X-Other: CN=grafana".to_string(), false),
+        ] {
+            let request = request(&headers, "{}").await;
+            assert_eq!(auth.authorize(&request), expected, "{auth:?} {headers}");
+        }
+
+        for auth in [&bearer, &header, &basic] {
+            let (name, value) = auth.credentials().expect("Missing credentials");
+            let request = request(&format!("{name}: {value}"), "{}").await;
+            assert!(auth.authorize(&request), "{auth:?}");
+        }
+        assert_eq!(hmac.credentials(), None);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::{
+    errors::{BulkError, MatcherError},
+    models::{
+        fingerprint::Fingerprints,
+        matcher::{self, Matcher, Target},
+        schedule::Span,
+        silence::{Silence, Silences},
+        tenant::Tenant,
+    },
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// `POST /api/fingerprints/bulk`, an action for every fingerprint the matchers match.
+#[derive(Debug, Deserialize)]
+pub(crate) struct BulkRequest {
+    matchers: Vec<Matcher>,
+    #[serde(flatten)]
+    action: BulkAction,
+    /// Set by `for_tenant`, for the snooze silence.
+    #[serde(skip)]
+    tenant: Option<Matcher>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub(crate) enum BulkAction {
+    /// Remove the fingerprints.
+    Delete,
+    /// Skip re-alerts for the fingerprints.
+    Ack {
+        #[serde(default = "default_minutes")]
+        minutes: Span,
+    },
+    /// Silence the fingerprints that match now.
+    Snooze {
+        #[serde(default = "default_minutes")]
+        minutes: Span,
+    },
+    /// Silence the matchers, including alerts that haven't arrived yet.
+    Silence {
+        #[serde(default = "default_minutes")]
+        minutes: Span,
+        #[serde(default = "String::new")]
+        comment: String,
+    },
+}
+
+fn default_minutes() -> Span {
+    Span::minutes(60)
+}
+
+/// `POST /api/fingerprints/{fingerprint}/ack`, an empty body acks for an hour.
+#[derive(Debug, Deserialize)]
+pub(crate) struct AckRequest {
+    #[serde(default = "default_minutes")]
+    minutes: Span,
+}
+
+impl AckRequest {
+    pub(crate) fn parse(body: &str) -> Result<AckRequest, serde_json::Error> {
+        match body.trim().is_empty() {
+            true => Ok(AckRequest {
+                minutes: default_minutes(),
+            }),
+            false => serde_json::from_str(body),
+        }
+    }
+
+    /// None when it would last past the end of time.
+    pub(crate) fn until(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        now.checked_add_signed(self.minutes.duration())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct BulkResult {
+    fingerprints: Vec<String>,
+    /// The ID of the silence that was added, if any.
+    silence: Option<String>,
+}
+
+impl BulkRequest {
+    /// Only matches the tenant's fingerprints, and its silences only its alerts.
+    pub(crate) fn for_tenant(mut self, tenant: &str) -> Result<Self, MatcherError> {
+        let matcher = Tenant::matcher(tenant)?;
+        self.matchers.retain(|x| x.target() != &Target::Tenant);
+        self.matchers.push(matcher.clone());
+        self.tenant = Some(matcher);
+        Ok(self)
+    }
+
+    pub(crate) fn apply(
+        &self,
+        fingerprints: &mut Fingerprints,
+        silences: &mut Silences,
+        now: DateTime<Utc>,
+    ) -> Result<BulkResult, BulkError> {
+        if self.matchers.is_empty() {
+            return Err(BulkError::NoMatchers);
+        }
+        let mut matched: Vec<String> = fingerprints
+            .iter()
+            .filter(|(_, event)| matcher::all_match(&self.matchers, *event))
+            .map(|(key, _)| key.clone())
+            .collect();
+        matched.sort();
+
+        let mut silence = None;
+        match &self.action {
+            BulkAction::Delete => {
+                for fingerprint in &matched {
+                    fingerprints.remove(fingerprint);
+                }
+            }
+            BulkAction::Ack { minutes } => {
+                let until = until(now, *minutes)?;
+                for fingerprint in &matched {
+                    fingerprints.ack(fingerprint, until);
+                }
+            }
+            BulkAction::Snooze { minutes } => {
+                let ends_at = until(now, *minutes)?;
+                if !matched.is_empty() {
+                    let pattern: Vec<String> = matched.iter().map(|x| regex::escape(x)).collect();
+                    let matcher =
+                        Matcher::parse(&format!("@fingerprint=~\"{}\"", pattern.join("|")))?;
+                    let matchers: Vec<Matcher> = std::iter::once(matcher)
+                        .chain(self.tenant.clone())
+                        .collect();
+                    let comment = format!("Snoozed {} fingerprints", matched.len());
+                    silence = Some(Silence::new(
+                        &matchers,
+                        now,
+                        ends_at,
+                        "bulk snooze".to_string(),
+                        comment,
+                    )?);
+                }
+            }
+            BulkAction::Silence { minutes, comment } => {
+                let ends_at = until(now, *minutes)?;
+                silence = Some(Silence::new(
+                    &self.matchers,
+                    now,
+                    ends_at,
+                    "bulk silence".to_string(),
+                    comment.clone(),
+                )?);
+            }
+        }
+
+        let id = silence.as_ref().map(|x| x.id().clone());
+        if let Some(silence) = silence {
+            silences.add(silence);
+        }
+        Ok(BulkResult {
+            fingerprints: matched,
+            silence: id,
+        })
+    }
+}
+
+/// `minutes` from now, which must be at least a minute and end before the end of time.
+fn until(now: DateTime<Utc>, minutes: Span) -> Result<DateTime<Utc>, BulkError> {
+    match minutes.duration() {
+        x if x < Duration::minutes(1) => Err(BulkError::BadDuration),
+        x => now.checked_add_signed(x).ok_or(BulkError::TooLong),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::{config::Config, grafana::Alert};
+
+    fn fingerprints() -> Fingerprints {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        for (prefix, fingerprint) in [("[high] ", "aaaa"), ("", "bbbb"), ("[high] ", "cccc")] {
+            let json = crate::test::consts::create_firing_alert_with_prefix(prefix)
+                .replace("581dd91e73c77248", fingerprint);
+            let alert: Alert = serde_json::from_str(&json).expect("Failed to load alert");
+            fingerprints.update_last_alerted(&alert, Utc::now());
+        }
+        fingerprints
+    }
+
+    fn request(json: &str) -> BulkRequest {
+        serde_json::from_str(json).expect("Failed to parse bulk request")
+    }
+
+    #[test]
+    fn delete_and_ack() {
+        let mut fingerprints = fingerprints();
+        let mut silences = Silences::default();
+        let now = Utc::now();
+
+        let ack = request(r#"{"matchers": ["@priority=High"], "action": "ack", "minutes": 30}"#);
+        let result = ack
+            .apply(&mut fingerprints, &mut silences, now)
+            .expect("Failed to ack");
+        assert_eq!(result.fingerprints, vec!["aaaa", "cccc"]);
+        let acked = |fingerprints: &Fingerprints, key: &str, minutes: i64| {
+            fingerprints
+                .iter()
+                .find(|(x, _)| *x == key)
+                .is_some_and(|(_, event)| event.is_acked(now + Duration::minutes(minutes)))
+        };
+        assert!(acked(&fingerprints, "aaaa", 29));
+        assert!(!acked(&fingerprints, "aaaa", 30));
+        assert!(!acked(&fingerprints, "bbbb", 0));
+
+        let delete = request(r#"{"matchers": ["@fingerprint=~a+|b+"], "action": "delete"}"#);
+        let result = delete
+            .apply(&mut fingerprints, &mut silences, now)
+            .expect("Failed to delete");
+        assert_eq!(result.fingerprints, vec!["aaaa", "bbbb"]);
+        assert_eq!(result.silence, None);
+        assert_eq!(fingerprints.len(), 1);
+
+        let everything = request(r#"{"matchers": [], "action": "delete"}"#);
+        assert!(matches!(
+            everything.apply(&mut fingerprints, &mut silences, now),
+            Err(BulkError::NoMatchers)
+        ));
+    }
+
+    #[test]
+    fn snooze_and_silence() {
+        let mut fingerprints = fingerprints();
+        let mut silences = Silences::default();
+        let now = Utc::now();
+        let later: Alert = serde_json::from_str(
+            &crate::test::consts::create_firing_alert_with_prefix("[high] ")
+                .replace("581dd91e73c77248", "dddd"),
+        )
+        .expect("Failed to load alert");
+
+        let snooze = request(r#"{"matchers": ["@priority=High"], "action": "snooze"}"#);
+        let result = snooze
+            .apply(&mut fingerprints, &mut silences, now)
+            .expect("Failed to snooze");
+        assert!(result.silence.is_some());
+        let (_, aaaa) = fingerprints
+            .iter()
+            .find(|(x, _)| *x == "aaaa")
+            .expect("Missing aaaa");
+        assert!(silences.is_silenced(aaaa, now));
+        assert!(!silences.is_silenced(&later, now));
+        assert!(!silences.is_silenced(aaaa, now + Duration::minutes(60)));
+
+        let silence = request(
+            r#"{"matchers": ["alertname=~\\[high\\].*"], "action": "silence", "minutes": "1h30m", "comment": "Maintenance"}"#,
+        );
+        silence
+            .apply(&mut fingerprints, &mut silences, now)
+            .expect("Failed to silence");
+        assert!(silences.is_silenced(&later, now + Duration::minutes(89)));
+        assert!(!silences.is_silenced(&later, now + Duration::minutes(91)));
+        assert_eq!(fingerprints.len(), 3);
+
+        let present = request(r#"{"matchers": ["alertname"], "action": "silence"}"#);
+        assert!(matches!(
+            present.apply(&mut fingerprints, &mut silences, now),
+            Err(BulkError::Matcher(_))
+        ));
+        let forever =
+            request(r#"{"matchers": ["alertname"], "action": "snooze", "minutes": "2147483647d"}"#);
+        assert!(matches!(
+            forever.apply(&mut fingerprints, &mut silences, now),
+            Err(BulkError::TooLong)
+        ));
+        let negative = request(r#"{"matchers": ["alertname"], "action": "ack", "minutes": 0}"#);
+        assert!(matches!(
+            negative.apply(&mut fingerprints, &mut silences, now),
+            Err(BulkError::BadDuration)
+        ));
+    }
+
+    #[test]
+    fn ack_too_long() {
+        let now = Utc::now();
+        let ack = AckRequest::parse(r#"{"minutes": "2147483647d"}"#).expect("Failed to parse ack");
+        assert_eq!(ack.until(now), None);
+        let ack = AckRequest::parse("").expect("Failed to parse ack");
+        assert_eq!(ack.until(now), Some(now + Duration::hours(1)));
+    }
+}
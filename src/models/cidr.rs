@@ -0,0 +1,113 @@
+use crate::errors::CidrError;
+use serde::Deserialize;
+use std::net::IpAddr;
+
+/// An address range like `192.168.1.0/24` or `fd00::/8`. A plain address is a range of one.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub(crate) struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub(crate) fn parse(source: &str) -> Result<Cidr, CidrError> {
+        let bad = || CidrError::Syntax(source.to_string());
+        let (address, prefix) = match source.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (source.trim(), None),
+        };
+        let network: IpAddr = address.parse().map_err(|_| bad())?;
+        let max = match network {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match prefix {
+            Some(x) => x.parse::<u8>().map_err(|_| bad())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(CidrError::Prefix(source.to_string(), max));
+        }
+        Ok(Cidr { network, prefix })
+    }
+
+    /// IPv4 clients of a dual-stack listener show up as `::ffff:a.b.c.d`,
+    /// so those match IPv4 ranges too.
+    pub(crate) fn contains(&self, address: &IpAddr) -> bool {
+        match (self.network, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = CidrError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Cidr::parse(&source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().expect("Failed to parse address")
+    }
+
+    #[test]
+    fn test_contains() {
+        let cidr = Cidr::parse("192.168.1.0/24").expect("Failed to parse");
+        assert!(cidr.contains(&ip("192.168.1.0")));
+        assert!(cidr.contains(&ip("192.168.1.255")));
+        assert!(cidr.contains(&ip("::ffff:192.168.1.7")));
+        assert!(!cidr.contains(&ip("192.168.2.1")));
+        assert!(!cidr.contains(&ip("fd00::1")));
+
+        let single = Cidr::parse("10.0.0.5").expect("Failed to parse");
+        assert!(single.contains(&ip("10.0.0.5")));
+        assert!(!single.contains(&ip("10.0.0.6")));
+
+        let everything = Cidr::parse("0.0.0.0/0").expect("Failed to parse");
+        assert!(everything.contains(&ip("8.8.8.8")));
+
+        let v6 = Cidr::parse("fd00::/8").expect("Failed to parse");
+        assert!(v6.contains(&ip("fd12:3456::1")));
+        assert!(!v6.contains(&ip("fe80::1")));
+        assert!(!v6.contains(&ip("10.0.0.5")));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (source, error) in [
+            ("10.0.0.0/33", "CIDR '10.0.0.0/33' has a prefix over 32."),
+            ("fd00::/129", "CIDR 'fd00::/129' has a prefix over 128."),
+            (
+                "10.0.0/8",
+                "'10.0.0/8' is not an address or CIDR like 10.0.0.0/8.",
+            ),
+            (
+                "10.0.0.0/x",
+                "'10.0.0.0/x' is not an address or CIDR like 10.0.0.0/8.",
+            ),
+        ] {
+            assert_eq!(
+                Cidr::parse(source).map_err(|e| e.to_string()),
+                Err(error.to_string())
+            );
+        }
+        let cidrs: Vec<Cidr> =
+            serde_json::from_str(r#"["10.0.0.0/8", "::1"]"#).expect("Failed to parse CIDRs");
+        assert_eq!(cidrs.len(), 2);
+    }
+}
@@ -1,6 +1,32 @@
+use crate::{
+    errors::ConfigError,
+    models::{
+        auth::{Auth, AuthGroups, RouteGroup},
+        cidr::Cidr,
+        digest::ResolvedDigest,
+        fingerprint::{LinkKind, Links},
+        forward_url::ForwardUrl,
+        grafana::{DatasourceAlerts, LabelLimits, UnknownStatus},
+        http,
+        listener::Listener,
+        matcher::{self, Matchable, Matcher, Target},
+        receiver::{Delivery, Receiver, Route},
+        schedule::Cron,
+        scheduled::ScheduledNotification,
+        sink::{Sink, SmtpSettings},
+        tenant::Tenant,
+        url_rewrite::{self, UrlRewrite},
+        webhook::{Parser, Webhook},
+    },
+};
 use derive_getters::Getters;
 use serde::Deserialize;
-use std::{fs::File, io::BufReader};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
 
 #[derive(Clone, Deserialize, Getters)]
 pub(crate) struct Config {
@@ -10,12 +36,134 @@ pub(crate) struct Config {
     app_name: String,
     #[serde(default = "default_bind_host")]
     bind_host: String,
+    #[serde(default = "Vec::new")]
+    listeners: Vec<Listener>,
+    #[serde(default = "default_max_connections")]
+    max_connections: usize,
+    #[serde(default = "default_request_timeout_secs")]
+    request_timeout_secs: u64,
+    #[serde(default = "default_keep_alive_timeout_secs")]
+    keep_alive_timeout_secs: u64,
+    #[serde(default = "default_read_timeout_secs")]
+    read_timeout_secs: u64,
+    #[serde(default = "default_header_timeout_secs")]
+    header_timeout_secs: u64,
+    #[serde(default = "default_request_deadline_secs")]
+    request_deadline_secs: u64,
+    #[serde(default = "default_max_body_bytes")]
+    max_body_bytes: usize,
+    access_log_file: Option<String>,
+    #[serde(default = "default_drain_timeout_secs")]
+    drain_timeout_secs: u64,
     alert_every_minutes: Option<i64>,
     realert_cron: Option<String>,
+    #[serde(default = "Vec::new")]
+    realert_matchers: Vec<Matcher>,
+    #[serde(default = "UnknownStatus::default")]
+    unknown_status: UnknownStatus,
+    #[serde(default = "DatasourceAlerts::default")]
+    datasource_alerts: DatasourceAlerts,
+    #[serde(default = "LabelLimits::default")]
+    label_limits: LabelLimits,
+    #[serde(default = "default_fingerprint_labels")]
+    fingerprint_labels: Vec<String>,
+    #[serde(default = "Vec::new")]
+    notification_labels: Vec<String>,
+    #[serde(default = "default_notification_annotations")]
+    notification_annotations: Vec<String>,
+    #[serde(default = "default_notification_url_preference")]
+    notification_url_preference: Vec<LinkKind>,
+    #[serde(default = "Vec::new")]
+    url_rewrite: Vec<UrlRewrite>,
+    large_webhook_bytes: Option<usize>,
+    large_webhook_alerts: Option<usize>,
+    #[serde(default = "Vec::new")]
     prowl_api_keys: Vec<String>,
+    prowl_provider_key: Option<String>,
+    #[serde(default = "default_prowl_timeout_secs")]
+    prowl_timeout_secs: u64,
+    #[serde(default = "default_prowl_connect_timeout_secs")]
+    prowl_connect_timeout_secs: u64,
+    #[serde(default = "default_prowl_keep_alive_secs")]
+    prowl_keep_alive_secs: u64,
+    #[serde(default = "default_delivery_workers")]
+    delivery_workers: usize,
+    auth_token: Option<String>,
+    auth_header: Option<String>,
+    /// When set, `/webhooks/github` needs a valid `X-Hub-Signature-256`.
+    github_webhook_secret: Option<String>,
+    /// Built in webhooks that need their body signed, by parser.
+    #[serde(default = "HashMap::new")]
+    hmac_secrets: HashMap<Parser, String>,
+    dashboard_user: Option<String>,
+    dashboard_password: Option<String>,
+    #[serde(default = "default_dashboard_page_size")]
+    dashboard_page_size: usize,
+    dashboard_refresh_secs: Option<u64>,
+    #[serde(default = "AuthGroups::default")]
+    auth: AuthGroups,
+    #[serde(default = "Vec::new")]
+    allowed_source_cidrs: Vec<Cidr>,
+    #[serde(default = "bool::default")]
+    read_only: bool,
+    #[serde(default = "HashMap::new")]
+    receivers: HashMap<String, Receiver>,
+    #[serde(default = "Vec::new", alias = "routing")]
+    routes: Vec<Route>,
+    /// Receivers notified with anything no route matches, `prowl` for `prowl_api_keys`.
+    #[serde(default = "default_destinations")]
+    destinations: Vec<String>,
+    /// Used instead of `destinations` for the priorities it has, like `Emergency`.
+    #[serde(default = "HashMap::new")]
+    priority_destinations: HashMap<String, Vec<String>>,
+    /// Notified instead when the service a notification is for is down, its circuit open.
+    #[serde(default = "Vec::new")]
+    fallback_destinations: Vec<String>,
+    #[serde(default = "Vec::new")]
+    webhooks: Vec<Webhook>,
+    #[serde(default = "HashMap::new")]
+    tenants: HashMap<String, Tenant>,
+    /// Where every webhook's body is also posted, as it arrived.
+    #[serde(default = "Vec::new")]
+    forward_urls: Vec<ForwardUrl>,
+    #[serde(default = "Vec::new")]
+    scheduled_notifications: Vec<ScheduledNotification>,
     fingerprints_file: String,
+    silences_file: Option<String>,
     #[serde(default = "bool::default")]
     test_mode: bool,
+    #[serde(default = "bool::default")]
+    lifecycle_notifications: bool,
+    #[serde(default = "bool::default")]
+    volume_anomalies: bool,
+    max_fingerprints_file_bytes: Option<u64>,
+    max_access_log_bytes: Option<u64>,
+    max_archive_bytes: Option<u64>,
+    min_free_disk_bytes: Option<u64>,
+    archive_dir: Option<String>,
+    #[serde(default = "default_archive_after_days")]
+    archive_after_days: i64,
+    expire_resolved_after_hours: Option<i64>,
+    prune_resolved_after_hours: Option<i64>,
+    prune_unseen_after_days: Option<i64>,
+    #[serde(default = "default_incident_window_minutes")]
+    incident_window_minutes: i64,
+    health_check_secs: Option<u64>,
+    /// The mail server `email` receivers send with.
+    smtp: Option<SmtpSettings>,
+    resolved_digest: Option<ResolvedDigest>,
+    /// Where `load` read it from.
+    #[serde(skip)]
+    file: Option<String>,
+}
+
+/// The destination that means `prowl_api_keys`.
+const PROWL_DESTINATION: &str = "prowl";
+/// How `@priority` matchers and `priority_destinations` name priorities.
+const PRIORITIES: [&str; 5] = ["VeryLow", "Moderate", "Normal", "High", "Emergency"];
+
+fn default_destinations() -> Vec<String> {
+    vec![PROWL_DESTINATION.to_string()]
 }
 
 fn default_retry_secs() -> u64 {
@@ -30,6 +178,83 @@ fn default_bind_host() -> String {
     "0.0.0.0:3333".to_string()
 }
 
+fn default_max_connections() -> usize {
+    64
+}
+
+fn default_request_timeout_secs() -> u64 {
+    10
+}
+
+fn default_keep_alive_timeout_secs() -> u64 {
+    5
+}
+
+fn default_read_timeout_secs() -> u64 {
+    5
+}
+
+fn default_header_timeout_secs() -> u64 {
+    10
+}
+
+fn default_request_deadline_secs() -> u64 {
+    30
+}
+
+fn default_max_body_bytes() -> usize {
+    4 * 1024 * 1024
+}
+
+fn default_prowl_timeout_secs() -> u64 {
+    30
+}
+
+fn default_prowl_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_prowl_keep_alive_secs() -> u64 {
+    90
+}
+
+fn default_delivery_workers() -> usize {
+    4
+}
+
+fn default_fingerprint_labels() -> Vec<String> {
+    vec![
+        "severity".to_string(),
+        "instance".to_string(),
+        "job".to_string(),
+    ]
+}
+
+fn default_notification_annotations() -> Vec<String> {
+    vec!["description".to_string(), "runbook_url".to_string()]
+}
+
+fn default_notification_url_preference() -> Vec<LinkKind> {
+    vec![LinkKind::Generator]
+}
+
+fn default_dashboard_page_size() -> usize {
+    100
+}
+
+fn default_drain_timeout_secs() -> u64 {
+    // under the 10 seconds `docker stop` waits before killing
+    8
+}
+
+fn default_archive_after_days() -> i64 {
+    90
+}
+
+fn default_incident_window_minutes() -> i64 {
+    60
+}
+
 impl Config {
     pub(crate) fn load(filename: Option<String>) -> Self {
         let filename = match filename {
@@ -42,17 +267,393 @@ impl Config {
                 "config.json".to_string()
             }
         };
+        match Config::read(&filename) {
+            Ok(config) => config,
+            Err(e) => panic!("Invalid configuration. {e}"),
+        }
+    }
 
+    /// Reads and validates the file, for starting up and for reloads.
+    pub(crate) fn read(filename: &str) -> Result<Self, ConfigError> {
         let config_file =
-            File::open(&filename).unwrap_or_else(|_| panic!("Faild to find config {filename}"));
+            File::open(filename).map_err(|e| ConfigError::Open(filename.to_string(), e))?;
         let config_reader = BufReader::new(config_file);
-        serde_json::from_reader(config_reader).expect("Error reading configuration.")
+        let mut config: Config = serde_json::from_reader(config_reader)
+            .map_err(|e| ConfigError::Parse(filename.to_string(), e))?;
+        config.validate()?;
+        config.file = Some(filename.to_string());
+        Ok(config)
+    }
+
+    /// Settings only read at startup that differ in `other`, which a reload can't apply.
+    pub(crate) fn startup_changes(&self, other: &Config) -> Vec<&'static str> {
+        let listeners = |x: &Config| -> Vec<(String, Vec<RouteGroup>)> {
+            x.listeners_or_default()
+                .iter()
+                .map(|x| (x.bind_host().clone(), x.groups().clone()))
+                .collect()
+        };
+        let changes = [
+            ("listeners", listeners(self) != listeners(other)),
+            (
+                "max_connections",
+                self.max_connections != other.max_connections,
+            ),
+            (
+                "access_log_file",
+                self.access_log_file != other.access_log_file,
+            ),
+            (
+                "linear_retry_secs",
+                self.linear_retry_secs != other.linear_retry_secs,
+            ),
+            (
+                "prowl_timeout_secs",
+                self.prowl_timeout_secs != other.prowl_timeout_secs,
+            ),
+            (
+                "prowl_connect_timeout_secs",
+                self.prowl_connect_timeout_secs != other.prowl_connect_timeout_secs,
+            ),
+            (
+                "prowl_keep_alive_secs",
+                self.prowl_keep_alive_secs != other.prowl_keep_alive_secs,
+            ),
+            (
+                "delivery_workers",
+                self.delivery_workers != other.delivery_workers,
+            ),
+            (
+                "fallback_destinations",
+                self.fallback_destinations != other.fallback_destinations,
+            ),
+            (
+                "fingerprints_file",
+                self.fingerprints_file != other.fingerprints_file,
+            ),
+            ("silences_file", self.silences_file != other.silences_file),
+            (
+                "fingerprint_labels",
+                self.fingerprint_labels != other.fingerprint_labels,
+            ),
+            ("test_mode", self.test_mode != other.test_mode),
+        ];
+        changes
+            .into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_connections == 0 {
+            return Err(ConfigError::NoConnections);
+        }
+        if self.delivery_workers == 0 {
+            return Err(ConfigError::NoWorkers);
+        }
+        if self.dashboard_user.is_some() != self.dashboard_password.is_some() {
+            return Err(ConfigError::HalfDashboardLogin);
+        }
+        if self.dashboard_page_size == 0 {
+            return Err(ConfigError::NoPageSize);
+        }
+        for listener in &self.listeners {
+            if listener.groups().is_empty() {
+                return Err(ConfigError::NoListenerGroups(listener.bind_host().clone()));
+            }
+        }
+        for (name, receiver) in &self.receivers {
+            if receiver.api_keys().is_empty() {
+                return Err(ConfigError::NoApiKeys(name.clone()));
+            }
+            let keys = receiver.api_keys();
+            if receiver.max_priority().keys().any(|x| !keys.contains(x)) {
+                return Err(ConfigError::UnknownCapKey(name.clone()));
+            }
+            if *receiver.sink() == Sink::Email && self.smtp.is_none() {
+                return Err(ConfigError::NoSmtp(name.clone()));
+            }
+        }
+        if let Some(x) = self
+            .priority_destinations
+            .keys()
+            .find(|x| !PRIORITIES.contains(&x.as_str()))
+        {
+            return Err(ConfigError::UnknownPriority(x.clone()));
+        }
+        if let Some(x) = self
+            .destinations
+            .iter()
+            .chain(self.priority_destinations.values().flatten())
+            .chain(&self.fallback_destinations)
+            .find(|x| *x != PROWL_DESTINATION && !self.receivers.contains_key(*x))
+        {
+            return Err(ConfigError::UnknownReceiver(x.clone()));
+        }
+        if let Some(digest) = &self.resolved_digest {
+            if !self.receivers.contains_key(digest.receiver()) {
+                return Err(ConfigError::UnknownReceiver(digest.receiver().clone()));
+            }
+        }
+        for (index, route) in self.routes.iter().enumerate() {
+            match (route.receiver(), route.api_keys().is_empty()) {
+                (Some(receiver), true) if !self.receivers.contains_key(receiver) => {
+                    return Err(ConfigError::UnknownReceiver(receiver.clone()));
+                }
+                (Some(_), true) | (None, false) => {}
+                _ => return Err(ConfigError::RouteTarget(index)),
+            }
+        }
+        for (name, tenant) in &self.tenants {
+            let path = format!("/webhooks/grafana/{name}");
+            let usable = Tenant::of_path(&path) == Some(name.as_str())
+                && Tenant::matcher(name).is_ok()
+                && !tenant.auth_token().is_empty()
+                && !tenant.prowl_api_keys().is_empty();
+            if !usable {
+                return Err(ConfigError::BadTenant(name.clone()));
+            }
+        }
+        for webhook in &self.webhooks {
+            if !webhook.valid_path() {
+                return Err(ConfigError::BadWebhookPath(webhook.path().clone()));
+            }
+            if let Some(x) = webhook
+                .receivers()
+                .iter()
+                .find(|x| !self.receivers.contains_key(*x))
+            {
+                return Err(ConfigError::UnknownReceiver(x.clone()));
+            }
+        }
+        for scheduled in &self.scheduled_notifications {
+            if let Some(receiver) = scheduled.receiver() {
+                if !self.receivers.contains_key(receiver) {
+                    return Err(ConfigError::UnknownReceiver(receiver.clone()));
+                }
+            }
+            if let Err(e) = Cron::parse(scheduled.cron()) {
+                let field = format!("scheduled notification '{}'", scheduled.title());
+                return Err(ConfigError::BadSchedule(field, e));
+            }
+        }
+        if let Some(url) = self
+            .forward_urls
+            .iter()
+            .chain(self.tenants.values().flat_map(|x| x.forward_urls()))
+            .map(ForwardUrl::url)
+            .find(|x| !x.starts_with("https://") && !x.starts_with("http://"))
+        {
+            return Err(ConfigError::BadForwardUrl(url.to_string()));
+        }
+        if let Some(rule) = self.url_rewrite.iter().find(|x| !x.valid()) {
+            return Err(ConfigError::BadUrlRewrite(rule.clone()));
+        }
+        if let Some(cron) = &self.realert_cron {
+            if let Err(e) = Cron::parse(cron) {
+                return Err(ConfigError::BadSchedule("realert_cron".to_string(), e));
+            }
+        }
+        Ok(())
+    }
+
+    /// `silences_file`, or next to the fingerprints file so silences survive restarts
+    /// like acks do. None when the fingerprints aren't kept in a file either, like `/dev/null`.
+    pub(crate) fn silences_path(&self) -> Option<PathBuf> {
+        if let Some(file) = &self.silences_file {
+            return Some(PathBuf::from(file));
+        }
+        let fingerprints = Path::new(&self.fingerprints_file);
+        if fingerprints.exists() && !fingerprints.is_file() {
+            return None;
+        }
+        Some(fingerprints.with_extension("silences.json"))
+    }
+
+    /// `listeners`, or `bind_host` serving everything when there are none.
+    pub(crate) fn listeners_or_default(&self) -> Vec<Listener> {
+        match self.listeners.is_empty() {
+            true => vec![Listener::new(self.bind_host.clone())],
+            false => self.listeners.clone(),
+        }
+    }
+
+    /// Checks the body of a built in webhook was signed with its `hmac_secrets`.
+    pub(crate) fn signature_for(&self, parser: Parser) -> Option<Auth> {
+        let secret = match (self.hmac_secrets.get(&parser), parser) {
+            (Some(secret), _) => secret,
+            (None, Parser::Github) => self.github_webhook_secret.as_ref()?,
+            (None, _) => return None,
+        };
+        Some(parser.signature(secret))
+    }
+
+    /// The `auth` set for the group, otherwise `auth_token` for webhooks and
+    /// `dashboard_user` for everything else.
+    pub(crate) fn auth_for(&self, group: RouteGroup) -> Auth {
+        if let Some(auth) = self.auth.get(group) {
+            return auth.clone();
+        }
+        match group {
+            RouteGroup::Webhooks => match &self.auth_token {
+                Some(token) => Auth::Bearer {
+                    token: token.clone(),
+                    header: self.auth_header.clone(),
+                },
+                None => Auth::None,
+            },
+            RouteGroup::Api | RouteGroup::Ui => {
+                match (&self.dashboard_user, &self.dashboard_password) {
+                    (Some(user), Some(password)) => Auth::Basic {
+                        user: user.clone(),
+                        password: password.clone(),
+                    },
+                    _ => Auth::None,
+                }
+            }
+        }
+    }
+
+    /// The link a notification opens, by `notification_url_preference` and `url_rewrite`.
+    pub(crate) fn notification_url(&self, links: &Links) -> Option<String> {
+        links
+            .preferred(&self.notification_url_preference)
+            .map(|x| url_rewrite::rewrite(&self.url_rewrite, x))
+    }
+
+    /// Every `prowl_api_keys` key in one request, used when no route matches.
+    pub(crate) fn default_receiver(&self) -> Receiver {
+        Receiver::new(self.prowl_api_keys.clone(), Delivery::Together)
+            .or_provider_key(&self.prowl_provider_key)
+    }
+
+    /// The `destinations`, for anything no route matches and the notifier's own notifications.
+    pub(crate) fn default_receivers(&self) -> Vec<Receiver> {
+        self.destination_receivers(&self.destinations)
+    }
+
+    /// The `fallback_destinations`, for notifications whose service is down.
+    pub(crate) fn fallback_receivers(&self) -> Vec<Receiver> {
+        self.destination_receivers(&self.fallback_destinations)
+    }
+
+    fn destination_receivers(&self, destinations: &[String]) -> Vec<Receiver> {
+        destinations
+            .iter()
+            .filter_map(|name| match name.as_str() {
+                PROWL_DESTINATION => Some(self.default_receiver()),
+                _ => self.named_receiver(name),
+            })
+            .collect()
+    }
+
+    fn named_receiver(&self, name: &str) -> Option<Receiver> {
+        self.receivers
+            .get(name)
+            .map(|x| x.clone().or_provider_key(&self.prowl_provider_key))
+    }
+
+    fn route_receiver(&self, route: &Route) -> Option<Receiver> {
+        match route.receiver() {
+            Some(name) => self.named_receiver(name),
+            None => Some(
+                Receiver::new(route.api_keys().clone(), Delivery::All)
+                    .or_provider_key(&self.prowl_provider_key),
+            ),
+        }
+    }
+
+    /// The tenant's `app_name` for its alerts, otherwise `app_name`.
+    pub(crate) fn app_name_for<T: Matchable>(&self, item: &T) -> &str {
+        item.value_of(&Target::Tenant)
+            .and_then(|x| self.tenants.get(&x))
+            .and_then(|x| x.app_name().as_deref())
+            .unwrap_or(&self.app_name)
+    }
+
+    /// The auth for a tenant's webhook path, which replaces the webhooks' own.
+    pub(crate) fn tenant_auth(&self, path: &str) -> Option<Auth> {
+        let tenant = self.tenants.get(Tenant::of_path(path)?)?;
+        Some(Auth::Bearer {
+            token: tenant.auth_token().clone(),
+            header: self.auth_header.clone(),
+        })
+    }
+
+    /// The tenant signed in to the dashboard or API with its own `auth_token`, if any.
+    pub(crate) fn tenant_of(&self, request: &http::Request) -> Option<&str> {
+        self.tenants
+            .iter()
+            .find(|(name, tenant)| {
+                tenant
+                    .auths(name, &self.auth_header)
+                    .iter()
+                    .any(|x| x.authorize(request))
+            })
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Where a webhook's body is forwarded, a tenant's own `forward_urls` for its webhooks.
+    pub(crate) fn forward_urls_for(&self, tenant: Option<&str>) -> &[ForwardUrl] {
+        match tenant {
+            Some(tenant) => self
+                .tenants
+                .get(tenant)
+                .map_or(&[], |x| x.forward_urls().as_slice()),
+            None => &self.forward_urls,
+        }
+    }
+
+    /// Where `resolved_digest` sends, when it is on.
+    pub(crate) fn digest_receiver(&self) -> Option<Receiver> {
+        self.named_receiver(self.resolved_digest.as_ref()?.receiver())
+    }
+
+    /// A tenant's own keys for its alerts. Otherwise the receiver of the first route that
+    /// matches, the `priority_destinations` for the item's priority, or the default receivers.
+    pub(crate) fn receivers_for_item<T: Matchable>(&self, item: &T) -> Vec<Receiver> {
+        if let Some(tenant) = item.value_of(&Target::Tenant) {
+            return self
+                .tenants
+                .get(&tenant)
+                .map(|x| vec![x.receiver()])
+                .unwrap_or_default();
+        }
+        if let Some(receiver) = self
+            .routes
+            .iter()
+            .find(|route| matcher::all_match(route.matchers(), item))
+            .and_then(|route| self.route_receiver(route))
+        {
+            return vec![receiver];
+        }
+        let destinations = item
+            .value_of(&Target::Priority)
+            .and_then(|x| self.priority_destinations.get(&x))
+            .unwrap_or(&self.destinations);
+        self.destination_receivers(destinations)
+    }
+
+    /// The named receivers, or the `receivers_for_item` when there are none.
+    pub(crate) fn receivers_for<T: Matchable>(&self, names: &[String], item: &T) -> Vec<Receiver> {
+        if names.is_empty() {
+            return self.receivers_for_item(item);
+        }
+        names
+            .iter()
+            .filter_map(|name| self.named_receiver(name))
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::models::{
+        grafana::{Alert, DatasourceProblem},
+        sink::{Sink, SmtpTls},
+    };
 
     #[test]
     fn test_default() {
@@ -60,9 +661,105 @@ mod test {
         assert_eq!(config.linear_retry_secs(), &60);
         assert_eq!(config.app_name(), "Grafana");
         assert_eq!(config.bind_host(), "0.0.0.0:3333");
+        let listeners = config.listeners_or_default();
+        assert_eq!(listeners.len(), 1);
+        assert_eq!(listeners[0].bind_host(), "0.0.0.0:3333");
+        assert!(listeners[0].serves(RouteGroup::Ui));
+        assert_eq!(config.max_connections(), &64);
+        assert_eq!(config.request_timeout_secs(), &10);
+        assert_eq!(config.keep_alive_timeout_secs(), &5);
+        assert_eq!(config.read_timeout_secs(), &5);
+        assert_eq!(config.header_timeout_secs(), &10);
+        assert_eq!(config.request_deadline_secs(), &30);
+        assert_eq!(config.max_body_bytes(), &4194304);
+        assert_eq!(config.access_log_file(), &None);
+        assert_eq!(config.drain_timeout_secs(), &8);
+        assert!(matches!(
+            config.unknown_status(),
+            UnknownStatus::Notify {
+                emoji: None,
+                priority: None
+            }
+        ));
         assert_eq!(config.alert_every_minutes(), &None);
         assert_eq!(config.realert_cron(), &None);
+        assert!(config.realert_matchers().is_empty());
         assert_eq!(config.test_mode(), &false);
+        assert_eq!(config.lifecycle_notifications(), &false);
+        assert_eq!(config.volume_anomalies(), &false);
+        assert_eq!(config.max_fingerprints_file_bytes(), &None);
+        assert_eq!(config.max_access_log_bytes(), &None);
+        assert_eq!(config.max_archive_bytes(), &None);
+        assert_eq!(config.min_free_disk_bytes(), &None);
+        assert!(config.receivers().is_empty());
+        assert_eq!(config.destinations(), &vec!["prowl"]);
+        assert_eq!(config.auth_token(), &None);
+        assert_eq!(config.auth_header(), &None);
+        assert_eq!(config.github_webhook_secret(), &None);
+        assert_eq!(config.dashboard_user(), &None);
+        assert_eq!(config.dashboard_password(), &None);
+        assert_eq!(config.dashboard_page_size(), &100);
+        assert_eq!(config.dashboard_refresh_secs(), &None);
+        assert_eq!(config.auth_for(RouteGroup::Webhooks), Auth::None);
+        assert_eq!(config.auth_for(RouteGroup::Api), Auth::None);
+        assert_eq!(config.auth_for(RouteGroup::Ui), Auth::None);
+        assert_eq!(config.archive_dir(), &None);
+        assert_eq!(config.silences_file(), &None);
+        assert_eq!(
+            config.silences_path(),
+            Some(PathBuf::from("fingerprints.silences.json"))
+        );
+        assert_eq!(config.archive_after_days(), &90);
+        assert_eq!(config.expire_resolved_after_hours(), &None);
+        assert_eq!(config.prune_resolved_after_hours(), &None);
+        assert_eq!(config.prune_unseen_after_days(), &None);
+        assert_eq!(config.incident_window_minutes(), &60);
+        assert_eq!(config.health_check_secs(), &None);
+        assert!(config.smtp().is_none());
+        assert!(config.resolved_digest().is_none());
+        assert_eq!(config.prowl_timeout_secs(), &30);
+        assert_eq!(config.prowl_connect_timeout_secs(), &10);
+        assert_eq!(config.prowl_keep_alive_secs(), &90);
+        assert_eq!(config.delivery_workers(), &4);
+        assert_eq!(config.prowl_provider_key(), &None);
+        assert!(config.allowed_source_cidrs().is_empty());
+        assert!(!config.read_only());
+        assert_eq!(config.large_webhook_bytes(), &None);
+        assert_eq!(config.large_webhook_alerts(), &None);
+        assert_eq!(
+            config.datasource_alerts().emoji(DatasourceProblem::NoData),
+            "❓"
+        );
+        assert_eq!(config.label_limits().max_labels, 64);
+        assert_eq!(config.label_limits().max_value_bytes, 1024);
+        assert_eq!(config.label_limits().max_fingerprints_per_alertname, 100);
+        assert_eq!(config.label_limits().max_alertnames_per_pattern, 20);
+        assert_eq!(
+            config.fingerprint_labels(),
+            &vec!["severity", "instance", "job"]
+        );
+        assert!(config.notification_labels().is_empty());
+        assert_eq!(
+            config.notification_annotations(),
+            &vec!["description", "runbook_url"]
+        );
+        assert_eq!(
+            config.notification_url_preference(),
+            &vec![LinkKind::Generator]
+        );
+        assert!(config.url_rewrite().is_empty());
+        assert!(config.routes().is_empty());
+        assert!(config.webhooks().is_empty());
+        assert!(config.forward_urls().is_empty());
+        assert!(config.tenants().is_empty());
+        assert!(config.tenant_auth("/webhooks/grafana/alice").is_none());
+        assert!(config.hmac_secrets().is_empty());
+        assert!(config.signature_for(Parser::Github).is_none());
+        assert_eq!(
+            config.file().as_deref(),
+            Some("src/resources/test-min-config.json")
+        );
+        assert!(config.scheduled_notifications().is_empty());
     }
 
     #[test]
@@ -70,11 +767,338 @@ mod test {
         let config = Config::load(Some("src/resources/test-max-config.json".to_string()));
         assert_eq!(config.app_name(), "Home Lab");
         assert_eq!(config.bind_host(), "127.0.0.1:1234");
+        let listeners = config.listeners_or_default();
+        assert_eq!(listeners.len(), 2);
+        assert!(listeners[0].serves(RouteGroup::Webhooks));
+        assert!(!listeners[0].serves(RouteGroup::Api));
+        assert_eq!(listeners[1].bind_host(), "127.0.0.1:1235");
+        assert_eq!(config.max_connections(), &8);
+        assert_eq!(config.request_timeout_secs(), &5);
+        assert_eq!(config.keep_alive_timeout_secs(), &0);
+        assert_eq!(config.read_timeout_secs(), &2);
+        assert_eq!(config.header_timeout_secs(), &3);
+        assert_eq!(config.request_deadline_secs(), &15);
+        assert_eq!(config.max_body_bytes(), &65536);
+        assert_eq!(
+            config.access_log_file(),
+            &Some("/var/log/grafana-prowl-notifier/access.log".to_string())
+        );
+        assert_eq!(config.drain_timeout_secs(), &25);
+        assert!(matches!(config.unknown_status(), UnknownStatus::Firing));
         assert_eq!(config.prowl_api_keys(), &vec!["api_key1", "api_key2"]);
         assert_eq!(config.fingerprints_file(), "/var/fingerprints.json");
+        assert_eq!(
+            config.silences_file(),
+            &Some("/var/silences.json".to_string())
+        );
+        assert_eq!(
+            config.silences_path(),
+            Some(PathBuf::from("/var/silences.json"))
+        );
         assert_eq!(config.linear_retry_secs(), &11);
         assert_eq!(config.alert_every_minutes(), &Some(33));
         assert_eq!(config.realert_cron(), &Some("0 9 * * MON-FRI".to_string()));
+        assert_eq!(config.realert_matchers().len(), 1);
         assert_eq!(config.test_mode(), &true);
+        assert_eq!(config.lifecycle_notifications(), &true);
+        assert_eq!(config.volume_anomalies(), &true);
+        assert_eq!(config.max_fingerprints_file_bytes(), &Some(1048576));
+        assert_eq!(config.max_access_log_bytes(), &Some(10485760));
+        assert_eq!(config.max_archive_bytes(), &Some(104857600));
+        assert_eq!(config.min_free_disk_bytes(), &Some(52428800));
+        assert_eq!(config.receivers().len(), 7);
+        assert_eq!(config.receivers()["ops"].api_keys(), &vec!["ops"]);
+        assert_eq!(config.receivers()["ops"].sink().name(), "ntfy");
+        assert_eq!(config.auth_token(), &Some("s3cret".to_string()));
+        assert_eq!(config.auth_header(), &Some("X-Webhook-Token".to_string()));
+        assert_eq!(config.github_webhook_secret(), &Some("octocat".to_string()));
+        assert_eq!(config.dashboard_user(), &Some("admin".to_string()));
+        assert_eq!(config.dashboard_password(), &Some("hunter2".to_string()));
+        assert_eq!(config.dashboard_page_size(), &25);
+        assert_eq!(config.dashboard_refresh_secs(), &Some(30));
+        assert_eq!(
+            config.auth_for(RouteGroup::Webhooks),
+            Auth::Bearer {
+                token: "s3cret".to_string(),
+                header: Some("X-Webhook-Token".to_string()),
+            }
+        );
+        assert_eq!(
+            config.auth_for(RouteGroup::Api),
+            Auth::Mtls {
+                header: "X-Client-Cert-Subject".to_string(),
+                subjects: vec!["CN=grafana".to_string()],
+            }
+        );
+        assert_eq!(
+            config.auth_for(RouteGroup::Ui),
+            Auth::Basic {
+                user: "admin".to_string(),
+                password: "hunter2".to_string(),
+            }
+        );
+        assert_eq!(config.archive_dir(), &Some("/var/archive".to_string()));
+        assert_eq!(config.archive_after_days(), &365);
+        assert_eq!(config.expire_resolved_after_hours(), &Some(168));
+        assert_eq!(config.prune_resolved_after_hours(), &Some(720));
+        assert_eq!(config.prune_unseen_after_days(), &Some(180));
+        assert_eq!(config.incident_window_minutes(), &240);
+        assert_eq!(config.health_check_secs(), &Some(300));
+        let smtp = config.smtp().as_ref().expect("Missing smtp");
+        assert_eq!(smtp.port(), &465);
+        assert_eq!(smtp.tls(), &SmtpTls::Tls);
+        assert_eq!(smtp.from(), "Grafana <grafana@example.com>");
+        let digest = config.resolved_digest().as_ref().expect("Missing digest");
+        assert_eq!(digest.every_minutes(), &120);
+        let inbox = config.digest_receiver().expect("Missing digest receiver");
+        assert_eq!(inbox.sink(), &Sink::Email);
+        assert_eq!(config.prowl_timeout_secs(), &20);
+        assert_eq!(config.prowl_connect_timeout_secs(), &5);
+        assert_eq!(config.prowl_keep_alive_secs(), &120);
+        assert_eq!(config.delivery_workers(), &2);
+        assert_eq!(config.prowl_provider_key().as_deref(), Some("provider"));
+        assert_eq!(
+            config.allowed_source_cidrs(),
+            &vec![
+                Cidr::parse("10.0.0.7").expect("Failed to parse"),
+                Cidr::parse("192.168.1.0/24").expect("Failed to parse"),
+            ]
+        );
+        assert!(config.read_only());
+        assert_eq!(config.large_webhook_bytes(), &Some(1048576));
+        assert_eq!(config.large_webhook_alerts(), &Some(100));
+        assert_eq!(
+            config.datasource_alerts().emoji(DatasourceProblem::NoData),
+            "🕳"
+        );
+        assert_eq!(
+            config.datasource_alerts().emoji(DatasourceProblem::Error),
+            "⚠️"
+        );
+        assert_eq!(config.label_limits().max_labels, 32);
+        assert_eq!(config.label_limits().max_value_bytes, 256);
+        assert_eq!(config.label_limits().max_fingerprints_per_alertname, 50);
+        assert_eq!(config.label_limits().max_alertnames_per_pattern, 10);
+        assert_eq!(config.fingerprint_labels(), &vec!["severity", "team"]);
+        assert_eq!(config.notification_labels(), &vec!["team"]);
+        assert!(config.notification_annotations().is_empty());
+        assert_eq!(
+            config.notification_url_preference(),
+            &vec![LinkKind::Panel, LinkKind::Dashboard, LinkKind::Generator]
+        );
+        assert_eq!(config.url_rewrite().len(), 2);
+        assert_eq!(config.routes().len(), 1);
+        assert_eq!(config.webhooks()[0].path(), "/webhooks/family/*");
+        assert_eq!(config.webhooks()[0].receivers(), &vec!["family", "me"]);
+        assert_eq!(
+            config.forward_urls(),
+            &vec![ForwardUrl::Plain(
+                "https://logs.example.com/ingest/grafana".to_string()
+            )]
+        );
+        let alice = config.tenants()["alice"].receiver();
+        assert_eq!(alice.api_keys(), &vec!["alice_key"]);
+        assert_eq!(alice.provider_key().as_deref(), Some("alice_provider"));
+        assert_eq!(
+            config.tenant_auth("/webhooks/grafana/alice"),
+            Some(Auth::Bearer {
+                token: "alice-token".to_string(),
+                header: Some("X-Webhook-Token".to_string())
+            })
+        );
+        assert!(config.tenant_auth("/webhooks/grafana/bob").is_none());
+        assert_eq!(
+            config.forward_urls_for(Some("alice")),
+            &[ForwardUrl::Signed {
+                url: "https://logs.example.com/ingest/alice".to_string(),
+                secret: "alice-forward-secret".to_string()
+            }]
+        );
+        assert_eq!(config.forward_urls_for(None), config.forward_urls());
+        assert!(config.forward_urls_for(Some("bob")).is_empty());
+        assert_eq!(
+            config.webhooks()[0].signature(),
+            Some(Auth::Hmac {
+                secret: "family-secret".to_string(),
+                header: "X-Family-Signature".to_string()
+            })
+        );
+        assert_eq!(
+            config.signature_for(Parser::Sentry),
+            Some(Parser::Sentry.signature("sign-me"))
+        );
+        // github_webhook_secret is GitHub's hmac_secrets
+        assert_eq!(
+            config.signature_for(Parser::Github),
+            Some(Auth::Hmac {
+                secret: "octocat".to_string(),
+                header: "X-Hub-Signature-256".to_string()
+            })
+        );
+        assert!(config.signature_for(Parser::Grafana).is_none());
+        assert_eq!(config.scheduled_notifications().len(), 2);
+    }
+
+    #[test]
+    fn test_receiver_for() {
+        let config = Config::load(Some("src/resources/test-max-config.json".to_string()));
+        let alert: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        let receivers = config.receivers_for_item(&alert);
+        assert_eq!(receivers.len(), 2);
+        let receiver = &receivers[0];
+        assert_eq!(receiver.api_keys(), &vec!["api_key1", "api_key2"]);
+        assert_eq!(receiver.delivery(), &Delivery::Together);
+        assert_eq!(receiver.timezone(), &chrono_tz::Tz::UTC);
+
+        let alert: Alert = serde_json::from_str(
+            &crate::test::consts::create_firing_alert_with_prefix("[critical] "),
+        )
+        .expect("Failed to load critical, firing alert");
+        let receiver = &config.receivers_for_item(&alert)[0];
+        assert_eq!(receiver.api_keys(), &vec!["family_key1", "family_key2"]);
+        assert_eq!(receiver.delivery(), &Delivery::Any);
+        assert_eq!(receiver.timezone(), &chrono_tz::Tz::Europe__Berlin);
+        assert_eq!(
+            receiver.max_priority().get("family_key2"),
+            Some(&prowl::Priority::High)
+        );
+        // receivers without their own provider key use prowl_provider_key
+        assert_eq!(receiver.provider_key().as_deref(), Some("provider"));
+        assert_eq!(
+            config.default_receiver().provider_key().as_deref(),
+            Some("provider")
+        );
+
+        let names = config.webhooks()[0].receivers();
+        let receivers = config.receivers_for(names, &alert);
+        assert_eq!(receivers.len(), 2);
+        assert_eq!(receivers[1].api_keys(), &vec!["api_key1"]);
+        assert_eq!(receivers[1].provider_key().as_deref(), Some("provider"));
+        assert_eq!(config.receivers_for(&[], &alert).len(), 1);
+        assert_eq!(
+            config.priority_destinations()["High"],
+            vec!["prowl", "partner"]
+        );
+        assert_eq!(
+            config.fallback_receivers()[0].api_keys(),
+            &vec!["grafana-alerts"]
+        );
+        // unrouted notifications go to every destination
+        let default = config.default_receivers();
+        assert_eq!(default[0].api_keys(), config.prowl_api_keys());
+        assert_eq!(default[1].api_keys(), &vec!["grafana-alerts"]);
+        assert!(
+            matches!(default[1].sink(), Sink::Ntfy(x) if x.server() == "https://ntfy.example.com")
+        );
+
+        assert_eq!(receivers[0].sink(), &Sink::Prowl);
+        match config.receivers()["partner"].sink() {
+            Sink::Pushover(pushover) => {
+                assert_eq!(pushover.token(), "pushover_app");
+                assert_eq!(pushover.emergency_retry_secs(), &120);
+                assert_eq!(pushover.emergency_expire_secs(), &3600);
+            }
+            other => panic!("Unexpected {other:?}"),
+        }
+        assert!(
+            matches!(config.receivers()["lab"].sink(), Sink::Gotify(x) if x.server() == "https://gotify.example.com")
+        );
+    }
+
+    #[test]
+    fn critical_to_prowl_rest_to_slack() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "prowl_api_keys": ["api_key1"],
+            "fingerprints_file": "/dev/null",
+            "receivers": {
+                "phones": {"api_keys": ["api_key1"]},
+                "channel": {"api_keys": ["https://hooks.slack.com/services/T0/B0/x"], "sink": "slack"}
+            },
+            "routes": [{"matchers": ["@priority=Emergency"], "receiver": "phones"}],
+            "destinations": ["channel"]
+        }))
+        .expect("Failed to parse config");
+        let alert = |prefix: &str| -> Alert {
+            serde_json::from_str(&crate::test::consts::create_firing_alert_with_prefix(
+                prefix,
+            ))
+            .expect("Failed to load alert")
+        };
+        let receivers = config.receivers_for_item(&alert("[critical] "));
+        assert_eq!(receivers.len(), 1);
+        assert_eq!(receivers[0].sink(), &Sink::Prowl);
+        let receivers = config.receivers_for_item(&alert(""));
+        assert_eq!(receivers.len(), 1);
+        assert_eq!(receivers[0].sink(), &Sink::Slack);
+    }
+
+    #[test]
+    fn priority_destinations() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "prowl_api_keys": ["mine"],
+            "fingerprints_file": "/dev/null",
+            "receivers": {
+                "family": {"api_keys": ["mine", "partner", "kid"], "delivery": "together"}
+            },
+            "priority_destinations": {"Emergency": ["family"], "High": ["family", "prowl"]}
+        }))
+        .expect("Failed to parse config");
+        config.validate().expect("Invalid config");
+        let keys = |prefix: &str| -> Vec<Vec<String>> {
+            let alert: Alert = serde_json::from_str(
+                &crate::test::consts::create_firing_alert_with_prefix(prefix),
+            )
+            .expect("Failed to load alert");
+            config
+                .receivers_for_item(&alert)
+                .iter()
+                .map(|x| x.api_keys().clone())
+                .collect()
+        };
+        assert_eq!(keys("[critical] "), vec![vec!["mine", "partner", "kid"]]);
+        assert_eq!(
+            keys("[high] "),
+            vec![vec!["mine", "partner", "kid"], vec!["mine"]]
+        );
+        assert_eq!(keys(""), vec![vec!["mine"]]);
+
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "prowl_api_keys": ["mine"],
+            "fingerprints_file": "/dev/null",
+            "priority_destinations": {"Critical": ["prowl"]}
+        }))
+        .expect("Failed to parse config");
+        assert_eq!(
+            config.validate().expect_err("Unknown priority").to_string(),
+            "priority_destinations has 'Critical', which isn't VeryLow, Moderate, Normal, High or Emergency."
+        );
+    }
+
+    #[test]
+    fn route_targets() {
+        let config = |route: serde_json::Value| -> Config {
+            serde_json::from_value(serde_json::json!({
+                "prowl_api_keys": ["mine"],
+                "fingerprints_file": "/dev/null",
+                "receivers": {"family": {"api_keys": ["partner"]}},
+                "routing": [route]
+            }))
+            .expect("Failed to parse config")
+        };
+        let keys =
+            config(serde_json::json!({"matchers": ["team=network"], "api_keys": ["netops"]}));
+        keys.validate().expect("Invalid config");
+        assert_eq!(keys.routes()[0].api_keys(), &vec!["netops"]);
+        let both = config(serde_json::json!({"receiver": "family", "api_keys": ["netops"]}));
+        assert_eq!(
+            both.validate().expect_err("Two targets").to_string(),
+            "Route 0 needs either a `receiver` or `api_keys`."
+        );
+        let neither = config(serde_json::json!({"matchers": ["team=network"]}));
+        assert!(matches!(
+            neither.validate(),
+            Err(ConfigError::RouteTarget(0))
+        ));
     }
 }
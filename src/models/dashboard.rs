@@ -0,0 +1,456 @@
+use crate::models::{fingerprint::PreviousEvent, http, notification::priority_number};
+use std::{cmp::Ordering, ops::Range};
+
+/// The most `per_page` can ask for, so one request can't render everything.
+const MAX_PER_PAGE: usize = 1000;
+
+/// What the `actions` buttons call, reloading the page once the API answers.
+pub(crate) const SCRIPT: &str = "<script>\
+window.act = function(method, path, body) { fetch(path, { method: method, body: body }).then(() => window.location.reload()) };\
+window.fp_path = function(id) { return '/api/fingerprints/' + encodeURIComponent(id) };\
+window.ack_fp = function(id) { act('POST', fp_path(id) + '/ack', '') };\
+window.silence_fp = function(id) { act('POST', '/api/silences', JSON.stringify({ fingerprint: id, minutes: 60, created_by: 'dashboard' })) };\
+window.resend_fp = function(id) { act('POST', fp_path(id) + '/resend', '') };\
+window.delete_fp = function(id) { if (confirm('Delete ' + id + '?')) act('DELETE', fp_path(id), '') };\
+</script>";
+
+/// Row colours by `row_class`, strongest for what needs attention first.
+pub(crate) const STYLE: &str = "<style>\
+.banner { padding: 0.5em; font-size: 1.4em; background: #d4edda; }\
+.banner.firing { background: #f8d7da; }\
+tr.resolved { background: #e2e3e5; }\
+tr.firing.emergency { background: #f5a3a9; }\
+tr.firing.high { background: #f8d7da; }\
+tr.firing.normal { background: #ffe8b3; }\
+tr.firing.moderate, tr.firing.verylow, tr.firing.unknown { background: #fff3cd; }\
+</style>";
+
+/// Columns of the dashboard's fingerprint tables, by their `sort` name and title.
+pub(crate) const COLUMNS: [(&str, &str); 9] = [
+    ("id", "ID"),
+    ("name", "Name"),
+    ("priority", "Priority"),
+    ("status", "Status"),
+    ("last_alert", "Last Alert"),
+    ("first_alert", "First Alert"),
+    ("started", "Started"),
+    ("ended", "Ended"),
+    ("group", "Group"),
+];
+
+/// The dashboard's `?status=firing&priority=High&q=disk&sort=name&order=asc&page=2&per_page=50`.
+/// Unknown or empty parameters are ignored, so a bad link still shows everything.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct DashboardQuery {
+    status: Option<String>,
+    priority: Option<String>,
+    q: Option<String>,
+    sort: Option<&'static str>,
+    ascending: Option<bool>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+}
+
+/// Where a page falls in the filtered fingerprints, counting pages from 1.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Page {
+    number: usize,
+    pages: usize,
+    size: usize,
+    total: usize,
+}
+
+impl Page {
+    /// Indexes of the fingerprints on the page, across all sections.
+    pub(crate) fn range(&self) -> Range<usize> {
+        let start = (self.number - 1) * self.size;
+        start..self.total.min(start + self.size)
+    }
+}
+
+impl DashboardQuery {
+    pub(crate) fn from_request(request: &http::Request) -> Self {
+        let mut query = DashboardQuery::default();
+        for (key, value) in request.request_line().query_params() {
+            let value = value.trim().to_string();
+            if value.is_empty() {
+                continue;
+            }
+            match key.as_str() {
+                "status" => query.status = Some(value.to_lowercase()),
+                "priority" => query.priority = Some(value.to_lowercase()),
+                "q" => query.q = Some(value.to_lowercase()),
+                "sort" => query.sort = COLUMNS.iter().map(|(x, _)| *x).find(|x| *x == value),
+                "order" => query.ascending = Some(value == "asc"),
+                "page" => query.page = value.parse().ok().filter(|x| *x > 0),
+                "per_page" => {
+                    query.per_page = value
+                        .parse()
+                        .ok()
+                        .filter(|x| *x > 0)
+                        .map(|x: usize| x.min(MAX_PER_PAGE))
+                }
+                _ => {}
+            }
+        }
+        query
+    }
+
+    pub(crate) fn matches(&self, fingerprint: &PreviousEvent) -> bool {
+        let priority = fingerprint
+            .priority()
+            .as_ref()
+            .map(|x| format!("{x:?}").to_lowercase());
+        let name = fingerprint
+            .name()
+            .clone()
+            .unwrap_or_default()
+            .to_lowercase();
+        let label_matches = |q: &str| {
+            fingerprint
+                .labels()
+                .values()
+                .any(|x| x.to_lowercase().contains(q))
+        };
+        self.status
+            .as_ref()
+            .is_none_or(|x| x == fingerprint.last_status())
+            && self
+                .priority
+                .as_ref()
+                .is_none_or(|x| Some(x) == priority.as_ref())
+            && self.q.as_ref().is_none_or(|x| {
+                name.contains(x) || fingerprint.fingerprint().contains(x) || label_matches(x)
+            })
+    }
+
+    /// Newest alerts first unless another column is asked for.
+    pub(crate) fn sort(&self, fingerprints: &mut [&PreviousEvent]) {
+        let column = self.sort.unwrap_or("last_alert");
+        fingerprints.sort_by(|a, b| {
+            let ordering = compare(column, a, b);
+            match self.ascending(column) {
+                true => ordering,
+                false => ordering.reverse(),
+            }
+        });
+    }
+
+    fn ascending(&self, column: &str) -> bool {
+        self.ascending.unwrap_or(ascending_by_default(column))
+    }
+
+    /// The query string for sorting by the column, flipping the order if it is sorted by it.
+    pub(crate) fn sort_link(&self, column: &str) -> String {
+        let ascending = match self.sort.unwrap_or("last_alert") == column {
+            true => !self.ascending(column),
+            false => ascending_by_default(column),
+        };
+        let mut params = vec![
+            format!("sort={column}"),
+            format!("order={}", if ascending { "asc" } else { "desc" }),
+        ];
+        params.extend(self.filters());
+        format!("?{}", params.join("&"))
+    }
+
+    /// The asked page of `total` fingerprints, or the last one if it is past the end.
+    pub(crate) fn page(&self, total: usize, page_size: usize) -> Page {
+        let size = self.per_page.unwrap_or(page_size).max(1);
+        let pages = total.div_ceil(size).max(1);
+        Page {
+            number: self.page.unwrap_or(1).min(pages),
+            pages,
+            size,
+            total,
+        }
+    }
+
+    /// How many are shown of how many, with links to the pages either side.
+    pub(crate) fn pager(&self, page: &Page) -> String {
+        let range = page.range();
+        let shown = match range.is_empty() {
+            true => format!("Showing 0 of {}", page.total),
+            false => format!(
+                "Showing {}–{} of {}",
+                range.start + 1,
+                range.end,
+                page.total
+            ),
+        };
+        let link = |number: usize, text: &str| {
+            let mut params = self.sorting();
+            params.extend(self.filters());
+            params.push(format!("page={number}"));
+            format!(" <a href='?{}'>{text}</a>", params.join("&"))
+        };
+        let previous = match page.number > 1 {
+            true => link(page.number - 1, "Prev"),
+            false => String::new(),
+        };
+        let next = match page.number < page.pages {
+            true => link(page.number + 1, "Next"),
+            false => String::new(),
+        };
+        format!(
+            "<p>{shown}, page {} of {}{previous}{next}</p>",
+            page.number, page.pages
+        )
+    }
+
+    /// Links to `/export` with the same filters and order, across all pages.
+    fn export_links(&self) -> String {
+        let mut params = self.sorting();
+        params.extend(
+            self.filters()
+                .into_iter()
+                .filter(|x| !x.starts_with("per_page=")),
+        );
+        let link = |format: &str, text: &str| {
+            let mut params = params.clone();
+            params.insert(0, format!("format={format}"));
+            format!("<a href='/export?{}'>{text}</a>", params.join("&"))
+        };
+        format!("Export {} {}", link("csv", "CSV"), link("json", "JSON"))
+    }
+
+    /// The sort as query parameters, if one was asked for.
+    fn sorting(&self) -> Vec<String> {
+        let mut params = vec![];
+        if let Some(sort) = self.sort {
+            params.push(format!("sort={sort}"));
+        }
+        if let Some(ascending) = self.ascending {
+            params.push(format!("order={}", if ascending { "asc" } else { "desc" }));
+        }
+        params
+    }
+
+    /// The filters and page size as query parameters, which every link keeps.
+    fn filters(&self) -> Vec<String> {
+        let mut params = vec![];
+        for (key, value) in [
+            ("status", &self.status),
+            ("priority", &self.priority),
+            ("q", &self.q),
+        ] {
+            if let Some(value) = value {
+                params.push(format!("{key}={}", urlencoding::encode(value)));
+            }
+        }
+        if let Some(per_page) = self.per_page {
+            params.push(format!("per_page={per_page}"));
+        }
+        params
+    }
+
+    /// A form for the filters, keeping the sort and page size but starting from the first page.
+    pub(crate) fn form(&self) -> String {
+        let select = |name: &str, options: &[&str], selected: &Option<String>| {
+            let options: String = options
+                .iter()
+                .map(|x| {
+                    let value = x.to_lowercase();
+                    let chosen = match selected.as_deref() == Some(value.as_str()) {
+                        true => " selected",
+                        false => "",
+                    };
+                    format!("<option value='{value}'{chosen}>{x}</option>")
+                })
+                .collect();
+            format!("<select name='{name}'><option value=''>Any {name}</option>{options}</select>")
+        };
+        let q = escape(self.q.as_deref().unwrap_or_default());
+        let sort = self
+            .sort
+            .map(|x| format!("<input type='hidden' name='sort' value='{x}'>"))
+            .unwrap_or_default();
+        let order = match (self.sort, self.ascending) {
+            (Some(_), Some(true)) => "<input type='hidden' name='order' value='asc'>",
+            (Some(_), Some(false)) => "<input type='hidden' name='order' value='desc'>",
+            _ => "",
+        };
+        let per_page = self
+            .per_page
+            .map(|x| format!("<input type='hidden' name='per_page' value='{x}'>"))
+            .unwrap_or_default();
+        format!(
+            "<form method='get'><input name='q' placeholder='Search names' value='{q}'> {} {} {sort}{order}{per_page}<button>Filter</button> <a href='/'>Clear</a> {}</form>",
+            select("status", &["Firing", "Resolved"], &self.status),
+            select(
+                "priority",
+                &["Emergency", "High", "Normal", "Moderate", "VeryLow"],
+                &self.priority
+            ),
+            self.export_links(),
+        )
+    }
+}
+
+/// How many are firing, the worst of them and when anything last alerted,
+/// from every fingerprint whatever the filters.
+pub(crate) fn banner<'a>(fingerprints: impl Iterator<Item = &'a PreviousEvent>) -> String {
+    let mut firing = 0;
+    let mut highest = None;
+    let mut latest = None;
+    for fingerprint in fingerprints {
+        latest = latest.max(Some(*fingerprint.last_alerted()));
+        if fingerprint.last_status() != "firing" {
+            continue;
+        }
+        firing += 1;
+        if let Some(priority) = fingerprint.priority() {
+            if highest.is_none_or(|x| priority_number(priority) > priority_number(x)) {
+                highest = Some(priority);
+            }
+        }
+    }
+    let class = match firing {
+        0 => "banner",
+        _ => "banner firing",
+    };
+    let highest = highest
+        .map(|x| format!(", highest {x:?}"))
+        .unwrap_or_default();
+    let latest = latest
+        .map(|x| format!(", last alert {}", x.format("%d/%m/%y %H:%M")))
+        .unwrap_or_default();
+    format!("<div class='{class}'><b>{firing} firing</b>{highest}{latest}</div>")
+}
+
+/// The status and priority as CSS classes, like `firing high`.
+pub(crate) fn row_class(fingerprint: &PreviousEvent) -> String {
+    let priority = fingerprint.priority().as_ref().map_or_else(
+        || "unknown".to_string(),
+        |x| format!("{x:?}").to_lowercase(),
+    );
+    escape(&format!("{} {priority}", fingerprint.last_status()))
+}
+
+/// The kept labels as `name=value` lines.
+pub(crate) fn labels(fingerprint: &PreviousEvent) -> String {
+    let pairs: Vec<String> = fingerprint
+        .labels()
+        .iter()
+        .map(|(name, value)| escape(&format!("{name}={value}")))
+        .collect();
+    pairs.join("<br>")
+}
+
+/// Buttons for the management API, run by `SCRIPT`. Re-sending only makes sense while firing.
+pub(crate) fn actions(fingerprint: &PreviousEvent) -> String {
+    // a JS string in an HTML attribute, so quoted for both
+    let id = escape(&serde_json::to_string(fingerprint.fingerprint()).unwrap_or_default());
+    let button =
+        |function: &str, text: &str| format!("<button onclick='{function}({id})'>{text}</button>");
+    let resend = match fingerprint.last_status() == "firing" {
+        true => button("resend_fp", "Re-send"),
+        false => String::new(),
+    };
+    format!(
+        "{}{}{resend}{}",
+        button("ack_fp", "Ack"),
+        button("silence_fp", "Silence 1h"),
+        button("delete_fp", "Delete")
+    )
+}
+
+/// Times and priorities start with the latest and highest, the rest from A.
+fn ascending_by_default(column: &str) -> bool {
+    !matches!(
+        column,
+        "last_alert" | "first_alert" | "started" | "ended" | "priority"
+    )
+}
+
+fn compare(column: &str, a: &PreviousEvent, b: &PreviousEvent) -> Ordering {
+    let name = |x: &PreviousEvent| x.name().clone().unwrap_or_default().to_lowercase();
+    let priority = |x: &PreviousEvent| x.priority().as_ref().map(priority_number);
+    match column {
+        "id" => a.fingerprint().cmp(b.fingerprint()),
+        "name" => name(a).cmp(&name(b)),
+        "priority" => priority(a).cmp(&priority(b)),
+        "status" => a.last_status().cmp(b.last_status()),
+        "first_alert" => a.first_alerted().cmp(b.first_alerted()),
+        "started" => a.started_at().cmp(b.started_at()),
+        "ended" => a.ended_at().cmp(b.ended_at()),
+        "group" => a.group_key().cmp(b.group_key()),
+        _ => a.last_alerted().cmp(b.last_alerted()),
+    }
+    // the same order on every refresh, whatever the column
+    .then_with(|| a.fingerprint().cmp(b.fingerprint()))
+}
+
+/// A link to the URL with the already escaped text, or just the text when the URL
+/// isn't http or https, so a `javascript:` URL from a webhook can't run.
+pub(crate) fn link(url: Option<&str>, text: &str) -> String {
+    let url = url.filter(|x| {
+        let x = x.to_ascii_lowercase();
+        x.starts_with("https://") || x.starts_with("http://")
+    });
+    match url {
+        Some(url) => format!("<a href='{}'>{text}</a>", escape(url)),
+        None => text.to_string(),
+    }
+}
+
+/// Enough for text put in attributes or between tags.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&#39;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::TestStream;
+
+    async fn query(path: &str) -> DashboardQuery {
+        let message = format!("GET {path} HTTP/1.1\r\n\r\n");
+        let mut stream = TestStream::new(message.as_bytes());
+        let request = http::Request::from_stream(&mut stream)
+            .await
+            .expect("Failed to build request");
+        DashboardQuery::from_request(&request)
+    }
+
+    #[tokio::test]
+    async fn parses_and_links() {
+        let parsed = query("/?status=Firing&q=Disk+full&sort=name&order=asc&priority=&x=1").await;
+        assert_eq!(
+            parsed,
+            DashboardQuery {
+                status: Some("firing".to_string()),
+                priority: None,
+                q: Some("disk full".to_string()),
+                sort: Some("name"),
+                ascending: Some(true),
+                page: None,
+                per_page: None,
+            }
+        );
+        // the sorted column flips, others start from their default
+        assert_eq!(
+            parsed.sort_link("name"),
+            "?sort=name&order=desc&status=firing&q=disk%20full"
+        );
+        assert_eq!(
+            parsed.sort_link("last_alert"),
+            "?sort=last_alert&order=desc&status=firing&q=disk%20full"
+        );
+
+        let default = query("/?sort=bogus").await;
+        assert_eq!(default, DashboardQuery::default());
+        assert_eq!(
+            default.sort_link("last_alert"),
+            "?sort=last_alert&order=asc"
+        );
+        assert_eq!(default.sort_link("id"), "?sort=id&order=asc");
+        assert!(default
+            .form()
+            .contains("<option value=''>Any status</option>"));
+    }
+}
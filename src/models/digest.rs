@@ -0,0 +1,108 @@
+use crate::models::notification::{duration_text, NotificationContent};
+use chrono::Duration;
+use derive_getters::Getters;
+use prowl::Priority;
+use serde::Deserialize;
+
+/// Resolved alerts go to `receiver` together every `every_minutes`, instead of one by one.
+#[derive(Clone, Debug, Deserialize, Getters)]
+pub(crate) struct ResolvedDigest {
+    receiver: String,
+    #[serde(default = "default_every_minutes")]
+    every_minutes: u64,
+}
+
+fn default_every_minutes() -> u64 {
+    60
+}
+
+/// A resolved alert waiting for the next digest.
+#[derive(Clone, Debug, PartialEq)]
+struct Resolved {
+    name: String,
+    summary: String,
+    duration: Option<Duration>,
+}
+
+/// Resolved alerts collected since the last digest was sent.
+#[derive(Debug, Default)]
+pub(crate) struct Digest {
+    resolved: Vec<Resolved>,
+}
+
+impl Digest {
+    pub(crate) fn add(&mut self, name: &str, summary: &str, duration: Option<Duration>) {
+        self.resolved.push(Resolved {
+            name: name.to_string(),
+            summary: summary.to_string(),
+            duration,
+        });
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.resolved.len()
+    }
+
+    /// One quiet notification listing everything resolved, emptying the digest.
+    /// None when nothing was resolved.
+    pub(crate) fn take(&mut self, app_name: &str) -> Option<NotificationContent> {
+        if self.resolved.is_empty() {
+            return None;
+        }
+        let resolved = std::mem::take(&mut self.resolved);
+        let event = match resolved.len() {
+            1 => "[✅] 1 alert resolved".to_string(),
+            n => format!("[✅] {n} alerts resolved"),
+        };
+        let lines: Vec<String> = resolved
+            .iter()
+            .map(|x| match x.duration {
+                Some(duration) => {
+                    format!(
+                        "{}: {} (after {})",
+                        x.name,
+                        x.summary,
+                        duration_text(duration)
+                    )
+                }
+                None => format!("{}: {}", x.name, x.summary),
+            })
+            .collect();
+        Some(NotificationContent::new(
+            Some(Priority::VeryLow),
+            None,
+            app_name.to_string(),
+            event,
+            lines.join("\n"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn takes_everything_resolved() {
+        let mut digest = Digest::default();
+        assert!(digest.take("Grafana").is_none());
+
+        digest.add("Disk full", "95% used", Some(Duration::minutes(125)));
+        digest.add("Backups late", "No backup today", None);
+        assert_eq!(digest.len(), 2);
+        let addressed = digest
+            .take("Grafana")
+            .expect("No digest")
+            .to_prowl(vec!["key".to_string()])
+            .expect("Failed to create notification");
+        let notification = addressed.notification();
+        assert_eq!(notification.event(), "[✅] 2 alerts resolved");
+        assert_eq!(
+            notification.description(),
+            "Disk full: 95% used (after 2h 5m)\nBackups late: No backup today"
+        );
+        assert!(matches!(notification.priority(), Some(Priority::VeryLow)));
+        assert_eq!(digest.len(), 0);
+        assert!(digest.take("Grafana").is_none());
+    }
+}
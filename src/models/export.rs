@@ -0,0 +1,118 @@
+use crate::models::fingerprint::PreviousEvent;
+use chrono::{DateTime, SecondsFormat, Utc};
+
+/// The columns of a CSV export, one row per fingerprint.
+const CSV_HEADER: &str = "fingerprint,name,priority,status,first_alerted,last_alerted,last_seen,acked_until,group_key,generator_url,summary";
+
+/// What `GET /export` writes, picked by `?format=`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    /// JSON unless the format is given, None if it isn't one of them.
+    pub(crate) fn parse(format: Option<&str>) -> Option<Self> {
+        match format.map(|x| x.to_lowercase()).as_deref() {
+            None | Some("json") => Some(ExportFormat::Json),
+            Some("csv") => Some(ExportFormat::Csv),
+            Some(_) => None,
+        }
+    }
+
+    pub(crate) fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Json => "application/json",
+        }
+    }
+
+    /// A download name with the time, so exports for a postmortem don't overwrite each other.
+    pub(crate) fn file_name(self, now: DateTime<Utc>) -> String {
+        let extension = match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        };
+        format!("fingerprints-{}.{extension}", now.format("%Y%m%dT%H%M%SZ"))
+    }
+}
+
+/// Fingerprints as CSV with RFC 3339 times, leaving out the history and incidents.
+pub(crate) fn to_csv(fingerprints: &[&PreviousEvent]) -> String {
+    let time = |x: &DateTime<Utc>| x.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let mut csv = format!("{CSV_HEADER}\r\n");
+    for fingerprint in fingerprints {
+        let fields = [
+            fingerprint.fingerprint().clone(),
+            fingerprint.name().clone().unwrap_or_default(),
+            fingerprint
+                .priority()
+                .as_ref()
+                .map(|x| format!("{x:?}"))
+                .unwrap_or_default(),
+            fingerprint.last_status().clone(),
+            fingerprint
+                .first_alerted()
+                .as_ref()
+                .map(time)
+                .unwrap_or_default(),
+            time(fingerprint.last_alerted()),
+            time(fingerprint.last_seen()),
+            fingerprint
+                .acked_until()
+                .as_ref()
+                .map(time)
+                .unwrap_or_default(),
+            fingerprint.group_key().clone().unwrap_or_default(),
+            fingerprint
+                .links()
+                .generator_url()
+                .clone()
+                .unwrap_or_default(),
+            fingerprint.summary().clone().unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|x| field(x)).collect();
+        csv += &row.join(",");
+        csv += "\r\n";
+    }
+    csv
+}
+
+/// Quoted when it has a comma, quote or line break, with quotes doubled. Fields that
+/// a spreadsheet would run as a formula get a leading `'`.
+fn field(value: &str) -> String {
+    let value = match value.starts_with(['=', '+', '-', '@']) {
+        true => format!("'{value}"),
+        false => value.to_string(),
+    };
+    match value.contains([',', '"', '\r', '\n']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csv_fields() {
+        assert_eq!(field("Disk full"), "Disk full");
+        assert_eq!(field("95%, rising"), "\"95%, rising\"");
+        assert_eq!(field("say \"hi\"\nbye"), "\"say \"\"hi\"\"\nbye\"");
+        assert_eq!(field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(field(""), "");
+
+        assert_eq!(ExportFormat::parse(None), Some(ExportFormat::Json));
+        assert_eq!(ExportFormat::parse(Some("CSV")), Some(ExportFormat::Csv));
+        assert_eq!(ExportFormat::parse(Some("xml")), None);
+        let now = "2022-09-09T10:05:00Z"
+            .parse()
+            .expect("Failed to parse time");
+        assert_eq!(
+            ExportFormat::Csv.file_name(now),
+            "fingerprints-20220909T100500Z.csv"
+        );
+    }
+}
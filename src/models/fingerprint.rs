@@ -1,13 +1,51 @@
-use crate::models::{config::Config, grafana::Alert};
+use crate::models::{
+    config::Config,
+    grafana::{Alert, LabelLimits},
+    tenant::Tenant,
+};
 use chrono::{serde::ts_seconds, DateTime, Utc};
 use derive_getters::Getters;
 use prowl::Priority;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+/// How many removals `changes_since` remembers.
+const MAX_REMOVED: usize = 1000;
+/// How many status changes each fingerprint remembers.
+const MAX_HISTORY: usize = 20;
+/// Recorded in the history when a resolved fingerprint's metadata is forgotten.
+const EXPIRED: &str = "expired";
+/// How many incidents each fingerprint remembers.
+const MAX_INCIDENTS: usize = 10;
+/// Until `incident_window_minutes` is loaded from the config.
+const DEFAULT_INCIDENT_WINDOW_MINUTES: i64 = 60;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct Fingerprints {
     data: HashMap<String, PreviousEvent>,
+    #[serde(skip, default = "first_revision")]
+    revision: u64,
+    /// Revision and fingerprint of recent removals, oldest first.
+    #[serde(skip)]
+    removed: VecDeque<(u64, String)>,
+    /// Removals after this revision are all in `removed`.
+    #[serde(skip)]
+    removed_since: u64,
+    /// Firing again within this long of resolving continues the same incident.
+    #[serde(skip, default = "default_incident_window")]
+    incident_window: chrono::Duration,
+    /// The `fingerprint_labels` kept from each alert.
+    #[serde(skip)]
+    kept_labels: Vec<String>,
+}
+
+fn default_incident_window() -> chrono::Duration {
+    chrono::Duration::minutes(DEFAULT_INCIDENT_WINDOW_MINUTES)
+}
+
+/// Starts at the load time, so revisions from before a restart aren't reused.
+pub(crate) fn first_revision() -> u64 {
+    Utc::now().timestamp_millis() as u64
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize, Getters)]
@@ -21,15 +59,228 @@ pub(crate) struct PreviousEvent {
     priority: Option<Priority>,
     name: Option<String>,
     summary: Option<String>,
+    /// The alert's `fingerprint_labels`, for the dashboard, matchers and re-alerts.
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    /// Grafana's `startsAt` and, once resolved, `endsAt` from the latest webhook.
+    #[serde(default)]
+    started_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    ended_at: Option<DateTime<Utc>>,
+    group_key: Option<String>,
+    /// The store's revision when this was last changed.
+    #[serde(default)]
+    revision: u64,
+    /// Status changes, oldest first.
+    #[serde(default)]
+    history: Vec<Transition>,
+    /// Re-alerts are skipped until then, cleared when the status changes.
+    #[serde(default)]
+    acked_until: Option<DateTime<Utc>>,
+    /// Oldest first, the last one may still be open.
+    #[serde(default)]
+    incidents: Vec<Incident>,
+    #[serde(flatten)]
+    links: Links,
+    /// The tenant it belongs to, None for alerts to the notifier's own webhooks.
+    #[serde(default)]
+    tenant: Option<String>,
+}
+
+/// Where the alert can be seen in Grafana, from the latest webhook.
+#[derive(Debug, Default, Deserialize, Clone, Serialize, Getters)]
+pub(crate) struct Links {
+    #[serde(default)]
+    generator_url: Option<String>,
+    #[serde(default)]
+    dashboard_url: Option<String>,
+    #[serde(default)]
+    panel_url: Option<String>,
+    #[serde(default)]
+    silence_url: Option<String>,
+}
+
+/// Which of the `Links` a notification opens, see `notification_url_preference`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LinkKind {
+    Generator,
+    Dashboard,
+    Panel,
+    Silence,
+}
+
+impl Links {
+    /// Grafana sends empty strings for URLs it doesn't have.
+    pub(crate) fn of(alert: &Alert) -> Self {
+        let url = |x: &str| Some(x.to_string()).filter(|x| !x.is_empty());
+        Links {
+            generator_url: url(alert.generator_url()),
+            dashboard_url: alert.dashboard_url().as_deref().and_then(url),
+            panel_url: alert.panel_url().as_deref().and_then(url),
+            silence_url: alert.silence_url().as_deref().and_then(url),
+        }
+    }
+
+    /// The first of `preference` that Grafana sent.
+    pub(crate) fn preferred(&self, preference: &[LinkKind]) -> Option<String> {
+        preference.iter().find_map(|kind| {
+            match kind {
+                LinkKind::Generator => &self.generator_url,
+                LinkKind::Dashboard => &self.dashboard_url,
+                LinkKind::Panel => &self.panel_url,
+                LinkKind::Silence => &self.silence_url,
+            }
+            .clone()
+        })
+    }
+}
+
+/// Firing and resolving cycles of a fingerprint close together, counted as one.
+#[derive(Debug, Deserialize, Clone, Serialize, Getters)]
+pub(crate) struct Incident {
+    id: String,
+    started_at: DateTime<Utc>,
+    /// None while it is firing.
+    resolved_at: Option<DateTime<Utc>>,
+    cycles: usize,
+    /// Alerts and re-alerts, including silenced ones.
+    notifications: usize,
+}
+
+/// An incident with the fingerprint it belongs to, for `/api/incidents`.
+#[derive(Debug, Serialize, Getters)]
+pub(crate) struct IncidentSummary {
+    fingerprint: String,
+    name: Option<String>,
+    #[serde(flatten)]
+    incident: Incident,
+    /// Until it resolved, or until now if it is open.
+    duration_secs: i64,
+}
+
+/// When a fingerprint changed status, or expired.
+#[derive(Debug, Deserialize, Clone, Serialize, Getters)]
+pub(crate) struct Transition {
+    at: DateTime<Utc>,
+    status: String,
+}
+
+impl PreviousEvent {
+    /// Everything is visible to the operator, a tenant only sees its own.
+    pub(crate) fn visible_to(&self, tenant: Option<&str>) -> bool {
+        tenant.is_none() || self.tenant.as_deref() == tenant
+    }
+
+    pub(crate) fn is_acked(&self, now: DateTime<Utc>) -> bool {
+        self.acked_until.is_some_and(|until| now < until)
+    }
+
+    /// Resolved long enough ago that its priority, summary, labels and first alert were forgotten.
+    pub(crate) fn expired(&self) -> bool {
+        self.history.last().is_some_and(|x| x.status == EXPIRED)
+    }
+}
+
+/// Counts a notification towards the latest incident.
+fn counted(mut incidents: Vec<Incident>) -> Vec<Incident> {
+    if let Some(last) = incidents.last_mut() {
+        last.notifications += 1;
+    }
+    incidents
+}
+
+fn push_transition(history: &mut Vec<Transition>, status: &str, at: DateTime<Utc>) {
+    history.push(Transition {
+        at,
+        status: status.to_string(),
+    });
+    if history.len() > MAX_HISTORY {
+        history.remove(0);
+    }
+}
+
+/// What changed after a revision, see `Fingerprints::changes_since`.
+#[derive(Debug, Serialize)]
+pub(crate) struct Changes {
+    revision: u64,
+    /// False when removals that old were forgotten, so the client should resync.
+    complete: bool,
+    changed: Vec<PreviousEvent>,
+    removed: Vec<String>,
+}
+
+/// Fingerprints that Grafana sent under the same notification policy group.
+#[derive(Debug, Serialize)]
+pub(crate) struct Group {
+    group_key: String,
+    firing: usize,
+    fingerprints: Vec<String>,
+}
+
+/// Alertnames that look like a rule has an unbounded label, for `/api/cardinality`.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum CardinalityWarning {
+    /// One alertname with many fingerprints, from a label that changes with every alert.
+    Fingerprints { alertname: String, count: usize },
+    /// Many alertnames that only differ in numbers or IDs, templated from such a label.
+    Alertnames {
+        pattern: String,
+        count: usize,
+        examples: Vec<String>,
+    },
+}
+
+/// The alertname with every word that has a digit in it, like an ID, replaced by `*`.
+fn alertname_pattern(alertname: &str) -> String {
+    let mut pattern = String::new();
+    let mut word = String::new();
+    for c in alertname.chars().chain(std::iter::once(' ')) {
+        if c.is_alphanumeric() || c == '-' || c == '_' {
+            word.push(c);
+            continue;
+        }
+        match word.chars().any(|x| x.is_ascii_digit()) {
+            true => pattern.push('*'),
+            false => pattern += &word,
+        }
+        word.clear();
+        pattern.push(c);
+    }
+    pattern.pop();
+    pattern
 }
 
 impl Fingerprints {
+    /// Continues from the newest saved revision if the clock went backwards.
+    fn from_data(data: HashMap<String, PreviousEvent>) -> Fingerprints {
+        let newest = data.values().map(|x| x.revision).max().unwrap_or(0);
+        let revision = first_revision().max(newest);
+        Fingerprints {
+            data,
+            revision,
+            removed: VecDeque::new(),
+            removed_since: revision,
+            incident_window: default_incident_window(),
+            kept_labels: vec![],
+        }
+    }
+
     pub(crate) fn load_or_default(config: &Config) -> Fingerprints {
+        let mut fingerprints = Fingerprints::load(config);
+        fingerprints.incident_window = chrono::Duration::minutes(*config.incident_window_minutes());
+        fingerprints.kept_labels = config.fingerprint_labels().clone();
+        fingerprints
+    }
+
+    fn load(config: &Config) -> Fingerprints {
         match std::fs::read_to_string(config.fingerprints_file()) {
             Ok(val) => match serde_json::from_str(&val) {
                 Ok(v) => {
                     log::trace!("Loaded fingerprints: {:?}", v);
-                    v
+                    let v: Fingerprints = v;
+                    Fingerprints::from_data(v.data)
                 }
                 Err(e) => {
                     log::error!(
@@ -37,9 +288,7 @@ impl Fingerprints {
                         config.fingerprints_file(),
                         e
                     );
-                    Fingerprints {
-                        data: HashMap::new(),
-                    }
+                    Fingerprints::from_data(HashMap::new())
                 }
             },
             Err(e) => {
@@ -48,9 +297,7 @@ impl Fingerprints {
                     config.fingerprints_file(),
                     e
                 );
-                Fingerprints {
-                    data: HashMap::new(),
-                }
+                Fingerprints::from_data(HashMap::new())
             }
         }
     }
@@ -70,10 +317,20 @@ impl Fingerprints {
                 name: None,
                 priority: None,
                 summary: None,
+                labels: BTreeMap::new(),
+                started_at: None,
+                ended_at: None,
+                group_key: None,
+                revision: 0,
+                history: vec![],
+                acked_until: None,
+                incidents: vec![],
+                links: Links::default(),
+                tenant: None,
             };
             new_data.insert(key, event);
         }
-        let new = Fingerprints { data: new_data };
+        let new = Fingerprints::from_data(new_data);
         match serde_json::to_string(&new) {
             Ok(serialized) => match std::fs::write(config.fingerprints_file(), serialized) {
                 Ok(_) => {
@@ -86,10 +343,51 @@ impl Fingerprints {
         }
     }
 
-    pub(crate) fn iter(&self) -> std::collections::hash_map::Iter<String, PreviousEvent> {
+    pub(crate) fn iter(&self) -> std::collections::hash_map::Iter<'_, String, PreviousEvent> {
         self.data.iter()
     }
 
+    pub(crate) fn get(&self, fingerprint: &str) -> Option<&PreviousEvent> {
+        self.data.get(fingerprint)
+    }
+
+    /// A copy with only the tenant's fingerprints, for showing it its own.
+    pub(crate) fn of_tenant(&self, tenant: &str) -> Fingerprints {
+        let prefix = Tenant::fingerprint(tenant, "");
+        Fingerprints {
+            data: self
+                .data
+                .iter()
+                .filter(|(_, event)| event.tenant.as_deref() == Some(tenant))
+                .map(|(key, event)| (key.clone(), event.clone()))
+                .collect(),
+            revision: self.revision,
+            removed: self
+                .removed
+                .iter()
+                .filter(|(_, fingerprint)| fingerprint.starts_with(&prefix))
+                .cloned()
+                .collect(),
+            removed_since: self.removed_since,
+            incident_window: self.incident_window,
+            kept_labels: self.kept_labels.clone(),
+        }
+    }
+
+    /// Every fingerprint, sorted so the JSON is stable between requests.
+    pub(crate) fn all(&self) -> BTreeMap<&String, &PreviousEvent> {
+        self.data.iter().collect()
+    }
+
+    /// Changes whenever the fingerprints do, for ETags.
+    pub(crate) fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.data.len()
+    }
+
     pub(crate) fn changed(&self, alert: &Alert) -> bool {
         match self.data.get(alert.fingerprint()) {
             None => {
@@ -112,9 +410,9 @@ impl Fingerprints {
         }
     }
 
-    pub(crate) fn update_last_seen(&mut self, alert: &Alert) {
+    pub(crate) fn update_last_seen(&mut self, alert: &Alert, now: DateTime<Utc>) {
         let last_alerted = match self.data.get(alert.fingerprint()) {
-            None => Utc::now(),
+            None => now,
             Some(prev) => *prev.last_alerted(),
         };
 
@@ -128,33 +426,62 @@ impl Fingerprints {
         };
 
         let event = PreviousEvent {
-            last_seen: Utc::now(),
+            last_seen: now,
             last_status: alert.status().clone(),
             first_alerted,
             last_alerted,
             fingerprint: alert.fingerprint().clone(),
-            name: Some(alert.labels().alertname().clone()),
+            name: Some(alert.name().to_string()),
             priority: Some(alert.get_priority()),
-            summary: Some(alert.annotations().summary().clone()),
+            summary: Some(alert.summary().to_string()),
+            labels: alert.labels_named(&self.kept_labels),
+            started_at: alert.started_at(),
+            ended_at: alert.ended_at(),
+            group_key: alert
+                .group_key()
+                .clone()
+                .or_else(|| self.group_key_of(alert.fingerprint())),
+            revision: self.next_revision(),
+            history: self.history_with(alert, now),
+            acked_until: self.acked_until_of(alert),
+            incidents: self.incidents_with(alert, false, now),
+            links: Links::of(alert),
+            tenant: alert.tenant().clone(),
         };
 
         self.data.insert(alert.fingerprint().clone(), event);
     }
 
-    pub(crate) fn update_last_alerted(&mut self, alert: &Alert) {
+    pub(crate) fn update_last_alerted(&mut self, alert: &Alert, now: DateTime<Utc>) {
+        // Grafana may have waited on a pending period or group wait before sending it
+        let started = alert.started_at().map_or(now, |x| x.min(now));
         let first_alerted = match self.data.get(alert.fingerprint()) {
-            None => Some(Utc::now()),
+            None => Some(started),
+            Some(prev) if prev.expired() => Some(started),
             Some(prev) => *prev.first_alerted(),
         };
         let event = PreviousEvent {
-            last_seen: Utc::now(),
+            last_seen: now,
             last_status: alert.status().clone(),
             first_alerted,
-            last_alerted: Utc::now(),
+            last_alerted: now,
             fingerprint: alert.fingerprint().clone(),
-            name: Some(alert.labels().alertname().clone()),
+            name: Some(alert.name().to_string()),
             priority: Some(alert.get_priority()),
-            summary: Some(alert.annotations().summary().clone()),
+            summary: Some(alert.summary().to_string()),
+            labels: alert.labels_named(&self.kept_labels),
+            started_at: alert.started_at(),
+            ended_at: alert.ended_at(),
+            group_key: alert
+                .group_key()
+                .clone()
+                .or_else(|| self.group_key_of(alert.fingerprint())),
+            revision: self.next_revision(),
+            history: self.history_with(alert, now),
+            acked_until: self.acked_until_of(alert),
+            incidents: self.incidents_with(alert, true, now),
+            links: Links::of(alert),
+            tenant: alert.tenant().clone(),
         };
         self.data.insert(alert.fingerprint().clone(), event);
     }
@@ -162,23 +489,380 @@ impl Fingerprints {
     pub(crate) fn update_last_alerted_from_previous_event(
         &mut self,
         previous_event: &PreviousEvent,
+        now: DateTime<Utc>,
     ) {
         let new_event = PreviousEvent {
             last_seen: *previous_event.last_seen(),
             last_status: previous_event.last_status().clone(),
             first_alerted: *previous_event.first_alerted(),
-            last_alerted: Utc::now(),
+            last_alerted: now,
             fingerprint: previous_event.fingerprint.clone(),
             name: previous_event.name().clone(),
             priority: previous_event.priority().clone(),
             summary: previous_event.summary().clone(),
+            labels: previous_event.labels().clone(),
+            started_at: *previous_event.started_at(),
+            ended_at: *previous_event.ended_at(),
+            group_key: previous_event.group_key().clone(),
+            revision: self.next_revision(),
+            history: previous_event.history().clone(),
+            acked_until: *previous_event.acked_until(),
+            incidents: counted(previous_event.incidents().clone()),
+            links: previous_event.links().clone(),
+            tenant: previous_event.tenant().clone(),
         };
         self.data
             .insert(previous_event.fingerprint.clone(), new_event);
     }
 
+    /// The history of the alert's fingerprint, with its status added if it changed.
+    fn history_with(&self, alert: &Alert, now: DateTime<Utc>) -> Vec<Transition> {
+        let (mut history, changed) = match self.data.get(alert.fingerprint()) {
+            None => (vec![], true),
+            Some(prev) => (prev.history.clone(), prev.last_status() != alert.status()),
+        };
+        if changed {
+            push_transition(&mut history, alert.status(), now);
+        }
+        history
+    }
+
+    /// The incidents of the alert's fingerprint, opening, continuing or resolving
+    /// the last one if the status changed, and counting a notification if `notified`.
+    fn incidents_with(&self, alert: &Alert, notified: bool, now: DateTime<Utc>) -> Vec<Incident> {
+        let (mut incidents, changed) = match self.data.get(alert.fingerprint()) {
+            None => (vec![], true),
+            Some(prev) => (prev.incidents.clone(), prev.last_status() != alert.status()),
+        };
+        if changed && alert.status() == "resolved" {
+            if let Some(last) = incidents.last_mut() {
+                last.resolved_at.get_or_insert(now);
+            }
+        } else if changed {
+            match incidents.last_mut() {
+                Some(last) if last.resolved_at.is_none() => {}
+                Some(last)
+                    if last
+                        .resolved_at
+                        .is_some_and(|x| now - x <= self.incident_window) =>
+                {
+                    last.resolved_at = None;
+                    last.cycles += 1;
+                }
+                _ => {
+                    incidents.push(Incident {
+                        id: format!("{}-{}", alert.fingerprint(), now.timestamp()),
+                        started_at: now,
+                        resolved_at: None,
+                        cycles: 1,
+                        notifications: 0,
+                    });
+                    if incidents.len() > MAX_INCIDENTS {
+                        incidents.remove(0);
+                    }
+                }
+            }
+        }
+        match notified {
+            true => counted(incidents),
+            false => incidents,
+        }
+    }
+
+    /// Every remembered incident, most recently started first.
+    pub(crate) fn incidents(&self, now: DateTime<Utc>) -> Vec<IncidentSummary> {
+        let mut incidents: Vec<IncidentSummary> = self
+            .data
+            .values()
+            .flat_map(|event| {
+                event.incidents.iter().map(|incident| IncidentSummary {
+                    fingerprint: event.fingerprint.clone(),
+                    name: event.name.clone(),
+                    duration_secs: (incident.resolved_at.unwrap_or(now) - incident.started_at)
+                        .num_seconds(),
+                    incident: incident.clone(),
+                })
+            })
+            .collect();
+        incidents.sort_by_key(|x| std::cmp::Reverse(x.incident.started_at));
+        incidents
+    }
+
+    /// The earliest time after `now` that an ack expires or an open incident's
+    /// duration reaches another minute, which change the dashboard without a revision.
+    pub(crate) fn next_change(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let acks = self
+            .data
+            .values()
+            .filter_map(|event| event.acked_until)
+            .filter(|until| now < *until);
+        let minutes = self
+            .data
+            .values()
+            .flat_map(|event| event.incidents.iter())
+            .filter(|incident| incident.resolved_at.is_none())
+            .map(|incident| {
+                let minutes = (now - incident.started_at).num_seconds().max(0) / 60;
+                incident.started_at + chrono::Duration::minutes(minutes + 1)
+            });
+        acks.chain(minutes).min()
+    }
+
+    fn acked_until_of(&self, alert: &Alert) -> Option<DateTime<Utc>> {
+        self.data
+            .get(alert.fingerprint())
+            .filter(|prev| prev.last_status() == alert.status())
+            .and_then(|prev| prev.acked_until)
+    }
+
+    /// Pauses re-alerts of the fingerprint until `until`. False if it isn't known.
+    pub(crate) fn ack(&mut self, fingerprint: &str, until: DateTime<Utc>) -> bool {
+        let revision = self.next_revision();
+        match self.data.get_mut(fingerprint) {
+            Some(event) => {
+                event.acked_until = Some(until);
+                event.revision = revision;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forgets the priority, summary, labels and first alert of fingerprints resolved and
+    /// not seen since `cutoff`, so if they fire again they start fresh.
+    /// Returns how many expired.
+    pub(crate) fn expire_resolved_before(
+        &mut self,
+        cutoff: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> usize {
+        let keys: Vec<String> = self
+            .data
+            .values()
+            .filter(|event| {
+                event.last_status() == "resolved" && event.last_seen < cutoff && !event.expired()
+            })
+            .map(|event| event.fingerprint.clone())
+            .collect();
+        for key in &keys {
+            let revision = self.next_revision();
+            if let Some(event) = self.data.get_mut(key) {
+                event.priority = None;
+                event.summary = None;
+                event.labels.clear();
+                event.first_alerted = None;
+                event.revision = revision;
+                push_transition(&mut event.history, EXPIRED, now);
+            }
+        }
+        keys.len()
+    }
+
+    fn next_revision(&mut self) -> u64 {
+        self.revision += 1;
+        self.revision
+    }
+
+    fn remember_removed(&mut self, fingerprint: String) {
+        let revision = self.next_revision();
+        self.removed.push_back((revision, fingerprint));
+        if self.removed.len() > MAX_REMOVED {
+            if let Some((forgotten, _)) = self.removed.pop_front() {
+                self.removed_since = forgotten;
+            }
+        }
+    }
+
+    /// Fingerprints changed or removed after `since`, oldest change first.
+    pub(crate) fn changes_since(&self, since: u64) -> Changes {
+        let mut changed: Vec<PreviousEvent> = self
+            .data
+            .values()
+            .filter(|x| x.revision > since)
+            .cloned()
+            .collect();
+        changed.sort_by_key(|x| x.revision);
+        let removed = self
+            .removed
+            .iter()
+            .filter(|(revision, fingerprint)| {
+                *revision > since && !self.data.contains_key(fingerprint)
+            })
+            .map(|(_, fingerprint)| fingerprint.clone())
+            .collect();
+        Changes {
+            revision: self.revision,
+            complete: since >= self.removed_since,
+            changed,
+            removed,
+        }
+    }
+
+    fn group_key_of(&self, fingerprint: &str) -> Option<String> {
+        self.data.get(fingerprint).and_then(|x| x.group_key.clone())
+    }
+
+    /// Fingerprints by group key, fingerprints without one are left out.
+    pub(crate) fn groups(&self) -> Vec<Group> {
+        let mut groups: Vec<Group> = vec![];
+        for event in self.data.values() {
+            let group_key = match &event.group_key {
+                Some(x) => x,
+                None => continue,
+            };
+            let index = match groups.iter().position(|x| &x.group_key == group_key) {
+                Some(index) => index,
+                None => {
+                    groups.push(Group {
+                        group_key: group_key.clone(),
+                        firing: 0,
+                        fingerprints: vec![],
+                    });
+                    groups.len() - 1
+                }
+            };
+            let group = &mut groups[index];
+            group.fingerprints.push(event.fingerprint.clone());
+            if event.last_status != "resolved" {
+                group.firing += 1;
+            }
+        }
+        for group in groups.iter_mut() {
+            group.fingerprints.sort();
+        }
+        groups.sort_by(|a, b| a.group_key.cmp(&b.group_key));
+        groups
+    }
+
+    /// Alertnames over the limits in `limits`, the worst first.
+    pub(crate) fn cardinality(&self, limits: &LabelLimits) -> Vec<CardinalityWarning> {
+        let mut per_alertname: BTreeMap<&str, usize> = BTreeMap::new();
+        for name in self.data.values().filter_map(|x| x.name.as_deref()) {
+            *per_alertname.entry(name).or_insert(0) += 1;
+        }
+        let mut per_pattern: BTreeMap<String, BTreeSet<&str>> = BTreeMap::new();
+        for name in per_alertname.keys() {
+            per_pattern
+                .entry(alertname_pattern(name))
+                .or_default()
+                .insert(name);
+        }
+
+        let mut warnings = vec![];
+        for (alertname, count) in per_alertname {
+            if count > limits.max_fingerprints_per_alertname {
+                let alertname = alertname.to_string();
+                warnings.push(CardinalityWarning::Fingerprints { alertname, count });
+            }
+        }
+        for (pattern, names) in per_pattern {
+            if names.len() > limits.max_alertnames_per_pattern {
+                warnings.push(CardinalityWarning::Alertnames {
+                    pattern,
+                    count: names.len(),
+                    examples: names.iter().take(3).map(|x| x.to_string()).collect(),
+                });
+            }
+        }
+        warnings.sort_by_key(|x| match x {
+            CardinalityWarning::Fingerprints { count, .. } => std::cmp::Reverse(*count),
+            CardinalityWarning::Alertnames { count, .. } => std::cmp::Reverse(*count),
+        });
+        warnings
+    }
+
     pub(crate) fn remove(&mut self, fingerprint: &String) -> Option<PreviousEvent> {
-        self.data.remove(fingerprint)
+        let removed = self.data.remove(fingerprint)?;
+        self.remember_removed(fingerprint.clone());
+        Some(removed)
+    }
+
+    /// Removes resolved fingerprints, least recently seen first, until the
+    /// serialized store is at most `max_bytes`. Returns how many were removed.
+    pub(crate) fn compact(&mut self, max_bytes: u64) -> usize {
+        let mut size = match serde_json::to_string(self) {
+            Ok(serialized) => serialized.len() as u64,
+            Err(e) => {
+                log::error!("Failed to serialize fingerprints: {:?}", e);
+                return 0;
+            }
+        };
+        let mut resolved: Vec<(DateTime<Utc>, String)> = self
+            .data
+            .values()
+            .filter(|event| event.last_status() == "resolved")
+            .map(|event| (event.last_seen, event.fingerprint.clone()))
+            .collect();
+        resolved.sort();
+
+        let mut removed = 0;
+        for (_, key) in resolved {
+            if size <= max_bytes {
+                break;
+            }
+            if let Some(event) = self.data.remove(&key) {
+                // key, quotes, colon and comma around the entry
+                let entry_len =
+                    serde_json::to_string(&event).map_or(0, |x| x.len()) + key.len() + 4;
+                size = size.saturating_sub(entry_len as u64);
+                removed += 1;
+                self.remember_removed(key);
+            }
+        }
+        removed
+    }
+
+    /// Removes and returns resolved fingerprints last seen before `cutoff`.
+    pub(crate) fn take_resolved_before(&mut self, cutoff: DateTime<Utc>) -> Vec<PreviousEvent> {
+        let keys: Vec<String> = self
+            .data
+            .values()
+            .filter(|event| event.last_status() == "resolved" && event.last_seen < cutoff)
+            .map(|event| event.fingerprint.clone())
+            .collect();
+        let mut taken: Vec<PreviousEvent> = keys
+            .iter()
+            .filter_map(|key| self.data.remove(key))
+            .collect();
+        taken.sort_by_key(|event| event.last_seen);
+        for event in &taken {
+            self.remember_removed(event.fingerprint.clone());
+        }
+        taken
+    }
+
+    /// Removes resolved fingerprints last seen before `resolved_cutoff`, and any
+    /// fingerprint, firing or not, last seen before `unseen_cutoff`. Returns how many were removed.
+    pub(crate) fn prune(
+        &mut self,
+        resolved_cutoff: Option<DateTime<Utc>>,
+        unseen_cutoff: Option<DateTime<Utc>>,
+    ) -> usize {
+        let keys: Vec<String> = self
+            .data
+            .values()
+            .filter(|event| {
+                let resolved = event.last_status() == "resolved"
+                    && resolved_cutoff.is_some_and(|cutoff| event.last_seen < cutoff);
+                let unseen = unseen_cutoff.is_some_and(|cutoff| event.last_seen < cutoff);
+                resolved || unseen
+            })
+            .map(|event| event.fingerprint.clone())
+            .collect();
+        for key in &keys {
+            self.remove(key);
+        }
+        keys.len()
+    }
+
+    /// Puts back events that were taken, unless the fingerprint has been seen since.
+    pub(crate) fn restore(&mut self, events: Vec<PreviousEvent>) {
+        for mut event in events {
+            if !self.data.contains_key(&event.fingerprint) {
+                event.revision = self.next_revision();
+                self.data.insert(event.fingerprint.clone(), event);
+            }
+        }
     }
 
     pub(crate) fn save(&self, config: &Config) {
@@ -195,9 +879,10 @@ impl Fingerprints {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::models::grafana::Alert;
+    use crate::models::grafana::{Alert, Message};
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_changed() {
         let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
         let mut fingerprints = Fingerprints::load_or_default(&config);
@@ -206,11 +891,11 @@ mod test {
         let resolved: Alert = serde_json::from_str(&crate::test::consts::create_resolved_alert())
             .expect("Failed to load default, resolved alert");
 
-        fingerprints.update_last_alerted(&alert);
+        fingerprints.update_last_alerted(&alert, Utc::now());
         assert_eq!(false, fingerprints.changed(&alert));
         assert_eq!(true, fingerprints.changed(&resolved));
 
-        fingerprints.update_last_alerted(&resolved);
+        fingerprints.update_last_alerted(&resolved, Utc::now());
         assert_eq!(true, fingerprints.changed(&alert));
         assert_eq!(false, fingerprints.changed(&resolved));
     }
@@ -222,10 +907,10 @@ mod test {
         let resolved: Alert = serde_json::from_str(&crate::test::consts::create_resolved_alert())
             .expect("Failed to load default, resolved alert");
 
-        fingerprints.update_last_seen(&resolved);
-        fingerprints.update_last_seen(&resolved);
-        fingerprints.update_last_alerted(&resolved);
-        fingerprints.update_last_seen(&resolved);
+        fingerprints.update_last_seen(&resolved, Utc::now());
+        fingerprints.update_last_seen(&resolved, Utc::now());
+        fingerprints.update_last_alerted(&resolved, Utc::now());
+        fingerprints.update_last_seen(&resolved, Utc::now());
         // TODO: asserts?
     }
 
@@ -240,5 +925,327 @@ mod test {
         assert_eq!(fingerprints.data.len(), 2);
     }
 
+    #[test]
+    fn compact_removes_oldest_resolved() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let firing: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        fingerprints.update_last_alerted(&firing, Utc::now());
+        for (index, fingerprint) in ["aaaa", "bbbb", "cccc"].iter().enumerate() {
+            let json = crate::test::consts::create_resolved_alert()
+                .replace("581dd91e73c77248", fingerprint);
+            let resolved: Alert = serde_json::from_str(&json).expect("Failed to load alert");
+            fingerprints.update_last_alerted(&resolved, Utc::now());
+            let event = fingerprints.data.get_mut(*fingerprint).expect("Missing");
+            event.last_seen = Utc::now() - chrono::Duration::minutes(10 - index as i64);
+        }
+
+        assert_eq!(fingerprints.compact(u64::MAX), 0);
+        assert_eq!(fingerprints.len(), 4);
+
+        let size = serde_json::to_string(&fingerprints).unwrap().len() as u64;
+        assert_eq!(fingerprints.compact(size - 1), 1);
+        assert!(!fingerprints.data.contains_key("aaaa"));
+        assert!(fingerprints.data.contains_key("bbbb"));
+
+        assert_eq!(fingerprints.compact(0), 2);
+        assert_eq!(fingerprints.len(), 1);
+        assert!(fingerprints.data.contains_key("581dd91e73c77248"));
+    }
+
+    #[test]
+    fn take_resolved_before() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let firing: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        fingerprints.update_last_alerted(&firing, Utc::now());
+        fingerprints
+            .data
+            .get_mut("581dd91e73c77248")
+            .expect("Missing")
+            .last_seen = Utc::now() - chrono::Duration::days(100);
+        for (days, fingerprint) in [(100, "aaaa"), (10, "bbbb")] {
+            let json = crate::test::consts::create_resolved_alert()
+                .replace("581dd91e73c77248", fingerprint);
+            let resolved: Alert = serde_json::from_str(&json).expect("Failed to load alert");
+            fingerprints.update_last_alerted(&resolved, Utc::now());
+            let event = fingerprints.data.get_mut(fingerprint).expect("Missing");
+            event.last_seen = Utc::now() - chrono::Duration::days(days);
+        }
+
+        let taken = fingerprints.take_resolved_before(Utc::now() - chrono::Duration::days(30));
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].fingerprint(), "aaaa");
+        assert_eq!(fingerprints.len(), 2);
+
+        fingerprints.restore(taken);
+        assert_eq!(fingerprints.len(), 3);
+    }
+
+    #[test]
+    fn expire_resolved_before() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let firing: Alert = serde_json::from_str(
+            &crate::test::consts::create_firing_alert_with_prefix("[critical] "),
+        )
+        .expect("Failed to load critical, firing alert");
+        let resolved: Alert = serde_json::from_str(&crate::test::consts::create_resolved_alert())
+            .expect("Failed to load default, resolved alert");
+        fingerprints.update_last_alerted(&firing, Utc::now());
+        fingerprints.update_last_seen(&firing, Utc::now());
+        fingerprints.update_last_alerted(&resolved, Utc::now());
+        let fingerprint = "581dd91e73c77248".to_string();
+        let first_alerted = fingerprints.data[&fingerprint].first_alerted;
+
+        let now = Utc::now();
+        let later = now + chrono::Duration::hours(2);
+        assert_eq!(
+            fingerprints.expire_resolved_before(now - chrono::Duration::hours(1), now),
+            0
+        );
+        assert_eq!(fingerprints.expire_resolved_before(later, later), 1);
+        assert_eq!(fingerprints.expire_resolved_before(later, later), 0);
+        let event = &fingerprints.data[&fingerprint];
+        assert!(event.expired());
+        assert_eq!(event.priority(), &None);
+        assert_eq!(event.summary(), &None);
+        assert_eq!(event.first_alerted(), &None);
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        fingerprints.update_last_alerted(&firing, Utc::now());
+        let event = &fingerprints.data[&fingerprint];
+        assert!(!event.expired());
+        assert!(event.first_alerted() > &first_alerted);
+        let statuses: Vec<&str> = event
+            .history()
+            .iter()
+            .map(|x| x.status().as_str())
+            .collect();
+        assert_eq!(statuses, vec!["firing", "resolved", "expired", "firing"]);
+    }
+
+    #[test]
+    fn prune() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let now = Utc::now();
+        for (hours, fingerprint, resolved) in [
+            (2, "aaaa", true),
+            (48, "bbbb", true),
+            (48, "cccc", false),
+            (24 * 40, "dddd", false),
+        ] {
+            let json = match resolved {
+                true => crate::test::consts::create_resolved_alert(),
+                false => crate::test::consts::create_firing_alert(),
+            }
+            .replace("581dd91e73c77248", fingerprint);
+            let alert: Alert = serde_json::from_str(&json).expect("Failed to load alert");
+            fingerprints.update_last_alerted(&alert, now);
+            let event = fingerprints.data.get_mut(fingerprint).expect("Missing");
+            event.last_seen = now - chrono::Duration::hours(hours);
+        }
+
+        assert_eq!(fingerprints.prune(None, None), 0);
+        let revision = fingerprints.revision;
+        assert_eq!(
+            fingerprints.prune(Some(now - chrono::Duration::hours(24)), None),
+            1
+        );
+        assert!(!fingerprints.data.contains_key("bbbb"));
+        assert_eq!(
+            fingerprints.prune(None, Some(now - chrono::Duration::days(30))),
+            1
+        );
+        assert!(!fingerprints.data.contains_key("dddd"));
+        assert_eq!(fingerprints.len(), 2);
+        assert_eq!(fingerprints.changes_since(revision).removed.len(), 2);
+    }
+
+    #[test]
+    fn incidents() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let firing: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        let resolved: Alert = serde_json::from_str(&crate::test::consts::create_resolved_alert())
+            .expect("Failed to load default, resolved alert");
+        let fingerprint = "581dd91e73c77248".to_string();
+
+        // flapping within the window is one incident
+        fingerprints.update_last_alerted(&firing, Utc::now());
+        fingerprints.update_last_seen(&firing, Utc::now());
+        fingerprints.update_last_alerted(&resolved, Utc::now());
+        fingerprints.update_last_alerted(&firing, Utc::now());
+        let event = fingerprints.data[&fingerprint].clone();
+        fingerprints.update_last_alerted_from_previous_event(&event, Utc::now());
+        let incidents = fingerprints.incidents(Utc::now());
+        assert_eq!(incidents.len(), 1);
+        assert_eq!(incidents[0].incident().cycles(), &2);
+        assert_eq!(incidents[0].incident().notifications(), &4);
+        assert_eq!(incidents[0].incident().resolved_at(), &None);
+
+        // firing again after the window starts a new one
+        fingerprints.update_last_alerted(&resolved, Utc::now());
+        let event = fingerprints.data.get_mut(&fingerprint).expect("Missing");
+        let resolved_at = Utc::now() - chrono::Duration::hours(2);
+        event.incidents[0].resolved_at = Some(resolved_at);
+        fingerprints.update_last_alerted(&firing, Utc::now());
+        let incidents = fingerprints.incidents(Utc::now());
+        assert_eq!(incidents.len(), 2);
+        assert_eq!(incidents[0].incident().cycles(), &1);
+        assert_eq!(incidents[0].incident().notifications(), &1);
+        assert_eq!(incidents[1].incident().resolved_at(), &Some(resolved_at));
+        assert_eq!(incidents[1].incident().notifications(), &5);
+        assert_eq!(incidents[0].name(), &Some("Alert Name".to_string()));
+    }
+
+    #[test]
+    fn groups_keep_key() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let firing = crate::test::consts::create_firing_alert();
+        let resolved =
+            crate::test::consts::create_resolved_alert().replace("581dd91e73c77248", "aaaa");
+        let json = format!("{{\"groupKey\": \"{{}}:{{}}\", \"alerts\": [{firing}, {resolved}]}}");
+        let message: Message = serde_json::from_str(&json).expect("Failed to load message");
+        for alert in message.alerts() {
+            fingerprints.update_last_alerted(alert, Utc::now());
+        }
+        // without a group key, the last one is kept
+        let firing: Alert = serde_json::from_str(&firing).expect("Failed to load alert");
+        fingerprints.update_last_seen(&firing, Utc::now());
+
+        let groups = fingerprints.groups();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].group_key, "{}:{}");
+        assert_eq!(groups[0].firing, 1);
+        assert_eq!(groups[0].fingerprints, vec!["581dd91e73c77248", "aaaa"]);
+    }
+
+    #[test]
+    fn keeps_links() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let json = crate::test::consts::create_firing_alert().replace(
+            "\"fingerprint\"",
+            "\"dashboardURL\": \"http://something/d/1\", \"panelURL\": \"\", \"fingerprint\"",
+        );
+        let alert: Alert = serde_json::from_str(&json).expect("Failed to load alert");
+        fingerprints.update_last_alerted(&alert, Utc::now());
+
+        let saved = serde_json::to_string(&fingerprints).expect("Failed to save");
+        let loaded: Fingerprints = serde_json::from_str(&saved).expect("Failed to load");
+        let links = loaded.get("581dd91e73c77248").expect("Missing").links();
+        assert_eq!(
+            links.generator_url().as_deref(),
+            Some("http://something/this")
+        );
+        assert_eq!(
+            links.dashboard_url().as_deref(),
+            Some("http://something/d/1")
+        );
+        assert_eq!(links.panel_url(), &None);
+
+        let preferred = |x: &[LinkKind]| links.preferred(x);
+        assert_eq!(
+            preferred(&[LinkKind::Panel, LinkKind::Dashboard, LinkKind::Generator]).as_deref(),
+            Some("http://something/d/1")
+        );
+        assert_eq!(
+            preferred(&[LinkKind::Silence, LinkKind::Generator]).as_deref(),
+            Some("http://something/this")
+        );
+        assert_eq!(preferred(&[LinkKind::Panel]), None);
+        assert_eq!(preferred(&[]), None);
+    }
+
+    #[test]
+    fn changes_since() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let start = fingerprints.revision();
+        let firing = crate::test::consts::create_firing_alert();
+        let other: Alert = serde_json::from_str(&firing.replace("581dd91e73c77248", "aaaa"))
+            .expect("Failed to load alert");
+        let firing: Alert = serde_json::from_str(&firing).expect("Failed to load alert");
+
+        fingerprints.update_last_seen(&firing, Utc::now());
+        fingerprints.update_last_seen(&other, Utc::now());
+        let middle = fingerprints.revision();
+        fingerprints.remove(&"aaaa".to_string());
+        assert!(fingerprints.remove(&"aaaa".to_string()).is_none());
+
+        let changes = fingerprints.changes_since(start);
+        assert_eq!(changes.revision, start + 3);
+        assert!(changes.complete);
+        assert_eq!(changes.changed.len(), 1);
+        assert_eq!(changes.removed, vec!["aaaa"]);
+
+        let changes = fingerprints.changes_since(middle);
+        assert!(changes.changed.is_empty());
+        assert_eq!(changes.removed, vec!["aaaa"]);
+        assert!(fingerprints.changes_since(middle + 1).removed.is_empty());
+        // from before this process started, removals may have been missed
+        assert!(!fingerprints.changes_since(0).complete);
+    }
+
+    #[test]
+    fn cardinality() {
+        assert_eq!(
+            alertname_pattern("Request 8f3a2b1c failed (attempt 2)"),
+            "Request * failed (attempt *)"
+        );
+        assert_eq!(alertname_pattern("Disk full"), "Disk full");
+
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let alerts = [
+            ("a", "Queue backed up"),
+            ("b", "Queue backed up"),
+            ("c", "Queue backed up"),
+            ("d", "Request 101 failed"),
+            ("e", "Request 102 failed"),
+            ("f", "Request 103 failed"),
+            ("g", "Disk full"),
+        ];
+        for (fingerprint, name) in alerts {
+            let alert: Alert = serde_json::from_str(
+                &crate::test::consts::create_firing_alert_with_prefix(name)
+                    .replace("Alert Name", "")
+                    .replace("581dd91e73c77248", fingerprint),
+            )
+            .expect("Failed to load alert");
+            fingerprints.update_last_alerted(&alert, Utc::now());
+        }
+        let limits = LabelLimits {
+            max_fingerprints_per_alertname: 2,
+            max_alertnames_per_pattern: 2,
+            ..LabelLimits::default()
+        };
+        assert_eq!(
+            fingerprints.cardinality(&limits),
+            vec![
+                CardinalityWarning::Fingerprints {
+                    alertname: "Queue backed up".to_string(),
+                    count: 3,
+                },
+                CardinalityWarning::Alertnames {
+                    pattern: "Request * failed".to_string(),
+                    count: 3,
+                    examples: vec![
+                        "Request 101 failed".to_string(),
+                        "Request 102 failed".to_string(),
+                        "Request 103 failed".to_string(),
+                    ],
+                },
+            ]
+        );
+        assert!(fingerprints.cardinality(&LabelLimits::default()).is_empty());
+    }
+
     // TODO: test alert is > realert time
 }
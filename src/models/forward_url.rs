@@ -0,0 +1,59 @@
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+/// Where webhooks are relayed, a URL or `{"url": ..., "secret": ...}` to sign them.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub(crate) enum ForwardUrl {
+    Plain(String),
+    Signed { url: String, secret: String },
+}
+
+impl ForwardUrl {
+    pub(crate) fn url(&self) -> &str {
+        match self {
+            ForwardUrl::Plain(url) | ForwardUrl::Signed { url, .. } => url,
+        }
+    }
+
+    /// A hex HMAC-SHA256 of `{timestamp}.{body}` with the URL's secret, so the receiver
+    /// can tell the body came from us and reject replays of old ones.
+    pub(crate) fn signature(&self, timestamp: i64, body: &str) -> Option<String> {
+        let ForwardUrl::Signed { secret, .. } = self else {
+            return None;
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(format!("{timestamp}.{body}").as_bytes());
+        Some(hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_and_signs() {
+        let urls: Vec<ForwardUrl> = serde_json::from_str(
+            r#"[
+                "https://logs.example.com/ingest",
+                {"url": "https://siem.example.com/ingest", "secret": "key"}
+            ]"#,
+        )
+        .expect("Failed to parse forward URLs");
+        assert_eq!(urls[0].url(), "https://logs.example.com/ingest");
+        assert_eq!(urls[0].signature(1700000000, "{}"), None);
+        assert_eq!(urls[1].url(), "https://siem.example.com/ingest");
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"key").expect("Failed to create HMAC");
+        mac.update(b"1700000000.{}");
+        let expected = hex::encode(mac.finalize().into_bytes());
+        assert_eq!(urls[1].signature(1700000000, "{}"), Some(expected));
+        assert_ne!(
+            urls[1].signature(1700000001, "{}"),
+            urls[1].signature(1700000000, "{}")
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use crate::models::grafana::{Alert, Message};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// A GitHub webhook, only completed `workflow_run` events become alerts. A failed run
+/// fires for its workflow and branch, and the next successful run resolves it.
+#[derive(Deserialize)]
+pub(crate) struct GithubEvent {
+    action: Option<String>,
+    workflow_run: Option<WorkflowRun>,
+    repository: Option<Repository>,
+}
+
+#[derive(Deserialize)]
+struct WorkflowRun {
+    name: String,
+    head_branch: Option<String>,
+    /// `success`, `failure`, `timed_out`, `cancelled`, `skipped` and others.
+    conclusion: Option<String>,
+    html_url: Option<String>,
+    run_number: Option<u64>,
+    updated_at: Option<DateTime<Utc>>,
+    head_commit: Option<HeadCommit>,
+}
+
+#[derive(Deserialize)]
+struct HeadCommit {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct Repository {
+    full_name: String,
+}
+
+impl From<GithubEvent> for Message {
+    /// Without a fingerprint, the labels make one for the repository, workflow and branch.
+    fn from(event: GithubEvent) -> Self {
+        let run = match (event.action.as_deref(), event.workflow_run) {
+            (Some("completed"), Some(run)) => run,
+            _ => return Message::new(vec![]),
+        };
+        let conclusion = run.conclusion.unwrap_or_default();
+        let status = match conclusion.as_str() {
+            "failure" | "timed_out" | "startup_failure" => "firing",
+            "success" => "resolved",
+            other => {
+                log::debug!("Ignored the {other} run of {}", run.name);
+                return Message::new(vec![]);
+            }
+        };
+        let repository = event
+            .repository
+            .map(|x| x.full_name)
+            .unwrap_or_else(|| "unknown".to_string());
+        let branch = run.head_branch.unwrap_or_default();
+        let labels = BTreeMap::from([
+            (
+                "alertname".to_string(),
+                format!("{repository} {}", run.name),
+            ),
+            ("repository".to_string(), repository),
+            ("branch".to_string(), branch.clone()),
+        ]);
+        let run_name = match run.run_number {
+            Some(number) => format!("Run #{number} on {branch}"),
+            None => format!("Run on {branch}"),
+        };
+        let mut summary = format!("{run_name} {}", conclusion.replace('_', " "));
+        if let Some(commit) = run.head_commit {
+            let subject = commit.message.lines().next().unwrap_or_default().trim();
+            if !subject.is_empty() {
+                summary = format!("{summary}: {subject}");
+            }
+        }
+        let annotations = BTreeMap::from([("summary".to_string(), summary)]);
+        let (starts_at, ends_at) = match status {
+            "firing" => (run.updated_at, None),
+            _ => (None, run.updated_at),
+        };
+        let alert = Alert::new(status, String::new(), labels, annotations)
+            .with_generator_url(run.html_url.unwrap_or_default())
+            .with_times(starts_at, ends_at);
+        Message::new(vec![alert])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn workflow_run(action: &str, conclusion: &str) -> Message {
+        let event: GithubEvent = serde_json::from_value(serde_json::json!({
+            "action": action,
+            "workflow_run": {
+                "id": 30433642,
+                "name": "CI",
+                "head_branch": "main",
+                "run_number": 562,
+                "event": "push",
+                "status": "completed",
+                "conclusion": conclusion,
+                "html_url": "https://github.com/octo/notifier/actions/runs/30433642",
+                "updated_at": "2022-09-09T10:00:00Z",
+                "head_commit": { "message": "Fix the build\n\nIt was broken." }
+            },
+            "repository": { "full_name": "octo/notifier" },
+            "workflow": { "name": "CI" }
+        }))
+        .expect("Failed to load event");
+        event.into()
+    }
+
+    #[test]
+    fn workflow_runs() {
+        let failed = workflow_run("completed", "timed_out");
+        let alert = &failed.alerts()[0];
+        assert_eq!(alert.status(), "firing");
+        assert_eq!(alert.name(), "octo/notifier CI");
+        assert_eq!(alert.summary(), "Run #562 on main timed out: Fix the build");
+        assert_eq!(
+            alert.generator_url(),
+            "https://github.com/octo/notifier/actions/runs/30433642"
+        );
+
+        let fixed = workflow_run("completed", "success");
+        assert_eq!(fixed.alerts()[0].status(), "resolved");
+        assert_eq!(fixed.alerts()[0].fingerprint(), alert.fingerprint());
+
+        assert!(workflow_run("completed", "cancelled").alerts().is_empty());
+        assert!(workflow_run("requested", "").alerts().is_empty());
+        let ping: GithubEvent =
+            serde_json::from_str(r#"{"zen": "Keep it logically awesome.", "hook_id": 1}"#)
+                .expect("Failed to load ping");
+        assert!(Message::from(ping).alerts().is_empty());
+    }
+}
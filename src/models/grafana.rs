@@ -1,37 +1,731 @@
+use crate::models::tenant::Tenant;
+use chrono::{DateTime, Utc};
 use derive_getters::Getters;
 use prowl::Priority;
 use serde::Deserialize;
+use std::collections::BTreeMap;
 
+/// Grafana's webhook, which is Alertmanager's with `orgId`, `state`, `title` and `message` added.
+/// What is used per alert, like the group key, title and text, is copied onto the alerts.
 #[derive(Deserialize, Getters)]
+#[serde(from = "RawMessage")]
 pub(crate) struct Message {
     alerts: Vec<Alert>,
+    receiver: Option<String>,
+    status: Option<String>,
+    group_labels: BTreeMap<String, String>,
+    common_labels: BTreeMap<String, String>,
+    version: Option<String>,
+    truncated_alerts: Option<u64>,
+    org_id: Option<i64>,
+    state: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawMessage {
+    alerts: Vec<Alert>,
+    receiver: Option<String>,
+    status: Option<String>,
+    #[serde(default)]
+    group_labels: BTreeMap<String, String>,
+    #[serde(default)]
+    common_labels: BTreeMap<String, String>,
+    #[serde(default)]
+    common_annotations: BTreeMap<String, String>,
+    #[serde(rename = "externalURL")]
+    external_url: Option<String>,
+    version: Option<String>,
+    group_key: Option<String>,
+    truncated_alerts: Option<u64>,
+    org_id: Option<i64>,
+    state: Option<String>,
+    title: Option<String>,
+    message: Option<String>,
 }
 
 #[allow(non_snake_case)]
-#[derive(Deserialize, Getters)]
+#[derive(Default, Deserialize, Getters)]
 pub(crate) struct Alert {
     status: String,
+    /// Rulers like Loki's and Mimir's may leave out what they have none of.
+    #[serde(default)]
     labels: Label,
+    #[serde(default)]
     annotations: Annotation,
-    #[serde(rename = "generatorURL")]
+    #[serde(rename = "generatorURL", default)]
     generator_url: String,
+    #[serde(rename = "dashboardURL")]
+    dashboard_url: Option<String>,
+    #[serde(rename = "panelURL")]
+    panel_url: Option<String>,
+    #[serde(rename = "silenceURL")]
+    silence_url: Option<String>,
+    /// Made from the labels like Alertmanager does when it isn't sent, see `Label::fingerprint`.
+    #[serde(default)]
     fingerprint: String,
+    #[serde(rename = "startsAt")]
+    starts_at: Option<DateTime<Utc>>,
+    #[serde(rename = "endsAt")]
+    ends_at: Option<DateTime<Utc>>,
+    /// The alert rule's query results by ref ID, null when there was no data.
+    #[serde(default)]
+    values: BTreeMap<String, Option<f64>>,
+    #[serde(rename = "valueString")]
+    value_string: Option<String>,
+    /// Copied from the message, Grafana only sends it once per payload.
+    #[serde(skip)]
+    group_key: Option<String>,
+    /// The message's title, for alerts without an alertname.
+    #[serde(skip)]
+    group_title: Option<String>,
+    /// The common summary or the message's text, for alerts without a summary.
+    #[serde(skip)]
+    group_summary: Option<String>,
+    /// From `unknown_status`, for statuses other than firing or resolved.
+    #[serde(skip)]
+    unknown_priority: Option<Priority>,
+    /// From `datasource_alerts`, for firing `DatasourceProblem`s.
+    #[serde(skip)]
+    datasource_priority: Option<Priority>,
+    /// The sender's own severity while firing, like Sentry's level.
+    #[serde(skip)]
+    source_priority: Option<Priority>,
+    /// The tenant whose webhook it came in on, see `apply_tenant`.
+    #[serde(skip)]
+    tenant: Option<String>,
 }
 
-#[derive(Deserialize, Getters)]
+/// Grafana's alerts about a rule's data source rather than its data, told apart by the
+/// alertname Grafana gives them. The rule's own name is in the `rulename` label.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DatasourceProblem {
+    /// `DatasourceNoData`, the query returned nothing.
+    NoData,
+    /// `DatasourceError`, the query failed or timed out.
+    Error,
+}
+
+impl DatasourceProblem {
+    /// Replaces `firing` in the notification.
+    pub(crate) fn text(self) -> &'static str {
+        match self {
+            DatasourceProblem::NoData => "no data",
+            DatasourceProblem::Error => "datasource error",
+        }
+    }
+}
+
+/// How alerts for a `DatasourceProblem` are sent.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub(crate) struct DatasourceAlert {
+    /// Shown instead of 🔥.
+    emoji: Option<String>,
+    /// Used instead of the alertname's priority while firing.
+    priority: Option<Priority>,
+    /// Ignore them, like a flapping data source.
+    #[serde(default)]
+    suppress: bool,
+}
+
+/// `datasource_alerts`, by problem.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub(crate) struct DatasourceAlerts {
+    #[serde(default)]
+    no_data: DatasourceAlert,
+    #[serde(default)]
+    error: DatasourceAlert,
+}
+
+impl DatasourceAlerts {
+    fn get(&self, problem: DatasourceProblem) -> &DatasourceAlert {
+        match problem {
+            DatasourceProblem::NoData => &self.no_data,
+            DatasourceProblem::Error => &self.error,
+        }
+    }
+
+    pub(crate) fn emoji(&self, problem: DatasourceProblem) -> &str {
+        let default = match problem {
+            DatasourceProblem::NoData => "❓",
+            DatasourceProblem::Error => "⚠️",
+        };
+        self.get(problem).emoji.as_deref().unwrap_or(default)
+    }
+}
+
+/// What to do with alerts whose status isn't `firing` or `resolved`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub(crate) enum UnknownStatus {
+    /// Handle them as firing alerts.
+    Firing,
+    /// Handle them as resolved alerts.
+    Resolved,
+    /// Ignore them.
+    Drop,
+    /// Notify with the status as it is, optionally shown as `emoji` and sent with `priority`.
+    Notify {
+        emoji: Option<String>,
+        priority: Option<Priority>,
+    },
+}
+
+impl Default for UnknownStatus {
+    fn default() -> Self {
+        UnknownStatus::Notify {
+            emoji: None,
+            priority: None,
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Getters)]
 pub(crate) struct Label {
+    /// Empty when it wasn't sent, see `Alert::name`.
+    #[serde(default)]
     alertname: String,
+    /// Every other label, like `severity`, `instance` and `job`.
+    #[serde(flatten)]
+    others: BTreeMap<String, String>,
 }
 
-#[derive(Deserialize, Getters)]
+/// Guards against rules with an unbounded label, like a request ID, filling the store.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct LabelLimits {
+    /// Labels past this many are dropped, `alertname` is always kept.
+    #[serde(default = "default_max_labels")]
+    pub(crate) max_labels: usize,
+    /// Longer label values are cut to this many bytes.
+    #[serde(default = "default_max_value_bytes")]
+    pub(crate) max_value_bytes: usize,
+    /// More fingerprints than this for one alertname is reported by `/api/cardinality`.
+    #[serde(default = "default_max_fingerprints_per_alertname")]
+    pub(crate) max_fingerprints_per_alertname: usize,
+    /// More alertnames than this differing only in numbers or IDs is reported too.
+    #[serde(default = "default_max_alertnames_per_pattern")]
+    pub(crate) max_alertnames_per_pattern: usize,
+}
+
+fn default_max_labels() -> usize {
+    64
+}
+
+fn default_max_value_bytes() -> usize {
+    1024
+}
+
+fn default_max_fingerprints_per_alertname() -> usize {
+    100
+}
+
+fn default_max_alertnames_per_pattern() -> usize {
+    20
+}
+
+impl Default for LabelLimits {
+    fn default() -> Self {
+        LabelLimits {
+            max_labels: default_max_labels(),
+            max_value_bytes: default_max_value_bytes(),
+            max_fingerprints_per_alertname: default_max_fingerprints_per_alertname(),
+            max_alertnames_per_pattern: default_max_alertnames_per_pattern(),
+        }
+    }
+}
+
+/// Which of `LabelLimits` an alert went over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LabelLimit {
+    MaxLabels,
+    MaxValueBytes,
+}
+
+impl LabelLimit {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            LabelLimit::MaxLabels => "max_labels",
+            LabelLimit::MaxValueBytes => "max_value_bytes",
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Getters)]
 pub(crate) struct Annotation {
-    summary: String,
+    summary: Option<String>,
+    description: Option<String>,
+    runbook_url: Option<String>,
+    /// Every other annotation.
+    #[serde(flatten)]
+    others: BTreeMap<String, String>,
+}
+
+impl From<RawMessage> for Message {
+    fn from(raw: RawMessage) -> Self {
+        let text = |x: &Option<String>| {
+            x.as_ref()
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+        };
+        let group_summary = raw
+            .common_annotations
+            .get("summary")
+            .cloned()
+            .or_else(|| text(&raw.message));
+        let alerts = raw
+            .alerts
+            .into_iter()
+            .map(|mut alert| {
+                if alert.fingerprint.is_empty() {
+                    alert.fingerprint = alert.labels.fingerprint();
+                }
+                if alert.generator_url.is_empty() {
+                    alert.generator_url = raw.external_url.clone().unwrap_or_default();
+                }
+                alert.group_key = raw.group_key.clone();
+                alert.group_title = text(&raw.title);
+                alert.group_summary = group_summary.clone();
+                alert
+            })
+            .collect();
+        Message {
+            alerts,
+            receiver: raw.receiver,
+            status: raw.status,
+            group_labels: raw.group_labels,
+            common_labels: raw.common_labels,
+            version: raw.version,
+            truncated_alerts: raw.truncated_alerts,
+            org_id: raw.org_id,
+            state: raw.state,
+        }
+    }
+}
+
+impl From<BTreeMap<String, String>> for Label {
+    fn from(mut labels: BTreeMap<String, String>) -> Self {
+        Label {
+            alertname: labels.remove("alertname").unwrap_or_default(),
+            others: labels,
+        }
+    }
+}
+
+impl From<BTreeMap<String, String>> for Annotation {
+    fn from(mut annotations: BTreeMap<String, String>) -> Self {
+        Annotation {
+            summary: annotations.remove("summary"),
+            description: annotations.remove("description"),
+            runbook_url: annotations.remove("runbook_url"),
+            others: annotations,
+        }
+    }
+}
+
+impl Message {
+    /// For alerts converted from other webhooks, as if Grafana had sent them alone.
+    pub(crate) fn new(alerts: Vec<Alert>) -> Self {
+        RawMessage {
+            alerts,
+            ..Default::default()
+        }
+        .into()
+    }
+
+    /// Several messages sent as one, like a buffering proxy does. The alerts are kept in
+    /// order, the rest is the first message's, with the truncated alerts added up.
+    pub(crate) fn merge(messages: Vec<Message>) -> Self {
+        let mut messages = messages.into_iter();
+        let mut merged = match messages.next() {
+            Some(x) => x,
+            None => return Message::new(vec![]),
+        };
+        for message in messages {
+            merged.alerts.extend(message.alerts);
+            merged.truncated_alerts = match (merged.truncated_alerts, message.truncated_alerts) {
+                (Some(x), Some(y)) => Some(x + y),
+                (x, y) => x.or(y),
+            };
+        }
+        merged
+    }
+
+    /// Where the webhook came from and how it was grouped, for the logs.
+    pub(crate) fn describe(&self) -> String {
+        let or_unknown = |x: &Option<String>| x.clone().unwrap_or_else(|| "unknown".to_string());
+        let labels = |x: &BTreeMap<String, String>| {
+            let pairs: Vec<String> = x.iter().map(|(k, v)| format!("{k}={v}")).collect();
+            format!("{{{}}}", pairs.join(", "))
+        };
+        format!(
+            "{} alerts for receiver {} in org {}, {} ({}), grouped by {} with common labels {}, version {}",
+            self.alerts.len(),
+            or_unknown(&self.receiver),
+            self.org_id.map_or_else(|| "unknown".to_string(), |x| x.to_string()),
+            or_unknown(&self.status),
+            or_unknown(&self.state),
+            labels(&self.group_labels),
+            labels(&self.common_labels),
+            or_unknown(&self.version),
+        )
+    }
+
+    /// Grafana may batch several states of the same alert into one payload,
+    /// only the most recent state for each fingerprint is kept.
+    pub(crate) fn latest_alerts(&self) -> Vec<&Alert> {
+        let mut latest: Vec<&Alert> = vec![];
+        for alert in self.alerts() {
+            match latest
+                .iter()
+                .position(|x| x.fingerprint() == alert.fingerprint())
+            {
+                None => latest.push(alert),
+                Some(index) => {
+                    log::debug!(
+                        "Fingerprint {} is in the payload more than once.",
+                        alert.fingerprint()
+                    );
+                    if alert.changed_at() >= latest[index].changed_at() {
+                        latest[index] = alert;
+                    }
+                }
+            }
+        }
+        latest
+    }
+
+    /// Marks the alerts as the tenant's, keeping their fingerprints apart from everyone else's.
+    pub(crate) fn apply_tenant(&mut self, tenant: &str) {
+        for alert in &mut self.alerts {
+            alert.fingerprint = Tenant::fingerprint(tenant, &alert.fingerprint);
+            alert.tenant = Some(tenant.to_string());
+        }
+    }
+
+    /// Applies `unknown` to alerts with a status other than firing or resolved,
+    /// removing dropped alerts. Returns the unknown statuses that were seen.
+    pub(crate) fn apply_unknown_status(&mut self, unknown: &UnknownStatus) -> Vec<String> {
+        let statuses = self
+            .alerts
+            .iter()
+            .filter(|alert| !alert.has_known_status())
+            .map(|alert| alert.status.clone())
+            .collect();
+        self.alerts
+            .retain_mut(|alert| alert.apply_unknown_status(unknown));
+        statuses
+    }
+
+    /// Applies `datasource_alerts` to Grafana's no data and error alerts, removing
+    /// suppressed ones. Returns the fingerprints that were suppressed.
+    pub(crate) fn apply_datasource_alerts(&mut self, alerts: &DatasourceAlerts) -> Vec<String> {
+        let mut suppressed = vec![];
+        self.alerts.retain_mut(|alert| {
+            let problem = match alert.datasource_problem() {
+                Some(x) => alerts.get(x),
+                None => return true,
+            };
+            if problem.suppress {
+                suppressed.push(alert.fingerprint.clone());
+                return false;
+            }
+            alert.datasource_priority = problem.priority.clone();
+            true
+        });
+        suppressed
+    }
+
+    /// Trims labels of alerts over the limits. Returns each alert's fingerprint
+    /// with the limit it went over.
+    pub(crate) fn apply_label_limits(&mut self, limits: &LabelLimits) -> Vec<(String, LabelLimit)> {
+        let mut hits = vec![];
+        for alert in &mut self.alerts {
+            for limit in alert.labels.apply_limits(limits) {
+                hits.push((alert.fingerprint.clone(), limit));
+            }
+        }
+        hits
+    }
+}
+
+impl Label {
+    /// The label's value, `alertname` only when it was sent.
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "alertname" => Some(self.alertname.as_str()).filter(|x| !x.is_empty()),
+            _ => self.others.get(name).map(String::as_str),
+        }
+    }
+
+    /// Alertmanager's fingerprint of the labels, FNV-1a over the sorted names and values,
+    /// for senders that don't send one, like Alertmanager before 0.19.
+    pub(crate) fn fingerprint(&self) -> String {
+        let mut labels: BTreeMap<&str, &str> = self
+            .others
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        if !self.alertname.is_empty() {
+            labels.insert("alertname", &self.alertname);
+        }
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for (name, value) in labels {
+            for byte in name
+                .bytes()
+                .chain([0xff])
+                .chain(value.bytes())
+                .chain([0xff])
+            {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        format!("{hash:016x}")
+    }
+
+    fn apply_limits(&mut self, limits: &LabelLimits) -> Vec<LabelLimit> {
+        let mut hits = vec![];
+        // alertname is one of the labels
+        let max_others = limits.max_labels.saturating_sub(1);
+        if self.others.len() > max_others {
+            let dropped: Vec<String> = self.others.keys().skip(max_others).cloned().collect();
+            for name in dropped {
+                self.others.remove(&name);
+            }
+            hits.push(LabelLimit::MaxLabels);
+        }
+        let values = std::iter::once(&mut self.alertname).chain(self.others.values_mut());
+        let mut truncated = false;
+        for value in values {
+            if value.len() > limits.max_value_bytes {
+                let mut end = limits.max_value_bytes;
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                value.truncate(end);
+                truncated = true;
+            }
+        }
+        if truncated {
+            hits.push(LabelLimit::MaxValueBytes);
+        }
+        hits
+    }
+}
+
+impl Annotation {
+    /// Rulers like Loki's often only send a description, which then stands in for the summary.
+    fn summary_or_description(&self) -> Option<&str> {
+        [&self.summary, &self.description]
+            .into_iter()
+            .flatten()
+            .map(|x| x.trim())
+            .find(|x| !x.is_empty())
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "summary" => self.summary.as_deref(),
+            "description" => self.description.as_deref(),
+            "runbook_url" => self.runbook_url.as_deref(),
+            _ => self.others.get(name).map(String::as_str),
+        }
+    }
+
+    /// The `notification_annotations` the alert has as lines of the notification,
+    /// `description` as it is and the others as `name: value`. Empty ones, and those
+    /// repeating the summary as Grafana's templates often do, are left out.
+    pub(crate) fn lines(&self, names: &[String]) -> Vec<String> {
+        names
+            .iter()
+            .filter_map(|name| {
+                let value = self.get(name)?.trim();
+                if value.is_empty() || Some(value) == self.summary_or_description() {
+                    return None;
+                }
+                match name.as_str() {
+                    "description" => Some(value.to_string()),
+                    _ => Some(format!("{name}: {value}")),
+                }
+            })
+            .collect()
+    }
 }
 
 impl Alert {
+    /// For other webhooks' alerts, an empty fingerprint is made from the labels.
+    pub(crate) fn new(
+        status: &str,
+        fingerprint: String,
+        labels: BTreeMap<String, String>,
+        annotations: BTreeMap<String, String>,
+    ) -> Self {
+        Alert {
+            status: status.to_string(),
+            fingerprint,
+            labels: labels.into(),
+            annotations: annotations.into(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn with_generator_url(mut self, url: String) -> Self {
+        self.generator_url = url;
+        self
+    }
+
+    pub(crate) fn with_values(mut self, values: BTreeMap<String, Option<f64>>) -> Self {
+        self.values = values;
+        self
+    }
+
+    pub(crate) fn with_priority(mut self, priority: Priority) -> Self {
+        self.source_priority = Some(priority);
+        self
+    }
+
+    pub(crate) fn with_times(
+        mut self,
+        starts_at: Option<DateTime<Utc>>,
+        ends_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        self.starts_at = starts_at;
+        self.ends_at = ends_at;
+        self
+    }
+
+    fn has_known_status(&self) -> bool {
+        self.status == "firing" || self.status == "resolved"
+    }
+
+    /// `false` when the alert should be dropped.
+    fn apply_unknown_status(&mut self, unknown: &UnknownStatus) -> bool {
+        if self.has_known_status() {
+            return true;
+        }
+        match unknown {
+            UnknownStatus::Firing => self.status = "firing".to_string(),
+            UnknownStatus::Resolved => self.status = "resolved".to_string(),
+            UnknownStatus::Drop => return false,
+            UnknownStatus::Notify { priority, .. } => self.unknown_priority = priority.clone(),
+        }
+        true
+    }
+
+    pub(crate) fn datasource_problem(&self) -> Option<DatasourceProblem> {
+        match self.labels.alertname.as_str() {
+            "DatasourceNoData" => Some(DatasourceProblem::NoData),
+            "DatasourceError" => Some(DatasourceProblem::Error),
+            _ => None,
+        }
+    }
+
+    /// The rule's name for `DatasourceProblem`s, otherwise `name`.
+    pub(crate) fn rule_name(&self) -> &str {
+        match self.datasource_problem() {
+            Some(_) => self.labels.get("rulename").unwrap_or_else(|| self.name()),
+            None => self.name(),
+        }
+    }
+
+    /// The alertname, or the message's title if it has none.
+    pub(crate) fn name(&self) -> &str {
+        match (self.labels.alertname.as_str(), &self.group_title) {
+            ("", Some(title)) => title,
+            ("", None) => "Unknown",
+            (alertname, _) => alertname,
+        }
+    }
+
+    /// The summary annotation, or else the message's common summary or text, or else
+    /// the alertname label as it is after `apply_label_limits`.
+    pub(crate) fn summary(&self) -> &str {
+        self.annotations
+            .summary_or_description()
+            .or(self.group_summary.as_deref())
+            .or(self.labels.get("alertname"))
+            .unwrap_or_default()
+    }
+
+    /// Those of the named labels the alert has.
+    pub(crate) fn labels_named(&self, names: &[String]) -> BTreeMap<String, String> {
+        names
+            .iter()
+            .filter_map(|name| Some((name.clone(), self.labels.get(name)?.to_string())))
+            .collect()
+    }
+
+    /// When Grafana says the alert started firing. Grafana sends year 1 for times it doesn't have.
+    pub(crate) fn started_at(&self) -> Option<DateTime<Utc>> {
+        self.starts_at.filter(|x| x.timestamp() > 0)
+    }
+
+    /// When Grafana says a resolved alert stopped firing. Firing alerts may have an `endsAt`
+    /// in the future, which is only when Alertmanager would assume it resolved.
+    pub(crate) fn ended_at(&self) -> Option<DateTime<Utc>> {
+        match self.status == "resolved" {
+            true => self.ends_at.filter(|x| x.timestamp() > 0),
+            false => None,
+        }
+    }
+
+    /// How long a resolved alert was firing for.
+    pub(crate) fn duration(&self) -> Option<chrono::Duration> {
+        Some(self.ended_at()? - self.started_at()?).filter(|x| *x >= chrono::Duration::zero())
+    }
+
+    /// What the alert rule's queries returned, like `value: 92.3` or `values: A=92.3, B=1`,
+    /// falling back to Grafana's `valueString`.
+    pub(crate) fn value_text(&self) -> Option<String> {
+        let number = |x: &Option<f64>| match x {
+            Some(x) => {
+                let text = format!("{x:.3}");
+                text.trim_end_matches('0').trim_end_matches('.').to_string()
+            }
+            None => "no data".to_string(),
+        };
+        match self.values.len() {
+            0 => self
+                .value_string
+                .as_ref()
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+                .map(|x| format!("value: {x}")),
+            1 => self
+                .values
+                .values()
+                .next()
+                .map(|x| format!("value: {}", number(x))),
+            _ => {
+                let pairs: Vec<String> = self
+                    .values
+                    .iter()
+                    .map(|(name, x)| format!("{name}={}", number(x)))
+                    .collect();
+                Some(format!("values: {}", pairs.join(", ")))
+            }
+        }
+    }
+
+    /// When the alert entered its current status, if Grafana told us.
+    pub(crate) fn changed_at(&self) -> Option<&DateTime<Utc>> {
+        if self.status() == "resolved" {
+            self.ends_at().as_ref()
+        } else {
+            self.starts_at().as_ref()
+        }
+    }
+
     pub(crate) fn get_priority(&self) -> Priority {
         if self.status() == "firing" {
-            let alertname = &self.labels().alertname();
+            if let Some(priority) = self
+                .datasource_priority
+                .as_ref()
+                .or(self.source_priority.as_ref())
+            {
+                return priority.clone();
+            }
+            let alertname = self.name();
             if alertname.starts_with("[critical]") || alertname.starts_with("[CRIT]") {
                 Priority::Emergency
             } else if alertname.starts_with("[high]") || alertname.starts_with("[HIGH]") {
@@ -39,17 +733,397 @@ impl Alert {
             } else {
                 Priority::Normal
             }
-        } else {
+        } else if self.status() == "resolved" {
             Priority::VeryLow
+        } else {
+            self.unknown_priority.clone().unwrap_or(Priority::VeryLow)
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::models::grafana::Alert;
+    use crate::models::grafana::{
+        Alert, Annotation, DatasourceAlerts, DatasourceProblem, Label, LabelLimit, LabelLimits,
+        Message, UnknownStatus,
+    };
     use prowl::Priority;
 
+    fn timed_alert(status: &str, starts_at: &str, ends_at: &str) -> String {
+        format!("{{\"status\": \"{status}\", \"generatorURL\": \"http://something/this\", \"fingerprint\": \"581dd91e73c77248\", \"labels\": {{ \"alertname\": \"Alert Name\" }}, \"annotations\": {{ \"summary\": \"Annotation Summary\"}}, \"startsAt\": \"{starts_at}\", \"endsAt\": \"{ends_at}\"}}")
+    }
+
+    #[test]
+    fn latest_alerts_keeps_most_recent_state() {
+        let firing = timed_alert("firing", "2022-09-04T22:00:00Z", "0001-01-01T00:00:00Z");
+        let resolved = timed_alert("resolved", "2022-09-04T22:00:00Z", "2022-09-04T22:05:00Z");
+
+        let json = format!("{{\"alerts\": [{firing}, {resolved}]}}");
+        let message: Message = serde_json::from_str(&json).expect("Failed to load message");
+        let latest = message.latest_alerts();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].status(), "resolved");
+
+        let json = format!("{{\"alerts\": [{resolved}, {firing}]}}");
+        let message: Message = serde_json::from_str(&json).expect("Failed to load message");
+        let latest = message.latest_alerts();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].status(), "resolved");
+    }
+
+    #[test]
+    fn start_end_and_duration() {
+        let alert =
+            |json: String| -> Alert { serde_json::from_str(&json).expect("Failed to load alert") };
+        let firing = alert(timed_alert(
+            "firing",
+            "2022-09-04T22:00:00Z",
+            "0001-01-01T00:00:00Z",
+        ));
+        assert!(firing.started_at().is_some());
+        assert_eq!(firing.ended_at(), None);
+        assert_eq!(firing.duration(), None);
+        // Alertmanager's firing alerts end when they would time out
+        let firing = alert(timed_alert(
+            "firing",
+            "2022-09-04T22:00:00Z",
+            "2022-09-04T22:05:00Z",
+        ));
+        assert_eq!(firing.ended_at(), None);
+
+        let resolved = alert(timed_alert(
+            "resolved",
+            "2022-09-04T22:00:00Z",
+            "2022-09-05T00:13:00Z",
+        ));
+        assert_eq!(resolved.duration(), Some(chrono::Duration::minutes(133)));
+        let resolved = alert(timed_alert(
+            "resolved",
+            "0001-01-01T00:00:00Z",
+            "2022-09-05T00:13:00Z",
+        ));
+        assert_eq!(resolved.started_at(), None);
+        assert_eq!(resolved.duration(), None);
+    }
+
+    #[test]
+    fn latest_alerts_without_timestamps() {
+        let json = format!(
+            "{{\"alerts\": [{}, {}, {}]}}",
+            crate::test::consts::create_firing_alert(),
+            crate::test::consts::create_resolved_alert(),
+            crate::test::consts::create_firing_alert_with_prefix("[high] ")
+                .replace("581dd91e73c77248", "8d3b85224c2e5c9f"),
+        );
+        let message: Message = serde_json::from_str(&json).expect("Failed to load message");
+        let latest = message.latest_alerts();
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].status(), "resolved");
+        assert_eq!(latest[1].fingerprint(), "8d3b85224c2e5c9f");
+    }
+
+    #[test]
+    fn full_payload() {
+        let json = r#"{
+            "receiver": "prowl",
+            "status": "firing",
+            "orgId": 1,
+            "alerts": [
+                {
+                    "status": "firing",
+                    "labels": { "alertname": "High CPU", "instance": "db1" },
+                    "annotations": {},
+                    "startsAt": "2022-09-04T22:00:00Z",
+                    "endsAt": "0001-01-01T00:00:00Z",
+                    "generatorURL": "",
+                    "fingerprint": "a",
+                    "silenceURL": "http://grafana/alerting/silence/new",
+                    "dashboardURL": "",
+                    "panelURL": "",
+                    "values": { "B": 97.5 },
+                    "valueString": "[ var='B' value=97.5 ]"
+                },
+                {
+                    "status": "firing",
+                    "labels": { "instance": "db2", "severity": "warning" },
+                    "annotations": { "summary": "db2 is busy", "runbook_url": "http://wiki/cpu" },
+                    "generatorURL": "http://grafana/alerting/grafana/b/view",
+                    "fingerprint": "b"
+                }
+            ],
+            "groupLabels": { "alertname": "High CPU" },
+            "commonLabels": { "alertname": "High CPU" },
+            "commonAnnotations": {},
+            "externalURL": "http://grafana/",
+            "version": "1",
+            "groupKey": "{}:{alertname=\"High CPU\"}",
+            "truncatedAlerts": 0,
+            "title": "[FIRING:2] High CPU",
+            "state": "alerting",
+            "message": "**Firing**\n\nValue: B=97.5\n"
+        }"#;
+        let message: Message = serde_json::from_str(json).expect("Failed to load message");
+        assert_eq!(
+            message.describe(),
+            "2 alerts for receiver prowl in org 1, firing (alerting), grouped by {alertname=High CPU} with common labels {alertname=High CPU}, version 1"
+        );
+        assert_eq!(message.truncated_alerts(), &Some(0));
+        let alerts = message.alerts();
+        // without their own summary, alertname or URL the message's are used
+        assert_eq!(alerts[0].name(), "High CPU");
+        assert_eq!(alerts[0].summary(), "**Firing**\n\nValue: B=97.5");
+        assert_eq!(alerts[0].generator_url(), "http://grafana/");
+        assert_eq!(alerts[0].value_text().as_deref(), Some("value: 97.5"));
+        assert_eq!(alerts[1].value_text(), None);
+        assert_eq!(alerts[1].name(), "[FIRING:2] High CPU");
+        assert_eq!(alerts[1].summary(), "db2 is busy");
+        assert_eq!(alerts[1].labels().get("severity"), Some("warning"));
+        assert_eq!(alerts[1].labels().get("alertname"), None);
+        assert_eq!(
+            alerts[1].annotations().get("runbook_url"),
+            Some("http://wiki/cpu")
+        );
+        let names = vec!["severity".to_string(), "job".to_string()];
+        assert_eq!(alerts[0].labels_named(&names).len(), 0);
+        assert_eq!(
+            alerts[1]
+                .labels_named(&names)
+                .get("severity")
+                .map(String::as_str),
+            Some("warning")
+        );
+        assert_eq!(
+            alerts[1].group_key().as_deref(),
+            Some("{}:{alertname=\"High CPU\"}")
+        );
+
+        let message: Message = serde_json::from_str(r#"{"alerts": [{"status": "firing"}]}"#)
+            .expect("Failed to load the least a message can have");
+        assert_eq!(message.alerts()[0].name(), "Unknown");
+        assert_eq!(message.alerts()[0].summary(), "");
+    }
+
+    #[test]
+    fn values() {
+        let values = |fields: &str| {
+            let json = crate::test::consts::create_firing_alert()
+                .replace("\"fingerprint\"", &format!("{fields}, \"fingerprint\""));
+            let alert: Alert = serde_json::from_str(&json).expect("Failed to load alert");
+            alert.value_text()
+        };
+        assert_eq!(
+            values(r#""values": {"A": 92.34567, "B": 1, "C": null}"#).as_deref(),
+            Some("values: A=92.346, B=1, C=no data")
+        );
+        assert_eq!(
+            values(r#""values": {}, "valueString": "[ var='A' value=3 ]""#).as_deref(),
+            Some("value: [ var='A' value=3 ]")
+        );
+        assert_eq!(values(r#""valueString": " ""#), None);
+    }
+
+    #[test]
+    fn loki_ruler() {
+        let json = std::fs::read_to_string("src/resources/test-loki-ruler.json")
+            .expect("Failed to read payload");
+        let mut message: Message = serde_json::from_str(&json).expect("Failed to load message");
+        let alerts = message.alerts();
+        assert_eq!(
+            alerts[0].summary(),
+            "checkout/api logged 42 errors per second over 5m"
+        );
+        let names = vec!["description".to_string()];
+        assert!(alerts[0].annotations().lines(&names).is_empty());
+        // without a generatorURL it links to Alertmanager
+        assert_eq!(alerts[0].generator_url(), "http://alertmanager:9093");
+
+        // no annotations, generatorURL or fingerprint at all
+        assert_eq!(alerts[1].summary(), "PromtailDroppedEntries");
+        assert_eq!(alerts[1].labels().get("__tenant_id__"), Some("fake"));
+        assert_eq!(alerts[1].fingerprint().len(), 16);
+
+        // the alertname standing in for the summary is limited like the label
+        let limits = LabelLimits {
+            max_value_bytes: 8,
+            ..LabelLimits::default()
+        };
+        message.apply_label_limits(&limits);
+        assert_eq!(message.alerts()[1].summary(), "Promtail");
+    }
+
+    #[test]
+    fn merge() {
+        let messages: Vec<Message> = serde_json::from_str(
+            r#"[
+                {"receiver": "prowl", "truncatedAlerts": 2, "alerts": [{"status": "firing", "fingerprint": "a"}]},
+                {"receiver": "other", "alerts": [{"status": "resolved", "fingerprint": "b"}]},
+                {"truncatedAlerts": 1, "alerts": []}
+            ]"#,
+        )
+        .expect("Failed to load messages");
+        let merged = Message::merge(messages);
+        let fingerprints: Vec<&str> = merged
+            .alerts()
+            .iter()
+            .map(|x| x.fingerprint().as_str())
+            .collect();
+        assert_eq!(fingerprints, vec!["a", "b"]);
+        assert_eq!(merged.receiver().as_deref(), Some("prowl"));
+        assert_eq!(merged.truncated_alerts(), &Some(3));
+        assert!(Message::merge(vec![]).alerts().is_empty());
+    }
+
+    #[test]
+    fn label_fingerprint() {
+        let message: Message = serde_json::from_str(
+            r#"{"version": "4", "alerts": [
+                {"status": "firing", "labels": {"instance": "db1", "alertname": "Disk full"},
+                    "annotations": {}, "generatorURL": ""},
+                {"status": "firing", "labels": {}, "annotations": {}, "generatorURL": "",
+                    "fingerprint": "sent"}
+            ]}"#,
+        )
+        .expect("Failed to load message");
+        // the same as Alertmanager's
+        assert_eq!(message.alerts()[0].fingerprint(), "8c753720cbb0a912");
+        assert_eq!(message.alerts()[1].fingerprint(), "sent");
+        let empty: Label = serde_json::from_str("{}").expect("Failed to load labels");
+        assert_eq!(empty.fingerprint(), "cbf29ce484222325");
+    }
+
+    #[test]
+    fn datasource_alerts() {
+        let alert = |alertname: &str| {
+            format!(
+                r#"{{"status": "firing", "generatorURL": "", "fingerprint": "{alertname}",
+                    "labels": {{"alertname": "{alertname}", "rulename": "[critical] Disk"}},
+                    "annotations": {{}}}}"#
+            )
+        };
+        let json = format!(
+            r#"{{"alerts": [{}, {}, {}]}}"#,
+            alert("DatasourceNoData"),
+            alert("DatasourceError"),
+            alert("[critical] Disk"),
+        );
+        let mut message: Message = serde_json::from_str(&json).expect("Failed to load message");
+        let alerts: DatasourceAlerts = serde_json::from_str(
+            r#"{"no_data": {"priority": "Moderate"}, "error": {"suppress": true}}"#,
+        )
+        .expect("Failed to parse datasource alerts");
+        assert_eq!(
+            message.apply_datasource_alerts(&alerts),
+            vec!["DatasourceError"]
+        );
+
+        let alerts = message.alerts();
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(
+            alerts[0].datasource_problem(),
+            Some(DatasourceProblem::NoData)
+        );
+        assert_eq!(alerts[0].rule_name(), "[critical] Disk");
+        assert_eq!(alerts[0].get_priority(), Priority::Moderate);
+        assert_eq!(alerts[1].datasource_problem(), None);
+        assert_eq!(alerts[1].get_priority(), Priority::Emergency);
+    }
+
+    #[test]
+    fn annotation_lines() {
+        let annotations: Annotation = serde_json::from_str(
+            r#"{"summary": "Disk full", "description": " 95% used on /var \n",
+                "runbook_url": "http://wiki/disk", "dashboard": "", "owner": "Disk full"}"#,
+        )
+        .expect("Failed to load annotations");
+        let names = |x: &[&str]| -> Vec<String> { x.iter().map(|x| x.to_string()).collect() };
+        assert_eq!(
+            annotations.lines(&names(&["description", "runbook_url"])),
+            vec!["95% used on /var", "runbook_url: http://wiki/disk"]
+        );
+        // missing, empty and repeated ones are left out, the order is the config's
+        assert_eq!(
+            annotations.lines(&names(&["runbook_url", "dashboard", "owner", "nope"])),
+            vec!["runbook_url: http://wiki/disk"]
+        );
+        assert!(annotations.lines(&[]).is_empty());
+    }
+
+    #[test]
+    fn unknown_statuses() {
+        let json = format!(
+            "{{\"alerts\": [{}, {}]}}",
+            crate::test::consts::create_firing_alert_with_prefix("[high] "),
+            crate::test::consts::create_firing_alert().replace("\"firing\"", "\"pending\""),
+        );
+        let message =
+            || -> Message { serde_json::from_str(&json).expect("Failed to load message") };
+
+        let mut dropped = message();
+        let statuses = dropped.apply_unknown_status(&UnknownStatus::Drop);
+        assert_eq!(statuses, vec!["pending".to_string()]);
+        assert_eq!(dropped.alerts().len(), 1);
+
+        let mut firing = message();
+        firing.apply_unknown_status(&UnknownStatus::Firing);
+        assert_eq!(firing.alerts()[1].status(), "firing");
+        assert_eq!(firing.alerts()[1].get_priority(), Priority::Normal);
+
+        let mut notify = message();
+        let unknown: UnknownStatus =
+            serde_json::from_str(r#"{"action": "notify", "emoji": "⏳", "priority": "Moderate"}"#)
+                .expect("Failed to parse unknown status");
+        notify.apply_unknown_status(&unknown);
+        assert_eq!(notify.alerts()[1].status(), "pending");
+        assert_eq!(notify.alerts()[1].get_priority(), Priority::Moderate);
+        assert_eq!(notify.alerts()[0].get_priority(), Priority::High);
+
+        let mut default = message();
+        default.apply_unknown_status(&UnknownStatus::default());
+        assert_eq!(default.alerts()[1].get_priority(), Priority::VeryLow);
+    }
+
+    #[test]
+    fn label_limits() {
+        let json = format!(
+            "{{\"alerts\": [{}, {}]}}",
+            crate::test::consts::create_firing_alert(),
+            crate::test::consts::create_firing_alert()
+                .replace("581dd91e73c77248", "unbounded")
+                .replace(
+                    "\"alertname\": \"Alert Name\"",
+                    "\"alertname\": \"Alert Name\", \"b\": \"2\", \"a\": \"ééé\", \"c\": \"3\""
+                ),
+        );
+        let mut message: Message = serde_json::from_str(&json).expect("Failed to load message");
+        let limits = LabelLimits {
+            max_labels: 3,
+            max_value_bytes: 5,
+            ..LabelLimits::default()
+        };
+        let hits = message.apply_label_limits(&limits);
+        assert_eq!(
+            hits,
+            vec![
+                ("581dd91e73c77248".to_string(), LabelLimit::MaxValueBytes),
+                ("unbounded".to_string(), LabelLimit::MaxLabels),
+                ("unbounded".to_string(), LabelLimit::MaxValueBytes),
+            ]
+        );
+        let labels = message.alerts()[1].labels();
+        assert_eq!(labels.alertname(), "Alert");
+        // the first labels by name are kept, and a multi-byte character isn't split
+        let others: Vec<_> = labels.others().iter().collect();
+        assert_eq!(others.len(), 2);
+        assert_eq!(others[0], (&"a".to_string(), &"éé".to_string()));
+        assert_eq!(others[1], (&"b".to_string(), &"2".to_string()));
+
+        let mut message: Message = serde_json::from_str(&json).expect("Failed to load message");
+        assert!(message
+            .apply_label_limits(&LabelLimits::default())
+            .is_empty());
+        assert_eq!(message.alerts()[1].labels().others()["a"], "ééé");
+    }
+
     #[test]
     fn no_prefix() {
         let firing: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
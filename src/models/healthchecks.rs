@@ -0,0 +1,86 @@
+use crate::models::grafana::{Alert, Message};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// The body of a Healthchecks.io webhook integration, written with its placeholders, like
+/// `{"code": "$CODE", "name": "$NAME", "status": "$STATUS", "desc": "$DESC", "tags": "$TAGS", "now": "$NOW"}`.
+/// Each check is one fingerprint, so missed pings are re-alerted until it is up again.
+#[derive(Deserialize)]
+pub(crate) struct HealthchecksEvent {
+    /// The check's UUID.
+    code: String,
+    /// `down` or `up`.
+    status: String,
+    name: Option<String>,
+    desc: Option<String>,
+    /// Space separated.
+    tags: Option<String>,
+    now: Option<DateTime<Utc>>,
+    /// The check's page, Healthchecks has no placeholder for it.
+    url: Option<String>,
+}
+
+impl From<HealthchecksEvent> for Message {
+    fn from(event: HealthchecksEvent) -> Self {
+        let text = |x: Option<String>| x.map(|x| x.trim().to_string()).filter(|x| !x.is_empty());
+        let name = text(event.name).unwrap_or_else(|| format!("Check {}", event.code));
+        let (status, starts_at, ends_at) = match event.status.as_str() {
+            "down" => ("firing", event.now, None),
+            "up" => ("resolved", None, event.now),
+            other => (other, event.now, None),
+        };
+        let summary = text(event.desc).unwrap_or_else(|| format!("{name} is {}", event.status));
+        let mut labels = BTreeMap::from([("alertname".to_string(), name)]);
+        if let Some(tags) = text(event.tags) {
+            labels.insert("tags".to_string(), tags);
+        }
+        let annotations = BTreeMap::from([("summary".to_string(), summary)]);
+        let alert = Alert::new(
+            status,
+            format!("healthchecks-{}", event.code),
+            labels,
+            annotations,
+        )
+        .with_generator_url(event.url.unwrap_or_default())
+        .with_times(starts_at, ends_at);
+        Message::new(vec![alert])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts() {
+        let convert = |json: &str| -> Message {
+            let event: HealthchecksEvent =
+                serde_json::from_str(json).expect("Failed to load event");
+            event.into()
+        };
+        let message = convert(
+            r#"{"code": "5f8e", "name": "Backups", "status": "down", "desc": " ",
+                "tags": "prod nightly", "now": "2022-09-09T10:00:00+00:00"}"#,
+        );
+        let alert = &message.alerts()[0];
+        assert_eq!(alert.fingerprint(), "healthchecks-5f8e");
+        assert_eq!(alert.status(), "firing");
+        assert_eq!(alert.name(), "Backups");
+        assert_eq!(alert.summary(), "Backups is down");
+        assert_eq!(alert.labels().get("tags"), Some("prod nightly"));
+        assert_eq!(
+            alert.started_at().map(|x| x.to_rfc3339()).as_deref(),
+            Some("2022-09-09T10:00:00+00:00")
+        );
+
+        let message = convert(
+            r#"{"code": "5f8e", "status": "up", "desc": "Nightly backup", "now": "2022-09-09T10:30:00+00:00"}"#,
+        );
+        let alert = &message.alerts()[0];
+        assert_eq!(alert.status(), "resolved");
+        assert_eq!(alert.name(), "Check 5f8e");
+        assert_eq!(alert.summary(), "Nightly backup");
+        assert!(alert.ended_at().is_some());
+    }
+}
@@ -1,24 +1,121 @@
 use crate::errors::RequestError;
 use derive_getters::Getters;
-use std::io::{Read, Write};
+use flate2::{write::GzEncoder, Compression};
+use std::io::Write;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::{timeout, Duration, Instant},
+};
+
+/// Defaults for tests, the server takes these from the config.
+#[cfg(test)]
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+#[cfg(test)]
+const HEAD_TIMEOUT: Duration = Duration::from_secs(5);
+#[cfg(test)]
+const REQUEST_DEADLINE: Duration = Duration::from_secs(10);
+/// The request line and headers, Grafana sends a few hundred bytes.
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+/// Smaller bodies aren't worth the CPU or the gzip header.
+const GZIP_MIN_BYTES: usize = 1024;
 
 #[derive(Debug, Getters)]
 pub(crate) struct RequestLine {
     method: String,
     path: String,
+    version: String,
+}
+
+impl RequestLine {
+    /// The query string's parameters in order, percent-decoded with `+` as a space.
+    /// A parameter without `=` has an empty value.
+    pub(crate) fn query_params(&self) -> Vec<(String, String)> {
+        let query = match self.path.split_once('?') {
+            Some((_, query)) => query,
+            None => return vec![],
+        };
+        let decode = |x: &str| {
+            let x = x.replace('+', " ");
+            match urlencoding::decode(&x) {
+                Ok(decoded) => decoded.into_owned(),
+                Err(_) => x,
+            }
+        };
+        query
+            .split('&')
+            .filter(|x| !x.is_empty())
+            .map(|x| x.split_once('=').unwrap_or((x, "")))
+            .map(|(key, value)| (decode(key), decode(value)))
+            .collect()
+    }
 }
 
 #[derive(Debug, Getters)]
 pub(crate) struct Request {
     request_line: RequestLine,
+    headers: Headers,
     body: String,
 }
 
+/// How long to wait for a request and how much of it to accept.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Limits {
+    /// How long to wait for the request to start.
+    idle: Duration,
+    /// How long to wait for more bytes once it has started.
+    read: Duration,
+    /// How long the request line and headers can take from the first byte.
+    head: Duration,
+    /// How long the whole request can take from the first byte, so clients
+    /// trickling bytes can't hold a connection open forever.
+    deadline: Duration,
+    /// Chunked bodies count their chunk framing too.
+    max_body_bytes: usize,
+}
+
+impl Limits {
+    pub(crate) fn new(
+        idle: Duration,
+        read: Duration,
+        head: Duration,
+        deadline: Duration,
+        max_body_bytes: usize,
+    ) -> Self {
+        Limits {
+            idle,
+            read,
+            head,
+            deadline,
+            max_body_bytes,
+        }
+    }
+
+    /// The same limits, waiting `idle` for the next request to start.
+    pub(crate) fn with_idle(self, idle: Duration) -> Self {
+        Limits { idle, ..self }
+    }
+}
+
+#[cfg(test)]
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::new(
+            READ_TIMEOUT,
+            READ_TIMEOUT,
+            HEAD_TIMEOUT,
+            REQUEST_DEADLINE,
+            1024 * 1024,
+        )
+    }
+}
+
 #[derive(Debug, Getters)]
 pub(crate) struct Response {
     status_line: String,
     headers: Vec<String>,
     body: Option<String>,
+    gzip: bool,
+    keep_alive: bool,
 }
 
 fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
@@ -33,18 +130,70 @@ impl Response {
             status_line,
             headers,
             body,
+            gzip: false,
+            keep_alive: false,
         }
     }
 
-    pub(crate) fn send<T: Write>(mut self, stream: &mut T) -> Result<(), std::io::Error> {
-        self.headers.push("Connection: close".to_string());
+    /// The number in the status line, 0 if it has none.
+    pub(crate) fn status_code(&self) -> u16 {
+        self.status_line
+            .split(' ')
+            .nth(1)
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn add_header(&mut self, header: String) {
+        self.headers.push(header);
+    }
+
+    /// Leaves the connection open for another request instead of sending `Connection: close`.
+    pub(crate) fn set_keep_alive(&mut self, keep_alive: bool) {
+        self.keep_alive = keep_alive;
+    }
+
+    /// Compresses the body when it is large and the client's `Accept-Encoding` allows gzip.
+    pub(crate) fn gzip_for(&mut self, accept_encoding: Option<&str>) {
+        let large = self
+            .body
+            .as_ref()
+            .is_some_and(|x| x.len() >= GZIP_MIN_BYTES);
+        self.gzip = large && accept_encoding.is_some_and(accepts_gzip);
+    }
+
+    /// Returns how many bytes of body were sent, after compression.
+    pub(crate) async fn send<T: AsyncWrite + Unpin>(
+        mut self,
+        stream: &mut T,
+    ) -> Result<usize, std::io::Error> {
+        if self.keep_alive {
+            self.headers.push("Connection: keep-alive".to_string());
+        } else {
+            self.headers.push("Connection: close".to_string());
+        }
         let status_line = self.status_line;
 
-        let response = match self.body {
+        let body = match self.body {
+            Some(body) if self.gzip => Some(gzip(body.as_bytes())?),
+            Some(body) => Some(body.into_bytes()),
+            None => None,
+        };
+        if self.gzip {
+            self.headers.push("Content-Encoding: gzip".to_string());
+            self.headers.push("Vary: Accept-Encoding".to_string());
+        }
+        let response = match &body {
             Some(body) => {
                 self.headers.push(format!("Content-Length: {}", body.len()));
                 let headers_string: String = self.headers.join("\r\n");
-                format!("{status_line}\r\n{headers_string}\r\n\r\n{body}")
+                format!("{status_line}\r\n{headers_string}\r\n\r\n")
+            }
+            // the client can't wait for the connection to close to find the end
+            None if self.keep_alive => {
+                self.headers.push("Content-Length: 0".to_string());
+                let headers_string: String = self.headers.join("\r\n");
+                format!("{status_line}\r\n{headers_string}\r\n\r\n")
             }
             None => {
                 let headers_string: String = self.headers.join("\r\n");
@@ -52,75 +201,277 @@ impl Response {
             }
         };
         log::trace!("Sending response =\n{response}\nEOF");
-        let _ = stream.write(response.as_bytes())?;
-        Ok(())
+        stream.write_all(response.as_bytes()).await?;
+        if let Some(body) = &body {
+            stream.write_all(body).await?;
+        }
+        stream.flush().await?;
+        Ok(body.map_or(0, |x| x.len()))
     }
 }
 
+fn gzip(bytes: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+/// True unless gzip is missing from the list or refused with `q=0`.
+fn accepts_gzip(accept_encoding: &str) -> bool {
+    accept_encoding.split(',').any(|coding| {
+        let mut params = coding.split(';').map(|x| x.trim());
+        let name = params.next().unwrap_or_default();
+        let refused = params
+            .filter_map(|x| x.strip_prefix("q="))
+            .any(|q| q.parse::<f32>().is_ok_and(|q| q == 0.0));
+        (name.eq_ignore_ascii_case("gzip") || name == "*") && !refused
+    })
+}
+
 impl Request {
-    // TODO: make it not a giant blob of code
-    pub(crate) fn from_stream<T: Read + Write>(stream: &mut T) -> Result<Request, RequestError> {
-        let mut read = vec![];
+    /// Reads a single request, for tests that don't reuse the connection.
+    #[cfg(test)]
+    pub(crate) async fn from_stream<T: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut T,
+    ) -> Result<Request, RequestError> {
+        Self::from_buffered_stream(stream, &mut vec![], &Limits::default()).await
+    }
+
+    /// Reads the next request, starting with any bytes left in `buffered` by the
+    /// previous one. Bytes past the end of this request are put back into `buffered`,
+    /// so pipelined requests are read in order. Oversized requests are rejected
+    /// before the rest of them is read.
+    pub(crate) async fn from_buffered_stream<T: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut T,
+        buffered: &mut Vec<u8>,
+        limits: &Limits,
+    ) -> Result<Request, RequestError> {
+        let mut read = std::mem::take(buffered);
         let mut buffer = vec![0; 1024];
-        let mut body_start_index = None;
-        let mut expected_len = None;
+        let mut head = None;
+        let mut chunked_body = None;
+        let mut continued = false;
+        // pipelined bytes count as the start of this request
+        let mut started = (!read.is_empty()).then(Instant::now);
 
         loop {
-            match stream.read(&mut buffer[..]) {
-                Ok(0) => {
-                    log::trace!("EOF found");
-                    break;
+            if head.is_none() {
+                if let Some(end) = find_subsequence(&read, b"\r\n\r\n") {
+                    head = Some(Head::parse(&read[..end], end + "\r\n\r\n".len())?);
+                }
+            }
+            let too_large = RequestError::BodyTooLarge(limits.max_body_bytes);
+            match &head {
+                None if read.len() > MAX_HEAD_BYTES => {
+                    return Err(RequestError::HeadTooLarge(MAX_HEAD_BYTES))
+                }
+                Some(head) if head.body_start > MAX_HEAD_BYTES => {
+                    return Err(RequestError::HeadTooLarge(MAX_HEAD_BYTES))
+                }
+                Some(head) if head.content_length > Some(limits.max_body_bytes) => {
+                    return Err(too_large)
                 }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    // Note: the right thing would be reading the buffer everytime,
-                    // looking for content-length, and seeing if we have got that many
-                    // bytes. If so, break. Will likely do in the future.
-                    if find_subsequence(&read, b"Expect: 100-continue").is_some() {
-                        log::trace!("Returning 100-coninue.");
-                        let response = "HTTP/1.1 100 Continue\r\n".as_bytes();
-                        let _ = stream.write(response).map_err(RequestError::StreamWrite)?;
-                    } else {
-                        log::trace!(
-                            "WouldBlock without 100-continue, assuming end of transmission."
-                        );
+                Some(head) if head.chunked => {
+                    chunked_body = decode_chunked(&read[head.body_start..])?;
+                    match &chunked_body {
+                        Some((body, _)) if body.len() > limits.max_body_bytes => {
+                            return Err(too_large)
+                        }
+                        Some(_) => break,
+                        // later requests can't have arrived before this one ended
+                        None if read.len() - head.body_start > limits.max_body_bytes => {
+                            return Err(too_large)
+                        }
+                        None => {}
+                    }
+                }
+                Some(head) => {
+                    // no body is expected without a content-length
+                    let body_len = head.content_length.unwrap_or(0);
+                    if read.len() >= head.body_start + body_len {
                         break;
                     }
                 }
-                Err(e) => {
+                None => {}
+            }
+
+            if let Some(head) = head.as_ref().filter(|_| !continued) {
+                // the client waits for this before sending the body, so only when none came
+                if head.expects_continue() && read.len() == head.body_start {
+                    log::trace!("Returning 100-continue.");
+                    stream
+                        .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                        .await
+                        .map_err(RequestError::StreamWrite)?;
+                    continued = true;
+                }
+            }
+
+            let wait = match started {
+                None => limits.idle,
+                Some(started) => {
+                    let until = match head {
+                        None => limits.head,
+                        Some(_) => limits.deadline,
+                    };
+                    let left = until.saturating_sub(started.elapsed());
+                    if left.is_zero() {
+                        return Err(RequestError::Timeout);
+                    }
+                    left.min(limits.read)
+                }
+            };
+            match timeout(wait, stream.read(&mut buffer[..])).await {
+                Ok(Ok(0)) if read.is_empty() => return Err(RequestError::ConnectionClosed),
+                Err(_) if read.is_empty() => return Err(RequestError::ConnectionClosed),
+                Ok(Ok(0)) => {
+                    log::trace!("EOF found");
+                    break;
+                }
+                // a pause isn't the end of the request, the client is too slow
+                Err(_) => return Err(RequestError::Timeout),
+                Ok(Err(e)) => {
                     log::error!("Failed to read from stream. {:?}", e);
                     return Err(RequestError::StreamRead(e));
                 }
-                Ok(bytes_read) => {
+                Ok(Ok(bytes_read)) => {
                     log::trace!("Read {} bytes from incoming stream.", bytes_read);
                     read.extend_from_slice(&buffer[0..bytes_read]);
+                    started.get_or_insert_with(Instant::now);
                 }
             }
+        }
 
-            // Check if we've gotten all the headers.
-            if body_start_index.is_none() {
-                log::trace!("Looking for body_start_index");
-                if let Some(len) = find_subsequence(&buffer, b"\r\n\r\n") {
-                    body_start_index = Some(len + "\r\n\r\n".len());
-                }
-                log::trace!("body_start_index is now {:?}", body_start_index);
+        let head = match head {
+            Some(head) => head,
+            None if find_subsequence(&read, b"\n").is_none() => {
+                return Err(RequestError::NoRequestLine)
             }
+            None => return Err(RequestError::NoMessageBody),
+        };
+        log::trace!("Request line = {:?}", head.request_line);
 
-            // Check if we've gotten all the content
-            if body_start_index.is_some() && expected_len.is_none() {
-                expected_len = try_to_get_expected_len(&buffer)?;
+        let (body, body_end) = match (head.chunked, head.content_length) {
+            (true, _) => {
+                let (body, used) = chunked_body.ok_or(RequestError::BadChunk)?;
+                (body, head.body_start + used)
             }
-
-            if let Some(len) = expected_len {
-                if read.len() >= len {
-                    break;
+            // neither header means there is no body, RFC 7230 section 3.3.3
+            (false, None) => (vec![], head.body_start),
+            (false, Some(expected_len)) => {
+                let end_index = head.body_start + expected_len;
+                if end_index > read.len() {
+                    let actual = read.len() - head.body_start;
+                    return Err(RequestError::BadContentLength(expected_len, actual));
                 }
+                (read[head.body_start..end_index].to_vec(), end_index)
+            }
+        };
+        let body = String::from_utf8(body).map_err(|e| RequestError::BadMessage(e.utf8_error()))?;
+        log::trace!("Request body =\n{body}\nEOF");
+        *buffered = read.split_off(body_end);
+
+        Ok(Request {
+            request_line: head.request_line,
+            headers: head.headers,
+            body,
+        })
+    }
+}
+
+impl Request {
+    /// HTTP/1.1 keeps the connection open unless the client sends `Connection: close`,
+    /// older versions only when it sends `Connection: keep-alive`.
+    pub(crate) fn keep_alive(&self) -> bool {
+        let connection = self.header("Connection").map(|x| x.to_ascii_lowercase());
+        match connection {
+            Some(x) if x.contains("close") => false,
+            Some(x) if x.contains("keep-alive") => true,
+            _ => self.request_line.version == "HTTP/1.1",
+        }
+    }
+
+    /// The request path without the query string.
+    pub(crate) fn path(&self) -> &str {
+        let path = self.request_line.path();
+        path.split_once('?').map_or(path.as_str(), |(path, _)| path)
+    }
+
+    /// The first value of a query string parameter.
+    pub(crate) fn query(&self, name: &str) -> Option<String> {
+        self.request_line
+            .query_params()
+            .into_iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The first header with the name, ignoring case.
+    pub(crate) fn header(&self, name: &str) -> Option<&String> {
+        self.headers.get(name)
+    }
+
+    /// The media type of the body without parameters, such as `application/json`.
+    pub(crate) fn content_type(&self) -> Option<String> {
+        self.header("Content-Type")
+            .and_then(|x| x.split(';').next())
+            .map(|x| x.trim().to_ascii_lowercase())
+    }
+}
+
+/// The body and how many bytes of `bytes` it used, or `None` if the last chunk
+/// hasn't arrived yet. Chunk extensions and trailers are ignored.
+fn decode_chunked(bytes: &[u8]) -> Result<Option<(Vec<u8>, usize)>, RequestError> {
+    let mut body = vec![];
+    let mut position = 0;
+    loop {
+        let line_end = match find_subsequence(&bytes[position..], b"\r\n") {
+            Some(x) => position + x,
+            None => return Ok(None),
+        };
+        let size_line =
+            std::str::from_utf8(&bytes[position..line_end]).map_err(|_| RequestError::BadChunk)?;
+        let size = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| RequestError::BadChunk)?;
+        position = line_end + "\r\n".len();
+
+        if size == 0 {
+            if bytes[position..].starts_with(b"\r\n") {
+                return Ok(Some((body, position + "\r\n".len())));
             }
+            return Ok(find_subsequence(&bytes[position..], b"\r\n\r\n")
+                .map(|x| (body, position + x + "\r\n\r\n".len())));
+        }
+        // sizes near usize::MAX would overflow, they can't be real
+        let chunk_end = position.checked_add(size).ok_or(RequestError::BadChunk)?;
+        let next = chunk_end
+            .checked_add("\r\n".len())
+            .ok_or(RequestError::BadChunk)?;
+        if bytes.len() < next {
+            return Ok(None);
         }
+        if &bytes[chunk_end..next] != b"\r\n" {
+            return Err(RequestError::BadChunk);
+        }
+        body.extend_from_slice(&bytes[position..chunk_end]);
+        position = next;
+    }
+}
+
+/// Everything before the body, parsed once the blank line has arrived.
+struct Head {
+    request_line: RequestLine,
+    headers: Headers,
+    body_start: usize,
+    content_length: Option<usize>,
+    /// `Transfer-Encoding: chunked`, which takes precedence over `Content-Length`.
+    chunked: bool,
+}
 
-        log::trace!("Recieved full request, now seperating headers and body.");
-        let end_index = find_subsequence(&read, b"\n").ok_or(RequestError::NoRequestLine)?;
-        let request_line_slice = &read[0..end_index];
-        let mut request_line_str = std::str::from_utf8(request_line_slice)
+impl Head {
+    fn parse(head: &[u8], body_start: usize) -> Result<Self, RequestError> {
+        let line_end = find_subsequence(head, b"\n").unwrap_or(head.len());
+        let mut request_line_str = std::str::from_utf8(&head[..line_end])
             .map_err(RequestError::BadMessage)?
             .split(' ');
         let request_line = RequestLine {
@@ -132,100 +483,104 @@ impl Request {
                 .next()
                 .ok_or(RequestError::RequestLineParse)?
                 .to_string(),
+            version: request_line_str
+                .next()
+                .unwrap_or("HTTP/1.0")
+                .trim()
+                .to_string(),
         };
-        log::trace!("Request line = {:?}", request_line);
-
-        let start_index = find_subsequence(&read, b"\r\n\r\n")
-            .ok_or(RequestError::NoMessageBody)?
-            + "\r\n\r\n".len();
-
-        match expected_len {
-            None => {
-                // TODO: body as option
-                if request_line.method() == "GET" {
-                    Ok(Request {
-                        request_line,
-                        body: "".to_string(),
-                    })
-                } else {
-                    Err(RequestError::NoContentLength)
-                }
-            }
-            Some(expected_len) => {
-                let end_index = start_index + expected_len;
-                if end_index > read.len() {
-                    let actual = read.len() - start_index;
-                    return Err(RequestError::BadContentLength(expected_len, actual));
-                }
-                let body_slice = &read[start_index..end_index];
-                let body = std::str::from_utf8(body_slice)
-                    .map_err(RequestError::BadMessage)?
-                    .to_string();
-                log::trace!("Request body =\n{body}\nEOF");
-
-                Ok(Request { request_line, body })
-            }
-        }
+        let headers = Headers::parse(&String::from_utf8_lossy(&head[line_end..]));
+        let content_length = headers.content_length()?;
+        let chunked = headers
+            .get("Transfer-Encoding")
+            .is_some_and(|x| x.to_ascii_lowercase().contains("chunked"));
+        Ok(Head {
+            request_line,
+            headers,
+            body_start,
+            content_length,
+            chunked,
+        })
     }
-}
 
-fn try_to_get_expected_len(buffer: &[u8]) -> Result<Option<usize>, RequestError> {
-    log::trace!("Looking for expected_len / content_length");
-    // TODO: make this not case sensitive
-    let content_length_index = match find_subsequence(buffer, b"Content-Length: ") {
-        Some(v) => v,
-        None => return Ok(None),
-    };
-
-    let end_of_line = find_subsequence(&buffer[content_length_index..], b"\r")
-        .ok_or(RequestError::NoContentLength)?
-        + content_length_index;
-    let start_index = content_length_index + "Content-Length: ".len();
-    let content_length =
-        std::str::from_utf8(&buffer[start_index..end_of_line]).map_err(RequestError::BadMessage)?;
-
-    log::trace!("Parced content_length as '{content_length}'");
-    let as_usize = content_length
-        .parse::<usize>()
-        .map_err(|_| RequestError::NoContentLength)?;
-    Ok(Some(as_usize))
+    /// HTTP/1.0 clients don't know about interim responses, so they never get one.
+    fn expects_continue(&self) -> bool {
+        let has_body = self.chunked || self.content_length.unwrap_or(0) > 0;
+        has_body
+            && self.request_line.version == "HTTP/1.1"
+            && self
+                .headers
+                .get("Expect")
+                .is_some_and(|x| x.eq_ignore_ascii_case("100-continue"))
+    }
 }
 
-// TODO: test for 100-continue
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::test::TestStream;
+/// Request headers in the order they were sent. Names are matched ignoring case.
+#[derive(Debug, Default)]
+pub(crate) struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    fn parse(section: &str) -> Self {
+        let headers = section
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            // whitespace before the colon is not allowed, so it isn't a header
+            .filter(|(name, _)| !name.is_empty() && !name.ends_with(char::is_whitespace))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        Headers(headers)
+    }
 
-    struct MockWriter {
-        data: Vec<u8>,
+    /// The first value for the name, ignoring case.
+    pub(crate) fn get(&self, name: &str) -> Option<&String> {
+        self.0
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
     }
 
-    impl MockWriter {
-        fn new() -> Self {
-            MockWriter { data: vec![] }
-        }
+    pub(crate) fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a String> {
+        self.0
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
 
-        fn to_string(&self) -> String {
-            std::str::from_utf8(&self.data)
-                .expect("Failed to convert data to string")
-                .to_string()
-        }
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
     }
 
-    impl Write for MockWriter {
-        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-            self.data.extend_from_slice(buf);
-            Ok(buf.len())
-        }
-        fn flush(&mut self) -> std::io::Result<()> {
-            Ok(())
+    /// Repeated `Content-Length` headers must agree, otherwise the end of the body
+    /// is ambiguous.
+    fn content_length(&self) -> Result<Option<usize>, RequestError> {
+        let mut lengths = self.get_all("Content-Length").map(|x| {
+            x.parse::<usize>()
+                .map_err(|_| RequestError::NoContentLength)
+        });
+        let first = match lengths.next() {
+            Some(x) => x?,
+            None => return Ok(None),
+        };
+        for length in lengths {
+            if length? != first {
+                return Err(RequestError::ConflictingContentLength);
+            }
         }
+        log::trace!("Parced content_length as '{first}'");
+        Ok(Some(first))
     }
+}
 
-    #[test]
-    fn send_response_with_none() {
-        let mut stream = MockWriter::new();
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::TestStream;
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn send_response_with_none() {
+        let mut stream = vec![];
         let status_line = "HTTP/1.1 200 OK".to_string();
         let headers = vec![
             "X-Something: Or the other".to_string(),
@@ -233,17 +588,20 @@ mod test {
         ];
 
         let response = Response::new(status_line, headers, None);
-        response
+        assert_eq!(response.status_code(), 200);
+        let sent = response
             .send(&mut stream)
+            .await
             .expect("Failed to send to stream");
-        let output = stream.to_string();
+        assert_eq!(sent, 0);
+        let output = String::from_utf8(stream).expect("Failed to convert data to string");
         let expected = "HTTP/1.1 200 OK\r\nX-Something: Or the other\r\nX-Order: persists\r\nConnection: close";
         assert_eq!(expected, output);
     }
 
-    #[test]
-    fn send_response_with_some() {
-        let mut stream = MockWriter::new();
+    #[tokio::test]
+    async fn send_response_with_some() {
+        let mut stream = vec![];
         let status_line = "HTTP/1.1 404 Not Found".to_string();
         let headers = vec![
             "X-Something: Or the other".to_string(),
@@ -253,87 +611,392 @@ mod test {
         let response = Response::new(status_line, headers, Some(body));
         response
             .send(&mut stream)
+            .await
             .expect("Failed to send to stream");
-        let output = stream.to_string();
+        let output = String::from_utf8(stream).expect("Failed to convert data to string");
         let expected = "HTTP/1.1 404 Not Found\r\nX-Something: Or the other\r\nX-Order: persists\r\nConnection: close\r\nContent-Length: 4\r\n\r\nNala";
         assert_eq!(expected, output);
     }
 
-    #[test]
-    fn request_from_stream_happy_case() {
+    #[tokio::test]
+    async fn send_response_gzip() {
+        let body = "Nala ".repeat(GZIP_MIN_BYTES);
+        let mut small = Response::new("HTTP/1.1 200 OK".to_string(), vec![], Some("Nala".into()));
+        small.gzip_for(Some("gzip"));
+        assert!(!small.gzip);
+        let mut refused = Response::new("HTTP/1.1 200 OK".to_string(), vec![], Some(body.clone()));
+        refused.gzip_for(Some("gzip;q=0, identity"));
+        assert!(!refused.gzip);
+        let mut response = Response::new("HTTP/1.1 200 OK".to_string(), vec![], Some(body.clone()));
+        response.gzip_for(Some("deflate, GZIP;q=0.5"));
+        assert!(response.gzip);
+
+        let mut stream = vec![];
+        response
+            .send(&mut stream)
+            .await
+            .expect("Failed to send to stream");
+        let start = find_subsequence(&stream, b"\r\n\r\n").expect("Missing body") + 4;
+        let headers = String::from_utf8_lossy(&stream[..start]);
+        assert!(headers.contains("Content-Encoding: gzip\r\n"));
+        assert!(headers.contains(&format!("Content-Length: {}\r\n", stream.len() - start)));
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&stream[start..])
+            .read_to_string(&mut decoded)
+            .expect("Failed to decompress");
+        assert_eq!(decoded, body);
+    }
+
+    #[tokio::test]
+    async fn request_from_buffered_stream_pipelined() {
+        let message = "POST /a HTTP/1.1\r\nContent-Length: 4\r\n\r\nNalaGET /b HTTP/1.1\r\nConnection: close\r\n\r\nGET /c HTTP/1.0\r\n\r\n";
+        let mut stream = TestStream::new(message.as_bytes());
+        let mut buffered = vec![];
+
+        let first = Request::from_buffered_stream(&mut stream, &mut buffered, &Limits::default())
+            .await
+            .expect("Failed to parse first request");
+        assert_eq!(first.request_line().path(), "/a");
+        assert_eq!(first.body(), "Nala");
+        assert!(first.keep_alive());
+        let second = Request::from_buffered_stream(&mut stream, &mut buffered, &Limits::default())
+            .await
+            .expect("Failed to parse second request");
+        assert_eq!(second.request_line().path(), "/b");
+        assert!(!second.keep_alive());
+        let third = Request::from_buffered_stream(&mut stream, &mut buffered, &Limits::default())
+            .await
+            .expect("Failed to parse third request");
+        assert_eq!(third.request_line().path(), "/c");
+        assert!(!third.keep_alive());
+        assert!(matches!(
+            Request::from_buffered_stream(&mut stream, &mut buffered, &Limits::default()).await,
+            Err(RequestError::ConnectionClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_response_keep_alive() {
+        let mut stream = vec![];
+        let mut response = Response::new("HTTP/1.1 204 No Content".to_string(), vec![], None);
+        response.set_keep_alive(true);
+        response
+            .send(&mut stream)
+            .await
+            .expect("Failed to send to stream");
+        let output = String::from_utf8(stream).expect("Failed to convert data to string");
+        let expected =
+            "HTTP/1.1 204 No Content\r\nConnection: keep-alive\r\nContent-Length: 0\r\n\r\n";
+        assert_eq!(expected, output);
+    }
+
+    #[tokio::test]
+    async fn request_from_stream_happy_case() {
         let message = "GET / HTTP/1.1\r\nX-Something: Or the other\r\nX-Order: persists\r\nConnection: close\r\nContent-Length: 4\r\n\r\nNala";
         let expected_body = "Nala";
         let mut request = TestStream::new(message.as_bytes());
-        let result = Request::from_stream(&mut request).expect("Failed to parse request");
+        let result = Request::from_stream(&mut request)
+            .await
+            .expect("Failed to parse request");
         assert_eq!(result.body(), expected_body);
         assert_eq!(result.request_line().method(), "GET");
         assert_eq!(result.request_line().path(), "/");
+        assert_eq!(result.headers().len(), 4);
+        assert_eq!(result.header("x-order"), Some(&"persists".to_string()));
+        assert_eq!(result.header("X-Missing"), None);
     }
 
-    #[test]
-    fn request_from_stream_extra_data() {
+    #[tokio::test]
+    async fn request_from_stream_query() {
+        let message = "GET /api/changes?flag&since=42&q=disk+full%21&since=7 HTTP/1.1\r\n\r\n";
+        let mut request = TestStream::new(message.as_bytes());
+        let result = Request::from_stream(&mut request)
+            .await
+            .expect("Failed to parse request");
+        assert_eq!(result.path(), "/api/changes");
+        assert_eq!(result.query("since").as_deref(), Some("42"));
+        assert_eq!(result.query("flag").as_deref(), Some(""));
+        assert_eq!(result.query("q").as_deref(), Some("disk full!"));
+        assert_eq!(result.query("missing"), None);
+        assert_eq!(result.request_line().query_params().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn request_from_stream_extra_data() {
         let message = "POST /somewhere HTTP/1.1\r\nX-Something: Or the other\r\nX-Order: persists\r\nConnection: close\r\nContent-Length: 4\r\n\r\nNala is the best dog.";
         let expected = "Nala";
         let mut request = TestStream::new(message.as_bytes());
-        let result = Request::from_stream(&mut request).expect("Failed to parse request");
+        let result = Request::from_stream(&mut request)
+            .await
+            .expect("Failed to parse request");
         assert_eq!(result.body(), expected);
         assert_eq!(result.request_line().method(), "POST");
         assert_eq!(result.request_line().path(), "/somewhere");
     }
 
-    #[test]
-    fn request_from_stream_missing_data() {
+    #[tokio::test]
+    async fn request_from_stream_missing_data() {
         let message = "POST /somewhere HTTP/1.1\r\nX-Something: Or the other\r\nX-Order: persists\r\nConnection: close\r\nContent-Length: 42\r\n\r\nNala is the best dog.";
         let mut request = TestStream::new(message.as_bytes());
-        let result = Request::from_stream(&mut request);
+        let result = Request::from_stream(&mut request).await;
         assert!(matches!(
             result,
             Err(RequestError::BadContentLength(42, 21))
         ));
     }
 
-    #[test]
-    fn request_from_stream_post_no_content_length() {
+    #[tokio::test]
+    async fn request_from_stream_post_no_content_length() {
         let message =
-            "POST /somewhere HTTP/1.1\r\nX-Something: Or the other\r\nX-Order: persists\r\nConnection: close\r\n\r\nNala";
-        let mut request = TestStream::new(message.as_bytes());
-        let result = Request::from_stream(&mut request);
-        assert!(matches!(result, Err(RequestError::NoContentLength)));
+            "POST /somewhere HTTP/1.1\r\nX-Something: Or the other\r\nX-Order: persists\r\n\r\nDELETE /api/fingerprints/abc HTTP/1.1\r\n\r\n";
+        let mut stream = TestStream::new(message.as_bytes());
+        let mut buffered = vec![];
+        let first = Request::from_buffered_stream(&mut stream, &mut buffered, &Limits::default())
+            .await
+            .expect("Failed to parse request");
+        assert_eq!(first.body(), "");
+        assert_eq!(first.request_line().method(), "POST");
+
+        // what followed is the next request, not the first one's body
+        let second = Request::from_buffered_stream(&mut stream, &mut buffered, &Limits::default())
+            .await
+            .expect("Failed to parse request");
+        assert_eq!(second.body(), "");
+        assert_eq!(second.request_line().method(), "DELETE");
+        assert_eq!(second.path(), "/api/fingerprints/abc");
     }
 
-    #[test]
-    fn request_from_stream_get_no_content_length() {
+    #[tokio::test]
+    async fn request_from_stream_get_no_content_length() {
         let message =
             "GET /somewhere HTTP/1.1\r\nX-Something: Or the other\r\nX-Order: persists\r\nConnection: close\r\n\r\n";
         let mut request = TestStream::new(message.as_bytes());
-        let result = Request::from_stream(&mut request).expect("Failed to parse request");
+        let result = Request::from_stream(&mut request)
+            .await
+            .expect("Failed to parse request");
         assert_eq!(result.body(), "");
         assert_eq!(result.request_line().method(), "GET");
         assert_eq!(result.request_line().path(), "/somewhere");
     }
 
-    #[test]
-    fn request_from_stream_bad_content_length() {
+    #[tokio::test]
+    async fn request_from_stream_bad_content_length() {
         let message = "X-Something: Or the other\r\nX-Order: persists\r\nConnection: close\r\nContent-Length: four\r\n\r\nNala";
         let mut request = TestStream::new(message.as_bytes());
-        let result = Request::from_stream(&mut request);
+        let result = Request::from_stream(&mut request).await;
         assert!(matches!(result, Err(RequestError::NoContentLength)));
     }
 
+    #[tokio::test]
+    async fn request_from_stream_lowercase_headers() {
+        let message = "POST / HTTP/1.1\r\ncontent-length: 4\r\ncontent-type: Application/JSON; charset=utf-8\r\nX-Bad : value\r\n\r\nNala";
+        let mut request = TestStream::new(message.as_bytes());
+        let result = Request::from_stream(&mut request)
+            .await
+            .expect("Failed to parse request");
+        assert_eq!(result.body(), "Nala");
+        assert_eq!(result.content_type(), Some("application/json".to_string()));
+        assert_eq!(result.headers().len(), 2);
+        assert_eq!(result.header("x-bad"), None);
+    }
+
+    #[tokio::test]
+    async fn request_from_stream_conflicting_content_length() {
+        let message = "POST / HTTP/1.1\r\nContent-Length: 4\r\ncontent-length: 2\r\n\r\nNala";
+        let mut request = TestStream::new(message.as_bytes());
+        let result = Request::from_stream(&mut request).await;
+        assert!(matches!(
+            result,
+            Err(RequestError::ConflictingContentLength)
+        ));
+
+        let message = "POST / HTTP/1.1\r\nContent-Length: 4\r\ncontent-length: 4\r\n\r\nNala";
+        let mut request = TestStream::new(message.as_bytes());
+        let result = Request::from_stream(&mut request)
+            .await
+            .expect("Failed to parse request");
+        assert_eq!(result.body(), "Nala");
+    }
+
+    #[tokio::test]
+    async fn request_from_stream_chunked() {
+        let message = "POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4;ext=1\r\nNala\r\nb\r\n is a corgi\r\n0\r\nX-Trailer: yes\r\n\r\nGET /b HTTP/1.1\r\n\r\n";
+        let mut stream = TestStream::new(message.as_bytes());
+        let mut buffered = vec![];
+        let result = Request::from_buffered_stream(&mut stream, &mut buffered, &Limits::default())
+            .await
+            .expect("Failed to parse chunked request");
+        assert_eq!(result.body(), "Nala is a corgi");
+        let result = Request::from_buffered_stream(&mut stream, &mut buffered, &Limits::default())
+            .await
+            .expect("Failed to parse request after chunked one");
+        assert_eq!(result.request_line().path(), "/b");
+
+        for message in [
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nNalaXX\r\n0\r\n\r\n",
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nNala\r\n0\r\n\r\n",
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nNala\r\n",
+        ] {
+            let mut stream = TestStream::new(message.as_bytes());
+            let result = Request::from_stream(&mut stream).await;
+            assert!(matches!(result, Err(RequestError::BadChunk)), "{message}");
+        }
+    }
+
     #[test]
-    fn request_from_stream_bad_request_line_empty() {
+    fn chunk_size_overflow() {
+        // the chunk would end at usize::MAX, just after the size line
+        let result = decode_chunked(b"FFFFFFFFFFFFFFED\r\nNala\r\n");
+        assert!(matches!(result, Err(RequestError::BadChunk)));
+        let result = decode_chunked(b"FFFFFFFFFFFFFFFF\r\nNala\r\n");
+        assert!(matches!(result, Err(RequestError::BadChunk)));
+    }
+
+    #[tokio::test]
+    async fn request_from_stream_expect_continue() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let reader = tokio::spawn(async move {
+            let mut buffered = vec![];
+            let request =
+                Request::from_buffered_stream(&mut server, &mut buffered, &Limits::default())
+                    .await
+                    .expect("Failed to parse request");
+            (request, buffered)
+        });
+        client
+            .write_all(b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\r\n")
+            .await
+            .expect("Failed to write head");
+        let continue_line = b"HTTP/1.1 100 Continue\r\n\r\n";
+        let mut answer = vec![0; continue_line.len()];
+        client
+            .read_exact(&mut answer)
+            .await
+            .expect("Missing 100 Continue");
+        assert_eq!(answer, continue_line);
+        // only Content-Length bytes are the body, the rest is the next request
+        client
+            .write_all(b"NalaGET / HTTP/1.1\r\n\r\n")
+            .await
+            .expect("Failed to write body");
+        let (request, buffered) = reader.await.expect("Reader failed");
+        assert_eq!(request.body(), "Nala");
+        assert_eq!(buffered, b"GET / HTTP/1.1\r\n\r\n");
+
+        // nothing is sent when the body came with the head, to HTTP/1.0 clients,
+        // or when the body is refused anyway
+        for (message, expected_body) in [
+            (
+                "POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\r\nNala",
+                Some("Nala"),
+            ),
+            (
+                "POST / HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\r\nNala",
+                Some("Nala"),
+            ),
+            (
+                "POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 99999999\r\n\r\n",
+                None,
+            ),
+        ] {
+            let mut stream = TestStream::new(message.as_bytes());
+            let result = Request::from_stream(&mut stream).await;
+            assert_eq!(
+                result.ok().map(|x| x.body().clone()).as_deref(),
+                expected_body,
+                "{message}"
+            );
+            assert_eq!(stream.sent(), "", "{message}");
+        }
+    }
+
+    #[tokio::test]
+    async fn request_from_stream_too_slow() {
+        let limits = Limits::new(
+            READ_TIMEOUT,
+            Duration::from_millis(200),
+            Duration::from_millis(500),
+            Duration::from_secs(1),
+            1024,
+        );
+        // a pause mid request is not the end of it
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client
+            .write_all(b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nNa")
+            .await
+            .expect("Failed to write");
+        let result = Request::from_buffered_stream(&mut server, &mut vec![], &limits).await;
+        assert!(matches!(result, Err(RequestError::Timeout)));
+
+        // trickling a byte at a time runs into the header deadline
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let trickle = tokio::spawn(async move {
+            for byte in b"GET / HTTP/1.1\r\nX-Slow: aaaaaaaaaaaaaaaa" {
+                if client.write_all(&[*byte]).await.is_err() {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        });
+        let started = Instant::now();
+        let result = Request::from_buffered_stream(&mut server, &mut vec![], &limits).await;
+        assert!(matches!(result, Err(RequestError::Timeout)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        trickle.abort();
+    }
+
+    #[tokio::test]
+    async fn request_from_stream_too_large() {
+        let limits = Limits {
+            max_body_bytes: 4,
+            ..Limits::default()
+        };
+        for (message, expected_ok) in [
+            (
+                "POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nNalaGET / HTTP/1.1\r\n\r\n",
+                true,
+            ),
+            ("POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\n", false),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nNala!\r\n0\r\n\r\n",
+                false,
+            ),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nNa\r\n2\r\nla",
+                false,
+            ),
+        ] {
+            let mut stream = TestStream::new(message.as_bytes());
+            let result = Request::from_buffered_stream(&mut stream, &mut vec![], &limits).await;
+            match expected_ok {
+                true => assert!(result.is_ok(), "{message}"),
+                false => assert!(
+                    matches!(result, Err(RequestError::BodyTooLarge(4))),
+                    "{message}"
+                ),
+            }
+        }
+
+        let message = format!("GET / HTTP/1.1\r\nX-Big: {}", "a".repeat(MAX_HEAD_BYTES));
+        let mut stream = TestStream::new(message.as_bytes());
+        let result = Request::from_buffered_stream(&mut stream, &mut vec![], &limits).await;
+        assert!(matches!(result, Err(RequestError::HeadTooLarge(_))));
+    }
+
+    #[tokio::test]
+    async fn request_from_stream_bad_request_line_empty() {
         // Without \r\n, it would think X-Something: is the method and "or" is the path.
         let message = "\r\nX-Something: Or the other\r\nX-Order: persists\r\nConnection: close\r\nContent-Length: 42\r\n\r\nNala";
         let mut request = TestStream::new(message.as_bytes());
-        let result = Request::from_stream(&mut request);
+        let result = Request::from_stream(&mut request).await;
         assert!(matches!(result, Err(RequestError::RequestLineParse)));
     }
 
-    #[test]
-    fn request_from_stream_bad_request_line_no_path() {
+    #[tokio::test]
+    async fn request_from_stream_bad_request_line_no_path() {
         let message = "GET\r\nX-Something: Or the other\r\nX-Order: persists\r\nConnection: close\r\nContent-Length: 42\r\n\r\nNala";
         let mut request = TestStream::new(message.as_bytes());
-        let result = Request::from_stream(&mut request);
+        let result = Request::from_stream(&mut request).await;
         assert!(matches!(result, Err(RequestError::RequestLineParse)));
     }
 }
@@ -0,0 +1,143 @@
+use crate::models::grafana::{Alert, Message};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// The webhook of Grafana's legacy alerting, before unified alerting in Grafana 8.
+/// It is about one rule, so it becomes one alert fingerprinted by the rule's ID.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LegacyMessage {
+    rule_id: i64,
+    /// `alerting`, `ok`, `no_data`, `paused` or `pending`.
+    state: String,
+    rule_name: Option<String>,
+    rule_url: Option<String>,
+    title: Option<String>,
+    message: Option<String>,
+    org_id: Option<i64>,
+    #[serde(default)]
+    eval_matches: Vec<EvalMatch>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+/// A series that matched the rule's condition.
+#[derive(Deserialize)]
+struct EvalMatch {
+    metric: String,
+    value: Option<f64>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+impl EvalMatch {
+    /// Series of the same metric are told apart by their tags, like `cpu{host=db1}`.
+    fn name(&self) -> String {
+        if self.tags.is_empty() {
+            return self.metric.clone();
+        }
+        let tags: Vec<String> = self.tags.iter().map(|(k, v)| format!("{k}={v}")).collect();
+        format!("{}{{{}}}", self.metric, tags.join(","))
+    }
+}
+
+impl From<LegacyMessage> for Message {
+    /// `no_data` becomes a `DatasourceNoData` alert like unified alerting sends, so
+    /// `datasource_alerts` applies to it. `paused` and `pending` are left to `unknown_status`.
+    fn from(legacy: LegacyMessage) -> Self {
+        let rule_name = legacy
+            .rule_name
+            .or(legacy.title)
+            .unwrap_or_else(|| format!("Rule {}", legacy.rule_id));
+        let mut labels = legacy.tags;
+        let status = match legacy.state.as_str() {
+            "alerting" => "firing",
+            "ok" => "resolved",
+            "no_data" => {
+                labels.insert("rulename".to_string(), rule_name.clone());
+                "firing"
+            }
+            other => other,
+        };
+        let alertname = match legacy.state.as_str() {
+            "no_data" => "DatasourceNoData".to_string(),
+            _ => rule_name,
+        };
+        labels.insert("alertname".to_string(), alertname);
+        let annotations = legacy
+            .message
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .map(|x| ("summary".to_string(), x))
+            .into_iter()
+            .collect();
+        let values = legacy
+            .eval_matches
+            .iter()
+            .map(|x| (x.name(), x.value))
+            .collect();
+        // org 1 is Grafana's default, which older versions don't send
+        let fingerprint = format!("legacy-{}-{}", legacy.org_id.unwrap_or(1), legacy.rule_id);
+        let alert = Alert::new(status, fingerprint, labels, annotations)
+            .with_generator_url(legacy.rule_url.unwrap_or_default())
+            .with_values(values);
+        Message::new(vec![alert])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn convert(state: &str) -> Message {
+        let json = format!(
+            r#"{{
+                "dashboardId": 1,
+                "evalMatches": [
+                    {{"value": 92.5, "metric": "cpu", "tags": {{"host": "db1"}}}},
+                    {{"value": null, "metric": "cpu", "tags": {{"host": "db2"}}}}
+                ],
+                "message": "CPU is high",
+                "orgId": 2,
+                "panelId": 3,
+                "ruleId": 7,
+                "ruleName": "[high] CPU",
+                "ruleUrl": "http://grafana:3000/d/abc?viewPanel=3",
+                "state": "{state}",
+                "tags": {{"team": "db"}},
+                "title": "[Alerting] [high] CPU"
+            }}"#
+        );
+        let legacy: LegacyMessage = serde_json::from_str(&json).expect("Failed to load message");
+        legacy.into()
+    }
+
+    #[test]
+    fn converts() {
+        let message = convert("alerting");
+        let alert = &message.alerts()[0];
+        assert_eq!(alert.fingerprint(), "legacy-2-7");
+        assert_eq!(alert.status(), "firing");
+        assert_eq!(alert.name(), "[high] CPU");
+        assert_eq!(alert.get_priority(), prowl::Priority::High);
+        assert_eq!(alert.summary(), "CPU is high");
+        assert_eq!(alert.labels().get("team"), Some("db"));
+        assert_eq!(
+            alert.generator_url(),
+            "http://grafana:3000/d/abc?viewPanel=3"
+        );
+        assert_eq!(
+            alert.value_text().as_deref(),
+            Some("values: cpu{host=db1}=92.5, cpu{host=db2}=no data")
+        );
+
+        assert_eq!(convert("ok").alerts()[0].status(), "resolved");
+        assert_eq!(convert("paused").alerts()[0].status(), "paused");
+        let message = convert("no_data");
+        let alert = &message.alerts()[0];
+        assert_eq!(alert.status(), "firing");
+        assert_eq!(alert.fingerprint(), "legacy-2-7");
+        assert!(alert.datasource_problem().is_some());
+        assert_eq!(alert.rule_name(), "[high] CPU");
+    }
+}
@@ -0,0 +1,47 @@
+use crate::models::auth::RouteGroup;
+use derive_getters::Getters;
+use serde::Deserialize;
+
+/// An address to accept connections on, serving only some route groups.
+#[derive(Clone, Debug, Deserialize, Getters)]
+pub(crate) struct Listener {
+    bind_host: String,
+    #[serde(default = "all_groups")]
+    groups: Vec<RouteGroup>,
+}
+
+fn all_groups() -> Vec<RouteGroup> {
+    vec![RouteGroup::Webhooks, RouteGroup::Api, RouteGroup::Ui]
+}
+
+impl Listener {
+    /// Serves every route group.
+    pub(crate) fn new(bind_host: String) -> Self {
+        Listener {
+            bind_host,
+            groups: all_groups(),
+        }
+    }
+
+    pub(crate) fn serves(&self, group: RouteGroup) -> bool {
+        self.groups.contains(&group)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_serves() {
+        let listener: Listener =
+            serde_json::from_str(r#"{"bind_host": "10.0.0.2:3333", "groups": ["webhooks"]}"#)
+                .expect("Failed to parse listener");
+        assert!(listener.serves(RouteGroup::Webhooks));
+        assert!(!listener.serves(RouteGroup::Api));
+        assert!(!listener.serves(RouteGroup::Ui));
+
+        let listener = Listener::new("127.0.0.1:3333".to_string());
+        assert!(listener.serves(RouteGroup::Ui));
+    }
+}
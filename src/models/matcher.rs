@@ -0,0 +1,351 @@
+use crate::{
+    errors::MatcherError,
+    models::{fingerprint::PreviousEvent, grafana::Alert},
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// What part of an alert a `Matcher` looks at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Target {
+    Label(String),
+    Annotation(String),
+    Status,
+    Priority,
+    Fingerprint,
+    Group,
+    Tenant,
+}
+
+#[derive(Clone, Debug)]
+enum Operator {
+    Equal(String),
+    NotEqual(String),
+    Regex(Regex),
+    NotRegex(Regex),
+    Present,
+    Absent,
+}
+
+/// A single condition over an alert, written like Alertmanager matchers.
+///
+/// * `team=network`, `team!=network`, `team=~net.*`, `team!~net.*`
+/// * `team` when the label is present, `!team` when it is absent
+/// * `@status`, `@priority`, `@fingerprint`, `@group` and `@annotation.<name>`
+///   can be used instead of a label name.
+///
+/// Missing values are treated as an empty string and regexes must match the whole value.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct Matcher {
+    source: String,
+    target: Target,
+    operator: Operator,
+}
+
+/// Anything that can be checked by a `Matcher`.
+pub(crate) trait Matchable {
+    /// The value of the target, or `None` if it is not present.
+    fn value_of(&self, target: &Target) -> Option<String>;
+}
+
+impl Matcher {
+    pub(crate) fn parse(source: &str) -> Result<Self, MatcherError> {
+        let trimmed = source.trim();
+        let (negated, body) = match trimmed.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed),
+        };
+
+        let (name, operator) = match find_operator(body) {
+            Some((index, op)) if !negated => {
+                let name = &body[..index];
+                let value = unquote(body[index + op.len()..].trim());
+                let operator = match op {
+                    "=" => Operator::Equal(value.to_string()),
+                    "!=" => Operator::NotEqual(value.to_string()),
+                    "=~" => Operator::Regex(anchored_regex(value)?),
+                    _ => Operator::NotRegex(anchored_regex(value)?),
+                };
+                (name, operator)
+            }
+            Some(_) => return Err(MatcherError::Syntax(source.to_string())),
+            None if negated => (body, Operator::Absent),
+            None => (body, Operator::Present),
+        };
+
+        Ok(Matcher {
+            source: trimmed.to_string(),
+            target: parse_target(name.trim())?,
+            operator,
+        })
+    }
+
+    pub(crate) fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub(crate) fn target(&self) -> &Target {
+        &self.target
+    }
+
+    /// The name, value, `isRegex` and `isEqual` of the matcher in Alertmanager's format,
+    /// or `None` for presence checks, which Alertmanager can't express.
+    pub(crate) fn alertmanager_parts(&self) -> Option<(&str, &str, bool, bool)> {
+        let (index, op) = find_operator(&self.source)?;
+        let name = self.source[..index].trim();
+        let value = unquote(self.source[index + op.len()..].trim());
+        let (is_regex, is_equal) = match self.operator {
+            Operator::Equal(_) => (false, true),
+            Operator::NotEqual(_) => (false, false),
+            Operator::Regex(_) => (true, true),
+            Operator::NotRegex(_) => (true, false),
+            Operator::Present | Operator::Absent => return None,
+        };
+        Some((name, value, is_regex, is_equal))
+    }
+
+    pub(crate) fn matches<T: Matchable + ?Sized>(&self, item: &T) -> bool {
+        let value = item.value_of(&self.target);
+        match &self.operator {
+            Operator::Present => value.is_some(),
+            Operator::Absent => value.is_none(),
+            Operator::Equal(expected) => value.unwrap_or_default() == *expected,
+            Operator::NotEqual(expected) => value.unwrap_or_default() != *expected,
+            Operator::Regex(regex) => regex.is_match(&value.unwrap_or_default()),
+            Operator::NotRegex(regex) => !regex.is_match(&value.unwrap_or_default()),
+        }
+    }
+}
+
+/// True when every matcher matches, so an empty list matches everything.
+pub(crate) fn all_match<T: Matchable + ?Sized>(matchers: &[Matcher], item: &T) -> bool {
+    matchers.iter().all(|matcher| matcher.matches(item))
+}
+
+fn find_operator(body: &str) -> Option<(usize, &'static str)> {
+    for (index, _) in body.char_indices() {
+        for op in ["!=", "!~", "=~", "="] {
+            if body[index..].starts_with(op) {
+                return Some((index, op));
+            }
+        }
+    }
+    None
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|x| x.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+fn anchored_regex(value: &str) -> Result<Regex, MatcherError> {
+    Regex::new(&format!("^(?:{value})$")).map_err(MatcherError::Regex)
+}
+
+fn parse_target(name: &str) -> Result<Target, MatcherError> {
+    match name {
+        "" => Err(MatcherError::Syntax(name.to_string())),
+        "@status" => Ok(Target::Status),
+        "@priority" => Ok(Target::Priority),
+        "@fingerprint" => Ok(Target::Fingerprint),
+        "@group" => Ok(Target::Group),
+        "@tenant" => Ok(Target::Tenant),
+        _ => match name.strip_prefix("@annotation.") {
+            Some(annotation) => Ok(Target::Annotation(annotation.to_string())),
+            None if name.starts_with('@') => Err(MatcherError::UnknownTarget(name.to_string())),
+            None => Ok(Target::Label(name.to_string())),
+        },
+    }
+}
+
+impl TryFrom<String> for Matcher {
+    type Error = MatcherError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Matcher::parse(&source)
+    }
+}
+
+impl From<Matcher> for String {
+    fn from(matcher: Matcher) -> Self {
+        matcher.source
+    }
+}
+
+impl Matchable for Alert {
+    fn value_of(&self, target: &Target) -> Option<String> {
+        match target {
+            Target::Label(name) if name == "alertname" => Some(self.name().to_string()),
+            Target::Label(name) => self.labels().get(name).map(str::to_string),
+            Target::Annotation(name) if name == "summary" => Some(self.summary().to_string()),
+            Target::Annotation(name) => self.annotations().get(name).map(str::to_string),
+            Target::Status => Some(self.status().clone()),
+            Target::Priority => Some(format!("{:?}", self.get_priority())),
+            Target::Fingerprint => Some(self.fingerprint().clone()),
+            Target::Group => self.group_key().clone(),
+            Target::Tenant => self.tenant().clone(),
+        }
+    }
+}
+
+impl Matchable for PreviousEvent {
+    fn value_of(&self, target: &Target) -> Option<String> {
+        match target {
+            Target::Label(name) if name == "alertname" => self.name().clone(),
+            // only the `fingerprint_labels` are kept
+            Target::Label(name) => self.labels().get(name).cloned(),
+            Target::Annotation(name) if name == "summary" => self.summary().clone(),
+            Target::Annotation(_) => None,
+            Target::Status => Some(self.last_status().clone()),
+            Target::Priority => self.priority().as_ref().map(|x| format!("{:?}", x)),
+            Target::Fingerprint => Some(self.fingerprint().clone()),
+            Target::Group => self.group_key().clone(),
+            Target::Tenant => self.tenant().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn firing_alert(prefix: &str) -> Alert {
+        serde_json::from_str(&crate::test::consts::create_firing_alert_with_prefix(
+            prefix,
+        ))
+        .expect("Failed to load firing alert")
+    }
+
+    fn resolved_alert() -> Alert {
+        serde_json::from_str(&crate::test::consts::create_resolved_alert())
+            .expect("Failed to load resolved alert")
+    }
+
+    fn matcher(source: &str) -> Matcher {
+        Matcher::parse(source).expect("Failed to parse matcher")
+    }
+
+    #[test]
+    fn exact() {
+        let alert = firing_alert("");
+        assert!(matcher("alertname=Alert Name").matches(&alert));
+        assert!(matcher("alertname = \"Alert Name\"").matches(&alert));
+        assert!(!matcher("alertname=Alert").matches(&alert));
+        assert!(matcher("alertname!=Alert").matches(&alert));
+        assert!(!matcher("alertname!=Alert Name").matches(&alert));
+    }
+
+    #[test]
+    fn regex() {
+        let alert = firing_alert("[high] ");
+        assert!(matcher("alertname=~\\[high\\].*").matches(&alert));
+        assert!(!matcher("alertname=~Alert").matches(&alert));
+        assert!(matcher("alertname!~Alert").matches(&alert));
+        assert!(!matcher("alertname!~.*Name").matches(&alert));
+    }
+
+    #[test]
+    fn presence() {
+        let alert = firing_alert("");
+        assert!(matcher("alertname").matches(&alert));
+        assert!(!matcher("!alertname").matches(&alert));
+        assert!(!matcher("team").matches(&alert));
+        assert!(matcher("!team").matches(&alert));
+        // missing values are treated as empty
+        assert!(matcher("team=").matches(&alert));
+        assert!(matcher("team!=network").matches(&alert));
+    }
+
+    #[test]
+    fn special_targets() {
+        let alert = firing_alert("[critical] ");
+        let resolved = resolved_alert();
+        assert!(matcher("@status=firing").matches(&alert));
+        assert!(matcher("@status=resolved").matches(&resolved));
+        assert!(matcher("@priority=Emergency").matches(&alert));
+        assert!(matcher("@priority=~Emergency|High").matches(&alert));
+        assert!(matcher("@priority=VeryLow").matches(&resolved));
+        assert!(matcher("@fingerprint=581dd91e73c77248").matches(&alert));
+        assert!(matcher("@annotation.summary=~Annotation.*").matches(&alert));
+        assert!(matcher("!@annotation.description").matches(&alert));
+        assert!(matcher("!@group").matches(&alert));
+    }
+
+    #[test]
+    fn other_labels_and_annotations() {
+        let alert: Alert = serde_json::from_str(
+            r#"{"status": "firing", "generatorURL": "", "fingerprint": "a",
+                "labels": {"alertname": "Disk full", "team": "db"},
+                "annotations": {"description": "95% used"}}"#,
+        )
+        .expect("Failed to load alert");
+        assert!(matcher("team=db").matches(&alert));
+        assert!(matcher("!instance").matches(&alert));
+        assert!(matcher("@annotation.description=~95.*").matches(&alert));
+        assert!(matcher("!@annotation.runbook_url").matches(&alert));
+    }
+
+    #[test]
+    fn previous_event() {
+        let config = crate::models::config::Config::load(Some(
+            "src/resources/test-dev-null.json".to_string(),
+        ));
+        let mut fingerprints = crate::models::fingerprint::Fingerprints::load_or_default(&config);
+        fingerprints.update_last_alerted(&firing_alert("[high] "), chrono::Utc::now());
+        let (_, event) = fingerprints.iter().next().expect("Missing fingerprint");
+        assert!(matcher("alertname=[high] Alert Name").matches(event));
+        assert!(matcher("@priority=High").matches(event));
+        assert!(matcher("@status!=resolved").matches(event));
+        assert!(matcher("@annotation.summary=Annotation Summary").matches(event));
+    }
+
+    #[test]
+    fn all() {
+        let alert = firing_alert("");
+        assert!(all_match(&[], &alert));
+        assert!(all_match(
+            &[matcher("@status=firing"), matcher("alertname=~Alert.*")],
+            &alert
+        ));
+        assert!(!all_match(
+            &[matcher("@status=firing"), matcher("alertname=Other")],
+            &alert
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(Matcher::parse(""), Err(MatcherError::Syntax(_))));
+        assert!(matches!(
+            Matcher::parse("=value"),
+            Err(MatcherError::Syntax(_))
+        ));
+        assert!(matches!(
+            Matcher::parse("!team=network"),
+            Err(MatcherError::Syntax(_))
+        ));
+        assert!(matches!(
+            Matcher::parse("@unknown=value"),
+            Err(MatcherError::UnknownTarget(_))
+        ));
+        assert!(matches!(
+            Matcher::parse("team=~(unclosed"),
+            Err(MatcherError::Regex(_))
+        ));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let matchers: Vec<Matcher> =
+            serde_json::from_str("[\"team=network\", \"@status!=resolved\"]")
+                .expect("Failed to deserialize matchers");
+        assert_eq!(matchers[0].target, Target::Label("team".to_string()));
+        assert_eq!(matchers[1].target, Target::Status);
+        let json = serde_json::to_string(&matchers).expect("Failed to serialize matchers");
+        assert_eq!(json, "[\"team=network\",\"@status!=resolved\"]");
+        assert!(serde_json::from_str::<Vec<Matcher>>("[\"@nope\"]").is_err());
+    }
+}
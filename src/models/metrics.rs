@@ -0,0 +1,205 @@
+use chrono::{DateTime, Duration, Utc};
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Upper bounds of the webhook body size buckets, from 1 KiB to 4 MiB.
+const BODY_BYTES_BUCKETS: [u64; 7] = [1024, 4096, 16384, 65536, 262144, 1048576, 4194304];
+const ALERTS_BUCKETS: [u64; 8] = [1, 2, 5, 10, 25, 50, 100, 250];
+/// Large webhooks are notified about at most this often, they tend to keep coming.
+const LARGE_WEBHOOK_NOTIFY_EVERY_HOURS: i64 = 1;
+
+/// Counters served from `/metrics` in Prometheus' text format.
+#[derive(Debug)]
+pub(crate) struct Metrics {
+    unknown_statuses: BTreeMap<String, u64>,
+    label_limits: BTreeMap<&'static str, u64>,
+    webhook_body_bytes: Histogram,
+    webhook_alerts: Histogram,
+    large_webhooks: BTreeMap<&'static str, u64>,
+    large_webhook_notified: Option<DateTime<Utc>>,
+}
+
+/// A Prometheus histogram, `counts` has one more than `buckets` for `+Inf`.
+#[derive(Debug)]
+struct Histogram {
+    buckets: &'static [u64],
+    counts: Vec<u64>,
+    sum: u64,
+}
+
+impl Histogram {
+    fn new(buckets: &'static [u64]) -> Self {
+        Histogram {
+            buckets,
+            counts: vec![0; buckets.len() + 1],
+            sum: 0,
+        }
+    }
+
+    fn observe(&mut self, value: u64) {
+        let index = self
+            .buckets
+            .iter()
+            .position(|x| value <= *x)
+            .unwrap_or(self.buckets.len());
+        self.counts[index] += 1;
+        self.sum += value;
+    }
+
+    fn render(&self, out: &mut String, name: &str) {
+        let mut cumulative = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            let le = match self.buckets.get(index) {
+                Some(x) => x.to_string(),
+                None => "+Inf".to_string(),
+            };
+            let _ = writeln!(out, "{name}_bucket{{le=\"{le}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "{name}_sum {}", self.sum);
+        let _ = writeln!(out, "{name}_count {cumulative}");
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            unknown_statuses: BTreeMap::new(),
+            label_limits: BTreeMap::new(),
+            webhook_body_bytes: Histogram::new(&BODY_BYTES_BUCKETS),
+            webhook_alerts: Histogram::new(&ALERTS_BUCKETS),
+            large_webhooks: BTreeMap::new(),
+            large_webhook_notified: None,
+        }
+    }
+}
+
+impl Metrics {
+    pub(crate) fn count_unknown_status(&mut self, status: &str) {
+        *self.unknown_statuses.entry(status.to_string()).or_insert(0) += 1;
+    }
+
+    pub(crate) fn count_label_limit(&mut self, limit: &'static str) {
+        *self.label_limits.entry(limit).or_insert(0) += 1;
+    }
+
+    pub(crate) fn observe_webhook(&mut self, body_bytes: usize, alerts: usize) {
+        self.webhook_body_bytes.observe(body_bytes as u64);
+        self.webhook_alerts.observe(alerts as u64);
+    }
+
+    /// Counts a webhook over a `large_webhook_*` threshold. True if it should be
+    /// notified, which is at most once an hour.
+    pub(crate) fn count_large_webhook(
+        &mut self,
+        threshold: &'static str,
+        now: DateTime<Utc>,
+    ) -> bool {
+        *self.large_webhooks.entry(threshold).or_insert(0) += 1;
+        let every = Duration::hours(LARGE_WEBHOOK_NOTIFY_EVERY_HOURS);
+        match self.large_webhook_notified {
+            Some(at) if now - at < every => false,
+            _ => {
+                self.large_webhook_notified = Some(now);
+                true
+            }
+        }
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::new();
+        out += "# HELP grafana_prowl_unknown_status_alerts_total Alerts received with a status other than firing or resolved.\n";
+        out += "# TYPE grafana_prowl_unknown_status_alerts_total counter\n";
+        for (status, count) in &self.unknown_statuses {
+            let _ = writeln!(
+                out,
+                "grafana_prowl_unknown_status_alerts_total{{status=\"{}\"}} {count}",
+                escape(status)
+            );
+        }
+        out += "# HELP grafana_prowl_label_limit_alerts_total Alerts whose labels were trimmed to fit label_limits.\n";
+        out += "# TYPE grafana_prowl_label_limit_alerts_total counter\n";
+        for (limit, count) in &self.label_limits {
+            let _ = writeln!(
+                out,
+                "grafana_prowl_label_limit_alerts_total{{limit=\"{limit}\"}} {count}"
+            );
+        }
+        out += "# HELP grafana_prowl_webhook_body_bytes Size of webhook bodies received.\n";
+        out += "# TYPE grafana_prowl_webhook_body_bytes histogram\n";
+        self.webhook_body_bytes
+            .render(&mut out, "grafana_prowl_webhook_body_bytes");
+        out += "# HELP grafana_prowl_webhook_alerts Alerts in each webhook received.\n";
+        out += "# TYPE grafana_prowl_webhook_alerts histogram\n";
+        self.webhook_alerts
+            .render(&mut out, "grafana_prowl_webhook_alerts");
+        out +=
+            "# HELP grafana_prowl_large_webhooks_total Webhooks over a large_webhook threshold.\n";
+        out += "# TYPE grafana_prowl_large_webhooks_total counter\n";
+        for (threshold, count) in &self.large_webhooks {
+            let _ = writeln!(
+                out,
+                "grafana_prowl_large_webhooks_total{{threshold=\"{threshold}\"}} {count}"
+            );
+        }
+        out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut metrics = Metrics::default();
+        metrics.count_unknown_status("pending");
+        metrics.count_unknown_status("pending");
+        metrics.count_unknown_status("odd\"one");
+        let rendered = metrics.render();
+        assert!(
+            rendered.contains("grafana_prowl_unknown_status_alerts_total{status=\"pending\"} 2\n")
+        );
+        assert!(rendered.contains("{status=\"odd\\\"one\"} 1\n"));
+
+        metrics.count_label_limit("max_labels");
+        assert!(metrics
+            .render()
+            .contains("grafana_prowl_label_limit_alerts_total{limit=\"max_labels\"} 1\n"));
+    }
+
+    #[test]
+    fn test_webhook_histograms() {
+        let mut metrics = Metrics::default();
+        metrics.observe_webhook(800, 1);
+        metrics.observe_webhook(5000, 3);
+        metrics.observe_webhook(5_000_000, 300);
+        let rendered = metrics.render();
+        for line in [
+            "grafana_prowl_webhook_body_bytes_bucket{le=\"1024\"} 1\n",
+            "grafana_prowl_webhook_body_bytes_bucket{le=\"4096\"} 1\n",
+            "grafana_prowl_webhook_body_bytes_bucket{le=\"16384\"} 2\n",
+            "grafana_prowl_webhook_body_bytes_bucket{le=\"4194304\"} 2\n",
+            "grafana_prowl_webhook_body_bytes_bucket{le=\"+Inf\"} 3\n",
+            "grafana_prowl_webhook_body_bytes_sum 5005800\n",
+            "grafana_prowl_webhook_body_bytes_count 3\n",
+            "grafana_prowl_webhook_alerts_bucket{le=\"5\"} 2\n",
+            "grafana_prowl_webhook_alerts_count 3\n",
+        ] {
+            assert!(rendered.contains(line), "{line}");
+        }
+
+        let now = Utc::now();
+        assert!(metrics.count_large_webhook("alerts", now));
+        assert!(!metrics.count_large_webhook("body_bytes", now + Duration::minutes(59)));
+        assert!(metrics.count_large_webhook("alerts", now + Duration::minutes(60)));
+        let rendered = metrics.render();
+        assert!(rendered.contains("grafana_prowl_large_webhooks_total{threshold=\"alerts\"} 2\n"));
+    }
+}
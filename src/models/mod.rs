@@ -1,4 +1,33 @@
+pub(crate) mod apprise;
+pub(crate) mod auth;
+pub(crate) mod bulk;
+pub(crate) mod cidr;
 pub(crate) mod config;
+pub(crate) mod dashboard;
+pub(crate) mod digest;
+pub(crate) mod export;
 pub(crate) mod fingerprint;
+pub(crate) mod forward_url;
+pub(crate) mod github;
 pub(crate) mod grafana;
+pub(crate) mod healthchecks;
 pub(crate) mod http;
+pub(crate) mod legacy_grafana;
+pub(crate) mod listener;
+pub(crate) mod matcher;
+pub(crate) mod metrics;
+pub(crate) mod notification;
+pub(crate) mod receiver;
+pub(crate) mod router;
+pub(crate) mod schedule;
+pub(crate) mod scheduled;
+pub(crate) mod sentry;
+pub(crate) mod silence;
+pub(crate) mod sink;
+pub(crate) mod sns;
+pub(crate) mod tenant;
+pub(crate) mod url_rewrite;
+pub(crate) mod version;
+pub(crate) mod volume;
+pub(crate) mod webhook;
+pub(crate) mod zabbix;
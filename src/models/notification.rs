@@ -0,0 +1,294 @@
+use crate::models::{
+    matcher::{Matchable, Target},
+    receiver::Receiver,
+    sink::Sink,
+};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use derive_getters::Getters;
+use prowl::{CreationError, Notification, Priority};
+
+/// What a notification says, before it is addressed to any API keys.
+#[derive(Clone, Debug)]
+pub(crate) struct NotificationContent {
+    priority: Option<Priority>,
+    url: Option<String>,
+    application: String,
+    event: String,
+    description: String,
+    /// Shown after the description in each receiver's timezone.
+    since: Option<DateTime<Utc>>,
+    /// The description's first line by name, for sinks that show them separately.
+    fields: Vec<(String, String)>,
+    /// When the alert started, or resolved.
+    time: Option<DateTime<Utc>>,
+}
+
+impl NotificationContent {
+    pub(crate) fn new(
+        priority: Option<Priority>,
+        url: Option<String>,
+        application: String,
+        event: String,
+        description: String,
+    ) -> Self {
+        NotificationContent {
+            priority,
+            url,
+            application,
+            event,
+            description,
+            since: None,
+            fields: vec![],
+            time: None,
+        }
+    }
+
+    pub(crate) fn with_since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self.time = Some(since);
+        self
+    }
+
+    pub(crate) fn with_field(mut self, name: &str, value: String) -> Self {
+        self.fields.push((name.to_string(), value));
+        self
+    }
+
+    pub(crate) fn with_time(mut self, time: Option<DateTime<Utc>>) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub(crate) fn to_prowl(&self, api_keys: Vec<String>) -> Result<Addressed, CreationError> {
+        let notification = Notification::new(
+            api_keys.clone(),
+            self.priority.clone(),
+            self.url.clone(),
+            self.application.clone(),
+            self.event.clone(),
+            self.description_in(&Tz::UTC),
+        )?;
+        Ok(Addressed {
+            api_keys,
+            notification,
+            sink: Sink::Prowl,
+            fields: self.fields.clone(),
+            time: self.time,
+            provider_key: None,
+            lowered_from: None,
+        })
+    }
+
+    /// Addressed to some of the receiver's API keys, in its timezone and no higher than
+    /// the lowest `max_priority` of the keys.
+    pub(crate) fn to_prowl_for(
+        &self,
+        api_keys: Vec<String>,
+        receiver: &Receiver,
+    ) -> Result<Addressed, CreationError> {
+        let wanted = self.priority.clone().unwrap_or(Priority::Normal);
+        let (priority, lowered_from) = match receiver.max_priority_of(&api_keys) {
+            Some(max) if priority_number(&max) < priority_number(&wanted) => {
+                (Some(max), Some(wanted))
+            }
+            _ => (self.priority.clone(), None),
+        };
+        let notification = Notification::new(
+            api_keys.clone(),
+            priority,
+            self.url.clone(),
+            self.application.clone(),
+            self.event.clone(),
+            self.description_in(receiver.timezone()),
+        )?;
+        Ok(Addressed {
+            api_keys,
+            notification,
+            sink: receiver.sink().clone(),
+            fields: self.fields.clone(),
+            time: self.time,
+            provider_key: receiver.provider_key().clone(),
+            lowered_from,
+        })
+    }
+
+    fn description_in(&self, timezone: &Tz) -> String {
+        match self.since {
+            Some(since) => {
+                let since = since.with_timezone(timezone).format("%a %d %b %H:%M %Z");
+                format!("{}\nSince {since}", self.description)
+            }
+            None => self.description.clone(),
+        }
+    }
+}
+
+/// A Prowl notification with the API keys it goes to, which `prowl::Notification`
+/// keeps to itself, and the service they are keys of.
+#[derive(Debug, Getters)]
+pub(crate) struct Addressed {
+    api_keys: Vec<String>,
+    notification: Notification,
+    sink: Sink,
+    fields: Vec<(String, String)>,
+    time: Option<DateTime<Utc>>,
+    provider_key: Option<String>,
+    /// The priority asked for, when a `max_priority` lowered it.
+    lowered_from: Option<Priority>,
+}
+
+/// `name=value` of the `notification_labels` the item has, in that order,
+/// or None if it has none of them.
+pub(crate) fn label_line<T: Matchable + ?Sized>(item: &T, names: &[String]) -> Option<String> {
+    let pairs: Vec<String> = names
+        .iter()
+        .filter_map(|name| {
+            let value = item.value_of(&Target::Label(name.clone()))?;
+            Some(format!("{name}={value}"))
+        })
+        .collect();
+    match pairs.is_empty() {
+        true => None,
+        false => Some(pairs.join(", ")),
+    }
+}
+
+/// Like `2h 13m`, `3d 4h` or `45s`, as precise as a glance at the phone needs.
+pub(crate) fn duration_text(duration: chrono::Duration) -> String {
+    let secs = duration.num_seconds().max(0);
+    let (days, hours, minutes) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{secs}s"),
+        (0, 0, _) => format!("{minutes}m"),
+        (0, _, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h"),
+    }
+}
+
+/// The number Prowl's API uses for a priority, -2 to 2.
+pub(crate) fn priority_number(priority: &Priority) -> i8 {
+    match priority {
+        Priority::VeryLow => -2,
+        Priority::Moderate => -1,
+        Priority::Normal => 0,
+        Priority::High => 1,
+        Priority::Emergency => 2,
+    }
+}
+
+impl Addressed {
+    /// What it says, to address it to someone else. The description keeps its time
+    /// in the first receiver's timezone.
+    pub(crate) fn content(&self) -> NotificationContent {
+        let notification = &self.notification;
+        NotificationContent {
+            priority: self
+                .lowered_from
+                .clone()
+                .or_else(|| notification.priority().clone()),
+            url: notification.url().clone(),
+            application: notification.application().clone(),
+            event: notification.event().clone(),
+            description: notification.description().clone(),
+            since: None,
+            fields: self.fields.clone(),
+            time: self.time,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn into_notification(self) -> Notification {
+        self.notification
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn since_in_timezone() {
+        let since = "2022-09-15T12:05:00Z".parse().expect("Bad timestamp");
+        let content = NotificationContent::new(
+            None,
+            None,
+            "Grafana".to_string(),
+            "[🕓] Disk full".to_string(),
+            "Disk full is still firing.".to_string(),
+        )
+        .with_since(since);
+        let key = vec!["key".to_string()];
+        for (timezone, expected) in [
+            ("UTC", "Since Thu 15 Sep 12:05 UTC"),
+            ("Europe/Berlin", "Since Thu 15 Sep 14:05 CEST"),
+            ("America/Los_Angeles", "Since Thu 15 Sep 05:05 PDT"),
+        ] {
+            let receiver: Receiver = serde_json::from_str(&format!(
+                r#"{{"api_keys": ["key"], "timezone": "{timezone}"}}"#
+            ))
+            .expect("Failed to parse receiver");
+            let addressed = content
+                .to_prowl_for(key.clone(), &receiver)
+                .expect("Failed to create notification");
+            assert_eq!(
+                addressed.notification().description(),
+                &format!("Disk full is still firing.\n{expected}")
+            );
+        }
+    }
+
+    #[test]
+    fn durations() {
+        let text = |secs| duration_text(chrono::Duration::seconds(secs));
+        assert_eq!(text(45), "45s");
+        assert_eq!(text(13 * 60 + 5), "13m");
+        assert_eq!(text(2 * 3600 + 13 * 60), "2h 13m");
+        assert_eq!(text(3 * 86400 + 4 * 3600 + 59), "3d 4h");
+        assert_eq!(text(-5), "0s");
+    }
+
+    #[test]
+    fn max_priority() {
+        let receiver: Receiver = serde_json::from_str(
+            r#"{
+                "api_keys": ["mine", "shared"],
+                "provider_key": "provider",
+                "max_priority": {"shared": "High"}
+            }"#,
+        )
+        .expect("Failed to parse receiver");
+        let content = |priority| {
+            NotificationContent::new(
+                priority,
+                None,
+                "Grafana".to_string(),
+                "Disk full".to_string(),
+                "firing".to_string(),
+            )
+        };
+        let keys = |x: &str| vec![x.to_string()];
+
+        let addressed = content(Some(Priority::Emergency))
+            .to_prowl_for(keys("shared"), &receiver)
+            .expect("Failed to create notification");
+        assert_eq!(addressed.notification().priority(), &Some(Priority::High));
+        assert_eq!(addressed.lowered_from(), &Some(Priority::Emergency));
+        assert_eq!(addressed.provider_key().as_deref(), Some("provider"));
+
+        let addressed = content(Some(Priority::Emergency))
+            .to_prowl_for(keys("mine"), &receiver)
+            .expect("Failed to create notification");
+        assert_eq!(
+            addressed.notification().priority(),
+            &Some(Priority::Emergency)
+        );
+        assert_eq!(addressed.lowered_from(), &None);
+
+        let addressed = content(None)
+            .to_prowl_for(keys("shared"), &receiver)
+            .expect("Failed to create notification");
+        assert_eq!(addressed.notification().priority(), &None);
+        assert_eq!(addressed.lowered_from(), &None);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{
+    errors::AppriseError,
+    models::{apprise, matcher::Matcher, notification::priority_number, sink::Sink},
+};
+use chrono_tz::Tz;
+use derive_getters::Getters;
+use prowl::Priority;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// A group of Prowl API keys, or another service's keys, that are notified together.
+#[derive(Clone, Debug, Deserialize, Getters)]
+#[serde(try_from = "ReceiverConfig")]
+pub(crate) struct Receiver {
+    api_keys: Vec<String>,
+    sink: Sink,
+    delivery: Delivery,
+    /// Times in its notifications are shown in this zone, like `Europe/Berlin`.
+    timezone: Tz,
+    /// Sent to Prowl as `providerkey`, for a higher rate limit.
+    provider_key: Option<String>,
+    /// The highest priority each API key may receive, higher ones are lowered to it.
+    max_priority: HashMap<String, Priority>,
+}
+
+/// A receiver as written in the config, with `api_keys` and a `sink`, or an Apprise `url`.
+#[derive(Deserialize)]
+struct ReceiverConfig {
+    #[serde(default = "Vec::new")]
+    api_keys: Vec<String>,
+    sink: Option<Sink>,
+    url: Option<String>,
+    #[serde(default = "default_delivery")]
+    delivery: Delivery,
+    #[serde(default = "default_timezone")]
+    timezone: Tz,
+    provider_key: Option<String>,
+    #[serde(default = "HashMap::new")]
+    max_priority: HashMap<String, Priority>,
+}
+
+impl TryFrom<ReceiverConfig> for Receiver {
+    type Error = AppriseError;
+
+    fn try_from(config: ReceiverConfig) -> Result<Self, Self::Error> {
+        let (api_keys, sink, provider_key) = match config.url {
+            Some(_) if !config.api_keys.is_empty() || config.sink.is_some() => {
+                return Err(AppriseError::Both)
+            }
+            Some(url) => {
+                let apprise = apprise::parse(&url)?;
+                let provider_key = config.provider_key.or(apprise.provider_key);
+                (apprise.api_keys, apprise.sink, provider_key)
+            }
+            None => (
+                config.api_keys,
+                config.sink.unwrap_or_default(),
+                config.provider_key,
+            ),
+        };
+        Ok(Receiver {
+            api_keys,
+            sink,
+            delivery: config.delivery,
+            timezone: config.timezone,
+            provider_key,
+            max_priority: config.max_priority,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Delivery {
+    /// One request to Prowl with every API key.
+    Together,
+    /// A request per API key, each retried until it is delivered.
+    All,
+    /// A request per API key, delivered once any of them succeeds.
+    Any,
+}
+
+/// Sends alerts matching every matcher to the named receiver, or straight to Prowl API keys.
+#[derive(Clone, Debug, Deserialize, Getters)]
+pub(crate) struct Route {
+    #[serde(default = "Vec::new")]
+    matchers: Vec<Matcher>,
+    receiver: Option<String>,
+    /// Prowl API keys, each sent to like a receiver's with the `all` delivery.
+    #[serde(default = "Vec::new")]
+    api_keys: Vec<String>,
+}
+
+fn default_delivery() -> Delivery {
+    Delivery::All
+}
+
+fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+impl Receiver {
+    pub(crate) fn new(api_keys: Vec<String>, delivery: Delivery) -> Self {
+        Receiver {
+            api_keys,
+            delivery,
+            sink: Sink::Prowl,
+            timezone: default_timezone(),
+            provider_key: None,
+            max_priority: HashMap::new(),
+        }
+    }
+
+    /// Uses `provider_key` unless the receiver has its own.
+    pub(crate) fn or_provider_key(mut self, provider_key: &Option<String>) -> Self {
+        if self.provider_key.is_none() {
+            self.provider_key = provider_key.clone();
+        }
+        self
+    }
+
+    /// The lowest `max_priority` of the keys, None if none of them are capped.
+    pub(crate) fn max_priority_of(&self, api_keys: &[String]) -> Option<Priority> {
+        api_keys
+            .iter()
+            .filter_map(|key| self.max_priority.get(key))
+            .min_by_key(|x| priority_number(x))
+            .cloned()
+    }
+
+    /// The API keys split by their `max_priority`, in order, so each group can be sent
+    /// together without lowering the priority for keys that allow more.
+    pub(crate) fn keys_by_max_priority(&self) -> Vec<Vec<String>> {
+        let mut groups: Vec<(Option<i8>, Vec<String>)> = vec![];
+        for key in &self.api_keys {
+            let cap = self.max_priority.get(key).map(priority_number);
+            match groups.iter_mut().find(|(x, _)| *x == cap) {
+                Some((_, keys)) => keys.push(key.clone()),
+                None => groups.push((cap, vec![key.clone()])),
+            }
+        }
+        groups.into_iter().map(|(_, keys)| keys).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn priority_caps() {
+        let receiver: Receiver = serde_json::from_str(
+            r#"{
+                "api_keys": ["mine", "shared", "kids", "partner"],
+                "delivery": "together",
+                "max_priority": {"shared": "High", "kids": "Normal", "partner": "High"}
+            }"#,
+        )
+        .expect("Failed to parse receiver");
+        let keys = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            receiver.keys_by_max_priority(),
+            vec![
+                keys(&["mine"]),
+                keys(&["shared", "partner"]),
+                keys(&["kids"])
+            ]
+        );
+        assert_eq!(receiver.max_priority_of(&keys(&["mine"])), None);
+        assert_eq!(
+            receiver.max_priority_of(&keys(&["shared", "kids"])),
+            Some(Priority::Normal)
+        );
+    }
+
+    #[test]
+    fn apprise_url() {
+        let receiver: Receiver =
+            serde_json::from_str(r#"{"url": "prowl://apikey/providerkey", "delivery": "any"}"#)
+                .expect("Failed to parse receiver");
+        assert_eq!(receiver.api_keys(), &vec!["apikey"]);
+        assert_eq!(receiver.sink(), &Sink::Prowl);
+        assert_eq!(receiver.provider_key(), &Some("providerkey".to_string()));
+        assert_eq!(receiver.delivery(), &Delivery::Any);
+
+        let error = serde_json::from_str::<Receiver>(
+            r#"{"url": "slack://T0/B0/xyz", "api_keys": ["https://hooks.slack.com/services/T0/B0/xyz"]}"#,
+        )
+        .expect_err("Parsed both");
+        assert!(
+            error.to_string().starts_with("A receiver has both"),
+            "{error}"
+        );
+    }
+}
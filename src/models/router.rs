@@ -0,0 +1,185 @@
+/// Finds what handles a request by method and path. Path segments written
+/// `{name}` match any one segment, which the handler reads from `Params`.
+/// A last segment of `*` matches the rest of the path, read as `*`.
+pub(crate) struct Router<T> {
+    routes: Vec<Route<T>>,
+}
+
+struct Route<T> {
+    method: &'static str,
+    segments: Vec<Segment>,
+    target: T,
+}
+
+enum Segment {
+    Literal(String),
+    Param(String),
+    Rest,
+}
+
+/// The path parameters of a matched route.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Params {
+    values: Vec<(String, String)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Match<T> {
+    Found(T, Params),
+    /// The path is known but not with this method, with the methods it has.
+    MethodNotAllowed(Vec<&'static str>),
+    NotFound,
+}
+
+impl<T: Copy> Router<T> {
+    pub(crate) fn new() -> Self {
+        Self { routes: vec![] }
+    }
+
+    pub(crate) fn route(mut self, method: &'static str, path: &str, target: T) -> Self {
+        let segments = split(path)
+            .map(|x| {
+                if x == "*" {
+                    return Segment::Rest;
+                }
+                match x.strip_prefix('{').and_then(|x| x.strip_suffix('}')) {
+                    Some(name) => Segment::Param(name.to_string()),
+                    None => Segment::Literal(x.to_string()),
+                }
+            })
+            .collect();
+        self.routes.push(Route {
+            method,
+            segments,
+            target,
+        });
+        self
+    }
+
+    /// Routes without `*` win, then those with the fewest parameters, so
+    /// `/api/items/bulk` isn't read as an id, then the first registered for the method.
+    pub(crate) fn find(&self, method: &str, path: &str) -> Match<T> {
+        let matched: Vec<(&Route<T>, Params)> = self
+            .routes
+            .iter()
+            .filter_map(|route| route.matches(path).map(|params| (route, params)))
+            .collect();
+        let specificity =
+            |(route, params): &(&Route<T>, Params)| (route.has_rest(), params.values.len());
+        let best = match matched.iter().map(specificity).min() {
+            Some(x) => x,
+            None => return Match::NotFound,
+        };
+        let mut allowed = vec![];
+        for matched in matched {
+            if specificity(&matched) != best {
+                continue;
+            }
+            let (route, params) = matched;
+            if route.method == method {
+                return Match::Found(route.target, params);
+            }
+            if !allowed.contains(&route.method) {
+                allowed.push(route.method);
+            }
+        }
+        Match::MethodNotAllowed(allowed)
+    }
+}
+
+impl<T> Route<T> {
+    fn has_rest(&self) -> bool {
+        matches!(self.segments.last(), Some(Segment::Rest))
+    }
+
+    fn matches(&self, path: &str) -> Option<Params> {
+        let mut values = vec![];
+        let mut parts = split(path);
+        for segment in &self.segments {
+            let part = parts.next()?;
+            match segment {
+                Segment::Literal(x) if x == part => {}
+                Segment::Literal(_) => return None,
+                Segment::Param(_) | Segment::Rest if part.is_empty() => return None,
+                Segment::Param(name) => values.push((name.clone(), part.to_string())),
+                Segment::Rest => {
+                    let rest = parts.fold(part.to_string(), |rest, x| format!("{rest}/{x}"));
+                    values.push(("*".to_string(), rest));
+                    return Some(Params { values });
+                }
+            }
+        }
+        match parts.next() {
+            Some(_) => None,
+            None => Some(Params { values }),
+        }
+    }
+}
+
+/// `/` is one empty segment, so it doesn't match `/{id}`.
+fn split(path: &str) -> impl Iterator<Item = &str> {
+    path.strip_prefix('/').unwrap_or(path).split('/')
+}
+
+impl Params {
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let router = Router::new()
+            .route("GET", "/", 1)
+            .route("GET", "/api/items", 2)
+            .route("DELETE", "/api/items/{id}", 3)
+            .route("POST", "/api/items/{id}/retry-now", 4)
+            .route("GET", "/api/items/{id}", 5)
+            .route("POST", "/api/items/bulk", 6)
+            .route("POST", "/hooks/*", 7)
+            .route("POST", "/hooks/{name}/test", 8);
+
+        assert!(matches!(router.find("GET", "/"), Match::Found(1, _)));
+        assert!(matches!(
+            router.find("GET", "/api/items"),
+            Match::Found(2, _)
+        ));
+        match router.find("POST", "/api/items/42/retry-now") {
+            Match::Found(4, params) => assert_eq!(params.get("id"), Some("42")),
+            other => panic!("Unexpected {other:?}"),
+        }
+        assert_eq!(
+            router.find("PUT", "/api/items/42"),
+            Match::MethodNotAllowed(vec!["DELETE", "GET"])
+        );
+        assert_eq!(
+            router.find("GET", "/api/items/bulk"),
+            Match::MethodNotAllowed(vec!["POST"])
+        );
+        assert!(matches!(
+            router.find("POST", "/api/items/bulk"),
+            Match::Found(6, _)
+        ));
+        assert_eq!(router.find("GET", "/api/items/"), Match::NotFound);
+        assert_eq!(router.find("GET", "/api/items/42/other"), Match::NotFound);
+        assert_eq!(router.find("GET", "/api"), Match::NotFound);
+
+        match router.find("POST", "/hooks/team/a/b") {
+            Match::Found(7, params) => assert_eq!(params.get("*"), Some("team/a/b")),
+            other => panic!("Unexpected {other:?}"),
+        }
+        assert!(matches!(
+            router.find("POST", "/hooks/team/test"),
+            Match::Found(8, _)
+        ));
+        assert_eq!(router.find("POST", "/hooks/"), Match::NotFound);
+        assert_eq!(router.find("POST", "/hooks"), Match::NotFound);
+    }
+}
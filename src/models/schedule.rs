@@ -0,0 +1,274 @@
+use crate::{clock::Clock, errors::ScheduleError};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+
+/// The five cron fields, in order, with the values each allows.
+const CRON_FIELDS: [(&str, u32, u32); 5] = [
+    ("minute", 0, 59),
+    ("hour", 0, 23),
+    ("day of month", 1, 31),
+    ("month", 1, 12),
+    ("day of week", 0, 6),
+];
+
+/// A UTC crontab schedule, checked when parsed so running it can't fail on its syntax.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Cron {
+    expression: String,
+}
+
+impl Cron {
+    pub(crate) fn parse(expression: &str) -> Result<Cron, ScheduleError> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != CRON_FIELDS.len() {
+            return Err(ScheduleError::CronFieldCount(
+                expression.to_string(),
+                fields.len(),
+            ));
+        }
+        for (field, (name, min, max)) in fields.iter().zip(CRON_FIELDS) {
+            let in_range = cron_parser::parse_field(field, min, max)
+                .is_ok_and(|values| values.iter().all(|x| (min..=max).contains(x)));
+            if !in_range {
+                return Err(ScheduleError::BadCronField(
+                    expression.to_string(),
+                    name,
+                    field.to_string(),
+                    min,
+                    max,
+                ));
+            }
+        }
+        let cron = Cron {
+            expression: expression.to_string(),
+        };
+        // catches what the fields can't, like dates that never happen
+        cron.next_after(Utc::now())?;
+        Ok(cron)
+    }
+
+    /// The first time the schedule matches, from the minute after `now`.
+    pub(crate) fn next_after(&self, now: DateTime<Utc>) -> Result<DateTime<Utc>, ScheduleError> {
+        cron_parser::parse(&self.expression, &now)
+            .map_err(|e| ScheduleError::BadCron(self.expression.clone(), e))
+    }
+
+    /// How long to sleep until the next match, None if it can't be worked out.
+    pub(crate) fn time_until_next(&self, clock: &dyn Clock) -> Option<std::time::Duration> {
+        let now = clock.now();
+        let next = match self.next_after(now) {
+            Ok(x) => x,
+            Err(e) => {
+                log::error!("{e}");
+                return None;
+            }
+        };
+        match (next - now).to_std() {
+            Ok(wait) => {
+                log::trace!("{:?} until next run of '{}'", wait, self.expression);
+                Some(wait)
+            }
+            Err(e) => {
+                log::error!("Next run of '{}' is in the past, {e}", self.expression);
+                None
+            }
+        }
+    }
+}
+
+/// A length of time, given as a number of minutes or a string like `90m`, `2h30m` or `1d`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "MinutesOrText")]
+pub(crate) struct Span(Duration);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MinutesOrText {
+    Minutes(i64),
+    Text(String),
+}
+
+impl TryFrom<MinutesOrText> for Span {
+    type Error = ScheduleError;
+
+    fn try_from(value: MinutesOrText) -> Result<Self, Self::Error> {
+        match value {
+            // Duration::minutes panics past its bounds
+            MinutesOrText::Minutes(x) => x
+                .checked_mul(60_000)
+                .map(|x| Span(Duration::milliseconds(x)))
+                .ok_or_else(|| ScheduleError::BadDuration(x.to_string())),
+            MinutesOrText::Text(x) => parse_duration(&x).map(Span),
+        }
+    }
+}
+
+impl Span {
+    pub(crate) fn minutes(minutes: i64) -> Span {
+        Span(Duration::minutes(minutes))
+    }
+
+    pub(crate) fn duration(&self) -> Duration {
+        self.0
+    }
+}
+
+/// Parses durations like `45s`, `90m`, `2h30m` or `1d12h`. Each unit can be used
+/// once, largest first.
+pub(crate) fn parse_duration(text: &str) -> Result<Duration, ScheduleError> {
+    let bad = || ScheduleError::BadDuration(text.to_string());
+    let units = [
+        ('d', Duration::days(1)),
+        ('h', Duration::hours(1)),
+        ('m', Duration::minutes(1)),
+        ('s', Duration::seconds(1)),
+    ];
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return Err(bad());
+    }
+    let mut total = Duration::zero();
+    let mut next_unit = 0;
+    while !rest.is_empty() {
+        let digits = rest.find(|x: char| !x.is_ascii_digit()).ok_or_else(bad)?;
+        let value: i64 = rest[..digits].parse().map_err(|_| bad())?;
+        let unit = rest[digits..].chars().next().ok_or_else(bad)?;
+        let index = units[next_unit..]
+            .iter()
+            .position(|(x, _)| *x == unit)
+            .ok_or_else(bad)?;
+        let (_, size) = units[next_unit + index];
+        total = total + size * i32::try_from(value).map_err(|_| bad())?;
+        next_unit += index + 1;
+        rest = &rest[digits + unit.len_utf8()..];
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::MockClock;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().expect("Failed to parse time")
+    }
+
+    #[test]
+    fn cron_next_after() {
+        let cron = Cron::parse("0 9 * * MON-FRI").expect("Failed to parse cron");
+        // Friday after 9 goes to Monday
+        assert_eq!(
+            cron.next_after(at("2022-09-09T10:00:00Z")).ok(),
+            Some(at("2022-09-12T09:00:00Z"))
+        );
+        // a match on the current minute is skipped, the caller just ran it
+        assert_eq!(
+            cron.next_after(at("2022-09-12T09:00:00Z")).ok(),
+            Some(at("2022-09-13T09:00:00Z"))
+        );
+
+        let cron = Cron::parse("30 23 31 12 *").expect("Failed to parse cron");
+        assert_eq!(
+            cron.next_after(at("2022-12-31T23:45:00Z")).ok(),
+            Some(at("2023-12-31T23:30:00Z"))
+        );
+
+        let cron = Cron::parse("0 0 29 2 *").expect("Failed to parse cron");
+        assert_eq!(
+            cron.next_after(at("2022-03-01T00:00:00Z")).ok(),
+            Some(at("2024-02-29T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn cron_ignores_daylight_saving() {
+        // schedules are UTC, so they don't move when Europe changes its clocks
+        let cron = Cron::parse("0 1 * * *").expect("Failed to parse cron");
+        for (now, next) in [
+            ("2022-03-26T12:00:00Z", "2022-03-27T01:00:00Z"),
+            ("2022-03-27T12:00:00Z", "2022-03-28T01:00:00Z"),
+            ("2022-10-29T12:00:00Z", "2022-10-30T01:00:00Z"),
+        ] {
+            assert_eq!(cron.next_after(at(now)).ok(), Some(at(next)), "{now}");
+        }
+        let clock = MockClock::new(at("2022-10-30T00:30:00Z"));
+        assert_eq!(
+            cron.time_until_next(&clock),
+            Some(std::time::Duration::from_secs(30 * 60))
+        );
+    }
+
+    #[test]
+    fn cron_errors() {
+        for (expression, error) in [
+            (
+                "0 9 * *",
+                "Cron '0 9 * *' has 4 fields, expected 5: minute hour day-of-month month day-of-week.",
+            ),
+            (
+                "0 9 * * * *",
+                "Cron '0 9 * * * *' has 6 fields, expected 5: minute hour day-of-month month day-of-week.",
+            ),
+            (
+                "61 * * * *",
+                "Cron '61 * * * *' has minute '61', which must be within 0-59.",
+            ),
+            (
+                "0 9 0 * *",
+                "Cron '0 9 0 * *' has day of month '0', which must be within 1-31.",
+            ),
+            (
+                "0 9 * * Funday",
+                "Cron '0 9 * * Funday' has day of week 'Funday', which must be within 0-6.",
+            ),
+            (
+                "0 0 31 2 *",
+                "Cron '0 0 31 2 *' could not be parsed. invalid cron",
+            ),
+        ] {
+            let result = Cron::parse(expression);
+            assert_eq!(
+                result.map_err(|e| e.to_string()),
+                Err(error.to_string()),
+                "{expression}"
+            );
+        }
+        assert!(Cron::parse("*/15 9-17 * 1,6 SUN").is_ok());
+    }
+
+    #[test]
+    fn durations() {
+        for (text, expected) in [
+            ("90m", Duration::minutes(90)),
+            ("2h30m", Duration::minutes(150)),
+            ("1d", Duration::days(1)),
+            ("1d12h", Duration::hours(36)),
+            ("45s", Duration::seconds(45)),
+            (" 5m ", Duration::minutes(5)),
+            ("0m", Duration::zero()),
+        ] {
+            assert_eq!(parse_duration(text).ok(), Some(expected), "{text}");
+        }
+        for text in [
+            "",
+            "90",
+            "m",
+            "30m2h",
+            "1h1h",
+            "1w",
+            "-5m",
+            "1.5h",
+            "99999999999d",
+        ] {
+            assert!(parse_duration(text).is_err(), "{text}");
+        }
+
+        let span: Span = serde_json::from_str("45").expect("Failed to parse minutes");
+        assert_eq!(span, Span::minutes(45));
+        let span: Span = serde_json::from_str("\"2h\"").expect("Failed to parse text");
+        assert_eq!(span.duration(), Duration::hours(2));
+        let error = serde_json::from_str::<Span>("\"soon\"").expect_err("Parsed a bad span");
+        assert!(error.to_string().contains("'soon' is not a duration"));
+    }
+}
@@ -0,0 +1,19 @@
+use derive_getters::Getters;
+use prowl::Priority;
+use serde::Deserialize;
+
+/// A reminder sent on a cron schedule, not tied to any Grafana alert.
+#[derive(Clone, Debug, Deserialize, Getters)]
+pub(crate) struct ScheduledNotification {
+    cron: String,
+    title: String,
+    #[serde(default = "String::new")]
+    message: String,
+    #[serde(default = "default_priority")]
+    priority: Priority,
+    receiver: Option<String>,
+}
+
+fn default_priority() -> Priority {
+    Priority::Normal
+}
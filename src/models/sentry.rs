@@ -0,0 +1,130 @@
+use crate::models::grafana::{Alert, Message};
+use prowl::Priority;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// What Sentry's webhooks integration posts for an issue alert. Every event of an issue
+/// has the same fingerprint, so only the first is notified. Sentry never says an
+/// issue is resolved, the fingerprint stays firing until it is deleted.
+#[derive(Deserialize)]
+pub(crate) struct SentryIssue {
+    /// The issue's ID, a string or a number depending on Sentry's version.
+    id: serde_json::Value,
+    project_name: Option<String>,
+    project: Option<String>,
+    /// `fatal`, `error`, `warning`, `info` or `debug`.
+    level: Option<String>,
+    culprit: Option<String>,
+    message: Option<String>,
+    /// The issue's page.
+    url: Option<String>,
+    event: Option<SentryEvent>,
+}
+
+#[derive(Deserialize)]
+struct SentryEvent {
+    title: Option<String>,
+    environment: Option<String>,
+}
+
+/// Sentry's level, `None` for levels it doesn't have.
+fn priority(level: &str) -> Option<Priority> {
+    match level {
+        "fatal" => Some(Priority::Emergency),
+        "error" => Some(Priority::High),
+        "warning" => Some(Priority::Normal),
+        "info" => Some(Priority::Moderate),
+        "debug" => Some(Priority::VeryLow),
+        _ => None,
+    }
+}
+
+impl From<SentryIssue> for Message {
+    fn from(issue: SentryIssue) -> Self {
+        let text = |x: Option<String>| x.map(|x| x.trim().to_string()).filter(|x| !x.is_empty());
+        let id = match issue.id {
+            serde_json::Value::String(x) => x,
+            other => other.to_string(),
+        };
+        let event = issue.event;
+        let (title, environment) = match event {
+            Some(x) => (text(x.title), text(x.environment)),
+            None => (None, None),
+        };
+        let message = text(issue.message);
+        let name = title
+            .or_else(|| message.clone())
+            .unwrap_or_else(|| format!("Sentry issue {id}"));
+        let level = text(issue.level);
+        let mut labels = BTreeMap::from([("alertname".to_string(), name)]);
+        let project = text(issue.project_name).or(text(issue.project));
+        for (label, value) in [
+            ("project", project),
+            ("environment", environment),
+            ("level", level.clone()),
+        ] {
+            if let Some(value) = value {
+                labels.insert(label.to_string(), value);
+            }
+        }
+        let annotations = text(issue.culprit)
+            .or(message)
+            .map(|x| ("summary".to_string(), x))
+            .into_iter()
+            .collect();
+        let mut alert = Alert::new("firing", format!("sentry-{id}"), labels, annotations)
+            .with_generator_url(issue.url.unwrap_or_default());
+        if let Some(priority) = level.as_deref().and_then(priority) {
+            alert = alert.with_priority(priority);
+        }
+        Message::new(vec![alert])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts() {
+        let issue: SentryIssue = serde_json::from_str(
+            r#"{
+                "id": "1117540176",
+                "project": "checkout",
+                "project_name": "Checkout",
+                "project_slug": "checkout",
+                "logger": null,
+                "level": "fatal",
+                "culprit": "app.payments in charge",
+                "message": "",
+                "url": "https://sentry.io/organizations/shop/issues/1117540176/?referrer=webhooks_plugin",
+                "triggering_rules": ["Every new issue"],
+                "event": {
+                    "event_id": "e4874d664c3540c1a32eab185f12c5ab",
+                    "level": "fatal",
+                    "title": "ZeroDivisionError: division by zero",
+                    "environment": "production"
+                }
+            }"#,
+        )
+        .expect("Failed to load issue");
+        let message = Message::from(issue);
+        let alert = &message.alerts()[0];
+        assert_eq!(alert.fingerprint(), "sentry-1117540176");
+        assert_eq!(alert.name(), "ZeroDivisionError: division by zero");
+        assert_eq!(alert.summary(), "app.payments in charge");
+        assert_eq!(alert.get_priority(), Priority::Emergency);
+        assert_eq!(alert.labels().get("project"), Some("Checkout"));
+        assert_eq!(alert.labels().get("environment"), Some("production"));
+        assert!(alert.generator_url().starts_with("https://sentry.io/"));
+
+        let issue: SentryIssue =
+            serde_json::from_str(r#"{"id": 42, "level": "error", "message": "Timeout"}"#)
+                .expect("Failed to load issue");
+        let message = Message::from(issue);
+        let alert = &message.alerts()[0];
+        assert_eq!(alert.fingerprint(), "sentry-42");
+        assert_eq!(alert.name(), "Timeout");
+        assert_eq!(alert.get_priority(), Priority::High);
+    }
+}
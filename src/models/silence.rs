@@ -0,0 +1,546 @@
+use crate::{
+    errors::{MatcherError, SilenceError},
+    models::{
+        config::Config,
+        fingerprint::first_revision,
+        matcher::{Matchable, Matcher, Target},
+        schedule::Span,
+        tenant::Tenant,
+    },
+};
+use chrono::{DateTime, Utc};
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counts silences made since starting, so two made at once still get their own ID.
+static CREATED: AtomicU64 = AtomicU64::new(0);
+
+/// Suppresses notifications for matching alerts between `starts_at` and `ends_at`.
+/// Stored and exchanged in the same JSON format as Alertmanager silences.
+#[derive(Clone, Debug, Deserialize, Serialize, Getters)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Silence {
+    id: String,
+    matchers: Vec<SilenceMatcher>,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    #[serde(default = "String::new")]
+    created_by: String,
+    #[serde(default = "String::new")]
+    comment: String,
+}
+
+/// An Alertmanager silence matcher, such as `{"name": "alertname", "value": "Disk", "isRegex": false}`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "AlertmanagerMatcher", into = "AlertmanagerMatcher")]
+pub(crate) struct SilenceMatcher {
+    source: AlertmanagerMatcher,
+    matcher: Matcher,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AlertmanagerMatcher {
+    name: String,
+    value: String,
+    is_regex: bool,
+    #[serde(default = "default_is_equal")]
+    is_equal: bool,
+}
+
+/// A silence as Alertmanager exports it, with its current state.
+#[derive(Serialize)]
+pub(crate) struct ExportedSilence<'a> {
+    #[serde(flatten)]
+    silence: &'a Silence,
+    status: SilenceStatus,
+}
+
+#[derive(Serialize)]
+pub(crate) struct SilenceStatus {
+    state: &'static str,
+}
+
+/// `POST /api/silences`, for an alertname or fingerprint, optionally a regex, or any
+/// matchers. It lasts until `ends_at`, or for `minutes` from `starts_at`.
+#[derive(Debug, Deserialize)]
+pub(crate) struct SilenceRequest {
+    alertname: Option<String>,
+    fingerprint: Option<String>,
+    #[serde(default = "bool::default")]
+    regex: bool,
+    #[serde(default = "Vec::new")]
+    matchers: Vec<Matcher>,
+    starts_at: Option<DateTime<Utc>>,
+    ends_at: Option<DateTime<Utc>>,
+    #[serde(default = "default_minutes")]
+    minutes: Span,
+    #[serde(default = "String::new")]
+    created_by: String,
+    #[serde(default = "String::new")]
+    comment: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Silences {
+    data: Vec<Silence>,
+    #[serde(skip, default = "first_revision")]
+    revision: u64,
+}
+
+impl Default for Silences {
+    fn default() -> Self {
+        Silences {
+            data: vec![],
+            revision: first_revision(),
+        }
+    }
+}
+
+fn default_is_equal() -> bool {
+    true
+}
+
+fn default_minutes() -> Span {
+    Span::minutes(60)
+}
+
+impl SilenceRequest {
+    /// Only silences the tenant's alerts, whatever `@tenant` matchers it was sent with.
+    pub(crate) fn for_tenant(mut self, tenant: &str) -> Result<Self, MatcherError> {
+        self.matchers.retain(|x| x.target() != &Target::Tenant);
+        self.matchers.push(Tenant::matcher(tenant)?);
+        Ok(self)
+    }
+
+    /// Starts now unless `starts_at` is given.
+    pub(crate) fn into_silence(self, now: DateTime<Utc>) -> Result<Silence, SilenceError> {
+        let operator = match self.regex {
+            true => "=~",
+            false => "=",
+        };
+        let mut matchers = self.matchers;
+        for (name, value) in [
+            ("alertname", self.alertname),
+            ("@fingerprint", self.fingerprint),
+        ] {
+            if let Some(value) = value {
+                matchers.push(Matcher::parse(&format!("{name}{operator}\"{value}\""))?);
+            }
+        }
+        if matchers.is_empty() {
+            return Err(SilenceError::NothingToSilence);
+        }
+        let starts_at = self.starts_at.unwrap_or(now);
+        let ends_at = match self.ends_at {
+            Some(x) => x,
+            None => starts_at
+                .checked_add_signed(self.minutes.duration())
+                .ok_or(SilenceError::TooLong)?,
+        };
+        if ends_at <= starts_at {
+            return Err(SilenceError::EndsBeforeStart);
+        }
+        Ok(Silence::new(
+            &matchers,
+            starts_at,
+            ends_at,
+            self.created_by,
+            self.comment,
+        )?)
+    }
+}
+
+impl Silence {
+    /// A silence over `matchers`, which must compare values since Alertmanager
+    /// can't express presence checks.
+    pub(crate) fn new(
+        matchers: &[Matcher],
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        created_by: String,
+        comment: String,
+    ) -> Result<Self, MatcherError> {
+        let matchers = matchers
+            .iter()
+            .map(|matcher| {
+                let (name, value, is_regex, is_equal) = matcher
+                    .alertmanager_parts()
+                    .ok_or_else(|| MatcherError::NotSilenceable(matcher.source().to_string()))?;
+                SilenceMatcher::try_from(AlertmanagerMatcher {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                    is_regex,
+                    is_equal,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // from when it was made rather than starts_at, which API clients often share
+        let id = format!(
+            "{:x}-{:x}",
+            Utc::now().timestamp_nanos(),
+            CREATED.fetch_add(1, Ordering::Relaxed)
+        );
+        Ok(Silence {
+            id,
+            matchers,
+            starts_at,
+            ends_at,
+            created_by,
+            comment,
+        })
+    }
+
+    /// The tenant it was made by, which its `@tenant` matcher limits it to.
+    pub(crate) fn tenant(&self) -> Option<&str> {
+        self.matchers
+            .iter()
+            .find(|x| x.source.name == "@tenant" && x.source.is_equal && !x.source.is_regex)
+            .map(|x| x.source.value.as_str())
+    }
+
+    pub(crate) fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.starts_at <= now && now < self.ends_at
+    }
+
+    pub(crate) fn matches<T: Matchable + ?Sized>(&self, item: &T) -> bool {
+        self.matchers.iter().all(|x| x.matcher.matches(item))
+    }
+
+    fn exported(&self, now: DateTime<Utc>) -> ExportedSilence<'_> {
+        ExportedSilence {
+            silence: self,
+            status: SilenceStatus {
+                state: self.state(now),
+            },
+        }
+    }
+
+    fn state(&self, now: DateTime<Utc>) -> &'static str {
+        if now >= self.ends_at {
+            "expired"
+        } else if now < self.starts_at {
+            "pending"
+        } else {
+            "active"
+        }
+    }
+}
+
+impl TryFrom<AlertmanagerMatcher> for SilenceMatcher {
+    type Error = MatcherError;
+
+    fn try_from(source: AlertmanagerMatcher) -> Result<Self, Self::Error> {
+        let operator = match (source.is_equal, source.is_regex) {
+            (true, false) => "=",
+            (false, false) => "!=",
+            (true, true) => "=~",
+            (false, true) => "!~",
+        };
+        let matcher = Matcher::parse(&format!("{}{operator}\"{}\"", source.name, source.value))?;
+        Ok(SilenceMatcher { source, matcher })
+    }
+}
+
+impl From<SilenceMatcher> for AlertmanagerMatcher {
+    fn from(matcher: SilenceMatcher) -> Self {
+        matcher.source
+    }
+}
+
+impl Silences {
+    pub(crate) fn load_or_default(config: &Config) -> Silences {
+        let path = match config.silences_path() {
+            Some(x) => x,
+            None => return Silences::default(),
+        };
+        let file = path.display();
+        match std::fs::read_to_string(&path) {
+            Ok(val) => match serde_json::from_str(&val) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("Failed to load JSON from {file}. Starting without silences. {e}");
+                    Silences::default()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::debug!("No silences saved in {file} yet.");
+                Silences::default()
+            }
+            Err(e) => {
+                log::warn!("Failed to load {file}, starting without silences. {e}");
+                Silences::default()
+            }
+        }
+    }
+
+    pub(crate) fn save(&self, config: &Config) {
+        let file = match config.silences_path() {
+            Some(x) => x,
+            None => return,
+        };
+        match serde_json::to_string(self) {
+            Ok(serialized) => {
+                if let Err(e) = std::fs::write(file, serialized) {
+                    log::error!("Failed to save silences: {:?}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to serialize silences: {:?}", e),
+        }
+    }
+
+    /// True if an active silence matches the item.
+    pub(crate) fn is_silenced<T: Matchable + ?Sized>(&self, item: &T, now: DateTime<Utc>) -> bool {
+        self.data
+            .iter()
+            .any(|silence| silence.is_active(now) && silence.matches(item))
+    }
+
+    /// Changes whenever the silences do, for ETags.
+    pub(crate) fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// When the next silence starts or ends, which changes what is silenced without a revision.
+    pub(crate) fn next_change(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.data
+            .iter()
+            .flat_map(|silence| [silence.starts_at, silence.ends_at])
+            .filter(|x| now < *x)
+            .min()
+    }
+
+    pub(crate) fn add(&mut self, silence: Silence) {
+        self.data.push(silence);
+        self.revision += 1;
+    }
+
+    /// False if there is no silence with the ID.
+    pub(crate) fn remove(&mut self, id: &str) -> bool {
+        let before = self.data.len();
+        self.data.retain(|silence| silence.id != id);
+        let removed = self.data.len() != before;
+        if removed {
+            self.revision += 1;
+        }
+        removed
+    }
+
+    pub(crate) fn get(&self, id: &str) -> Option<&Silence> {
+        self.data.iter().find(|silence| silence.id == id)
+    }
+
+    pub(crate) fn export_one(&self, id: &str, now: DateTime<Utc>) -> Option<ExportedSilence<'_>> {
+        self.data
+            .iter()
+            .find(|silence| silence.id == id)
+            .map(|silence| silence.exported(now))
+    }
+
+    /// Adds silences, replacing any with the same ID. Expired silences are skipped.
+    /// Returns how many were added or replaced.
+    pub(crate) fn import(&mut self, silences: Vec<Silence>, now: DateTime<Utc>) -> usize {
+        self.revision += 1;
+        self.data.retain(|silence| now < silence.ends_at);
+        let mut imported = 0;
+        for silence in silences {
+            if now >= silence.ends_at {
+                continue;
+            }
+            self.data.retain(|existing| existing.id != silence.id);
+            self.data.push(silence);
+            imported += 1;
+        }
+        imported
+    }
+
+    pub(crate) fn export(&self, now: DateTime<Utc>) -> Vec<ExportedSilence<'_>> {
+        self.data
+            .iter()
+            .map(|silence| silence.exported(now))
+            .collect()
+    }
+
+    /// Only the silences the tenant made.
+    pub(crate) fn export_for(&self, tenant: &str, now: DateTime<Utc>) -> Vec<ExportedSilence<'_>> {
+        self.data
+            .iter()
+            .filter(|silence| silence.tenant() == Some(tenant))
+            .map(|silence| silence.exported(now))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::grafana::Alert;
+
+    const AMTOOL_EXPORT: &str = r#"[
+        {
+            "id": "d5a2f3a8-1d9b-4c8e-9d35-07d1a6f6d111",
+            "matchers": [
+                {"name": "alertname", "value": "\\[critical\\] .*", "isRegex": true},
+                {"name": "@status", "value": "resolved", "isRegex": false, "isEqual": false}
+            ],
+            "startsAt": "2022-09-01T00:00:00Z",
+            "endsAt": "2022-10-01T00:00:00Z",
+            "createdBy": "myles",
+            "comment": "Maintenance",
+            "updatedAt": "2022-09-01T00:00:00Z",
+            "status": {"state": "active"}
+        },
+        {
+            "id": "expired",
+            "matchers": [{"name": "alertname", "value": "Other", "isRegex": false}],
+            "startsAt": "2022-08-01T00:00:00Z",
+            "endsAt": "2022-08-02T00:00:00Z"
+        }
+    ]"#;
+
+    fn time(value: &str) -> DateTime<Utc> {
+        value.parse().expect("Bad timestamp")
+    }
+
+    #[test]
+    fn import_and_match() {
+        let imported: Vec<Silence> =
+            serde_json::from_str(AMTOOL_EXPORT).expect("Failed to parse silences");
+        let mut silences = Silences::default();
+        let now = time("2022-09-15T00:00:00Z");
+        assert_eq!(silences.import(imported.clone(), now), 1);
+        assert_eq!(silences.import(imported, now), 1);
+        assert_eq!(silences.data.len(), 1);
+
+        let critical: Alert = serde_json::from_str(
+            &crate::test::consts::create_firing_alert_with_prefix("[critical] "),
+        )
+        .expect("Failed to load critical, firing alert");
+        let normal: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        assert!(silences.is_silenced(&critical, now));
+        assert!(!silences.is_silenced(&normal, now));
+        assert!(!silences.is_silenced(&critical, time("2022-10-01T00:00:00Z")));
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let imported: Vec<Silence> =
+            serde_json::from_str(AMTOOL_EXPORT).expect("Failed to parse silences");
+        let mut silences = Silences::default();
+        silences.import(imported, time("2022-08-01T12:00:00Z"));
+
+        let exported = serde_json::to_value(silences.export(time("2022-08-15T00:00:00Z")))
+            .expect("Failed to export");
+        assert_eq!(exported[0]["status"]["state"], "pending");
+        assert_eq!(exported[0]["matchers"][0]["isRegex"], true);
+        assert_eq!(exported[0]["matchers"][1]["isEqual"], false);
+        assert_eq!(exported[1]["status"]["state"], "expired");
+        let again: Vec<Silence> = serde_json::from_value(exported).expect("Failed to re-import");
+        assert_eq!(again.len(), 2);
+    }
+
+    #[test]
+    fn silence_requests() {
+        let now = time("2022-09-15T00:00:00Z");
+        let critical: Alert = serde_json::from_str(
+            &crate::test::consts::create_firing_alert_with_prefix("[critical] "),
+        )
+        .expect("Failed to load critical, firing alert");
+        let request = |json: &str| {
+            serde_json::from_str::<SilenceRequest>(json)
+                .expect("Failed to parse request")
+                .into_silence(now)
+        };
+
+        let silence = request(r#"{"alertname": "\\[critical\\] .*", "regex": true}"#)
+            .expect("Failed to create silence");
+        assert!(silence.matches(&critical));
+        assert_eq!(silence.ends_at(), &time("2022-09-15T01:00:00Z"));
+
+        let fingerprint = critical.fingerprint();
+        let silence = request(&format!(
+            r#"{{"fingerprint": "{fingerprint}", "starts_at": "2022-09-16T00:00:00Z", "ends_at": "2022-09-17T00:00:00Z"}}"#
+        ))
+        .expect("Failed to create silence");
+        assert!(silence.matches(&critical));
+        assert!(!silence.is_active(now));
+        assert!(silence.is_active(time("2022-09-16T12:00:00Z")));
+
+        let silence = request(r#"{"alertname": "Other", "minutes": "1d"}"#)
+            .expect("Failed to create silence");
+        assert!(!silence.matches(&critical));
+        assert_eq!(silence.ends_at(), &time("2022-09-16T00:00:00Z"));
+
+        for (json, error) in [
+            (
+                "{}",
+                "Set an alertname, fingerprint or matchers to silence.",
+            ),
+            (
+                r#"{"alertname": "x", "ends_at": "2022-09-14T00:00:00Z"}"#,
+                "Silence ends before it starts.",
+            ),
+            (
+                r#"{"alertname": "x", "minutes": "2147483647d"}"#,
+                "Silence lasts too long.",
+            ),
+        ] {
+            let result = request(json).map_err(|e| e.to_string());
+            assert_eq!(result.err().as_deref(), Some(error), "{json}");
+        }
+    }
+
+    #[test]
+    fn same_start() {
+        let request = || {
+            serde_json::from_str::<SilenceRequest>(
+                r#"{"alertname": "Disk", "starts_at": "2022-09-16T00:00:00Z"}"#,
+            )
+            .expect("Failed to parse request")
+            .into_silence(time("2022-09-15T00:00:00Z"))
+            .expect("Failed to create silence")
+        };
+        let first = request();
+        let second = request();
+        assert_ne!(first.id(), second.id());
+
+        let mut silences = Silences::default();
+        silences.add(first.clone());
+        silences.add(second.clone());
+        assert!(silences.remove(first.id()));
+        assert_eq!(silences.data.len(), 1);
+        assert_eq!(silences.data[0].id(), second.id());
+    }
+
+    #[test]
+    fn survives_restart() {
+        let dir = std::env::temp_dir().join(format!("silences-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Failed to create dir");
+        let fingerprints_file = dir.join("fingerprints.json");
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "prowl_api_keys": [],
+            "fingerprints_file": fingerprints_file,
+        }))
+        .expect("Failed to parse config");
+
+        let imported: Vec<Silence> =
+            serde_json::from_str(AMTOOL_EXPORT).expect("Failed to parse silences");
+        let mut silences = Silences::default();
+        silences.import(imported, time("2022-09-15T00:00:00Z"));
+        silences.save(&config);
+        let saved = dir.join("fingerprints.silences.json");
+        assert!(saved.exists());
+
+        let restored = Silences::load_or_default(&config);
+        std::fs::remove_dir_all(&dir).expect("Failed to clean up");
+        let critical: Alert = serde_json::from_str(
+            &crate::test::consts::create_firing_alert_with_prefix("[critical] "),
+        )
+        .expect("Failed to load critical, firing alert");
+        // still ends when it was meant to
+        assert!(restored.is_silenced(&critical, time("2022-09-20T00:00:00Z")));
+        assert!(!restored.is_silenced(&critical, time("2022-10-01T00:00:00Z")));
+    }
+}
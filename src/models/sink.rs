@@ -0,0 +1,147 @@
+use derive_getters::Getters;
+use serde::Deserialize;
+
+/// The service a receiver's notifications are sent with. Its `api_keys` are that
+/// service's keys.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Sink {
+    #[default]
+    Prowl,
+    /// `api_keys` are Pushover user or group keys.
+    Pushover(PushoverSink),
+    /// `api_keys` are ntfy topics.
+    Ntfy(NtfySink),
+    /// `api_keys` are Gotify application tokens.
+    Gotify(GotifySink),
+    /// `api_keys` are the chat IDs the bot sends to.
+    Telegram(TelegramSink),
+    /// `api_keys` are Discord webhook URLs.
+    Discord,
+    /// `api_keys` are Slack incoming webhook URLs.
+    Slack,
+    /// `api_keys` are email addresses, sent to with the `smtp` server.
+    Email,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub(crate) struct PushoverSink {
+    /// The application's API token.
+    token: String,
+    /// How often Pushover repeats an `Emergency` notification until it is acknowledged.
+    #[serde(default = "default_emergency_retry_secs")]
+    emergency_retry_secs: u64,
+    /// How long Pushover keeps repeating it.
+    #[serde(default = "default_emergency_expire_secs")]
+    emergency_expire_secs: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub(crate) struct NtfySink {
+    /// Like `https://ntfy.sh` or a self-hosted server.
+    #[serde(default = "default_ntfy_server")]
+    server: String,
+    /// An access token, for topics that need one.
+    token: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub(crate) struct GotifySink {
+    /// The self-hosted server, like `https://gotify.example.com`.
+    server: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub(crate) struct TelegramSink {
+    /// The token BotFather gave the bot, like `123456:ABC-DEF`.
+    bot_token: String,
+}
+
+/// The mail server email receivers are sent with.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub(crate) struct SmtpSettings {
+    host: String,
+    #[serde(default = "default_smtp_port")]
+    port: u16,
+    #[serde(default = "default_smtp_tls")]
+    tls: SmtpTls,
+    username: Option<String>,
+    password: Option<String>,
+    /// The sender's address, like `grafana@example.com`.
+    from: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SmtpTls {
+    /// Upgrades a plain connection, usually on port 587.
+    Starttls,
+    /// TLS from the start, usually on port 465.
+    Tls,
+    /// No encryption, only for a relay on the same machine or network.
+    None,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_smtp_tls() -> SmtpTls {
+    SmtpTls::Starttls
+}
+
+fn default_emergency_retry_secs() -> u64 {
+    60
+}
+
+fn default_emergency_expire_secs() -> u64 {
+    3600
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+impl PushoverSink {
+    pub(crate) fn new(token: String) -> Self {
+        PushoverSink {
+            token,
+            emergency_retry_secs: default_emergency_retry_secs(),
+            emergency_expire_secs: default_emergency_expire_secs(),
+        }
+    }
+}
+
+impl NtfySink {
+    pub(crate) fn new(server: String, token: Option<String>) -> Self {
+        NtfySink { server, token }
+    }
+}
+
+impl GotifySink {
+    pub(crate) fn new(server: String) -> Self {
+        GotifySink { server }
+    }
+}
+
+impl TelegramSink {
+    pub(crate) fn new(bot_token: String) -> Self {
+        TelegramSink { bot_token }
+    }
+}
+
+impl Sink {
+    /// The service's name for logs and errors.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Sink::Prowl => "Prowl",
+            Sink::Pushover(_) => "Pushover",
+            Sink::Ntfy(_) => "ntfy",
+            Sink::Gotify(_) => "Gotify",
+            Sink::Telegram(_) => "Telegram",
+            Sink::Discord => "Discord",
+            Sink::Slack => "Slack",
+            Sink::Email => "Email",
+        }
+    }
+}
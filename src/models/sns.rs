@@ -0,0 +1,223 @@
+use crate::models::grafana::{Alert, Message};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// What Amazon SNS posts to an HTTPS subscription. CloudWatch alarms are JSON in `Message`.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct SnsEnvelope {
+    /// `SubscriptionConfirmation`, `Notification` or `UnsubscribeConfirmation`.
+    #[serde(rename = "Type")]
+    kind: String,
+    message: String,
+    #[serde(rename = "SubscribeURL")]
+    subscribe_url: Option<String>,
+    topic_arn: Option<String>,
+}
+
+/// A CloudWatch alarm's state change, one fingerprint per alarm.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CloudWatchAlarm {
+    alarm_name: String,
+    alarm_arn: String,
+    /// `ALARM`, `OK` or `INSUFFICIENT_DATA`.
+    new_state_value: String,
+    new_state_reason: Option<String>,
+    alarm_description: Option<String>,
+    /// Like `2022-09-09T10:00:00.000+0000`, which isn't RFC 3339.
+    state_change_time: Option<String>,
+    #[serde(rename = "AWSAccountId")]
+    aws_account_id: Option<String>,
+    trigger: Option<Trigger>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Trigger {
+    metric_name: Option<String>,
+    namespace: Option<String>,
+}
+
+impl SnsEnvelope {
+    /// The URL to fetch to confirm a new subscription, if this is a confirmation.
+    pub(crate) fn subscribe_url(&self) -> Option<&str> {
+        match self.kind.as_str() {
+            "SubscriptionConfirmation" => self.subscribe_url.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Only SNS's own endpoints are fetched, so a forged confirmation can't make
+/// the notifier request anything else.
+pub(crate) fn trusted_subscribe_url(url: &str) -> bool {
+    let host = match url.strip_prefix("https://") {
+        Some(rest) => rest.split(['/', '?']).next().unwrap_or_default(),
+        None => return false,
+    };
+    host.starts_with("sns.") && host.ends_with(".amazonaws.com")
+}
+
+impl CloudWatchAlarm {
+    /// The region's code, from the ARN like `arn:aws:cloudwatch:us-east-1:123456789012:alarm:name`.
+    fn region(&self) -> Option<&str> {
+        self.alarm_arn.split(':').nth(3).filter(|x| !x.is_empty())
+    }
+
+    fn changed_at(&self) -> Option<DateTime<Utc>> {
+        let time = self.state_change_time.as_deref()?;
+        DateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%.f%z")
+            .ok()
+            .map(|x| x.with_timezone(&Utc))
+    }
+
+    fn console_url(&self) -> Option<String> {
+        let region = self.region()?;
+        Some(format!(
+            "https://{region}.console.aws.amazon.com/cloudwatch/home?region={region}#alarmsV2:alarm/{}",
+            urlencoding::encode(&self.alarm_name)
+        ))
+    }
+}
+
+impl From<CloudWatchAlarm> for Alert {
+    /// `INSUFFICIENT_DATA` becomes a `DatasourceNoData` alert, like Grafana's.
+    fn from(alarm: CloudWatchAlarm) -> Self {
+        let changed_at = alarm.changed_at();
+        let (status, starts_at, ends_at) = match alarm.new_state_value.as_str() {
+            "ALARM" | "INSUFFICIENT_DATA" => ("firing", changed_at, None),
+            "OK" => ("resolved", None, changed_at),
+            other => (other, changed_at, None),
+        };
+        let mut labels = BTreeMap::new();
+        match alarm.new_state_value.as_str() {
+            "INSUFFICIENT_DATA" => {
+                labels.insert("alertname".to_string(), "DatasourceNoData".to_string());
+                labels.insert("rulename".to_string(), alarm.alarm_name.clone());
+            }
+            _ => {
+                labels.insert("alertname".to_string(), alarm.alarm_name.clone());
+            }
+        }
+        if let Some(region) = alarm.region() {
+            labels.insert("region".to_string(), region.to_string());
+        }
+        if let Some(account) = &alarm.aws_account_id {
+            labels.insert("account".to_string(), account.clone());
+        }
+        if let Some(trigger) = &alarm.trigger {
+            if let Some(metric) = &trigger.metric_name {
+                labels.insert("metric".to_string(), metric.clone());
+            }
+            if let Some(namespace) = &trigger.namespace {
+                labels.insert("namespace".to_string(), namespace.clone());
+            }
+        }
+        let mut annotations = BTreeMap::new();
+        if let Some(reason) = &alarm.new_state_reason {
+            annotations.insert("summary".to_string(), reason.clone());
+        }
+        if let Some(description) = alarm.alarm_description.clone().filter(|x| !x.is_empty()) {
+            annotations.insert("description".to_string(), description);
+        }
+        let url = alarm.console_url().unwrap_or_default();
+        Alert::new(status, alarm.alarm_arn, labels, annotations)
+            .with_generator_url(url)
+            .with_times(starts_at, ends_at)
+    }
+}
+
+impl From<SnsEnvelope> for Message {
+    /// Subscription messages and notifications that aren't CloudWatch alarms have no alerts.
+    fn from(envelope: SnsEnvelope) -> Self {
+        if envelope.kind != "Notification" {
+            log::info!(
+                "Received SNS {} for {}",
+                envelope.kind,
+                envelope.topic_arn.as_deref().unwrap_or("unknown topic")
+            );
+            return Message::new(vec![]);
+        }
+        match serde_json::from_str::<CloudWatchAlarm>(&envelope.message) {
+            Ok(alarm) => Message::new(vec![alarm.into()]),
+            Err(e) => {
+                log::warn!("Ignored an SNS notification that isn't a CloudWatch alarm. {e}");
+                Message::new(vec![])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn notification(state: &str) -> Message {
+        let alarm = serde_json::json!({
+            "AlarmName": "High CPU",
+            "AlarmDescription": "CPU over 90% for 5 minutes",
+            "AWSAccountId": "123456789012",
+            "NewStateValue": state,
+            "NewStateReason": "Threshold Crossed: 1 datapoint [95.0] was greater than the threshold (90.0).",
+            "StateChangeTime": "2022-09-09T10:00:00.000+0000",
+            "Region": "US East (N. Virginia)",
+            "AlarmArn": "arn:aws:cloudwatch:us-east-1:123456789012:alarm:High CPU",
+            "OldStateValue": "OK",
+            "Trigger": { "MetricName": "CPUUtilization", "Namespace": "AWS/EC2" }
+        });
+        let envelope: SnsEnvelope = serde_json::from_value(serde_json::json!({
+            "Type": "Notification",
+            "MessageId": "22b80b92-fdea-4c2c-8f9d-bdfb0c7bf324",
+            "TopicArn": "arn:aws:sns:us-east-1:123456789012:alarms",
+            "Message": alarm.to_string(),
+            "Timestamp": "2022-09-09T10:00:01.000Z"
+        }))
+        .expect("Failed to load envelope");
+        envelope.into()
+    }
+
+    #[test]
+    fn cloudwatch_alarms() {
+        let message = notification("ALARM");
+        let alert = &message.alerts()[0];
+        assert_eq!(
+            alert.fingerprint(),
+            "arn:aws:cloudwatch:us-east-1:123456789012:alarm:High CPU"
+        );
+        assert_eq!(alert.status(), "firing");
+        assert_eq!(alert.name(), "High CPU");
+        assert_eq!(alert.labels().get("region"), Some("us-east-1"));
+        assert_eq!(alert.labels().get("metric"), Some("CPUUtilization"));
+        assert_eq!(alert.generator_url(), "https://us-east-1.console.aws.amazon.com/cloudwatch/home?region=us-east-1#alarmsV2:alarm/High%20CPU");
+        assert_eq!(
+            alert.started_at().map(|x| x.to_rfc3339()).as_deref(),
+            Some("2022-09-09T10:00:00+00:00")
+        );
+
+        assert_eq!(notification("OK").alerts()[0].status(), "resolved");
+        let message = notification("INSUFFICIENT_DATA");
+        assert!(message.alerts()[0].datasource_problem().is_some());
+        assert_eq!(message.alerts()[0].rule_name(), "High CPU");
+    }
+
+    #[test]
+    fn subscriptions() {
+        let envelope: SnsEnvelope = serde_json::from_str(
+            r#"{"Type": "SubscriptionConfirmation", "Message": "You have chosen to subscribe",
+                "SubscribeURL": "https://sns.us-east-1.amazonaws.com/?Action=ConfirmSubscription&Token=abc"}"#,
+        )
+        .expect("Failed to load envelope");
+        let url = envelope.subscribe_url().expect("No subscribe URL");
+        assert!(trusted_subscribe_url(url));
+        assert!(Message::from(envelope).alerts().is_empty());
+        for url in [
+            "http://sns.us-east-1.amazonaws.com/",
+            "https://sns.us-east-1.amazonaws.com.example.com/",
+            "https://example.com/sns.x.amazonaws.com",
+        ] {
+            assert!(!trusted_subscribe_url(url), "{url}");
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{
+    errors::MatcherError,
+    models::{
+        auth::Auth,
+        forward_url::ForwardUrl,
+        matcher::Matcher,
+        receiver::{Delivery, Receiver},
+    },
+};
+use derive_getters::Getters;
+use serde::Deserialize;
+
+/// Someone else's Grafana, sending to `/webhooks/grafana/{tenant}` with its own token.
+/// Its alerts are kept apart from everyone else's and only notify its own keys.
+#[derive(Clone, Debug, Deserialize, Getters)]
+pub(crate) struct Tenant {
+    auth_token: String,
+    prowl_api_keys: Vec<String>,
+    prowl_provider_key: Option<String>,
+    /// Shown as the application instead of `app_name`.
+    app_name: Option<String>,
+    /// Where its webhooks' bodies are also posted, instead of `forward_urls`.
+    #[serde(default = "Vec::new")]
+    forward_urls: Vec<ForwardUrl>,
+}
+
+/// Where tenants send their webhooks, with the tenant as the last segment.
+pub(crate) const TENANT_WEBHOOK_PATH: &str = "/webhooks/grafana/{tenant}";
+
+impl Tenant {
+    /// Every key in one request, like `prowl_api_keys`.
+    pub(crate) fn receiver(&self) -> Receiver {
+        Receiver::new(self.prowl_api_keys.clone(), Delivery::Together)
+            .or_provider_key(&self.prowl_provider_key)
+    }
+
+    /// Alerts are stored under `tenant:fingerprint`, so two tenants can't share one.
+    /// Not a `/`, so the API's fingerprint paths still work.
+    pub(crate) fn fingerprint(tenant: &str, fingerprint: &str) -> String {
+        format!("{tenant}:{fingerprint}")
+    }
+
+    /// How the tenant signs in to the dashboard and API, its `auth_token` like its
+    /// webhooks or as the password for its name, which browsers can send.
+    pub(crate) fn auths(&self, name: &str, header: &Option<String>) -> [Auth; 2] {
+        [
+            Auth::Bearer {
+                token: self.auth_token.clone(),
+                header: header.clone(),
+            },
+            Auth::Basic {
+                user: name.to_string(),
+                password: self.auth_token.clone(),
+            },
+        ]
+    }
+
+    /// Added to the tenant's silences and bulk updates, so they only reach its alerts.
+    pub(crate) fn matcher(tenant: &str) -> Result<Matcher, MatcherError> {
+        Matcher::parse(&format!("@tenant=\"{tenant}\""))
+    }
+
+    /// The tenant a webhook path is for, if it is a tenant's path.
+    pub(crate) fn of_path(path: &str) -> Option<&str> {
+        path.strip_prefix("/webhooks/grafana/")
+            .filter(|x| !x.is_empty() && !x.contains('/'))
+    }
+}
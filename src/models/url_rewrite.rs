@@ -0,0 +1,91 @@
+use serde::Deserialize;
+
+/// Changes a notification's link, like Grafana's internal address to one a phone can reach.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub(crate) enum UrlRewrite {
+    /// Links starting with `from` start with `to` instead.
+    Prefix { from: String, to: String },
+    /// Every link's scheme and host are replaced by `base`, keeping the path and query.
+    Base { base: String },
+}
+
+impl UrlRewrite {
+    /// The rewritten link, or None if the rule doesn't apply to it.
+    fn apply(&self, url: &str) -> Option<String> {
+        match self {
+            UrlRewrite::Prefix { from, to } => url
+                .strip_prefix(from.as_str())
+                .map(|rest| format!("{to}{rest}")),
+            UrlRewrite::Base { base } => {
+                let (_, rest) = url.split_once("://")?;
+                let path = rest.find(['/', '?', '#']).map_or("", |x| &rest[x..]);
+                Some(format!("{}{path}", base.trim_end_matches('/')))
+            }
+        }
+    }
+
+    /// A base that isn't an absolute URL would make every link relative.
+    pub(crate) fn valid(&self) -> bool {
+        match self {
+            UrlRewrite::Prefix { from, .. } => !from.is_empty(),
+            UrlRewrite::Base { base } => {
+                base.starts_with("http://") || base.starts_with("https://")
+            }
+        }
+    }
+}
+
+/// The link rewritten by the first rule that applies to it, or as it was.
+pub(crate) fn rewrite(rules: &[UrlRewrite], url: String) -> String {
+    rules
+        .iter()
+        .find_map(|rule| rule.apply(&url))
+        .unwrap_or(url)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rewrites() {
+        let rules: Vec<UrlRewrite> = serde_json::from_str(
+            r#"[
+                {"from": "http://grafana:3000/", "to": "https://grafana.example.com/"},
+                {"base": "https://lab.example.com/grafana/"}
+            ]"#,
+        )
+        .expect("Failed to parse rules");
+        let rewritten = |x: &str| rewrite(&rules, x.to_string());
+        assert_eq!(
+            rewritten("http://grafana:3000/d/abc?viewPanel=2"),
+            "https://grafana.example.com/d/abc?viewPanel=2"
+        );
+        // the first rule that applies wins, the base applies to any absolute link
+        assert_eq!(
+            rewritten("http://10.0.0.5:3000/alerting/grafana/xyz/view"),
+            "https://lab.example.com/grafana/alerting/grafana/xyz/view"
+        );
+        assert_eq!(
+            rewritten("http://10.0.0.5:3000?orgId=1"),
+            "https://lab.example.com/grafana?orgId=1"
+        );
+        assert_eq!(rewritten("/d/abc"), "/d/abc");
+        assert_eq!(
+            rewrite(&[], "http://grafana:3000/".to_string()),
+            "http://grafana:3000/"
+        );
+
+        assert!(!UrlRewrite::Base {
+            base: "lab.example.com".to_string()
+        }
+        .valid());
+        assert!(!UrlRewrite::Prefix {
+            from: String::new(),
+            to: "x".to_string()
+        }
+        .valid());
+        assert!(rules.iter().all(UrlRewrite::valid));
+    }
+}
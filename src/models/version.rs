@@ -0,0 +1,52 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+
+/// What `GET /version` shows about the running build.
+#[derive(Debug, Serialize)]
+pub(crate) struct Version {
+    version: &'static str,
+    commit: &'static str,
+    built_at: Option<DateTime<Utc>>,
+    rustc: &'static str,
+    config_file: Option<String>,
+    started_at: DateTime<Utc>,
+    uptime_secs: i64,
+}
+
+impl Version {
+    pub(crate) fn new(
+        config_file: Option<String>,
+        started_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        Version {
+            version: env!("CARGO_PKG_VERSION"),
+            commit: env!("GIT_COMMIT"),
+            built_at: env!("BUILD_TIMESTAMP")
+                .parse()
+                .ok()
+                .and_then(|x| Utc.timestamp_opt(x, 0).single()),
+            rustc: env!("RUSTC_VERSION"),
+            config_file,
+            started_at,
+            uptime_secs: (now - started_at).num_seconds(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let started_at = Utc::now();
+        let now = started_at + chrono::Duration::minutes(90);
+        let version = Version::new(Some("config.json".to_string()), started_at, now);
+        assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(version.uptime_secs, 5400);
+        assert!(!version.commit.is_empty());
+        assert!(version.rustc.starts_with("rustc ") || version.rustc == "unknown");
+        assert!(version.built_at.is_some_and(|x| x <= started_at));
+    }
+}
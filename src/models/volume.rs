@@ -0,0 +1,200 @@
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How far back the baseline looks.
+const WINDOW_HOURS: i64 = 7 * 24;
+/// Completed hours needed before anything is reported.
+const MIN_HISTORY_HOURS: i64 = 24;
+/// Spikes need at least this many alerts in the hour, so quiet setups aren't noisy.
+const MIN_SPIKE: f64 = 10.0;
+/// Standard deviations above the baseline that count as a spike.
+const SPIKE_DEVIATIONS: f64 = 5.0;
+/// A silence is unusual once it would happen by chance less than 1 in 1000 times.
+const QUIET_ODDS: f64 = 1000.0;
+
+/// Rolling count of alerts per hour, used to notice storms and alerts that stop arriving.
+#[derive(Debug)]
+pub(crate) struct Volume {
+    since: DateTime<Utc>,
+    hours: BTreeMap<DateTime<Utc>, u64>,
+    last_alert: Option<DateTime<Utc>>,
+    spike_notified: Option<DateTime<Utc>>,
+    quiet_notified: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Anomaly {
+    Spike { count: u64, baseline: f64 },
+    Quiet { hours: i64, baseline: f64 },
+}
+
+/// What the API shows about alert volume.
+#[derive(Debug, Serialize)]
+pub(crate) struct VolumeStatus {
+    baseline_per_hour: Option<f64>,
+    this_hour: u64,
+    last_alert: Option<DateTime<Utc>>,
+    state: &'static str,
+}
+
+fn hour_of(time: DateTime<Utc>) -> DateTime<Utc> {
+    time.duration_trunc(Duration::hours(1)).unwrap_or(time)
+}
+
+impl Volume {
+    pub(crate) fn new(now: DateTime<Utc>) -> Self {
+        Volume {
+            since: hour_of(now),
+            hours: BTreeMap::new(),
+            last_alert: None,
+            spike_notified: None,
+            quiet_notified: false,
+        }
+    }
+
+    pub(crate) fn record(&mut self, alerts: usize, now: DateTime<Utc>) {
+        if alerts == 0 {
+            return;
+        }
+        *self.hours.entry(hour_of(now)).or_insert(0) += alerts as u64;
+        self.last_alert = Some(now);
+        self.quiet_notified = false;
+        let oldest = hour_of(now) - Duration::hours(WINDOW_HOURS);
+        self.hours = self.hours.split_off(&oldest);
+    }
+
+    /// Average alerts per completed hour, once there is enough history.
+    pub(crate) fn baseline(&self, now: DateTime<Utc>) -> Option<f64> {
+        let current = hour_of(now);
+        let start = self.since.max(current - Duration::hours(WINDOW_HOURS));
+        let completed = (current - start).num_hours();
+        if completed < MIN_HISTORY_HOURS {
+            return None;
+        }
+        let total: u64 = self.hours.range(start..current).map(|(_, x)| x).sum();
+        Some(total as f64 / completed as f64)
+    }
+
+    fn this_hour(&self, now: DateTime<Utc>) -> u64 {
+        *self.hours.get(&hour_of(now)).unwrap_or(&0)
+    }
+
+    fn spiking(&self, now: DateTime<Utc>, baseline: f64) -> bool {
+        let threshold = (baseline + SPIKE_DEVIATIONS * baseline.sqrt()).max(MIN_SPIKE);
+        self.this_hour(now) as f64 >= threshold
+    }
+
+    /// Hours without alerts, if that is unusual for the baseline.
+    fn quiet_hours(&self, now: DateTime<Utc>, baseline: f64) -> Option<i64> {
+        if baseline <= 0.0 {
+            return None;
+        }
+        let since = self.last_alert.unwrap_or(self.since);
+        let hours = (now - since).num_minutes() as f64 / 60.0;
+        // alerts arriving at random, the chance of none for `hours` is e^(-baseline * hours)
+        match hours >= QUIET_ODDS.ln() / baseline {
+            true => Some(hours as i64),
+            false => None,
+        }
+    }
+
+    /// Returns an anomaly the first time it is seen, spikes at most once an hour.
+    pub(crate) fn check(&mut self, now: DateTime<Utc>) -> Option<Anomaly> {
+        let baseline = self.baseline(now)?;
+        if self.spiking(now, baseline) && self.spike_notified != Some(hour_of(now)) {
+            self.spike_notified = Some(hour_of(now));
+            let count = self.this_hour(now);
+            return Some(Anomaly::Spike { count, baseline });
+        }
+        if let Some(hours) = self.quiet_hours(now, baseline) {
+            if !self.quiet_notified {
+                self.quiet_notified = true;
+                return Some(Anomaly::Quiet { hours, baseline });
+            }
+        }
+        None
+    }
+
+    pub(crate) fn status(&self, now: DateTime<Utc>) -> VolumeStatus {
+        let baseline = self.baseline(now);
+        let state = match baseline {
+            None => "learning",
+            Some(x) if self.spiking(now, x) => "spike",
+            Some(x) if self.quiet_hours(now, x).is_some() => "quiet",
+            Some(_) => "normal",
+        };
+        VolumeStatus {
+            baseline_per_hour: baseline,
+            this_hour: self.this_hour(now),
+            last_alert: self.last_alert,
+            state,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn start() -> DateTime<Utc> {
+        "2022-09-05T00:00:00Z".parse().expect("Bad timestamp")
+    }
+
+    /// Two alerts every hour for two days.
+    fn steady() -> (Volume, DateTime<Utc>) {
+        let mut volume = Volume::new(start());
+        let mut now = start();
+        for _ in 0..48 {
+            volume.record(2, now + Duration::minutes(30));
+            now += Duration::hours(1);
+        }
+        (volume, now)
+    }
+
+    #[test]
+    fn learns_before_reporting() {
+        let mut volume = Volume::new(start());
+        volume.record(500, start());
+        let now = start() + Duration::hours(23);
+        assert_eq!(volume.baseline(now), None);
+        assert_eq!(volume.check(now), None);
+        assert_eq!(volume.status(now).state, "learning");
+    }
+
+    #[test]
+    fn spike_once_per_hour() {
+        let (mut volume, now) = steady();
+        assert_eq!(volume.baseline(now), Some(2.0));
+        assert_eq!(volume.check(now), None);
+        assert_eq!(volume.status(now).state, "normal");
+
+        volume.record(15, now);
+        assert_eq!(
+            volume.check(now),
+            Some(Anomaly::Spike {
+                count: 15,
+                baseline: 2.0
+            })
+        );
+        assert_eq!(volume.check(now + Duration::minutes(5)), None);
+        assert_eq!(volume.status(now).state, "spike");
+    }
+
+    #[test]
+    fn quiet_until_alerts_resume() {
+        let (mut volume, now) = steady();
+        // ln(1000) / 2 is about 3.5 hours, since the last alert half an hour ago
+        assert_eq!(volume.check(now + Duration::hours(2)), None);
+        let later = now + Duration::hours(4);
+        assert!(matches!(
+            volume.check(later),
+            Some(Anomaly::Quiet { hours: 4, .. })
+        ));
+        assert_eq!(volume.check(later), None);
+
+        volume.record(1, later);
+        assert_eq!(volume.check(later), None);
+        assert!(volume.check(later + Duration::hours(5)).is_some());
+    }
+}
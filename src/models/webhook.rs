@@ -0,0 +1,198 @@
+use crate::models::{
+    auth::Auth, github::GithubEvent, grafana::Message, healthchecks::HealthchecksEvent,
+    legacy_grafana::LegacyMessage, sentry::SentryIssue, sns::SnsEnvelope, zabbix::ZabbixEvent,
+};
+use derive_getters::Getters;
+use serde::Deserialize;
+
+/// An extra webhook path from `webhooks`, served alongside `/webhooks/grafana`.
+#[derive(Clone, Debug, Deserialize, Getters)]
+pub(crate) struct Webhook {
+    /// Under `/webhooks/`, with `{name}` segments or a last `*` like the built in routes.
+    path: String,
+    #[serde(default = "default_parser")]
+    parser: Parser,
+    /// Receivers notified for every alert, otherwise `routes` decide.
+    #[serde(default = "Vec::new")]
+    receivers: Vec<String>,
+    /// When set, requests need a hex HMAC-SHA256 of their body in `hmac_header`.
+    hmac_secret: Option<String>,
+    /// Defaults to the parser's `signature_header`.
+    hmac_header: Option<String>,
+}
+
+/// How a webhook's body is read.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Parser {
+    /// Grafana's webhook contact point, or the legacy alerting's webhook of Grafana 7.
+    Grafana,
+    /// Prometheus Alertmanager's webhook receiver, which Grafana's extends.
+    Alertmanager,
+    /// A Healthchecks.io webhook integration, see `HealthchecksEvent`.
+    Healthchecks,
+    /// An Amazon SNS subscription, for CloudWatch alarms.
+    Sns,
+    /// Sentry's webhooks integration, for issue alerts.
+    Sentry,
+    /// GitHub's `workflow_run` events, for failed Actions runs.
+    Github,
+    /// A Zabbix webhook media type, see `ZabbixEvent`.
+    Zabbix,
+}
+
+/// The only version of Alertmanager's webhook so far.
+const ALERTMANAGER_VERSION: &str = "4";
+
+impl Parser {
+    /// Each has its own route, `path`.
+    pub(crate) const ALL: [Parser; 7] = [
+        Parser::Grafana,
+        Parser::Alertmanager,
+        Parser::Healthchecks,
+        Parser::Sns,
+        Parser::Sentry,
+        Parser::Github,
+        Parser::Zabbix,
+    ];
+
+    /// Where the webhook comes from, for the logs.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Parser::Grafana => "Grafana",
+            Parser::Alertmanager => "Alertmanager",
+            Parser::Healthchecks => "Healthchecks.io",
+            Parser::Sns => "Amazon SNS",
+            Parser::Sentry => "Sentry",
+            Parser::Github => "GitHub",
+            Parser::Zabbix => "Zabbix",
+        }
+    }
+
+    /// Where its senders put the signature of the body.
+    pub(crate) fn signature_header(self) -> &'static str {
+        match self {
+            Parser::Github => "X-Hub-Signature-256",
+            _ => "X-Signature",
+        }
+    }
+
+    /// Checks `secret` was used to sign a request.
+    pub(crate) fn signature(self, secret: &str) -> Auth {
+        Auth::Hmac {
+            secret: secret.to_string(),
+            header: self.signature_header().to_string(),
+        }
+    }
+
+    /// The built in route for it.
+    pub(crate) fn path(self) -> &'static str {
+        match self {
+            Parser::Grafana => "/webhooks/grafana",
+            Parser::Alertmanager => "/webhooks/alertmanager",
+            Parser::Healthchecks => "/webhooks/healthchecks",
+            Parser::Sns => "/webhooks/sns",
+            Parser::Sentry => "/webhooks/sentry",
+            Parser::Github => "/webhooks/github",
+            Parser::Zabbix => "/webhooks/zabbix",
+        }
+    }
+
+    pub(crate) fn parse(self, body: &str) -> Result<Message, serde_json::Error> {
+        match self {
+            Parser::Grafana | Parser::Alertmanager if body.trim_start().starts_with('[') => {
+                let messages: Vec<Message> = serde_json::from_str(body)?;
+                log::debug!("Received {} messages at once", messages.len());
+                Ok(Message::merge(messages))
+            }
+            Parser::Grafana => match serde_json::from_str(body) {
+                Ok(x) => Ok(x),
+                Err(e) => match serde_json::from_str::<LegacyMessage>(body) {
+                    Ok(legacy) => {
+                        log::debug!("Received Grafana's legacy alerting webhook");
+                        Ok(legacy.into())
+                    }
+                    // the unified webhook's error is the useful one
+                    Err(_) => Err(e),
+                },
+            },
+            Parser::Alertmanager => {
+                let message: Message = serde_json::from_str(body)?;
+                if message.version().as_deref() != Some(ALERTMANAGER_VERSION) {
+                    log::warn!(
+                        "Expected version {ALERTMANAGER_VERSION} of Alertmanager's webhook, got {:?}",
+                        message.version()
+                    );
+                }
+                Ok(message)
+            }
+            Parser::Healthchecks => {
+                serde_json::from_str::<HealthchecksEvent>(body).map(Message::from)
+            }
+            Parser::Sns => serde_json::from_str::<SnsEnvelope>(body).map(Message::from),
+            Parser::Sentry => serde_json::from_str::<SentryIssue>(body).map(Message::from),
+            Parser::Github => serde_json::from_str::<GithubEvent>(body).map(Message::from),
+            Parser::Zabbix => serde_json::from_str::<ZabbixEvent>(body).map(Message::from),
+        }
+    }
+}
+
+fn default_parser() -> Parser {
+    Parser::Grafana
+}
+
+impl Webhook {
+    pub(crate) fn signature(&self) -> Option<Auth> {
+        let secret = self.hmac_secret.as_deref()?;
+        Some(match &self.hmac_header {
+            Some(header) => Auth::Hmac {
+                secret: secret.to_string(),
+                header: header.clone(),
+            },
+            None => self.parser.signature(secret),
+        })
+    }
+
+    /// Paths outside `/webhooks/` would skip its auth and `allowed_source_cidrs`.
+    pub(crate) fn valid_path(&self) -> bool {
+        let segments = match self.path.strip_prefix("/webhooks/") {
+            Some(x) if !x.is_empty() => x.split('/').collect::<Vec<_>>(),
+            _ => return false,
+        };
+        let last = segments.len() - 1;
+        segments
+            .iter()
+            .enumerate()
+            .all(|(index, x)| !x.is_empty() && (*x != "*" || index == last))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_valid_path() {
+        let webhook = |path: &str| -> Webhook {
+            serde_json::from_value(serde_json::json!({ "path": path }))
+                .expect("Failed to parse webhook")
+        };
+        assert_eq!(webhook("/webhooks/team").parser(), &Parser::Grafana);
+        for path in [
+            "/webhooks/team",
+            "/webhooks/{team}/grafana",
+            "/webhooks/teams/*",
+        ] {
+            assert!(webhook(path).valid_path(), "{path}");
+        }
+        for path in [
+            "/api/team",
+            "/webhooks/",
+            "/webhooks/*/grafana",
+            "/webhooks//team",
+            "webhooks/team",
+        ] {
+            assert!(!webhook(path).valid_path(), "{path}");
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::models::grafana::{Alert, Message};
+use prowl::Priority;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// The parameters a Zabbix webhook media type posts as JSON, each set to a macro like
+/// `"event_id": "{EVENT.ID}"`. Recovery messages have the problem's `{EVENT.ID}`,
+/// so both are one fingerprint.
+#[derive(Deserialize)]
+pub(crate) struct ZabbixEvent {
+    event_id: String,
+    /// `{EVENT.VALUE}`, `1` for a problem and `0` for its resolution.
+    event_value: String,
+    /// `{EVENT.UPDATE.STATUS}`, `1` for acknowledgements and other updates.
+    event_update_status: Option<String>,
+    /// `{EVENT.NSEVERITY}`, `0` (not classified) to `5` (disaster).
+    event_nseverity: Option<String>,
+    /// `{EVENT.SEVERITY}`, for media types without `event_nseverity`.
+    event_severity: Option<String>,
+    event_name: Option<String>,
+    host: Option<String>,
+    message: Option<String>,
+    /// `{$ZABBIX.URL}`, the frontend's address, to link to the event.
+    zabbix_url: Option<String>,
+    trigger_id: Option<String>,
+}
+
+/// Zabbix's severity by number or name, `None` for custom names.
+fn priority(nseverity: Option<&str>, severity: Option<&str>) -> Option<Priority> {
+    let level = match (nseverity, severity.map(str::to_lowercase).as_deref()) {
+        (Some(x), _) => x.trim().parse().ok()?,
+        (None, Some("not classified")) => 0,
+        (None, Some("information")) => 1,
+        (None, Some("warning")) => 2,
+        (None, Some("average")) => 3,
+        (None, Some("high")) => 4,
+        (None, Some("disaster")) => 5,
+        (None, _) => return None,
+    };
+    match level {
+        0 => Some(Priority::VeryLow),
+        1 => Some(Priority::Moderate),
+        2 | 3 => Some(Priority::Normal),
+        4 => Some(Priority::High),
+        5 => Some(Priority::Emergency),
+        _ => None,
+    }
+}
+
+impl From<ZabbixEvent> for Message {
+    /// Updates like acknowledgements have no alerts.
+    fn from(event: ZabbixEvent) -> Self {
+        if event.event_update_status.as_deref() == Some("1") {
+            log::debug!("Ignored an update of Zabbix event {}", event.event_id);
+            return Message::new(vec![]);
+        }
+        let text = |x: Option<String>| x.map(|x| x.trim().to_string()).filter(|x| !x.is_empty());
+        let status = match event.event_value.trim() {
+            "1" => "firing",
+            "0" => "resolved",
+            other => other,
+        };
+        let name =
+            text(event.event_name).unwrap_or_else(|| format!("Zabbix event {}", event.event_id));
+        let mut labels = BTreeMap::from([("alertname".to_string(), name)]);
+        if let Some(host) = text(event.host) {
+            labels.insert("host".to_string(), host);
+        }
+        let severity = text(event.event_severity);
+        if let Some(severity) = &severity {
+            labels.insert("severity".to_string(), severity.clone());
+        }
+        let annotations = text(event.message)
+            .map(|x| ("summary".to_string(), x))
+            .into_iter()
+            .collect();
+        let url = match (text(event.zabbix_url), text(event.trigger_id)) {
+            (Some(base), Some(trigger)) => format!(
+                "{}/tr_events.php?triggerid={trigger}&eventid={}",
+                base.trim_end_matches('/'),
+                event.event_id
+            ),
+            (Some(base), None) => base,
+            _ => String::new(),
+        };
+        let mut alert = Alert::new(
+            status,
+            format!("zabbix-{}", event.event_id),
+            labels,
+            annotations,
+        )
+        .with_generator_url(url);
+        if let Some(priority) = priority(event.event_nseverity.as_deref(), severity.as_deref()) {
+            alert = alert.with_priority(priority);
+        }
+        Message::new(vec![alert])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts() {
+        let convert = |json: &str| -> Message {
+            let event: ZabbixEvent = serde_json::from_str(json).expect("Failed to load event");
+            event.into()
+        };
+        let message = convert(
+            r#"{"event_id": "4321", "event_value": "1", "event_update_status": "0",
+                "event_nseverity": "5", "event_severity": "Disaster", "event_name": "Host is unreachable",
+                "host": "db1", "message": "Ping failed for 5 minutes",
+                "zabbix_url": "https://zabbix.example.com/", "trigger_id": "17"}"#,
+        );
+        let alert = &message.alerts()[0];
+        assert_eq!(alert.fingerprint(), "zabbix-4321");
+        assert_eq!(alert.status(), "firing");
+        assert_eq!(alert.name(), "Host is unreachable");
+        assert_eq!(alert.summary(), "Ping failed for 5 minutes");
+        assert_eq!(alert.get_priority(), Priority::Emergency);
+        assert_eq!(alert.labels().get("host"), Some("db1"));
+        assert_eq!(
+            alert.generator_url(),
+            "https://zabbix.example.com/tr_events.php?triggerid=17&eventid=4321"
+        );
+
+        let message = convert(r#"{"event_id": "4321", "event_value": "0"}"#);
+        assert_eq!(message.alerts()[0].status(), "resolved");
+        assert_eq!(message.alerts()[0].fingerprint(), "zabbix-4321");
+        let message =
+            convert(r#"{"event_id": "4321", "event_value": "1", "event_update_status": "1"}"#);
+        assert!(message.alerts().is_empty());
+
+        assert_eq!(priority(None, Some("Average")), Some(Priority::Normal));
+        assert_eq!(priority(Some("4"), Some("Custom")), Some(Priority::High));
+        assert_eq!(priority(None, Some("Custom")), None);
+    }
+}
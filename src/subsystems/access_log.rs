@@ -0,0 +1,109 @@
+use chrono::Utc;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// One line per HTTP request, written to `access_log_file` or else the log.
+#[derive(Clone, Default)]
+pub(crate) struct AccessLog {
+    file: Option<Arc<Mutex<File>>>,
+}
+
+/// What is logged about a request. Requests that couldn't be read have no method or path.
+pub(crate) struct Entry<'a> {
+    pub(crate) remote: Option<SocketAddr>,
+    pub(crate) method: Option<&'a str>,
+    pub(crate) path: Option<&'a str>,
+    pub(crate) status: u16,
+    pub(crate) body_bytes: usize,
+    pub(crate) duration: Duration,
+}
+
+impl AccessLog {
+    pub(crate) fn new(access_log_file: &Option<String>) -> Self {
+        let path = match access_log_file {
+            Some(x) => x,
+            None => return AccessLog::default(),
+        };
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => AccessLog {
+                file: Some(Arc::new(Mutex::new(file))),
+            },
+            Err(e) => {
+                log::error!("Failed to open access log {path}, logging requests instead. {e}");
+                AccessLog::default()
+            }
+        }
+    }
+
+    pub(crate) fn record(&self, entry: &Entry) {
+        let line = entry.to_string();
+        let file = match &self.file {
+            Some(file) => file,
+            None => {
+                log::info!(target: "access_log", "{line}");
+                return;
+            }
+        };
+        let mut file = file.lock().expect("Access log lock was poisoned");
+        let at = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ");
+        if let Err(e) = writeln!(file, "{at} {line}") {
+            log::error!("Failed to write to the access log. {e}");
+        }
+    }
+}
+
+impl std::fmt::Display for Entry<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let remote = self
+            .remote
+            .map_or_else(|| "-".to_string(), |x| x.to_string());
+        write!(
+            f,
+            "{remote} \"{} {}\" {} {} {}ms",
+            self.method.unwrap_or("-"),
+            self.path.unwrap_or("-"),
+            self.status,
+            self.body_bytes,
+            self.duration.as_millis()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_entry() {
+        let entry = Entry {
+            remote: Some("10.0.0.2:51234".parse().expect("Failed to parse address")),
+            method: Some("POST"),
+            path: Some("/webhooks/grafana"),
+            status: 200,
+            body_bytes: 8,
+            duration: Duration::from_millis(12),
+        };
+        assert_eq!(
+            entry.to_string(),
+            "10.0.0.2:51234 \"POST /webhooks/grafana\" 200 8 12ms"
+        );
+
+        let path = std::env::temp_dir().join(format!("access-{}.log", std::process::id()));
+        let access_log = AccessLog::new(&Some(path.to_string_lossy().to_string()));
+        access_log.record(&Entry {
+            remote: None,
+            method: None,
+            path: None,
+            status: 408,
+            ..entry
+        });
+        let written = std::fs::read_to_string(&path).expect("Failed to read access log");
+        let _ = std::fs::remove_file(&path);
+        assert!(written.ends_with(" - \"- -\" 408 8 12ms\n"), "{written}");
+    }
+}
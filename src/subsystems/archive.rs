@@ -0,0 +1,161 @@
+use crate::{
+    clock::Clock,
+    models::{
+        config::Config,
+        fingerprint::{Fingerprints, PreviousEvent},
+    },
+    subsystems::shutdown::sleep_or_shutdown,
+};
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    sync::{broadcast::Receiver, Mutex},
+    time::Duration,
+};
+
+const ARCHIVE_EVERY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Moves old, resolved fingerprints out of the live store and into
+/// gzipped JSON lines files, one per day the archive ran.
+pub(crate) async fn main_loop(
+    config: Config,
+    fingerprints: Arc<Mutex<Fingerprints>>,
+    clock: Arc<dyn Clock>,
+    mut shutdown: Receiver<()>,
+) {
+    if config.archive_dir().is_none() {
+        log::trace!("Archive not configured. Exiting archive loop.");
+        return;
+    }
+    loop {
+        let mut finger_guard = fingerprints.lock().await;
+        if archive_old(&config, &mut finger_guard, clock.now()) > 0 {
+            finger_guard.save(&config);
+        }
+        drop(finger_guard);
+        if sleep_or_shutdown(ARCHIVE_EVERY, &mut shutdown).await {
+            log::debug!("Archive loop stopped.");
+            return;
+        }
+    }
+}
+
+/// Archives what is older than `archive_after_days`, none when `archive_dir` isn't set.
+/// The guardrails archive too when the fingerprints file grows too large.
+pub(crate) fn archive_old(
+    config: &Config,
+    fingerprints: &mut Fingerprints,
+    now: DateTime<Utc>,
+) -> usize {
+    match config.archive_dir() {
+        Some(directory) => archive(
+            Path::new(directory),
+            fingerprints,
+            now,
+            chrono::Duration::days(*config.archive_after_days()),
+        ),
+        None => 0,
+    }
+}
+
+/// Archive files oldest first, the date in their names sorts them.
+pub(crate) fn files(directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|x| x.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|x| x.to_str())
+                .is_some_and(|x| x.starts_with("fingerprints-") && x.ends_with(".jsonl.gz"))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Returns how many fingerprints were archived. If the archive could not be
+/// written they are left in the store to try again next time.
+fn archive(
+    directory: &Path,
+    fingerprints: &mut Fingerprints,
+    now: DateTime<Utc>,
+    max_age: chrono::Duration,
+) -> usize {
+    let events = fingerprints.take_resolved_before(now - max_age);
+    if events.is_empty() {
+        return 0;
+    }
+    let file = directory.join(format!("fingerprints-{}.jsonl.gz", now.format("%Y-%m-%d")));
+    match write(&file, &events) {
+        Ok(_) => {
+            log::info!("Archived {} fingerprints to {:?}", events.len(), file);
+            events.len()
+        }
+        Err(e) => {
+            log::error!("Failed to archive fingerprints to {:?}, {e}", file);
+            fingerprints.restore(events);
+            0
+        }
+    }
+}
+
+/// Appends to an existing file as another gzip member, which gunzip reads as one stream.
+fn write(file: &Path, events: &[PreviousEvent]) -> std::io::Result<()> {
+    std::fs::create_dir_all(file.parent().unwrap_or_else(|| Path::new(".")))?;
+    let file = OpenOptions::new().create(true).append(true).open(file)?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    for event in events {
+        serde_json::to_writer(&mut encoder, event)?;
+        encoder.write_all(b"\n")?;
+    }
+    encoder.finish()?.sync_all()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::grafana::Alert;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn archives_old_resolved() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let resolved: Alert = serde_json::from_str(&crate::test::consts::create_resolved_alert())
+            .expect("Failed to load default, resolved alert");
+        fingerprints.update_last_alerted(&resolved, Utc::now());
+        let directory = std::env::temp_dir().join(format!("archive-test-{}", std::process::id()));
+        let max_age = chrono::Duration::days(30);
+
+        assert_eq!(
+            archive(&directory, &mut fingerprints, Utc::now(), max_age),
+            0
+        );
+        let later = Utc::now() + chrono::Duration::days(31);
+        assert_eq!(archive(&directory, &mut fingerprints, later, max_age), 1);
+        assert_eq!(fingerprints.len(), 0);
+
+        fingerprints.update_last_alerted(&resolved, Utc::now());
+        assert_eq!(archive(&directory, &mut fingerprints, later, max_age), 1);
+
+        let file = directory.join(format!(
+            "fingerprints-{}.jsonl.gz",
+            later.format("%Y-%m-%d")
+        ));
+        let mut contents = String::new();
+        MultiGzDecoder::new(std::fs::File::open(&file).expect("Missing archive"))
+            .read_to_string(&mut contents)
+            .expect("Failed to read archive");
+        std::fs::remove_dir_all(&directory).expect("Failed to clean up");
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let event: PreviousEvent = serde_json::from_str(lines[0]).expect("Bad JSON line");
+        assert_eq!(event.fingerprint(), "581dd91e73c77248");
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{
+    errors::AddNotificationError,
+    models::{config::Config, digest::Digest},
+    subsystems::{queue::QueueSender, shutdown::sleep_or_shutdown},
+};
+use std::sync::Arc;
+use tokio::{
+    sync::{broadcast::Receiver, Mutex},
+    time::Duration,
+};
+
+pub(crate) async fn main_loop(
+    config: Config,
+    sender: QueueSender,
+    digest: Arc<Mutex<Digest>>,
+    mut shutdown: Receiver<()>,
+) {
+    let every = match config.resolved_digest() {
+        Some(x) => Duration::from_secs(x.every_minutes().max(&1) * 60),
+        None => {
+            log::trace!("Resolved digest not enabled. Exiting digest loop.");
+            return;
+        }
+    };
+    loop {
+        let stopping = sleep_or_shutdown(every, &mut shutdown).await;
+        // sent when stopping too, so nothing collected is lost
+        if let Err(e) = send_digest(&config, &sender, &mut *digest.lock().await) {
+            log::error!("Failed to queue the resolved digest. {e}");
+        }
+        if stopping {
+            log::debug!("Digest loop stopped.");
+            return;
+        }
+    }
+}
+
+/// Queues what was resolved since the last digest to the `resolved_digest` receiver,
+/// returning how many alerts it listed.
+pub(crate) fn send_digest(
+    config: &Config,
+    sender: &QueueSender,
+    digest: &mut Digest,
+) -> Result<usize, AddNotificationError> {
+    let receiver = match config.digest_receiver() {
+        Some(x) => x,
+        None => return Ok(0),
+    };
+    let count = digest.len();
+    if let Some(content) = digest.take(config.app_name()) {
+        sender.add_for(&receiver, &content)?;
+        log::debug!("Queued the resolved digest of {count} alerts");
+    }
+    Ok(count)
+}
@@ -0,0 +1,42 @@
+use crate::{
+    clock::Clock, models::config::Config, models::fingerprint::Fingerprints,
+    subsystems::shutdown::sleep_or_shutdown,
+};
+use std::sync::Arc;
+use tokio::{
+    sync::{broadcast::Receiver, Mutex},
+    time::Duration,
+};
+
+const EXPIRE_EVERY: Duration = Duration::from_secs(60 * 60);
+
+/// Forgets the metadata of fingerprints resolved for `expire_resolved_after_hours`,
+/// so an unrelated alert with the same fingerprint later starts fresh.
+pub(crate) async fn main_loop(
+    config: Config,
+    fingerprints: Arc<Mutex<Fingerprints>>,
+    clock: Arc<dyn Clock>,
+    mut shutdown: Receiver<()>,
+) {
+    let hours = match config.expire_resolved_after_hours() {
+        Some(x) => chrono::Duration::hours(*x),
+        None => {
+            log::trace!("Expiry not configured. Exiting expiry loop.");
+            return;
+        }
+    };
+    loop {
+        let now = clock.now();
+        let mut finger_guard = fingerprints.lock().await;
+        let expired = finger_guard.expire_resolved_before(now - hours, now);
+        if expired > 0 {
+            log::info!("Expired {expired} resolved fingerprints");
+            finger_guard.save(&config);
+        }
+        drop(finger_guard);
+        if sleep_or_shutdown(EXPIRE_EVERY, &mut shutdown).await {
+            log::debug!("Expiry loop stopped.");
+            return;
+        }
+    }
+}
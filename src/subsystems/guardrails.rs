@@ -0,0 +1,484 @@
+use crate::{
+    clock::Clock,
+    models::{config::Config, fingerprint::Fingerprints},
+    subsystems::{
+        archive, prune, queue::QueueSender, self_notification, shutdown::sleep_or_shutdown,
+    },
+};
+use chrono::{DateTime, Utc};
+use nix::sys::statvfs::statvfs;
+use prowl::Priority;
+use std::{fs::OpenOptions, path::Path, sync::Arc};
+use tokio::{
+    sync::{broadcast::Receiver, Mutex},
+    time::Duration,
+};
+
+const CHECK_EVERY: Duration = Duration::from_secs(5 * 60);
+
+pub(crate) async fn main_loop(
+    config: Config,
+    sender: QueueSender,
+    fingerprints: Arc<Mutex<Fingerprints>>,
+    clock: Arc<dyn Clock>,
+    mut shutdown: Receiver<()>,
+) {
+    if config.max_fingerprints_file_bytes().is_none()
+        && config.max_access_log_bytes().is_none()
+        && config.max_archive_bytes().is_none()
+        && config.min_free_disk_bytes().is_none()
+    {
+        log::trace!("Disk guardrails not configured. Exiting guardrails loop.");
+        return;
+    }
+    let mut low_space_notified = false;
+    loop {
+        check(
+            &config,
+            &sender,
+            &fingerprints,
+            clock.now(),
+            &mut low_space_notified,
+        )
+        .await;
+        if sleep_or_shutdown(CHECK_EVERY, &mut shutdown).await {
+            log::debug!("Guardrails loop stopped.");
+            return;
+        }
+    }
+}
+
+async fn check(
+    config: &Config,
+    sender: &QueueSender,
+    fingerprints: &Arc<Mutex<Fingerprints>>,
+    now: DateTime<Utc>,
+    low_space_notified: &mut bool,
+) {
+    if let Some(max_bytes) = config.max_fingerprints_file_bytes() {
+        check_fingerprints_file(config, sender, fingerprints, now, *max_bytes).await;
+    }
+    if let (Some(file), Some(max_bytes)) = (config.access_log_file(), config.max_access_log_bytes())
+    {
+        check_access_log(config, sender, file, *max_bytes);
+    }
+    if let (Some(directory), Some(max_bytes)) = (config.archive_dir(), config.max_archive_bytes()) {
+        check_archive(config, sender, Path::new(directory), *max_bytes);
+    }
+    if let Some(min_free) = config.min_free_disk_bytes() {
+        match lowest_free_bytes(config) {
+            Some((free, path)) if free < *min_free => {
+                log::warn!("Only {free} bytes free on the volume holding {path}");
+                let mut finger_guard = fingerprints.lock().await;
+                if prune::prune(config, &mut finger_guard, now) > 0 {
+                    finger_guard.save(config);
+                }
+                drop(finger_guard);
+                if !*low_space_notified {
+                    let description = format!(
+                        "Only {} free on the volume holding {path}.",
+                        human_bytes(free)
+                    );
+                    self_notification::queue(
+                        config,
+                        sender,
+                        Priority::Moderate,
+                        "[💾] Low disk space",
+                        description,
+                    );
+                    *low_space_notified = true;
+                }
+            }
+            Some(_) => *low_space_notified = false,
+            None => {}
+        }
+    }
+}
+
+/// Prunes and archives first, as those keep what the user asked to keep,
+/// then removes resolved fingerprints until the file fits.
+async fn check_fingerprints_file(
+    config: &Config,
+    sender: &QueueSender,
+    fingerprints: &Arc<Mutex<Fingerprints>>,
+    now: DateTime<Utc>,
+    max_bytes: u64,
+) {
+    let size = match std::fs::metadata(config.fingerprints_file()) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            log::debug!("Could not read size of fingerprints file, {e}");
+            return;
+        }
+    };
+    if size <= max_bytes {
+        return;
+    }
+
+    log::warn!("Fingerprints file is {size} bytes, compacting to {max_bytes} bytes");
+    let mut finger_guard = fingerprints.lock().await;
+    let pruned = prune::prune(config, &mut finger_guard, now);
+    let archived = archive::archive_old(config, &mut finger_guard, now);
+    let removed = finger_guard.compact(max_bytes);
+    finger_guard.save(config);
+    let remaining = finger_guard.len();
+    drop(finger_guard);
+
+    let description = format!(
+        "Fingerprints file was {}, over the limit of {}. Pruned {pruned}, archived {archived} and removed {removed} resolved fingerprints, {remaining} remain.",
+        human_bytes(size),
+        human_bytes(max_bytes),
+    );
+    self_notification::queue(
+        config,
+        sender,
+        Priority::Moderate,
+        "[💾] Fingerprints compacted",
+        description,
+    );
+}
+
+/// Copies the log aside and empties it, as it is kept open for appending.
+/// Requests logged between the two are lost.
+fn check_access_log(config: &Config, sender: &QueueSender, file: &str, max_bytes: u64) {
+    let size = match std::fs::metadata(file) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            log::debug!("Could not read size of access log, {e}");
+            return;
+        }
+    };
+    if size <= max_bytes {
+        return;
+    }
+
+    let rotated = format!("{file}.1");
+    let result = std::fs::copy(file, &rotated)
+        .and_then(|_| OpenOptions::new().write(true).open(file))
+        .and_then(|x| x.set_len(0));
+    if let Err(e) = result {
+        log::error!("Failed to rotate the access log {file}, {e}");
+        return;
+    }
+    log::info!("Rotated the access log {file} to {rotated}");
+    let description = format!(
+        "Access log was {}, over the limit of {}. Moved to {rotated}.",
+        human_bytes(size),
+        human_bytes(max_bytes),
+    );
+    self_notification::queue(
+        config,
+        sender,
+        Priority::VeryLow,
+        "[💾] Access log rotated",
+        description,
+    );
+}
+
+fn check_archive(config: &Config, sender: &QueueSender, directory: &Path, max_bytes: u64) {
+    let files = match archive::files(directory) {
+        Ok(files) => files,
+        Err(e) => {
+            log::debug!("Could not list the archive {:?}, {e}", directory);
+            return;
+        }
+    };
+    let sized: Vec<_> = files
+        .into_iter()
+        .filter_map(|file| Some((std::fs::metadata(&file).ok()?.len(), file)))
+        .collect();
+    let size: u64 = sized.iter().map(|(bytes, _)| bytes).sum();
+    if size <= max_bytes {
+        return;
+    }
+
+    let mut remaining = size;
+    let mut removed = 0;
+    // the newest is still being appended to
+    for (bytes, file) in &sized[..sized.len() - 1] {
+        if remaining <= max_bytes {
+            break;
+        }
+        match std::fs::remove_file(file) {
+            Ok(_) => {
+                log::info!("Removed archive {:?}", file);
+                remaining -= bytes;
+                removed += 1;
+            }
+            Err(e) => log::error!("Failed to remove archive {:?}, {e}", file),
+        }
+    }
+    let description = format!(
+        "Archive was {}, over the limit of {}. Removed the {removed} oldest files, {} remain.",
+        human_bytes(size),
+        human_bytes(max_bytes),
+        human_bytes(remaining),
+    );
+    self_notification::queue(
+        config,
+        sender,
+        Priority::VeryLow,
+        "[💾] Archive rotated",
+        description,
+    );
+}
+
+/// The least free space of the volumes holding what the notifier writes.
+fn lowest_free_bytes(config: &Config) -> Option<(u64, String)> {
+    let directory = |file: &str| match Path::new(file).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => Path::new(".").to_path_buf(),
+    };
+    let mut directories = vec![(
+        directory(config.fingerprints_file()),
+        config.fingerprints_file(),
+    )];
+    if let Some(file) = config.access_log_file() {
+        directories.push((directory(file), file));
+    }
+    if let Some(archive_dir) = config.archive_dir() {
+        directories.push((Path::new(archive_dir).to_path_buf(), archive_dir));
+    }
+    directories
+        .into_iter()
+        .filter_map(|(directory, path)| Some((free_bytes(&directory)?, path.clone())))
+        .min_by_key(|(free, _)| *free)
+}
+
+fn free_bytes(directory: &Path) -> Option<u64> {
+    match statvfs(directory) {
+        // the field types are platform dependent
+        #[allow(clippy::unnecessary_cast)]
+        Ok(stats) => Some(stats.blocks_available() as u64 * stats.fragment_size() as u64),
+        Err(e) => {
+            log::error!("Failed to get free space for {:?}, {e}", directory);
+            None
+        }
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const MB: u64 = 1024 * 1024;
+    if bytes >= MB {
+        format!("{} MB", bytes / MB)
+    } else {
+        format!("{bytes} bytes")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        models::grafana::Alert,
+        subsystems::queue::{NotificationQueue, QueueReceiver},
+        test::MockClock,
+    };
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("guardrails-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).expect("Failed to create temp dir");
+        directory
+    }
+
+    fn config(directory: &Path, options: serde_json::Value) -> Config {
+        let mut config = json!({
+            "prowl_api_keys": ["key"],
+            "fingerprints_file": directory.join("fingerprints.json"),
+        });
+        for (name, value) in options.as_object().expect("Options must be an object") {
+            config[name] = value.clone();
+        }
+        serde_json::from_value(config).expect("Failed to parse config")
+    }
+
+    fn events(reciever: &QueueReceiver) -> Vec<String> {
+        std::iter::from_fn(|| reciever.pop())
+            .map(|x| x.event().clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn fingerprints_file_prunes_and_archives() {
+        let directory = temp_dir("fingerprints");
+        let clock = MockClock::new(Utc::now());
+        let config = config(
+            &directory,
+            json!({
+                "max_fingerprints_file_bytes": 10,
+                "prune_unseen_after_days": 3,
+                "archive_dir": directory.join("archive"),
+                "archive_after_days": 1,
+            }),
+        );
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        for (status, fingerprint) in [("firing", "aaaa"), ("resolved", "bbbb")] {
+            let json = match status {
+                "firing" => crate::test::consts::create_firing_alert(),
+                _ => crate::test::consts::create_resolved_alert(),
+            }
+            .replace("581dd91e73c77248", fingerprint);
+            let alert: Alert = serde_json::from_str(&json).expect("Failed to load alert");
+            fingerprints.update_last_alerted(&alert, clock.now());
+        }
+        fingerprints.save(&config);
+        let fingerprints = Arc::new(Mutex::new(fingerprints));
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let mut low_space_notified = false;
+
+        // within a day the resolved one can't be archived, so it is compacted
+        clock.advance(chrono::Duration::hours(2));
+        check(
+            &config,
+            &sender,
+            &fingerprints,
+            clock.now(),
+            &mut low_space_notified,
+        )
+        .await;
+        assert_eq!(fingerprints.lock().await.len(), 1);
+        assert!(archive::files(&directory.join("archive")).is_err());
+        assert_eq!(events(&reciever), vec!["[💾] Fingerprints compacted"]);
+
+        // the resolved one is archived, and the firing one unseen for days is pruned
+        let resolved: Alert = serde_json::from_str(&crate::test::consts::create_resolved_alert())
+            .expect("Failed to load default, resolved alert");
+        fingerprints
+            .lock()
+            .await
+            .update_last_alerted(&resolved, clock.now());
+        fingerprints.lock().await.save(&config);
+        clock.advance(chrono::Duration::hours(71));
+        check(
+            &config,
+            &sender,
+            &fingerprints,
+            clock.now(),
+            &mut low_space_notified,
+        )
+        .await;
+        assert_eq!(fingerprints.lock().await.len(), 0);
+        let archived = archive::files(&directory.join("archive")).expect("Missing archive");
+        assert_eq!(archived.len(), 1);
+        let notification = reciever.pop().expect("Missing notification");
+        assert!(
+            notification
+                .description()
+                .contains("Pruned 1, archived 1 and removed 0 resolved fingerprints, 0 remain."),
+            "{}",
+            notification.description()
+        );
+        std::fs::remove_dir_all(&directory).expect("Failed to clean up");
+    }
+
+    #[tokio::test]
+    async fn access_log_and_archive_rotate() {
+        let directory = temp_dir("rotate");
+        let access_log = directory.join("access.log");
+        let archive_dir = directory.join("archive");
+        let config = config(
+            &directory,
+            json!({
+                "access_log_file": access_log,
+                "max_access_log_bytes": 10,
+                "archive_dir": archive_dir,
+                "max_archive_bytes": 25,
+            }),
+        );
+        std::fs::write(&access_log, "GET / 200\nGET / 200\n").expect("Failed to write log");
+        std::fs::create_dir_all(&archive_dir).expect("Failed to create archive");
+        for day in ["01", "02", "03"] {
+            let file = archive_dir.join(format!("fingerprints-2023-01-{day}.jsonl.gz"));
+            std::fs::write(file, "0123456789").expect("Failed to write archive");
+        }
+        std::fs::write(archive_dir.join("notes.txt"), "0123456789").expect("Failed to write");
+        let fingerprints = Arc::new(Mutex::new(Fingerprints::load_or_default(&config)));
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let mut low_space_notified = false;
+
+        check(
+            &config,
+            &sender,
+            &fingerprints,
+            Utc::now(),
+            &mut low_space_notified,
+        )
+        .await;
+        assert_eq!(
+            events(&reciever),
+            vec!["[💾] Access log rotated", "[💾] Archive rotated"]
+        );
+        let read = |path: PathBuf| std::fs::read_to_string(path).expect("Failed to read");
+        assert_eq!(read(access_log.clone()), "");
+        assert_eq!(
+            read(directory.join("access.log.1")),
+            "GET / 200\nGET / 200\n"
+        );
+        let names: Vec<_> = archive::files(&archive_dir)
+            .expect("Missing archive")
+            .iter()
+            .map(|x| {
+                x.file_name()
+                    .expect("No name")
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "fingerprints-2023-01-02.jsonl.gz",
+                "fingerprints-2023-01-03.jsonl.gz"
+            ]
+        );
+        assert!(archive_dir.join("notes.txt").exists());
+
+        // now within the limits
+        check(
+            &config,
+            &sender,
+            &fingerprints,
+            Utc::now(),
+            &mut low_space_notified,
+        )
+        .await;
+        assert!(events(&reciever).is_empty());
+        std::fs::remove_dir_all(&directory).expect("Failed to clean up");
+    }
+
+    #[tokio::test]
+    async fn low_disk_space() {
+        let directory = temp_dir("space");
+        let config = config(
+            &directory,
+            json!({ "min_free_disk_bytes": u64::MAX, "prune_resolved_after_hours": 1 }),
+        );
+        let clock = MockClock::new(Utc::now());
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let resolved: Alert = serde_json::from_str(&crate::test::consts::create_resolved_alert())
+            .expect("Failed to load default, resolved alert");
+        fingerprints.update_last_alerted(&resolved, clock.now());
+        let fingerprints = Arc::new(Mutex::new(fingerprints));
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let mut low_space_notified = false;
+
+        clock.advance(chrono::Duration::hours(2));
+        for _ in 0..2 {
+            check(
+                &config,
+                &sender,
+                &fingerprints,
+                clock.now(),
+                &mut low_space_notified,
+            )
+            .await;
+        }
+        // notified once, and pruned to make room
+        assert_eq!(events(&reciever), vec!["[💾] Low disk space"]);
+        assert_eq!(fingerprints.lock().await.len(), 0);
+        std::fs::remove_dir_all(&directory).expect("Failed to clean up");
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{
+    models::{config::Config, sink::Sink, tenant::Tenant},
+    subsystems::{shutdown::sleep_or_shutdown, sinks::Sinks},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tokio::{
+    sync::{broadcast::Receiver, Notify},
+    time::Duration,
+};
+
+/// Failed deliveries or probes in a row before the circuit opens.
+const FAILURE_THRESHOLD: usize = 3;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CircuitState {
+    /// Deliveries are sent as usual.
+    Closed,
+    /// The backend is down, deliveries wait instead of using up their retries.
+    Open,
+    /// The cooldown passed, the next delivery checks if the backend is back.
+    HalfOpen,
+}
+
+/// What `/api/backends` shows for a backend.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct BackendStatus {
+    name: String,
+    state: CircuitState,
+    since: DateTime<Utc>,
+    consecutive_failures: usize,
+    last_error: Option<String>,
+    last_probe: Option<DateTime<Utc>>,
+}
+
+/// Circuit breaker for a delivery backend, shared by the queue and the health checks.
+#[derive(Clone)]
+pub(crate) struct Breaker {
+    shared: Arc<BreakerShared>,
+}
+
+struct BreakerShared {
+    status: Mutex<BackendStatus>,
+    cooldown: Duration,
+    /// Shared by every breaker of a `Breakers`.
+    closed: Arc<Notify>,
+}
+
+impl Breaker {
+    #[cfg(test)]
+    pub(crate) fn new(name: &str, cooldown: Duration) -> Self {
+        Self::sharing(name, cooldown, Arc::new(Notify::new()))
+    }
+
+    fn sharing(name: &str, cooldown: Duration, closed: Arc<Notify>) -> Self {
+        Self {
+            shared: Arc::new(BreakerShared {
+                status: Mutex::new(BackendStatus {
+                    name: name.to_string(),
+                    state: CircuitState::Closed,
+                    since: Utc::now(),
+                    consecutive_failures: 0,
+                    last_error: None,
+                    last_probe: None,
+                }),
+                cooldown,
+                closed,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BackendStatus> {
+        self.shared
+            .status
+            .lock()
+            .expect("Circuit breaker lock was poisoned")
+    }
+
+    pub(crate) fn status(&self) -> BackendStatus {
+        self.lock().clone()
+    }
+
+    pub(crate) fn name(&self) -> String {
+        self.lock().name.clone()
+    }
+
+    pub(crate) fn record_success(&self, now: DateTime<Utc>) {
+        let mut status = self.lock();
+        status.consecutive_failures = 0;
+        status.last_error = None;
+        if status.state != CircuitState::Closed {
+            log::info!("{} is reachable again, closing circuit", status.name);
+            status.state = CircuitState::Closed;
+            status.since = now;
+            drop(status);
+            self.shared.closed.notify_waiters();
+        }
+    }
+
+    pub(crate) fn record_failure(&self, error: String, now: DateTime<Utc>) {
+        let mut status = self.lock();
+        status.consecutive_failures += 1;
+        status.last_error = Some(error);
+        let open = match status.state {
+            CircuitState::Closed => status.consecutive_failures >= FAILURE_THRESHOLD,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if open {
+            log::warn!(
+                "{} failed {} times in a row, opening circuit",
+                status.name,
+                status.consecutive_failures
+            );
+            status.state = CircuitState::Open;
+            status.since = now;
+        }
+    }
+
+    pub(crate) fn record_probe(&self, result: Result<(), String>, now: DateTime<Utc>) {
+        self.lock().last_probe = Some(now);
+        match result {
+            Ok(()) => self.record_success(now),
+            Err(e) => {
+                log::warn!("Health check failed: {e}");
+                self.record_failure(e, now)
+            }
+        }
+    }
+
+    /// How long deliveries should wait before trying the backend, if at all.
+    /// Once the cooldown has passed the circuit goes half open to let one through.
+    pub(crate) fn wait_time(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let mut status = self.lock();
+        if status.state != CircuitState::Open {
+            return None;
+        }
+        let elapsed = (now - status.since).to_std().unwrap_or_default();
+        match self.shared.cooldown.checked_sub(elapsed) {
+            Some(remaining) if !remaining.is_zero() => Some(remaining),
+            _ => {
+                status.state = CircuitState::HalfOpen;
+                status.since = now;
+                None
+            }
+        }
+    }
+}
+
+/// A circuit breaker per delivery backend by its `Sink::name`, so one service being down
+/// doesn't hold up deliveries to the others.
+#[derive(Clone)]
+pub(crate) struct Breakers {
+    cooldown: Duration,
+    breakers: Arc<Mutex<BTreeMap<&'static str, Breaker>>>,
+    closed: Arc<Notify>,
+}
+
+impl Breakers {
+    pub(crate) fn new(cooldown: Duration) -> Self {
+        let breakers = Self {
+            cooldown,
+            breakers: Arc::new(Mutex::new(BTreeMap::new())),
+            closed: Arc::new(Notify::new()),
+        };
+        // always shown, as it is sent to without a receiver
+        breakers.get(Sink::Prowl.name());
+        breakers
+    }
+
+    /// The backend's breaker, made the first time it is delivered to.
+    pub(crate) fn get(&self, name: &'static str) -> Breaker {
+        self.breakers
+            .lock()
+            .expect("Circuit breakers lock was poisoned")
+            .entry(name)
+            .or_insert_with(|| Breaker::sharing(name, self.cooldown, self.closed.clone()))
+            .clone()
+    }
+
+    pub(crate) fn statuses(&self) -> Vec<BackendStatus> {
+        self.breakers
+            .lock()
+            .expect("Circuit breakers lock was poisoned")
+            .values()
+            .map(Breaker::status)
+            .collect()
+    }
+
+    /// Resolves the next time any of the circuits closes.
+    pub(crate) async fn closed(&self) {
+        self.closed.notified().await
+    }
+}
+
+/// Probes every service the notifier sends with each `health_check_secs`, so a circuit
+/// closes as soon as its service is reachable again instead of waiting for the next delivery.
+pub(crate) async fn main_loop(
+    config: Config,
+    sinks: Sinks,
+    breakers: Breakers,
+    mut shutdown: Receiver<()>,
+) {
+    let every = match config.health_check_secs() {
+        Some(x) => Duration::from_secs(*x),
+        None => {
+            log::trace!("Health checks not configured. Exiting health loop.");
+            return;
+        }
+    };
+    let probes = probes(&config);
+    if probes.is_empty() || *config.test_mode() {
+        log::debug!("Nothing to check, or in test mode. Exiting health loop.");
+        return;
+    }
+    loop {
+        check(&sinks, &breakers, &probes, Utc::now()).await;
+        if sleep_or_shutdown(every, &mut shutdown).await {
+            log::debug!("Health loop stopped.");
+            return;
+        }
+    }
+}
+
+/// A key of each receiver, so each server a service is configured with is checked.
+fn probes(config: &Config) -> Vec<(Sink, String)> {
+    let mut probes: Vec<(Sink, String)> = config
+        .prowl_api_keys()
+        .first()
+        .map(|x| (Sink::Prowl, x.clone()))
+        .into_iter()
+        .collect();
+    let receivers = config
+        .receivers()
+        .values()
+        .cloned()
+        .chain(config.tenants().values().map(Tenant::receiver));
+    for receiver in receivers {
+        let Some(key) = receiver.api_keys().first() else {
+            continue;
+        };
+        let probe = (receiver.sink().clone(), key.clone());
+        if !probes.contains(&probe) {
+            probes.push(probe);
+        }
+    }
+    probes
+}
+
+/// Each service's circuit gets one result, a failure if any of its servers failed.
+async fn check(sinks: &Sinks, breakers: &Breakers, probes: &[(Sink, String)], now: DateTime<Utc>) {
+    let mut results: BTreeMap<&'static str, Result<(), String>> = BTreeMap::new();
+    for (sink, key) in probes {
+        let result = sinks.verify(sink, key, PROBE_TIMEOUT).await;
+        let combined = results.entry(sink.name()).or_insert(Ok(()));
+        if combined.is_ok() {
+            *combined = result;
+        }
+    }
+    for (name, result) in results {
+        breakers.get(name).record_probe(result, now);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        models::sink::NtfySink,
+        subsystems::sinks::test::{answer, fake_server},
+    };
+    use std::collections::BTreeSet;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn opens_and_closes() {
+        let breaker = Breaker::new("prowl", Duration::from_secs(60));
+        let start = Utc::now();
+        for _ in 1..FAILURE_THRESHOLD {
+            breaker.record_failure("timeout".to_string(), start);
+        }
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert_eq!(breaker.wait_time(start), None);
+
+        breaker.record_failure("timeout".to_string(), start);
+        assert_eq!(breaker.status().state, CircuitState::Open);
+        let later = start + chrono::Duration::seconds(20);
+        assert_eq!(breaker.wait_time(later), Some(Duration::from_secs(40)));
+
+        // after the cooldown one attempt goes through, and failing opens it again
+        let after_cooldown = start + chrono::Duration::seconds(60);
+        assert_eq!(breaker.wait_time(after_cooldown), None);
+        assert_eq!(breaker.status().state, CircuitState::HalfOpen);
+        breaker.record_failure("timeout".to_string(), after_cooldown);
+        assert_eq!(breaker.status().state, CircuitState::Open);
+
+        breaker.record_probe(Ok(()), after_cooldown);
+        let status = breaker.status();
+        assert_eq!(status.state, CircuitState::Closed);
+        assert_eq!(status.consecutive_failures, 0);
+        assert_eq!(status.last_probe, Some(after_cooldown));
+        assert_eq!(breaker.wait_time(after_cooldown), None);
+    }
+
+    #[test]
+    fn breaker_per_backend() {
+        let breakers = Breakers::new(Duration::from_secs(60));
+        let now = Utc::now();
+        for _ in 0..FAILURE_THRESHOLD {
+            breakers
+                .get(Sink::Slack.name())
+                .record_failure("timeout".to_string(), now);
+        }
+        assert!(breakers.get(Sink::Slack.name()).wait_time(now).is_some());
+        assert_eq!(breakers.get(Sink::Prowl.name()).wait_time(now), None);
+
+        let states: Vec<(String, CircuitState)> = breakers
+            .statuses()
+            .into_iter()
+            .map(|x| (x.name, x.state))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("Prowl".to_string(), CircuitState::Closed),
+                ("Slack".to_string(), CircuitState::Open)
+            ]
+        );
+    }
+
+    #[test]
+    fn probes_each_receiver() {
+        let config = Config::load(Some("src/resources/test-max-config.json".to_string()));
+        let probes = probes(&config);
+        let names: BTreeSet<&str> = probes.iter().map(|(sink, _)| sink.name()).collect();
+        assert_eq!(
+            names,
+            BTreeSet::from(["Email", "Gotify", "Prowl", "Pushover", "ntfy"])
+        );
+        let topics: Vec<&str> = probes
+            .iter()
+            .filter(|(sink, _)| sink.name() == "ntfy")
+            .map(|(_, key)| key.as_str())
+            .collect();
+        assert_eq!(topics.len(), 2);
+        assert!(topics.contains(&"grafana-alerts") && topics.contains(&"ops"));
+        assert_eq!(probes[0], (Sink::Prowl, config.prowl_api_keys()[0].clone()));
+    }
+
+    #[tokio::test]
+    async fn probe_closes_circuit() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let server = format!("http://{}", listener.local_addr().expect("No address"));
+        let fake = tokio::spawn(fake_server(
+            listener,
+            vec![answer("200 OK", "", b"{\"event\":\"open\"}")],
+        ));
+        let breakers = Breakers::new(Duration::from_secs(60));
+        let now = Utc::now();
+        for _ in 0..FAILURE_THRESHOLD {
+            breakers
+                .get("ntfy")
+                .record_failure("timeout".to_string(), now);
+        }
+        let sink = Sink::Ntfy(NtfySink::new(server, Some("tk_phone".to_string())));
+
+        check(
+            &Sinks::default(),
+            &breakers,
+            &[(sink, "alerts".to_string())],
+            now,
+        )
+        .await;
+        let status = breakers.get("ntfy").status();
+        assert_eq!(status.state, CircuitState::Closed);
+        assert_eq!(status.last_probe, Some(now));
+        // Prowl isn't configured here, so it isn't probed
+        assert_eq!(breakers.get("Prowl").status().last_probe, None);
+        let requests = fake.await.expect("Fake ntfy failed");
+        assert!(
+            requests[0].starts_with("GET /alerts/json?poll=1&since=latest HTTP/1.1"),
+            "{}",
+            requests[0]
+        );
+        assert!(requests[0].contains("authorization: Bearer tk_phone"));
+    }
+}
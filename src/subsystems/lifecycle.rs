@@ -0,0 +1,48 @@
+use crate::{
+    models::config::Config,
+    subsystems::{
+        queue::QueueSender,
+        self_notification,
+        sinks::{prowl::ProwlClient, NotificationSink},
+    },
+};
+use prowl::Priority;
+use tokio::time::{timeout, Duration};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub(crate) fn notify_started(config: &Config, sender: &QueueSender, fingerprints: usize) {
+    if !*config.lifecycle_notifications() {
+        return;
+    }
+    let description = format!(
+        "grafana-prowl-notifier {VERSION} started with {fingerprints} fingerprints loaded."
+    );
+    self_notification::queue(
+        config,
+        sender,
+        Priority::VeryLow,
+        "[🟢] Notifier started",
+        description,
+    );
+}
+
+/// The queue may be busy retrying, so this is sent directly with a short timeout.
+pub(crate) async fn notify_stopping(config: &Config, client: &ProwlClient) {
+    if !*config.lifecycle_notifications() || *config.test_mode() {
+        return;
+    }
+    let description = format!("grafana-prowl-notifier {VERSION} is shutting down.");
+    if let Some(notification) = self_notification::create(
+        config,
+        Priority::VeryLow,
+        "[🔴] Notifier shutting down",
+        description,
+    ) {
+        match timeout(Duration::from_secs(10), client.send(&notification)).await {
+            Ok(Ok(_)) => log::debug!("Sent shutdown notification"),
+            Ok(Err(e)) => log::error!("Failed to send shutdown notification, {e}"),
+            Err(_) => log::error!("Timed out sending shutdown notification"),
+        }
+    }
+}
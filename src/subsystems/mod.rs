@@ -1,3 +1,19 @@
+pub(crate) mod access_log;
+pub(crate) mod archive;
+pub(crate) mod digest;
+pub(crate) mod expiry;
+pub(crate) mod guardrails;
+pub(crate) mod health;
+pub(crate) mod lifecycle;
+pub(crate) mod prune;
+pub(crate) mod queue;
+pub(crate) mod realert;
 pub(crate) mod realert_cron;
 pub(crate) mod realert_every;
+pub(crate) mod reload;
+pub(crate) mod scheduled;
+pub(crate) mod self_notification;
 pub(crate) mod server;
+pub(crate) mod shutdown;
+pub(crate) mod sinks;
+pub(crate) mod volume;
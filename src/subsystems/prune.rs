@@ -0,0 +1,51 @@
+use crate::{
+    clock::Clock, models::config::Config, models::fingerprint::Fingerprints,
+    subsystems::shutdown::sleep_or_shutdown,
+};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use tokio::{
+    sync::{broadcast::Receiver, Mutex},
+    time::Duration,
+};
+
+const PRUNE_EVERY: Duration = Duration::from_secs(60 * 60);
+
+/// Removes fingerprints resolved for `prune_resolved_after_hours` or not seen at all
+/// for `prune_unseen_after_days`, so alerts from deleted rules don't stay forever.
+pub(crate) async fn main_loop(
+    config: Config,
+    fingerprints: Arc<Mutex<Fingerprints>>,
+    clock: Arc<dyn Clock>,
+    mut shutdown: Receiver<()>,
+) {
+    if config.prune_resolved_after_hours().is_none() && config.prune_unseen_after_days().is_none() {
+        log::trace!("Pruning not configured. Exiting prune loop.");
+        return;
+    }
+    loop {
+        let mut finger_guard = fingerprints.lock().await;
+        if prune(&config, &mut finger_guard, clock.now()) > 0 {
+            finger_guard.save(&config);
+        }
+        drop(finger_guard);
+        if sleep_or_shutdown(PRUNE_EVERY, &mut shutdown).await {
+            log::debug!("Prune loop stopped.");
+            return;
+        }
+    }
+}
+
+/// Returns how many fingerprints were pruned, none when pruning isn't configured.
+/// The guardrails prune too when the fingerprints file grows too large.
+pub(crate) fn prune(config: &Config, fingerprints: &mut Fingerprints, now: DateTime<Utc>) -> usize {
+    let resolved = config
+        .prune_resolved_after_hours()
+        .map(chrono::Duration::hours);
+    let unseen = config.prune_unseen_after_days().map(chrono::Duration::days);
+    let pruned = fingerprints.prune(resolved.map(|x| now - x), unseen.map(|x| now - x));
+    if pruned > 0 {
+        log::info!("Pruned {pruned} stale fingerprints");
+    }
+    pruned
+}
@@ -0,0 +1,1227 @@
+use crate::{
+    errors::{AddNotificationError, QueueAddError},
+    models::{
+        notification::{Addressed, NotificationContent},
+        receiver::{Delivery, Receiver},
+        sink::Sink,
+    },
+    subsystems::{
+        health::{Breaker, Breakers},
+        sinks::{NotificationSink, Sinks},
+    },
+};
+use chrono::{DateTime, Utc};
+use prowl::{CreationError, Priority};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tokio::{
+    sync::Notify,
+    time::{sleep_until, Duration, Instant},
+};
+
+/// How many delivery events are kept for `/api/deliveries`.
+const MAX_DELIVERY_EVENTS: usize = 500;
+
+/// Notification queue that, unlike a channel, can be inspected and changed while running.
+/// Notifications for the same API keys form a lane, sent in order with the head of the lane
+/// retried until it succeeds. Workers deliver different lanes at the same time, so one slow
+/// receiver doesn't hold up the others.
+/// An item may hold one notification per API key, it succeeds once any of them are delivered.
+pub(crate) struct NotificationQueue {
+    sender: QueueSender,
+    reciever: QueueReceiver,
+}
+
+pub(crate) struct QueueSender {
+    shared: Arc<Shared>,
+    breakers: Breakers,
+}
+
+pub(crate) struct QueueReceiver {
+    shared: Arc<Shared>,
+    breakers: Breakers,
+    sinks: Sinks,
+    /// Sent to instead when an item's service is down.
+    fallbacks: Vec<Receiver>,
+    retry_backoff: Duration,
+    max_retries: Option<usize>,
+    test_mode: bool,
+    workers: usize,
+}
+
+/// What the API shows for a queued notification, the API keys are left out.
+#[derive(Debug, Serialize)]
+pub(crate) struct QueuedNotification {
+    id: u64,
+    event: String,
+    description: String,
+    application: String,
+    priority: Option<Priority>,
+    url: Option<String>,
+    recipients: usize,
+    enqueued_at: DateTime<Utc>,
+    attempts: usize,
+    last_error: Option<String>,
+}
+
+/// What `/api/queue` shows, to see the queue backing up when Prowl is down.
+#[derive(Debug, Serialize)]
+pub(crate) struct QueueStatus {
+    depth: usize,
+    /// Notifications being delivered right now.
+    sending: usize,
+    oldest_enqueued_at: Option<DateTime<Utc>>,
+    oldest_age_secs: Option<i64>,
+    /// Failed attempts that were retried, since the queue started.
+    retries: u64,
+    last_error: Option<LastError>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct LastError {
+    at: DateTime<Utc>,
+    error: String,
+}
+
+/// Something that happened to a queued notification, for following deliveries.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct DeliveryEvent {
+    id: u64,
+    at: DateTime<Utc>,
+    /// The queue ID of the notification.
+    item: u64,
+    event: String,
+    status: DeliveryStatus,
+    error: Option<String>,
+}
+
+impl DeliveryEvent {
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl fmt::Display for DeliveryEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = self.at.with_timezone(&chrono::Local);
+        let status = format!("{:?}", self.status).to_lowercase();
+        write!(
+            f,
+            "{} #{:<4} {status:<8} {}",
+            at.format("%Y-%m-%d %H:%M:%S"),
+            self.item,
+            self.event
+        )?;
+        match &self.error {
+            Some(error) => write!(f, " ({error})"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DeliveryStatus {
+    Queued,
+    Sent,
+    Retrying,
+    Failed,
+    Removed,
+    /// Queued with a lower priority than asked for, because of a `max_priority`.
+    Lowered,
+    /// Sent to the fallback receivers instead, as its service is down.
+    Rerouted,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Woken on anything a waiting worker may care about, like a new item or a sender dropping.
+    changed: Notify,
+}
+
+struct State {
+    next_id: u64,
+    items: VecDeque<QueueItem>,
+    senders: usize,
+    closed: bool,
+    events: VecDeque<DeliveryEvent>,
+    next_event_id: u64,
+    /// Lanes a worker is delivering, which no other worker may take from.
+    busy: HashSet<String>,
+    retries: u64,
+    last_error: Option<LastError>,
+}
+
+struct QueueItem {
+    id: u64,
+    /// The API keys it goes to, items in the same lane are delivered in order.
+    lane: String,
+    notifications: Vec<Arc<Addressed>>,
+    enqueued_at: DateTime<Utc>,
+    attempts: usize,
+    last_error: Option<String>,
+    /// Waiting for the retry backoff until then.
+    retry_at: Option<Instant>,
+    /// Waiting for its service's circuit to close, until then at the latest.
+    held_until: Option<Instant>,
+    /// Addressed to the fallback receivers, which it isn't rerouted from again.
+    rerouted: bool,
+}
+
+/// An item taken by a worker, its lane stays busy until the worker is done.
+struct Taken {
+    id: u64,
+    lane: String,
+    notifications: Vec<Arc<Addressed>>,
+}
+
+/// What a worker should do next.
+enum Next {
+    Deliver(Taken),
+    /// Nothing can be taken now, the earliest retry is at the instant, if any.
+    Wait(Option<Instant>),
+    Exit,
+}
+
+enum Attempt {
+    Sent,
+    Retry(String),
+    Failed(String),
+}
+
+impl NotificationQueue {
+    pub(crate) fn new(
+        sinks: Sinks,
+        retry_backoff: Duration,
+        max_retries: Option<usize>,
+        test_mode: bool,
+        workers: usize,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                next_id: 1,
+                items: VecDeque::new(),
+                senders: 1,
+                closed: false,
+                events: VecDeque::new(),
+                // from the time, so IDs keep increasing across restarts
+                next_event_id: Utc::now().timestamp_millis() as u64,
+                busy: HashSet::new(),
+                retries: 0,
+                last_error: None,
+            }),
+            changed: Notify::new(),
+        });
+        // waits as long as a retry before letting a notification try again
+        let breakers = Breakers::new(retry_backoff);
+        Self {
+            sender: QueueSender {
+                shared: shared.clone(),
+                breakers: breakers.clone(),
+            },
+            reciever: QueueReceiver {
+                shared,
+                breakers,
+                sinks,
+                fallbacks: vec![],
+                retry_backoff,
+                max_retries,
+                test_mode,
+                workers: workers.max(1),
+            },
+        }
+    }
+
+    /// Receivers items are sent to instead while their own service's circuit is open.
+    pub(crate) fn with_fallbacks(mut self, fallbacks: Vec<Receiver>) -> Self {
+        self.reciever.fallbacks = fallbacks;
+        self
+    }
+
+    pub(crate) fn into_parts(self) -> (QueueSender, QueueReceiver) {
+        (self.sender, self.reciever)
+    }
+}
+
+impl Default for NotificationQueue {
+    fn default() -> Self {
+        NotificationQueue::new(Sinks::default(), Duration::from_secs(60), None, false, 1)
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .expect("Notification queue lock was poisoned")
+    }
+}
+
+impl State {
+    fn record(&mut self, item: u64, status: DeliveryStatus, error: Option<String>) {
+        let event = self
+            .items
+            .iter()
+            .find(|x| x.id == item)
+            .and_then(|x| x.notifications.first())
+            .map(|x| x.notification().event().clone())
+            .unwrap_or_default();
+        let at = Utc::now();
+        if let (DeliveryStatus::Retrying | DeliveryStatus::Failed, Some(error)) = (status, &error) {
+            self.last_error = Some(LastError {
+                at,
+                error: error.clone(),
+            });
+        }
+        if status == DeliveryStatus::Retrying {
+            self.retries += 1;
+        }
+        self.next_event_id += 1;
+        self.events.push_back(DeliveryEvent {
+            id: self.next_event_id,
+            at,
+            item,
+            event,
+            status,
+            error,
+        });
+        if self.events.len() > MAX_DELIVERY_EVENTS {
+            self.events.pop_front();
+        }
+    }
+
+    /// Takes the first item that is the head of its lane, when that lane is free
+    /// and not waiting on a retry.
+    fn next(&mut self, now: Instant) -> Next {
+        let mut seen = HashSet::new();
+        let mut earliest: Option<Instant> = None;
+        let mut taken = None;
+        for item in &self.items {
+            if !seen.insert(item.lane.as_str()) || self.busy.contains(&item.lane) {
+                continue;
+            }
+            match item.retry_at.max(item.held_until) {
+                Some(at) if at > now => earliest = Some(earliest.map_or(at, |x| x.min(at))),
+                _ => {
+                    taken = Some(Taken {
+                        id: item.id,
+                        lane: item.lane.clone(),
+                        notifications: item.notifications.clone(),
+                    });
+                    break;
+                }
+            }
+        }
+        match taken {
+            Some(taken) => {
+                self.busy.insert(taken.lane.clone());
+                Next::Deliver(taken)
+            }
+            None if self.items.is_empty() && self.senders == 0 => Next::Exit,
+            None => Next::Wait(earliest),
+        }
+    }
+}
+
+impl QueueSender {
+    /// Queue a notification for sending, returning its queue ID.
+    pub(crate) fn add(&self, notification: Addressed) -> Result<u64, QueueAddError> {
+        self.push(vec![notification])
+    }
+
+    /// Queue notifications that are delivered once any one of them is sent.
+    pub(crate) fn add_any(&self, notifications: Vec<Addressed>) -> Result<u64, QueueAddError> {
+        self.push(notifications)
+    }
+
+    /// Queue the content for each of the receiver's API keys, as its delivery asks.
+    pub(crate) fn add_for(
+        &self,
+        receiver: &Receiver,
+        content: &NotificationContent,
+    ) -> Result<(), AddNotificationError> {
+        match receiver.delivery() {
+            Delivery::Together => {
+                // keys with a max_priority go separately, so the others keep their priority
+                for keys in receiver.keys_by_max_priority() {
+                    self.add(content.to_prowl_for(keys, receiver)?)?;
+                }
+            }
+            Delivery::All => {
+                for key in receiver.api_keys() {
+                    self.add(content.to_prowl_for(vec![key.clone()], receiver)?)?;
+                }
+            }
+            Delivery::Any => {
+                let notifications = receiver
+                    .api_keys()
+                    .iter()
+                    .map(|key| content.to_prowl_for(vec![key.clone()], receiver))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.add_any(notifications)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `add_for` every receiver, like the `destinations` of anything no route matches.
+    pub(crate) fn add_for_each(
+        &self,
+        receivers: &[Receiver],
+        content: &NotificationContent,
+    ) -> Result<(), AddNotificationError> {
+        for receiver in receivers {
+            self.add_for(receiver, content)?;
+        }
+        Ok(())
+    }
+
+    fn push(&self, notifications: Vec<Addressed>) -> Result<u64, QueueAddError> {
+        let mut state = self.shared.lock();
+        if state.closed {
+            return Err(QueueAddError::Closed);
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        let lowered: Vec<String> = notifications
+            .iter()
+            .filter_map(|x| {
+                let from = x.lowered_from().as_ref()?;
+                let to = x.notification().priority().as_ref()?;
+                Some(format!("{from:?} lowered to {to:?} by max_priority"))
+            })
+            .collect();
+        let mut keys: Vec<&String> = notifications.iter().flat_map(|x| x.api_keys()).collect();
+        keys.sort();
+        keys.dedup();
+        let lane = keys
+            .iter()
+            .map(|x| x.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        state.items.push_back(QueueItem {
+            id,
+            lane,
+            notifications: notifications.into_iter().map(Arc::new).collect(),
+            enqueued_at: Utc::now(),
+            attempts: 0,
+            last_error: None,
+            retry_at: None,
+            held_until: None,
+            rerouted: false,
+        });
+        state.record(id, DeliveryStatus::Queued, None);
+        for lowered in lowered {
+            state.record(id, DeliveryStatus::Lowered, Some(lowered));
+        }
+        drop(state);
+        self.shared.changed.notify_waiters();
+        Ok(id)
+    }
+
+    pub(crate) fn items(&self) -> Vec<QueuedNotification> {
+        self.shared
+            .lock()
+            .items
+            .iter()
+            .filter_map(|item| {
+                let notification = item.notifications.first()?.notification();
+                Some(QueuedNotification {
+                    id: item.id,
+                    event: notification.event().clone(),
+                    description: notification.description().clone(),
+                    application: notification.application().clone(),
+                    priority: notification.priority().clone(),
+                    url: notification.url().clone(),
+                    recipients: item.notifications.len(),
+                    enqueued_at: item.enqueued_at,
+                    attempts: item.attempts,
+                    last_error: item.last_error.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Drops a queued notification. Returns false if it was not in the queue.
+    pub(crate) fn remove(&self, id: u64) -> bool {
+        let mut state = self.shared.lock();
+        let index = match state.items.iter().position(|item| item.id == id) {
+            Some(index) => index,
+            None => return false,
+        };
+        state.record(id, DeliveryStatus::Removed, None);
+        state.items.remove(index);
+        drop(state);
+        // the next in its lane may go now
+        self.shared.changed.notify_waiters();
+        true
+    }
+
+    pub(crate) fn status(&self, now: DateTime<Utc>) -> QueueStatus {
+        let state = self.shared.lock();
+        let oldest = state.items.iter().map(|item| item.enqueued_at).min();
+        QueueStatus {
+            depth: state.items.len(),
+            sending: state.busy.len(),
+            oldest_enqueued_at: oldest,
+            oldest_age_secs: oldest.map(|x| (now - x).num_seconds()),
+            retries: state.retries,
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    /// Delivery events after the event ID `since`, oldest first.
+    pub(crate) fn deliveries_since(&self, since: u64) -> Vec<DeliveryEvent> {
+        self.shared
+            .lock()
+            .events
+            .iter()
+            .filter(|x| x.id > since)
+            .cloned()
+            .collect()
+    }
+
+    /// False once the queue's task has stopped, even if it panicked.
+    pub(crate) fn is_running(&self) -> bool {
+        !self.shared.lock().closed
+    }
+
+    /// The circuit breakers deliveries go through, one per service.
+    pub(crate) fn breakers(&self) -> &Breakers {
+        &self.breakers
+    }
+
+    /// Moves a notification to the head of its lane and sends it without waiting
+    /// for the retry backoff. Returns false if it was not in the queue.
+    pub(crate) fn retry_now(&self, id: u64) -> bool {
+        let mut state = self.shared.lock();
+        let index = match state.items.iter().position(|item| item.id == id) {
+            Some(index) => index,
+            None => return false,
+        };
+        if let Some(mut item) = state.items.remove(index) {
+            item.retry_at = None;
+            // only ahead of its own lane, other lanes keep their place
+            let head = state
+                .items
+                .iter()
+                .position(|x| x.lane == item.lane)
+                .unwrap_or(index)
+                .min(index);
+            state.items.insert(head, item);
+        }
+        drop(state);
+        self.shared.changed.notify_waiters();
+        true
+    }
+}
+
+impl Clone for QueueSender {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+            breakers: self.breakers.clone(),
+        }
+    }
+}
+
+impl Drop for QueueSender {
+    fn drop(&mut self) {
+        self.shared.lock().senders -= 1;
+        self.shared.changed.notify_waiters();
+    }
+}
+
+impl QueueReceiver {
+    /// Sends and retries notifications with `workers` tasks. Returns once every
+    /// `QueueSender` has been dropped and the queue is empty, with how many were given up on.
+    pub(crate) async fn async_loop(self) -> usize {
+        log::debug!(
+            "Notification queue processor started with {} workers.",
+            self.workers
+        );
+        let reciever = Arc::new(self);
+        let workers: Vec<_> = (0..reciever.workers)
+            .map(|_| tokio::spawn(reciever.clone().work()))
+            .collect();
+        let mut dropped = 0;
+        for worker in workers {
+            match worker.await {
+                Ok(x) => dropped += x,
+                Err(e) => log::error!("Delivery worker stopped unexpectedly. {e}"),
+            }
+        }
+        log::warn!("Notification queue has been closed.");
+        dropped
+    }
+
+    /// One worker, delivering the head of whichever lane is free.
+    async fn work(self: Arc<Self>) -> usize {
+        let mut dropped = 0;
+        while let Some(Taken {
+            id,
+            lane,
+            notifications,
+        }) = self.next().await
+        {
+            let breaker = self.breaker_for(&notifications);
+            if let Some(wait) = self.wait_for_circuit(&breaker) {
+                if self.reroute(id, &breaker.name()) {
+                    log::info!(
+                        "{} is down, sending notification {id} to the fallbacks",
+                        breaker.name()
+                    );
+                    self.release(&lane);
+                    continue;
+                }
+                log::debug!("Circuit is open, holding notification {id} for {wait:?}");
+                self.hold(id, wait);
+                self.release(&lane);
+                continue;
+            }
+            match self.send(&notifications).await {
+                Attempt::Sent => {
+                    breaker.record_success(Utc::now());
+                    self.finish(id, DeliveryStatus::Sent, None)
+                }
+                Attempt::Retry(e) => {
+                    log::warn!("Will retry notification {id}, failed due to {e}");
+                    breaker.record_failure(e.clone(), Utc::now());
+                    if !self.failed(id, e) {
+                        dropped += 1;
+                    }
+                }
+                Attempt::Failed(e) => {
+                    // API or internal error - lets not hammer with invalid requests.
+                    log::error!("Terminally failed to send notification due to {e}");
+                    self.finish(id, DeliveryStatus::Failed, Some(e));
+                    dropped += 1;
+                }
+            }
+            self.release(&lane);
+        }
+        dropped
+    }
+
+    /// Lets other workers take from the lane again.
+    fn release(&self, lane: &str) {
+        self.shared.lock().busy.remove(lane);
+        self.shared.changed.notify_waiters();
+    }
+
+    /// The breaker of the service an item is sent with.
+    fn breaker_for(&self, notifications: &[Arc<Addressed>]) -> Breaker {
+        let sink = notifications
+            .first()
+            .map(|x| x.sink().name())
+            .unwrap_or(Sink::Prowl.name());
+        self.breakers.get(sink)
+    }
+
+    /// Nothing is sent in test mode, so the circuit never matters there.
+    fn wait_for_circuit(&self, breaker: &Breaker) -> Option<Duration> {
+        if self.test_mode {
+            return None;
+        }
+        breaker.wait_time(Utc::now())
+    }
+
+    /// Holds back an item while its service's circuit is open, so other
+    /// services' lanes keep going.
+    fn hold(&self, id: u64, wait: Duration) {
+        let mut state = self.shared.lock();
+        if let Some(item) = state.items.iter_mut().find(|item| item.id == id) {
+            item.held_until = Some(Instant::now() + wait);
+        }
+    }
+
+    /// Addresses an item to the fallbacks not sent with the service that is down.
+    /// Returns false if there are none, or it already was.
+    fn reroute(&self, id: u64, down: &str) -> bool {
+        let fallbacks: Vec<&Receiver> = self
+            .fallbacks
+            .iter()
+            .filter(|x| x.sink().name() != down)
+            .collect();
+        if fallbacks.is_empty() {
+            return false;
+        }
+        let mut state = self.shared.lock();
+        let item = match state.items.iter_mut().find(|item| item.id == id) {
+            Some(item) if !item.rerouted => item,
+            _ => return false,
+        };
+        let content = match item.notifications.first() {
+            Some(x) => x.content(),
+            None => return false,
+        };
+        let addressed = fallbacks
+            .iter()
+            .map(|receiver| address(receiver, &content))
+            .collect::<Result<Vec<_>, _>>();
+        match addressed {
+            Ok(addressed) => {
+                item.notifications = addressed.into_iter().flatten().map(Arc::new).collect();
+                item.rerouted = true;
+                item.held_until = None;
+                state.record(
+                    id,
+                    DeliveryStatus::Rerouted,
+                    Some(format!("{down} is down")),
+                );
+                true
+            }
+            Err(e) => {
+                log::error!("Failed to address notification {id} to the fallbacks. {e}");
+                false
+            }
+        }
+    }
+
+    /// Lets held items be tried again, after a circuit closed.
+    fn unhold(&self) {
+        let mut state = self.shared.lock();
+        for item in state.items.iter_mut() {
+            item.held_until = None;
+        }
+    }
+
+    async fn next(&self) -> Option<Taken> {
+        loop {
+            // created before looking, so a change in between still wakes it
+            let changed = self.shared.changed.notified();
+            let closed = self.breakers.closed();
+            let retry_at = match self.shared.lock().next(Instant::now()) {
+                Next::Deliver(taken) => return Some(taken),
+                Next::Exit => return None,
+                Next::Wait(retry_at) => retry_at,
+            };
+            match retry_at {
+                Some(at) => {
+                    tokio::select! {
+                        _ = sleep_until(at) => {}
+                        _ = changed => {}
+                        _ = closed => self.unhold(),
+                    }
+                }
+                None => {
+                    tokio::select! {
+                        _ = changed => {}
+                        _ = closed => self.unhold(),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends every notification of an item at once. Any success counts, otherwise
+    /// the item is retried if any of them could not reach their service.
+    async fn send(&self, notifications: &[Arc<Addressed>]) -> Attempt {
+        if self.test_mode {
+            for notification in notifications {
+                log::debug!(
+                    "Test mode, not sending {}",
+                    notification.notification().event()
+                );
+            }
+            return Attempt::Sent;
+        }
+        let handles: Vec<_> = notifications
+            .iter()
+            .cloned()
+            .map(|notification| {
+                let sinks = self.sinks.clone();
+                tokio::spawn(async move { sinks.send(&notification).await })
+            })
+            .collect();
+        let mut sent = false;
+        let mut retry = None;
+        let mut failed = None;
+        for handle in handles {
+            match handle.await {
+                Ok(Ok(_)) => sent = true,
+                Ok(Err(e)) if e.is_retryable() => retry = Some(e.to_string()),
+                Ok(Err(e)) => failed = Some(e.to_string()),
+                Err(e) => failed = Some(e.to_string()),
+            }
+        }
+        match (sent, retry, failed) {
+            (true, _, _) => Attempt::Sent,
+            (false, Some(e), _) => Attempt::Retry(e),
+            (false, None, Some(e)) => Attempt::Failed(e),
+            (false, None, None) => Attempt::Failed("Nothing to send".to_string()),
+        }
+    }
+
+    fn finish(&self, id: u64, status: DeliveryStatus, error: Option<String>) {
+        let mut state = self.shared.lock();
+        state.record(id, status, error);
+        state.items.retain(|item| item.id != id);
+    }
+
+    /// Records a failed attempt. Returns true if the notification should be retried.
+    fn failed(&self, id: u64, error: String) -> bool {
+        let mut state = self.shared.lock();
+        let item = match state.items.iter_mut().find(|item| item.id == id) {
+            Some(item) => item,
+            None => return false,
+        };
+        item.attempts += 1;
+        item.last_error = Some(error.clone());
+        let attempts = item.attempts;
+        if let Some(max) = self.max_retries {
+            if attempts > max {
+                log::warn!("Dropping notification {id} after {attempts} attempts");
+                state.record(id, DeliveryStatus::Failed, Some(error));
+                state.items.retain(|item| item.id != id);
+                return false;
+            }
+        }
+        item.retry_at = Some(Instant::now() + self.retry_backoff);
+        state.record(id, DeliveryStatus::Retrying, Some(error));
+        true
+    }
+
+    #[cfg(test)]
+    pub(crate) fn pop(&self) -> Option<prowl::Notification> {
+        let item = self.shared.lock().items.pop_front()?;
+        let notification = item.notifications.into_iter().next()?;
+        Arc::try_unwrap(notification)
+            .ok()
+            .map(Addressed::into_notification)
+    }
+}
+
+/// The content for each of the receiver's API keys as its delivery asks, for one item
+/// that is delivered once any of them are sent.
+fn address(
+    receiver: &Receiver,
+    content: &NotificationContent,
+) -> Result<Vec<Addressed>, CreationError> {
+    let keys = match receiver.delivery() {
+        Delivery::Together => receiver.keys_by_max_priority(),
+        Delivery::All | Delivery::Any => receiver
+            .api_keys()
+            .iter()
+            .map(|key| vec![key.clone()])
+            .collect(),
+    };
+    keys.into_iter()
+        .map(|keys| content.to_prowl_for(keys, receiver))
+        .collect()
+}
+
+impl Drop for QueueReceiver {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn notification(event: &str) -> Addressed {
+        NotificationContent::new(
+            Some(Priority::Normal),
+            None,
+            "Grafana".to_string(),
+            event.to_string(),
+            "description".to_string(),
+        )
+        .to_prowl(vec!["key".to_string()])
+        .expect("Failed to create notification")
+    }
+
+    #[test]
+    fn add_and_inspect() {
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let first = sender.add(notification("first")).expect("Failed to add");
+        let second = sender.add(notification("second")).expect("Failed to add");
+        assert_ne!(first, second);
+
+        let items = sender.items();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].id, first);
+        assert_eq!(items[0].event, "first");
+        assert_eq!(items[0].attempts, 0);
+        let json = serde_json::to_string(&items).expect("Failed to serialize");
+        assert!(!json.contains("key"));
+
+        assert_eq!(reciever.pop().expect("Missing first").event(), "first");
+        assert_eq!(reciever.pop().expect("Missing second").event(), "second");
+        assert!(reciever.pop().is_none());
+    }
+
+    #[test]
+    fn remove_and_retry_now() {
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let first = sender.add(notification("first")).expect("Failed to add");
+        let second = sender.add(notification("second")).expect("Failed to add");
+        let third = sender.add(notification("third")).expect("Failed to add");
+
+        assert!(sender.remove(first));
+        assert!(!sender.remove(first));
+        assert!(sender.retry_now(third));
+        assert!(!sender.retry_now(first));
+
+        let ids: Vec<u64> = sender.items().iter().map(|item| item.id).collect();
+        assert_eq!(ids, vec![third, second]);
+        assert!(sender.is_running());
+        drop(reciever);
+        assert!(!sender.is_running());
+        assert!(matches!(
+            sender.add(notification("closed")),
+            Err(QueueAddError::Closed)
+        ));
+    }
+
+    #[test]
+    fn failed_attempts() {
+        let (sender, reciever) =
+            NotificationQueue::new(Sinks::default(), Duration::from_secs(60), Some(1), false, 1)
+                .into_parts();
+        let id = sender.add(notification("first")).expect("Failed to add");
+        assert!(reciever.failed(id, "timeout".to_string()));
+        assert_eq!(sender.items()[0].attempts, 1);
+        assert_eq!(sender.items()[0].last_error, Some("timeout".to_string()));
+        assert!(!reciever.failed(id, "timeout".to_string()));
+        assert!(sender.items().is_empty());
+
+        let status = sender.status(Utc::now());
+        assert_eq!(status.depth, 0);
+        assert_eq!(status.oldest_age_secs, None);
+        assert_eq!(status.retries, 1);
+        let last_error = status.last_error.expect("Missing last error");
+        assert_eq!(last_error.error, "timeout");
+
+        sender.add(notification("second")).expect("Failed to add");
+        let later = Utc::now() + chrono::Duration::seconds(90);
+        let status = sender.status(later);
+        assert_eq!(status.depth, 1);
+        assert!(matches!(status.oldest_age_secs, Some(89..=90)));
+    }
+
+    #[test]
+    fn add_for_receiver() {
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let keys = vec!["key1".to_string(), "key2".to_string()];
+        let content = NotificationContent::new(
+            Some(Priority::High),
+            None,
+            "Grafana".to_string(),
+            "event".to_string(),
+            "description".to_string(),
+        );
+
+        let together = Receiver::new(keys.clone(), Delivery::Together);
+        sender.add_for(&together, &content).expect("Failed to add");
+        let recipients: Vec<usize> = sender.items().iter().map(|i| i.recipients).collect();
+        assert_eq!(recipients, vec![1]);
+        // the API keys are only visible through Debug
+        let notification = format!("{:?}", reciever.pop().expect("Missing notification"));
+        assert!(notification.contains("[\"key1\", \"key2\"]"));
+
+        let all = Receiver::new(keys.clone(), Delivery::All);
+        sender.add_for(&all, &content).expect("Failed to add");
+        let recipients: Vec<usize> = sender.items().iter().map(|i| i.recipients).collect();
+        assert_eq!(recipients, vec![1, 1]);
+        let first = format!("{:?}", reciever.pop().expect("Missing first"));
+        let second = format!("{:?}", reciever.pop().expect("Missing second"));
+        assert!(first.contains("[\"key1\"]"));
+        assert!(second.contains("[\"key2\"]"));
+
+        let any = Receiver::new(keys, Delivery::Any);
+        sender.add_for(&any, &content).expect("Failed to add");
+        let recipients: Vec<usize> = sender.items().iter().map(|i| i.recipients).collect();
+        assert_eq!(recipients, vec![2]);
+    }
+
+    #[test]
+    fn add_for_capped_receiver() {
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let content = NotificationContent::new(
+            Some(Priority::Emergency),
+            None,
+            "Grafana".to_string(),
+            "event".to_string(),
+            "description".to_string(),
+        );
+        let receiver: Receiver = serde_json::from_str(
+            r#"{"api_keys": ["key1", "key2", "key3"], "delivery": "together", "max_priority": {"key2": "Normal"}}"#,
+        )
+        .expect("Failed to parse receiver");
+        sender.add_for(&receiver, &content).expect("Failed to add");
+        let items = sender.items();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].priority, Some(Priority::Emergency));
+        assert_eq!(items[1].priority, Some(Priority::Normal));
+        let first = format!("{:?}", reciever.pop().expect("Missing first"));
+        assert!(first.contains("[\"key1\", \"key3\"]"));
+
+        let lowered: Vec<DeliveryEvent> = sender
+            .deliveries_since(0)
+            .into_iter()
+            .filter(|x| x.status == DeliveryStatus::Lowered)
+            .collect();
+        assert_eq!(lowered.len(), 1);
+        assert_eq!(lowered[0].item, items[1].id);
+        assert_eq!(
+            lowered[0].error.as_deref(),
+            Some("Emergency lowered to Normal by max_priority")
+        );
+    }
+
+    #[test]
+    fn retry_now_within_lane() {
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let addressed = |event: &str, key: &str| {
+            NotificationContent::new(
+                Some(Priority::Normal),
+                None,
+                "Grafana".to_string(),
+                event.to_string(),
+                "description".to_string(),
+            )
+            .to_prowl(vec![key.to_string()])
+            .expect("Failed to create notification")
+        };
+        let other = sender
+            .add(addressed("other", "key2"))
+            .expect("Failed to add");
+        let first = sender
+            .add(addressed("first", "key1"))
+            .expect("Failed to add");
+        let second = sender
+            .add(addressed("second", "key1"))
+            .expect("Failed to add");
+
+        // ahead of its own lane, but not of the lanes before it
+        assert!(sender.retry_now(second));
+        let ids: Vec<u64> = sender.items().iter().map(|item| item.id).collect();
+        assert_eq!(ids, vec![other, second, first]);
+    }
+
+    #[test]
+    fn circuit_per_service() {
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let content = NotificationContent::new(
+            Some(Priority::Normal),
+            None,
+            "Grafana".to_string(),
+            "event".to_string(),
+            "description".to_string(),
+        );
+        let slack: Receiver =
+            serde_json::from_str(r#"{"api_keys": ["https://hooks.slack.com/x"], "sink": "slack"}"#)
+                .expect("Failed to parse receiver");
+        sender.add_for(&slack, &content).expect("Failed to add");
+        sender.add(notification("prowl")).expect("Failed to add");
+        let now = Utc::now();
+        for _ in 0..3 {
+            sender
+                .breakers()
+                .get("Slack")
+                .record_failure("timeout".to_string(), now);
+        }
+
+        let take = || match reciever.shared.lock().next(Instant::now()) {
+            Next::Deliver(taken) => Some(taken),
+            _ => None,
+        };
+        let held = take().expect("Missing Slack notification");
+        let wait = reciever
+            .wait_for_circuit(&reciever.breaker_for(&held.notifications))
+            .expect("Slack circuit is not open");
+        reciever.hold(held.id, wait);
+        reciever.release(&held.lane);
+
+        // Prowl deliveries go on while Slack is down
+        let taken = take().expect("Missing Prowl notification");
+        assert_eq!(taken.notifications[0].notification().event(), "prowl");
+        let breaker = reciever.breaker_for(&taken.notifications);
+        assert_eq!(reciever.wait_for_circuit(&breaker), None);
+    }
+
+    #[tokio::test]
+    async fn fallback_while_down() {
+        use crate::subsystems::sinks::test::{answer, fake_server};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let fallback: Receiver = serde_json::from_value(serde_json::json!({
+            "api_keys": [format!("http://{}/hook", listener.local_addr().expect("No address"))],
+            "sink": "slack"
+        }))
+        .expect("Failed to parse receiver");
+        let server = tokio::spawn(fake_server(listener, vec![answer("200 OK", "", b"ok")]));
+        let (sender, reciever) =
+            NotificationQueue::new(Sinks::default(), Duration::from_secs(60), None, false, 1)
+                .with_fallbacks(vec![fallback])
+                .into_parts();
+        let primary: Receiver =
+            serde_json::from_str(r#"{"api_keys": ["http://127.0.0.1:9/down"], "sink": "discord"}"#)
+                .expect("Failed to parse receiver");
+        let now = Utc::now();
+        for _ in 0..3 {
+            sender
+                .breakers()
+                .get("Discord")
+                .record_failure("connection refused".to_string(), now);
+        }
+        let content = NotificationContent::new(
+            Some(Priority::High),
+            None,
+            "Grafana".to_string(),
+            "Disk full".to_string(),
+            "description".to_string(),
+        );
+        sender.add_for(&primary, &content).expect("Failed to add");
+        let shared = sender.shared.clone();
+        drop(sender);
+
+        let dropped = tokio::time::timeout(Duration::from_secs(5), reciever.async_loop())
+            .await
+            .expect("Queue did not exit");
+        assert_eq!(dropped, 0);
+        let requests = server.await.expect("Fake Slack failed");
+        assert!(requests[0].starts_with("POST /hook HTTP/1.1"));
+        assert!(requests[0].contains("Disk full"));
+        let statuses: Vec<DeliveryStatus> = shared.lock().events.iter().map(|x| x.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                DeliveryStatus::Queued,
+                DeliveryStatus::Rerouted,
+                DeliveryStatus::Sent
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn prowl_unavailable() {
+        use crate::subsystems::sinks::{
+            prowl::ProwlClient,
+            test::{answer, fake_server},
+        };
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let base = format!("http://{}", listener.local_addr().expect("No address"));
+        let unavailable = || answer("503 Service Unavailable", "", b"down for maintenance");
+        let server = tokio::spawn(fake_server(
+            listener,
+            vec![unavailable(), unavailable(), unavailable()],
+        ));
+        let (sender, reciever) = NotificationQueue::new(
+            Sinks::with_prowl(ProwlClient::at(&base)),
+            Duration::from_millis(10),
+            Some(2),
+            false,
+            1,
+        )
+        .into_parts();
+        sender
+            .add(notification("Disk full"))
+            .expect("Failed to add");
+        let shared = sender.shared.clone();
+        let breaker = sender.breakers().get(Sink::Prowl.name());
+        drop(sender);
+
+        let dropped = tokio::time::timeout(Duration::from_secs(5), reciever.async_loop())
+            .await
+            .expect("Queue did not exit");
+        assert_eq!(dropped, 1);
+        assert_eq!(server.await.expect("Fake Prowl failed").len(), 3);
+        let statuses: Vec<DeliveryStatus> = shared.lock().events.iter().map(|x| x.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                DeliveryStatus::Queued,
+                DeliveryStatus::Retrying,
+                DeliveryStatus::Retrying,
+                DeliveryStatus::Failed
+            ]
+        );
+        // retried like a connection failure, so the circuit opens too
+        let status = serde_json::to_value(breaker.status()).expect("Failed to serialize");
+        assert_eq!(status["state"], "open");
+        assert_eq!(status["consecutive_failures"], 3);
+    }
+
+    #[tokio::test]
+    async fn drains_before_exiting() {
+        let (sender, reciever) =
+            NotificationQueue::new(Sinks::default(), Duration::from_secs(60), None, true, 4)
+                .into_parts();
+        let other_sender = sender.clone();
+        sender.add(notification("first")).expect("Failed to add");
+        other_sender
+            .add(notification("second"))
+            .expect("Failed to add");
+        let shared = sender.shared.clone();
+        drop(sender);
+        drop(other_sender);
+        tokio::time::timeout(Duration::from_secs(5), reciever.async_loop())
+            .await
+            .expect("Queue did not exit");
+        assert!(shared.lock().items.is_empty());
+    }
+
+    #[test]
+    fn lanes() {
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let addressed = |event: &str, key: &str| {
+            NotificationContent::new(
+                Some(Priority::Normal),
+                None,
+                "Grafana".to_string(),
+                event.to_string(),
+                "description".to_string(),
+            )
+            .to_prowl(vec![key.to_string()])
+            .expect("Failed to create notification")
+        };
+        let slow = sender
+            .add(addressed("slow", "key1"))
+            .expect("Failed to add");
+        let after_slow = sender
+            .add(addressed("after slow", "key1"))
+            .expect("Failed to add");
+        let other = sender
+            .add(addressed("other", "key2"))
+            .expect("Failed to add");
+        let now = Instant::now();
+
+        let take = || match reciever.shared.lock().next(now) {
+            Next::Deliver(taken) => Some(taken.id),
+            _ => None,
+        };
+        // a busy lane is skipped, but not passed within
+        assert_eq!(take(), Some(slow));
+        assert_eq!(take(), Some(other));
+        assert_eq!(take(), None);
+
+        // waiting on a retry frees the lane but still holds back the rest of it
+        assert!(reciever.failed(slow, "timeout".to_string()));
+        reciever.release("key1");
+        match reciever.shared.lock().next(now) {
+            Next::Wait(Some(at)) => assert_eq!(at.duration_since(now).as_secs(), 60),
+            _ => panic!("Expected to wait for the retry"),
+        }
+        assert!(sender.retry_now(slow));
+        assert_eq!(take(), Some(slow));
+        reciever.finish(slow, DeliveryStatus::Sent, None);
+        reciever.release("key1");
+        assert_eq!(take(), Some(after_slow));
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{
+    clock::Clock,
+    models::{
+        config::Config,
+        fingerprint::{Fingerprints, PreviousEvent},
+        matcher,
+        notification::{label_line, NotificationContent},
+        silence::Silences,
+    },
+    subsystems::queue::QueueSender,
+};
+
+/// Queues a "still firing" notification for every unresolved, unsilenced fingerprint
+/// that `due` accepts, then marks them as alerted. Returns how many were queued.
+pub(crate) fn realert<F>(
+    config: &Config,
+    sender: &QueueSender,
+    fingerprints: &mut Fingerprints,
+    silences: &Silences,
+    clock: &dyn Clock,
+    due: F,
+) -> usize
+where
+    F: Fn(&PreviousEvent) -> bool,
+{
+    let mut updated: Vec<PreviousEvent> = vec![];
+    for (_, fingerprint) in fingerprints.iter() {
+        let resolved = fingerprint.last_status() == "resolved";
+        let wanted = matcher::all_match(config.realert_matchers(), fingerprint)
+            && !silences.is_silenced(fingerprint, clock.now())
+            && !fingerprint.is_acked(clock.now());
+        if resolved || !wanted || !due(fingerprint) {
+            continue;
+        }
+        let content = still_firing(config, fingerprint);
+        log::trace!("Queued {:?}", content);
+        updated.push(fingerprint.clone());
+        if let Err(e) = sender.add_for_each(&config.receivers_for_item(fingerprint), &content) {
+            log::error!("Failed to add re-alert notification due to {e}");
+        }
+    }
+    let count = updated.len();
+    for fingerprint in updated {
+        fingerprints.update_last_alerted_from_previous_event(&fingerprint, clock.now());
+    }
+    count
+}
+
+/// The "still firing" reminder for a fingerprint, as re-alerts send it.
+pub(crate) fn still_firing(config: &Config, fingerprint: &PreviousEvent) -> NotificationContent {
+    let name = match fingerprint.name() {
+        Some(name) => name.clone(),
+        None => "Unknown".to_string(),
+    };
+    let event = format!("[🕓] {}", name);
+    let mut description = format!("{name} is still firing.");
+    if let Some(labels) = label_line(fingerprint, config.notification_labels()) {
+        description = format!("{description}\n{labels}");
+    }
+    let content = NotificationContent::new(
+        fingerprint.priority().clone(),
+        config.notification_url(fingerprint.links()),
+        config.app_name_for(fingerprint).to_string(),
+        event,
+        description,
+    );
+    match fingerprint.first_alerted() {
+        Some(since) => content.with_since(*since),
+        None => content,
+    }
+}
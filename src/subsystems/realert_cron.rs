@@ -1,85 +1,105 @@
-use crate::models::{config::Config, fingerprint::Fingerprints};
-use chrono::Utc;
-use prowl::Notification;
-use prowl_queue::ProwlQueueSender;
+use crate::{
+    clock::Clock,
+    models::{fingerprint::Fingerprints, schedule::Cron, silence::Silences},
+    subsystems::{
+        queue::QueueSender, realert::realert, reload::SharedConfig, shutdown::sleep_or_shutdown,
+    },
+};
 use std::sync::Arc;
-use tokio::{sync::Mutex, time::sleep};
+use tokio::sync::{broadcast::Receiver, Mutex};
 
-// TODO: tests
+/// Sleeps until the next match of `realert_cron`, starting over with the new
+/// schedule when the config is reloaded.
 pub(crate) async fn main_loop(
-    config: Config,
-    sender: ProwlQueueSender,
+    config: SharedConfig,
+    sender: QueueSender,
     fingerprints: Arc<Mutex<Fingerprints>>,
+    silences: Arc<Mutex<Silences>>,
+    clock: Arc<dyn Clock>,
+    mut shutdown: Receiver<()>,
 ) {
-    let cron_string = match config.realert_cron() {
-        Some(x) => x,
-        None => {
-            log::trace!("Cron re-alert not configured. Exiting cron loop.");
-            return;
-        }
-    };
+    let mut reloads = config.subscribe();
     loop {
-        let now = Utc::now();
-        match cron_parser::parse(cron_string, &now) {
-            Ok(next_time) => {
-                let again_time = match next_time.signed_duration_since(now).to_std() {
-                    Ok(x) => x,
-                    Err(e) => {
-                        log::error!("Failed to convert chrono duration to std, {e}. Exiting loop because wtf.");
-                        return;
-                    }
-                };
-                log::trace!("{:?} until next cron re-alert", again_time);
-                sleep(again_time).await;
+        let config = reloads.borrow_and_update().clone();
+        let again_time = match config.realert_cron().as_deref().map(Cron::parse) {
+            Some(Ok(cron)) => cron.time_until_next(clock.as_ref()),
+            Some(Err(e)) => {
+                log::error!("{e}");
+                None
             }
-            Err(e) => {
-                log::error!("Cron string could not be parsed, {e}");
-                break;
+            None => {
+                log::trace!("Cron re-alert not configured.");
+                None
             }
         };
+        let stopped = match again_time {
+            Some(x) => tokio::select! {
+                stopped = sleep_or_shutdown(x, &mut shutdown) => stopped,
+                _ = reloads.changed() => continue,
+            },
+            None => tokio::select! {
+                _ = shutdown.recv() => true,
+                _ = reloads.changed() => continue,
+            },
+        };
+        if stopped {
+            log::debug!("Cron re-alert loop stopped.");
+            return;
+        }
 
         let mut finger_guard = fingerprints.lock().await;
-        let mut updated: Vec<crate::models::fingerprint::PreviousEvent> = vec![];
-        {
-            for (_, fingerprint) in finger_guard.iter() {
-                let resolved = fingerprint.last_status() == "resolved";
-                if !resolved {
-                    let name = match fingerprint.name() {
-                        Some(name) => name.clone(),
-                        None => "Unknown".to_string(),
-                    };
-                    let event = format!("[🕓] {}", name);
-                    let description = format!("{name} is still firing.");
-                    let notification = Notification::new(
-                        config.prowl_api_keys().to_owned(),
-                        fingerprint.priority().clone(),
-                        None,
-                        config.app_name().to_string(),
-                        event,
-                        description,
-                    );
-                    log::trace!("Queued {:?}", notification);
-                    updated.push(fingerprint.clone());
-                    match notification {
-                        Ok(notification) => match sender.add(notification) {
-                            Ok(_) => {}
-                            Err(e) => {
-                                log::error!("Failed to add notification, {e}");
-                            }
-                        },
-                        Err(e) => {
-                            log::error!("Failed to add re-alert notification due to {e}");
-                        }
-                    }
-                }
-            }
-        }
-        for fingerprint in updated {
-            finger_guard.update_last_alerted_from_previous_event(&fingerprint);
-        }
+        let silence_guard = silences.lock().await;
+        realert(
+            &config,
+            &sender,
+            &mut finger_guard,
+            &silence_guard,
+            clock.as_ref(),
+            |_| true,
+        );
+        drop(silence_guard);
         finger_guard.save(&config);
         drop(finger_guard);
         // wait a minute to not match an infinite number of times during that one minute.
-        sleep(std::time::Duration::from_secs(60)).await;
+        if sleep_or_shutdown(std::time::Duration::from_secs(60), &mut shutdown).await {
+            log::debug!("Cron re-alert loop stopped.");
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::MockClock;
+    use chrono::{DateTime, Utc};
+    use std::time::Duration;
+
+    fn clock_at(time: &str) -> MockClock {
+        let time: DateTime<Utc> = time.parse().expect("Failed to parse time");
+        MockClock::new(time)
+    }
+
+    #[test]
+    fn next_weekday_morning() {
+        let cron = Cron::parse("0 9 * * MON-FRI").expect("Failed to parse cron");
+        // Monday
+        let clock = clock_at("2022-09-05T08:30:00Z");
+        assert_eq!(
+            cron.time_until_next(&clock),
+            Some(Duration::from_secs(30 * 60))
+        );
+
+        // Friday, after 9
+        let clock = clock_at("2022-09-09T10:00:00Z");
+        assert_eq!(
+            cron.time_until_next(&clock),
+            Some(Duration::from_secs(71 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn bad_cron() {
+        assert!(Cron::parse("61 * * * *").is_err());
     }
 }
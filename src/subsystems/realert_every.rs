@@ -1,72 +1,175 @@
-use crate::models::{config::Config, fingerprint::Fingerprints};
-use chrono::Utc;
-use prowl::Notification;
-use prowl_queue::ProwlQueueSender;
+use crate::{
+    clock::Clock,
+    models::{config::Config, fingerprint::Fingerprints, silence::Silences},
+    subsystems::{
+        queue::QueueSender, realert::realert, reload::SharedConfig, shutdown::sleep_or_shutdown,
+    },
+};
 use std::sync::Arc;
 use tokio::{
-    sync::Mutex,
-    time::{sleep, Duration},
+    sync::{broadcast::Receiver, Mutex},
+    time::Duration,
 };
 
-// TODO: tests
+/// Checks every minute, with the config as it is then, so reloads can turn it on and off.
 pub(crate) async fn main_loop(
-    config: Config,
-    sender: ProwlQueueSender,
+    config: SharedConfig,
+    sender: QueueSender,
     fingerprints: Arc<Mutex<Fingerprints>>,
+    silences: Arc<Mutex<Silences>>,
+    clock: Arc<dyn Clock>,
+    mut shutdown: Receiver<()>,
 ) {
-    let ttl = match config.alert_every_minutes() {
-        Some(x) => chrono::Duration::minutes(*x),
-        None => {
-            log::trace!("Alert-every-minutes re-alert not configured. Exiting cron loop.");
-            return;
-        }
-    };
     loop {
-        let mut finger_guard = fingerprints.lock().await;
-        let alert_again_time = Utc::now()
-            .checked_sub_signed(ttl)
-            .expect("The alert_every_minutes is before epoch");
-        let mut updated: Vec<crate::models::fingerprint::PreviousEvent> = vec![];
-        {
-            for (_, fingerprint) in finger_guard.iter() {
-                let past_time = fingerprint.last_alerted() <= &alert_again_time;
-                let resolved = fingerprint.last_status() == "resolved";
-                if past_time && !resolved {
-                    let name = match fingerprint.name() {
-                        Some(name) => name.clone(),
-                        None => "Unknown".to_string(),
-                    };
-                    let event = format!("[🕓] {}", name);
-                    let description = format!("{name} is still firing.");
-                    let notification = Notification::new(
-                        config.prowl_api_keys().to_owned(),
-                        fingerprint.priority().clone(),
-                        None,
-                        config.app_name().to_string(),
-                        event,
-                        description,
-                    );
-                    log::trace!("Queued {:?}", notification);
-                    updated.push(fingerprint.clone());
-                    match notification {
-                        Ok(notification) => match sender.add(notification) {
-                            Ok(_) => {}
-                            Err(e) => {
-                                log::error!("Failed to add notification, {e}");
-                            }
-                        },
-                        Err(e) => {
-                            log::error!("Failed to add re-alert notification due to {e}");
-                        }
-                    }
-                }
+        let config = config.current();
+        match config.alert_every_minutes() {
+            Some(x) => {
+                let mut finger_guard = fingerprints.lock().await;
+                let silence_guard = silences.lock().await;
+                realert_expired(
+                    &config,
+                    &sender,
+                    &mut finger_guard,
+                    &silence_guard,
+                    clock.as_ref(),
+                    chrono::Duration::minutes(*x),
+                );
+                drop(silence_guard);
+                finger_guard.save(&config);
             }
+            None => log::trace!("Alert-every-minutes re-alert not configured."),
         }
-        for fingerprint in updated {
-            finger_guard.update_last_alerted_from_previous_event(&fingerprint);
+        if sleep_or_shutdown(Duration::from_secs(60), &mut shutdown).await {
+            log::debug!("Alert-every-minutes re-alert loop stopped.");
+            return;
         }
-        finger_guard.save(&config);
-        drop(finger_guard);
-        sleep(Duration::from_secs(60)).await;
+    }
+}
+
+pub(crate) fn realert_expired(
+    config: &Config,
+    sender: &QueueSender,
+    fingerprints: &mut Fingerprints,
+    silences: &Silences,
+    clock: &dyn Clock,
+    ttl: chrono::Duration,
+) -> usize {
+    let alert_again_time = clock
+        .now()
+        .checked_sub_signed(ttl)
+        .expect("The alert_every_minutes is before epoch");
+    realert(
+        config,
+        sender,
+        fingerprints,
+        silences,
+        clock,
+        |fingerprint| fingerprint.last_alerted() <= &alert_again_time,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::subsystems::queue::NotificationQueue;
+    use crate::{models::grafana::Alert, test::MockClock};
+    use chrono::Utc;
+
+    #[test]
+    fn realerts_after_ttl() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let firing: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        fingerprints.update_last_alerted(&firing, Utc::now());
+        let clock = MockClock::new(Utc::now());
+        let ttl = chrono::Duration::minutes(5);
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let silences = Silences::default();
+
+        clock.advance(chrono::Duration::minutes(4));
+        assert_eq!(
+            realert_expired(&config, &sender, &mut fingerprints, &silences, &clock, ttl),
+            0
+        );
+
+        clock.advance(chrono::Duration::minutes(1));
+        assert_eq!(
+            realert_expired(&config, &sender, &mut fingerprints, &silences, &clock, ttl),
+            1
+        );
+        let (_, fingerprint) = fingerprints.iter().next().expect("Missing fingerprint");
+        assert_eq!(fingerprint.last_alerted(), &clock.now());
+
+        // clock has not moved since the re-alert
+        assert_eq!(
+            realert_expired(&config, &sender, &mut fingerprints, &silences, &clock, ttl),
+            0
+        );
+
+        clock.advance(chrono::Duration::minutes(5));
+        assert_eq!(
+            realert_expired(&config, &sender, &mut fingerprints, &silences, &clock, ttl),
+            1
+        );
+
+        drop(sender);
+        let notification = reciever.pop().expect("Missing first re-alert");
+        assert_eq!(notification.event(), "[🕓] Alert Name");
+        assert!(notification
+            .description()
+            .starts_with("Alert Name is still firing.\nSince "));
+        assert!(notification.description().ends_with(" UTC"));
+        assert_eq!(notification.url().as_deref(), Some("http://something/this"));
+        assert_eq!(notification.priority(), &Some(prowl::Priority::Normal));
+        assert!(reciever.pop().is_some());
+        assert!(reciever.pop().is_none());
+    }
+
+    #[test]
+    fn resolved_is_not_realerted() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let resolved: Alert = serde_json::from_str(&crate::test::consts::create_resolved_alert())
+            .expect("Failed to load default, resolved alert");
+        fingerprints.update_last_alerted(&resolved, Utc::now());
+        let clock = MockClock::new(Utc::now());
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let silences = Silences::default();
+
+        clock.advance(chrono::Duration::days(1));
+        let ttl = chrono::Duration::minutes(5);
+        assert_eq!(
+            realert_expired(&config, &sender, &mut fingerprints, &silences, &clock, ttl),
+            0
+        );
+    }
+
+    #[test]
+    fn acked_is_not_realerted_until_it_ends() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let firing: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        fingerprints.update_last_alerted(&firing, Utc::now());
+        let clock = MockClock::new(Utc::now());
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let silences = Silences::default();
+        let ttl = chrono::Duration::minutes(5);
+        assert!(fingerprints.ack(
+            firing.fingerprint(),
+            clock.now() + chrono::Duration::minutes(30)
+        ));
+
+        clock.advance(chrono::Duration::minutes(29));
+        assert_eq!(
+            realert_expired(&config, &sender, &mut fingerprints, &silences, &clock, ttl),
+            0
+        );
+        clock.advance(chrono::Duration::minutes(1));
+        assert_eq!(
+            realert_expired(&config, &sender, &mut fingerprints, &silences, &clock, ttl),
+            1
+        );
     }
 }
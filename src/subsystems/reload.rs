@@ -0,0 +1,134 @@
+use crate::{errors::ConfigError, models::config::Config};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::{broadcast, watch},
+};
+
+/// The config as last loaded, swapped whole by `reload` so nothing sees half of one.
+/// The server and re-alert loops read it as they go, other tasks keep the one they started with.
+#[derive(Clone)]
+pub(crate) struct SharedConfig {
+    sender: Arc<watch::Sender<Arc<Config>>>,
+}
+
+/// What a reload applied, and what it couldn't.
+#[derive(Debug, Serialize)]
+pub(crate) struct Reloaded {
+    file: String,
+    /// Changed settings that are only read at startup.
+    restart_needed: Vec<&'static str>,
+}
+
+impl SharedConfig {
+    pub(crate) fn new(config: Config) -> Self {
+        let (sender, _) = watch::channel(Arc::new(config));
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+
+    pub(crate) fn current(&self) -> Arc<Config> {
+        self.sender.borrow().clone()
+    }
+
+    /// Sees every reload after the current config.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<Arc<Config>> {
+        self.sender.subscribe()
+    }
+
+    /// Reads the file the current config came from again, keeping the current one if
+    /// it is invalid or moves the fingerprints or silences, which would split their state.
+    pub(crate) fn reload(&self) -> Result<Reloaded, ConfigError> {
+        let current = self.current();
+        let file = current
+            .file()
+            .clone()
+            .unwrap_or_else(|| "config.json".to_string());
+        let config = Config::read(&file)?;
+        let restart_needed = current.startup_changes(&config);
+        if let Some(x) = restart_needed
+            .iter()
+            .find(|x| ["fingerprints_file", "silences_file"].contains(x))
+        {
+            return Err(ConfigError::NeedsRestart(x));
+        }
+        if !restart_needed.is_empty() {
+            log::warn!("Reloaded {file}, restart to apply {restart_needed:?}");
+        }
+        self.sender.send_replace(Arc::new(config));
+        log::info!("Reloaded {file}");
+        Ok(Reloaded {
+            file,
+            restart_needed,
+        })
+    }
+}
+
+/// Reloads the config on SIGHUP, like `POST /api/reload`.
+pub(crate) async fn main_loop(config: SharedConfig, mut shutdown: broadcast::Receiver<()>) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(x) => x,
+        Err(e) => {
+            log::error!("Failed to listen for SIGHUP, reload with POST /api/reload. {e}");
+            return;
+        }
+    };
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {
+                log::info!("Received SIGHUP, reloading the config");
+                if let Err(e) = config.reload() {
+                    log::error!("Kept the current config. {e}");
+                }
+            }
+            _ = shutdown.recv() => {
+                log::debug!("Reload loop stopped.");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reloads_from_the_file() {
+        let path = std::env::temp_dir().join(format!("reload-{}.json", std::process::id()));
+        let write = |json: &str| std::fs::write(&path, json).expect("Failed to write config");
+        write(r#"{"prowl_api_keys": ["a"], "fingerprints_file": "/dev/null"}"#);
+        let shared = SharedConfig::new(Config::load(Some(path.to_string_lossy().to_string())));
+        let mut changes = shared.subscribe();
+
+        write(
+            r#"{"prowl_api_keys": ["a"], "fingerprints_file": "/dev/null", "alert_every_minutes": 5, "delivery_workers": 2}"#,
+        );
+        let reloaded = shared.reload().expect("Failed to reload");
+        assert_eq!(reloaded.restart_needed, vec!["delivery_workers"]);
+        assert_eq!(shared.current().alert_every_minutes(), &Some(5));
+        assert!(changes.has_changed().unwrap_or_default());
+
+        // a bad file or a new fingerprints file keeps what is running
+        write(
+            r#"{"prowl_api_keys": ["a"], "fingerprints_file": "/dev/null", "max_connections": 0}"#,
+        );
+        let error = shared.reload().expect_err("Reloaded a bad config");
+        assert_eq!(error.to_string(), "max_connections must be at least 1.");
+        write(r#"{"prowl_api_keys": ["a"], "fingerprints_file": "/tmp/elsewhere.json"}"#);
+        let error = shared.reload().expect_err("Moved the fingerprints");
+        assert_eq!(
+            error.to_string(),
+            "fingerprints_file can't be changed by a reload, restart instead."
+        );
+        write("{");
+        assert!(shared.reload().is_err());
+        let _ = std::fs::remove_file(&path);
+        assert!(shared.reload().is_err());
+        assert_eq!(shared.current().alert_every_minutes(), &Some(5));
+        changes.borrow_and_update();
+        assert!(!changes.has_changed().unwrap_or_default());
+    }
+}
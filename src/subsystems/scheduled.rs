@@ -0,0 +1,126 @@
+use crate::{
+    clock::Clock,
+    models::{
+        config::Config, notification::NotificationContent, schedule::Cron,
+        scheduled::ScheduledNotification,
+    },
+    subsystems::{queue::QueueSender, shutdown::sleep_or_shutdown},
+};
+use std::sync::Arc;
+use tokio::sync::broadcast::Receiver;
+
+pub(crate) async fn main_loop(
+    config: Config,
+    sender: QueueSender,
+    clock: Arc<dyn Clock>,
+    shutdown: Receiver<()>,
+) {
+    if config.scheduled_notifications().is_empty() {
+        log::trace!("No scheduled notifications. Exiting scheduled loop.");
+        return;
+    }
+    let tasks: Vec<_> = config
+        .scheduled_notifications()
+        .iter()
+        .map(|scheduled| {
+            tokio::spawn(schedule_loop(
+                scheduled.clone(),
+                config.clone(),
+                sender.clone(),
+                clock.clone(),
+                shutdown.resubscribe(),
+            ))
+        })
+        .collect();
+    drop(sender);
+    for task in tasks {
+        let _ = task.await;
+    }
+    log::debug!("Scheduled notification loops stopped.");
+}
+
+async fn schedule_loop(
+    scheduled: ScheduledNotification,
+    config: Config,
+    sender: QueueSender,
+    clock: Arc<dyn Clock>,
+    mut shutdown: Receiver<()>,
+) {
+    let cron = match Cron::parse(scheduled.cron()) {
+        Ok(x) => x,
+        Err(e) => {
+            log::error!("{e}");
+            return;
+        }
+    };
+    loop {
+        let wait = match cron.time_until_next(clock.as_ref()) {
+            Some(x) => x,
+            None => return,
+        };
+        if sleep_or_shutdown(wait, &mut shutdown).await {
+            return;
+        }
+        queue(&config, &sender, &scheduled);
+        // wait a minute to not match an infinite number of times during that one minute.
+        if sleep_or_shutdown(std::time::Duration::from_secs(60), &mut shutdown).await {
+            return;
+        }
+    }
+}
+
+fn queue(config: &Config, sender: &QueueSender, scheduled: &ScheduledNotification) {
+    let content = NotificationContent::new(
+        Some(scheduled.priority().clone()),
+        None,
+        config.app_name().to_string(),
+        scheduled.title().clone(),
+        scheduled.message().clone(),
+    );
+    let receiver = match scheduled.receiver() {
+        Some(name) => config.receivers().get(name),
+        None => None,
+    };
+    let receivers = match receiver {
+        Some(receiver) => vec![receiver.clone()],
+        None => config.default_receivers(),
+    };
+    match sender.add_for_each(&receivers, &content) {
+        Ok(_) => log::debug!("Queued scheduled notification {}", scheduled.title()),
+        Err(e) => log::error!(
+            "Failed to queue scheduled notification {}, {e}",
+            scheduled.title()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::subsystems::queue::NotificationQueue;
+
+    #[test]
+    fn queues_scheduled() {
+        let config = Config::load(Some("src/resources/test-max-config.json".to_string()));
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        for scheduled in config.scheduled_notifications() {
+            queue(&config, &sender, scheduled);
+        }
+
+        let filter = reciever.pop().expect("Missing HVAC reminder");
+        assert_eq!(filter.event(), "Change the HVAC filter");
+        assert_eq!(filter.description(), "");
+        assert_eq!(filter.priority(), &Some(prowl::Priority::Normal));
+        assert_eq!(filter.application(), "Home Lab");
+        // without a receiver, it goes to every destination
+        let filter = reciever.pop().expect("Missing HVAC reminder for ntfy");
+        assert_eq!(filter.event(), "Change the HVAC filter");
+
+        // the family receiver delivers to any key, so it is one queue item
+        let items = sender.items();
+        assert_eq!(items.len(), 1);
+        let trash = reciever.pop().expect("Missing trash reminder");
+        assert_eq!(trash.event(), "Trash day");
+        assert_eq!(trash.priority(), &Some(prowl::Priority::High));
+    }
+}
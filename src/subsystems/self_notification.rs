@@ -0,0 +1,51 @@
+use crate::{
+    models::{
+        config::Config,
+        notification::{Addressed, NotificationContent},
+    },
+    subsystems::queue::QueueSender,
+};
+use prowl::Priority;
+
+/// Notifications about the notifier itself rather than a Grafana alert.
+/// These are addressed to `prowl_api_keys`, while `queue` sends to the `destinations`.
+pub(crate) fn create(
+    config: &Config,
+    priority: Priority,
+    event: &str,
+    description: String,
+) -> Option<Addressed> {
+    let content = NotificationContent::new(
+        Some(priority),
+        None,
+        config.app_name().to_string(),
+        event.to_string(),
+        description,
+    );
+    match content.to_prowl(config.prowl_api_keys().to_owned()) {
+        Ok(notification) => Some(notification),
+        Err(e) => {
+            log::error!("Failed to create notification for '{event}', {e}");
+            None
+        }
+    }
+}
+
+pub(crate) fn queue(
+    config: &Config,
+    sender: &QueueSender,
+    priority: Priority,
+    event: &str,
+    description: String,
+) {
+    let content = NotificationContent::new(
+        Some(priority),
+        None,
+        config.app_name().to_string(),
+        event.to_string(),
+        description,
+    );
+    if let Err(e) = sender.add_for_each(&config.default_receivers(), &content) {
+        log::error!("Failed to queue notification for '{event}', {e}");
+    }
+}
@@ -1,76 +1,828 @@
 use crate::{
+    clock::Clock,
     errors::{AddNotificationError, GrafanaWebhookError, RequestError},
     models::{
+        auth::{Auth, RouteGroup},
+        bulk::{AckRequest, BulkRequest},
         config::Config,
-        fingerprint::Fingerprints,
-        grafana::{Alert, Message},
+        dashboard::{
+            actions, banner, escape, labels, link, row_class, DashboardQuery, COLUMNS, SCRIPT,
+            STYLE,
+        },
+        digest::Digest,
+        export::{self, ExportFormat},
+        fingerprint::{Fingerprints, Links, PreviousEvent},
+        grafana::{Alert, Message, UnknownStatus},
         http,
+        listener::Listener,
+        metrics::Metrics,
+        notification::{duration_text, label_line, NotificationContent},
+        router::{Match, Params, Router},
+        silence::{Silence, SilenceRequest, Silences},
+        sns::{trusted_subscribe_url, SnsEnvelope},
+        tenant::TENANT_WEBHOOK_PATH,
+        version::Version,
+        volume::Volume,
+        webhook::Parser,
     },
+    subsystems::{
+        access_log::{AccessLog, Entry},
+        queue::QueueSender,
+        realert,
+        reload::SharedConfig,
+        self_notification,
+        sinks::forward::Forwarder,
+    },
+};
+use chrono::{DateTime, Utc};
+use prowl::Priority;
+use serde::Serialize;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::OpenOptions,
+    hash::{Hash, Hasher},
+    net::SocketAddr,
+    path::Path,
+    sync::Arc,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::{broadcast::Receiver, Mutex, Semaphore},
+    time::{timeout, Duration, Instant},
 };
-use prowl::Notification;
-use prowl_queue::ProwlQueueSender;
-use std::{net::TcpListener, sync::Arc};
-use tokio::{sync::Mutex, time::Duration};
 
 // TODO: tests for HTTP
 
-pub(crate) async fn main_loop(
-    listener: TcpListener,
-    config: Config,
-    sender: ProwlQueueSender,
-    mut fingerprints: Arc<Mutex<Fingerprints>>,
-) {
+/// What a request is handled by, see `router`.
+#[derive(Clone, Copy, Debug)]
+enum Endpoint {
+    /// The built in webhook for each `Parser`.
+    SourceWebhook(Parser),
+    Dashboard,
+    DeleteFingerprint,
+    ListFingerprints,
+    GetFingerprint,
+    RemoveFingerprint,
+    AckFingerprint,
+    ResendFingerprint,
+    Export,
+    QueueStatus,
+    QueueItems,
+    RemoveQueueItem,
+    RetryQueueItem,
+    Groups,
+    Incidents,
+    Cardinality,
+    BulkUpdate,
+    Changes,
+    Deliveries,
+    Backends,
+    Metrics,
+    Volume,
+    ExportSilences,
+    CreateSilence,
+    RemoveSilence,
+    ImportSilences,
+    TestNotification,
+    Reload,
+    Version,
+    /// One of `webhooks`, by its index.
+    Webhook(usize),
+    /// Grafana webhooks for one of the `tenants`.
+    TenantWebhook,
+}
+
+impl Endpoint {
+    /// Endpoints that change state, which `read_only` turns off. Webhooks still
+    /// work, otherwise nothing would be notified.
+    fn mutates(self) -> bool {
+        matches!(
+            self,
+            Endpoint::DeleteFingerprint
+                | Endpoint::RemoveFingerprint
+                | Endpoint::AckFingerprint
+                | Endpoint::ResendFingerprint
+                | Endpoint::RemoveQueueItem
+                | Endpoint::RetryQueueItem
+                | Endpoint::BulkUpdate
+                | Endpoint::CreateSilence
+                | Endpoint::RemoveSilence
+                | Endpoint::ImportSilences
+                | Endpoint::TestNotification
+                | Endpoint::Reload
+        )
+    }
+
+    /// Endpoints a tenant signed in with its own token may use, limited to its
+    /// fingerprints and silences. The queue, metrics and config are the operator's.
+    fn for_tenants(self) -> bool {
+        matches!(
+            self,
+            Endpoint::Dashboard
+                | Endpoint::DeleteFingerprint
+                | Endpoint::ListFingerprints
+                | Endpoint::GetFingerprint
+                | Endpoint::RemoveFingerprint
+                | Endpoint::AckFingerprint
+                | Endpoint::ResendFingerprint
+                | Endpoint::Export
+                | Endpoint::Groups
+                | Endpoint::Incidents
+                | Endpoint::BulkUpdate
+                | Endpoint::Changes
+                | Endpoint::ExportSilences
+                | Endpoint::CreateSilence
+                | Endpoint::RemoveSilence
+        )
+    }
+}
+
+/// Every endpoint by method and path. A known path with another method gets a 405.
+fn router(config: &Config) -> Router<Endpoint> {
+    let builtin = Parser::ALL
+        .into_iter()
+        .fold(Router::new(), |router, parser| {
+            router.route("POST", parser.path(), Endpoint::SourceWebhook(parser))
+        })
+        .route("GET", "/", Endpoint::Dashboard)
+        .route("DELETE", "/delete/fingerprint", Endpoint::DeleteFingerprint)
+        .route("GET", "/api/queue", Endpoint::QueueStatus)
+        .route("GET", "/api/queue/items", Endpoint::QueueItems)
+        .route("DELETE", "/api/queue/items/{id}", Endpoint::RemoveQueueItem)
+        .route(
+            "POST",
+            "/api/queue/items/{id}/retry-now",
+            Endpoint::RetryQueueItem,
+        )
+        .route("GET", "/api/groups", Endpoint::Groups)
+        .route("GET", "/api/incidents", Endpoint::Incidents)
+        .route("GET", "/api/cardinality", Endpoint::Cardinality)
+        .route("GET", "/api/fingerprints", Endpoint::ListFingerprints)
+        .route("GET", "/export", Endpoint::Export)
+        .route(
+            "GET",
+            "/api/fingerprints/{fingerprint}",
+            Endpoint::GetFingerprint,
+        )
+        .route(
+            "DELETE",
+            "/api/fingerprints/{fingerprint}",
+            Endpoint::RemoveFingerprint,
+        )
+        .route(
+            "POST",
+            "/api/fingerprints/{fingerprint}/ack",
+            Endpoint::AckFingerprint,
+        )
+        .route(
+            "POST",
+            "/api/fingerprints/{fingerprint}/resend",
+            Endpoint::ResendFingerprint,
+        )
+        .route("POST", "/api/fingerprints/bulk", Endpoint::BulkUpdate)
+        .route("GET", "/api/changes", Endpoint::Changes)
+        .route("GET", "/api/deliveries", Endpoint::Deliveries)
+        .route("GET", "/api/backends", Endpoint::Backends)
+        .route("GET", "/metrics", Endpoint::Metrics)
+        .route("GET", "/api/volume", Endpoint::Volume)
+        .route("GET", "/api/silences", Endpoint::ExportSilences)
+        .route("POST", "/api/silences", Endpoint::CreateSilence)
+        .route("DELETE", "/api/silences/{id}", Endpoint::RemoveSilence)
+        .route("POST", "/api/silences/import", Endpoint::ImportSilences)
+        .route("POST", "/api/test", Endpoint::TestNotification)
+        .route("POST", "/api/reload", Endpoint::Reload)
+        .route("GET", "/version", Endpoint::Version);
+    let builtin = match config.tenants().is_empty() {
+        true => builtin,
+        false => builtin.route("POST", TENANT_WEBHOOK_PATH, Endpoint::TenantWebhook),
+    };
+    config
+        .webhooks()
+        .iter()
+        .enumerate()
+        .fold(builtin, |router, (index, webhook)| {
+            router.route("POST", webhook.path(), Endpoint::Webhook(index))
+        })
+}
+
+/// How long a new connection has to start its first request.
+const FIRST_REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub(crate) async fn main_loop(listener: TcpListener, handler: Handler, mut shutdown: Receiver<()>) {
+    let max_connections = *handler.config.max_connections();
+    let permits = Arc::new(Semaphore::new(max_connections));
     log::trace!("Listening for incoming connections");
-    for stream in listener.incoming() {
+    loop {
+        // wait for a free slot before accepting, extra connections wait in the listen backlog
+        let permit = tokio::select! {
+            permit = permits.clone().acquire_owned() => permit.expect("Connection semaphore was closed"),
+            _ = shutdown.recv() => break,
+        };
+        let stream = tokio::select! {
+            stream = listener.accept() => stream,
+            _ = shutdown.recv() => break,
+        };
         log::trace!("Connection incoming");
         match stream {
-            Ok(mut stream) => {
-                stream
-                    .set_read_timeout(Some(Duration::from_secs(1)))
-                    .expect("Failed to set read timeout");
-                match http::Request::from_stream(&mut stream) {
-                    Ok(request) => match request.request_line().path().as_str() {
-                        "/webhooks/grafana" => {
-                            let response =
-                                grafana_webook(&config, request, &sender, &mut fingerprints).await;
-                            let _ = response.send(&mut stream);
-                        }
-                        "/" => {
-                            let response = display_fingerprints(request, &fingerprints).await;
-                            let _ = response.send(&mut stream);
-                        }
-                        "/delete/fingerprint" => {
-                            let response = delete_fingerprint(request, &mut fingerprints).await;
-                            let _ = response.send(&mut stream);
-                        }
-                        _ => {
-                            let body = "Not found".to_string();
-                            let status_line = "HTTP/1.1 404 Not Found".to_string();
-                            let headers = vec!["Content-Type: text/plain".to_string()];
-                            let _ = http::Response::new(status_line, headers, Some(body))
-                                .send(&mut stream);
-                        }
-                    },
-                    Err(RequestError::NoContentLength) => {
-                        let status_line = "HTTP/1.1 411 Length Required".to_string();
-                        let _ = http::Response::new(status_line, vec![], None).send(&mut stream);
-                    }
-                    Err(e) => {
-                        log::error!("Failed to process request due to {}", e);
-                        let body = format!("{}", e);
-                        let status_line = "HTTP/1.1 500 Internal Server Error".to_string();
-                        let headers = vec!["Content-Type: text/plain".to_string()];
-                        let _ =
-                            http::Response::new(status_line, headers, Some(body)).send(&mut stream);
-                    }
-                }
-                fingerprints.lock().await.save(&config);
+            Ok((stream, remote)) => {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    handler.handle(stream, Some(remote)).await;
+                    drop(permit);
+                });
             }
             Err(io_error) => {
                 log::warn!("Could not open stream {}", io_error);
             }
         }
     }
+    log::debug!("Stopped listening for incoming connections");
+    // every permit is given back once the connections in flight are done
+    let _ = permits.acquire_many(max_connections as u32).await;
+    log::debug!("Finished handling open connections");
+}
+
+/// Everything a connection needs, cloned into the task handling it.
+#[derive(Clone)]
+pub(crate) struct Handler {
+    shared_config: SharedConfig,
+    /// The shared config as of the request being handled, with `router` built from it.
+    config: Arc<Config>,
+    sender: QueueSender,
+    fingerprints: Arc<Mutex<Fingerprints>>,
+    silences: Arc<Mutex<Silences>>,
+    volume: Arc<Mutex<Volume>>,
+    digest: Arc<Mutex<Digest>>,
+    metrics: Arc<Mutex<Metrics>>,
+    clock: Arc<dyn Clock>,
+    listener: Listener,
+    router: Arc<Router<Endpoint>>,
+    access_log: AccessLog,
+    forwarder: Forwarder,
+    started_at: DateTime<Utc>,
+}
+
+impl Handler {
+    pub(crate) fn new(
+        shared_config: SharedConfig,
+        sender: QueueSender,
+        fingerprints: Arc<Mutex<Fingerprints>>,
+        silences: Arc<Mutex<Silences>>,
+        volume: Arc<Mutex<Volume>>,
+        digest: Arc<Mutex<Digest>>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let config = shared_config.current();
+        Handler {
+            shared_config,
+            sender,
+            fingerprints,
+            silences,
+            volume,
+            digest,
+            metrics: Arc::new(Mutex::new(Metrics::default())),
+            listener: Listener::new(config.bind_host().clone()),
+            router: Arc::new(router(&config)),
+            access_log: AccessLog::new(config.access_log_file()),
+            forwarder: Forwarder::new(&config),
+            started_at: clock.now(),
+            clock,
+            config,
+        }
+    }
+
+    /// Only serves the route groups of `listener`, answering the others with 404.
+    pub(crate) fn for_listener(&self, listener: Listener) -> Self {
+        Handler {
+            listener,
+            ..self.clone()
+        }
+    }
+
+    /// Serves requests from the stream in order until the client closes it, asks for
+    /// `Connection: close`, or sends nothing for `keep_alive_timeout_secs`.
+    pub(crate) async fn handle<T: AsyncRead + AsyncWrite + Unpin>(
+        mut self,
+        mut stream: T,
+        remote: Option<SocketAddr>,
+    ) {
+        let keep_alive_timeout = Duration::from_secs(*self.config.keep_alive_timeout_secs());
+        let mut buffered = vec![];
+        let mut limits = http::Limits::new(
+            FIRST_REQUEST_TIMEOUT,
+            Duration::from_secs(*self.config.read_timeout_secs()),
+            Duration::from_secs(*self.config.header_timeout_secs()),
+            Duration::from_secs(*self.config.request_deadline_secs()),
+            *self.config.max_body_bytes(),
+        );
+        loop {
+            let request =
+                http::Request::from_buffered_stream(&mut stream, &mut buffered, &limits).await;
+            let started = Instant::now();
+            let line = request
+                .as_ref()
+                .ok()
+                .map(|x| (x.request_line().method().clone(), x.path().to_string()));
+            let (mut response, keep_alive) = match request {
+                Ok(request) => {
+                    let keep_alive = request.keep_alive() && !keep_alive_timeout.is_zero();
+                    (self.respond(request, remote).await, keep_alive)
+                }
+                Err(RequestError::ConnectionClosed) => return,
+                Err(RequestError::ConflictingContentLength | RequestError::BadChunk) => {
+                    let status_line = "HTTP/1.1 400 Bad Request".to_string();
+                    (http::Response::new(status_line, vec![], None), false)
+                }
+                Err(RequestError::BodyTooLarge(max)) => {
+                    log::warn!("Rejected a request body over {max} bytes");
+                    let status_line = "HTTP/1.1 413 Payload Too Large".to_string();
+                    (http::Response::new(status_line, vec![], None), false)
+                }
+                Err(RequestError::HeadTooLarge(_)) => {
+                    let status_line = "HTTP/1.1 431 Request Header Fields Too Large".to_string();
+                    (http::Response::new(status_line, vec![], None), false)
+                }
+                Err(RequestError::Timeout) => {
+                    log::warn!("Closed a connection that was too slow to send its request");
+                    let status_line = "HTTP/1.1 408 Request Timeout".to_string();
+                    (http::Response::new(status_line, vec![], None), false)
+                }
+                Err(RequestError::NoContentLength) => {
+                    let status_line = "HTTP/1.1 411 Length Required".to_string();
+                    (http::Response::new(status_line, vec![], None), false)
+                }
+                Err(e) => {
+                    log::error!("Failed to process request due to {}", e);
+                    let body = format!("{}", e);
+                    let status_line = "HTTP/1.1 500 Internal Server Error".to_string();
+                    let headers = vec!["Content-Type: text/plain".to_string()];
+                    (http::Response::new(status_line, headers, Some(body)), false)
+                }
+            };
+            response.set_keep_alive(keep_alive);
+            let status = response.status_code();
+            let sent = response.send(&mut stream).await;
+            self.access_log.record(&Entry {
+                remote,
+                method: line.as_ref().map(|(method, _)| method.as_str()),
+                path: line.as_ref().map(|(_, path)| path.as_str()),
+                status,
+                body_bytes: *sent.as_ref().unwrap_or(&0),
+                duration: started.elapsed(),
+            });
+            if sent.is_err() || !keep_alive {
+                return;
+            }
+            limits = limits.with_idle(keep_alive_timeout);
+        }
+    }
+
+    /// Picks up a reloaded config between requests, so one request sees one config.
+    fn refresh_config(&mut self) {
+        let current = self.shared_config.current();
+        if Arc::ptr_eq(&current, &self.config) {
+            return;
+        }
+        self.router = Arc::new(router(&current));
+        self.config = current;
+    }
+
+    async fn respond(
+        &mut self,
+        request: http::Request,
+        remote: Option<SocketAddr>,
+    ) -> http::Response {
+        self.refresh_config();
+        let method = request.request_line().method().clone();
+        let path = request.request_line().path().clone();
+        let accept_encoding = request.header("Accept-Encoding").cloned();
+        let limit = Duration::from_secs(*self.config.request_timeout_secs());
+        match timeout(limit, self.route(request, remote)).await {
+            Ok(mut response) => {
+                response.gzip_for(accept_encoding.as_deref());
+                response
+            }
+            Err(_) => {
+                log::error!(
+                    "{method} {path} took over {limit:?}, the fingerprints lock or disk may be stuck"
+                );
+                let status_line = "HTTP/1.1 504 Gateway Timeout".to_string();
+                http::Response::new(status_line, vec![], None)
+            }
+        }
+    }
+
+    async fn route(
+        &mut self,
+        request: http::Request,
+        remote: Option<SocketAddr>,
+    ) -> http::Response {
+        let path = request.path();
+        // probes skip auth, and don't save the fingerprints every few seconds
+        match path {
+            "/healthz" => return text_response("OK".to_string()),
+            "/readyz" => return readiness(&self.config, &self.sender),
+            _ => {}
+        }
+        let group = RouteGroup::of(path);
+        if !self.listener.serves(group) {
+            return not_found();
+        }
+        if group == RouteGroup::Webhooks && !self.allows_source(remote) {
+            log::warn!("Rejected a webhook from {remote:?}, it is not in allowed_source_cidrs");
+            return forbidden();
+        }
+        // a tenant's own token shows it only its own alerts
+        let tenant = match group {
+            RouteGroup::Webhooks => None,
+            RouteGroup::Api | RouteGroup::Ui => self.config.tenant_of(&request).map(str::to_string),
+        };
+        let auth = match self.config.tenant_auth(path) {
+            Some(auth) => auth,
+            None => self.config.auth_for(group),
+        };
+        if tenant.is_none() && !auth.authorize(&request) {
+            log::warn!("Rejected unauthorized request to {path}");
+            return unauthorized(&auth);
+        }
+        let method = request.request_line().method().as_str();
+        let response = match self.router.find(method, path) {
+            Match::Found(endpoint, _) if *self.config.read_only() && endpoint.mutates() => {
+                log::warn!("Rejected {method} {path}, the server is read only");
+                read_only()
+            }
+            Match::Found(endpoint, _) if tenant.is_some() && !endpoint.for_tenants() => {
+                log::warn!("Rejected {method} {path}, it isn't for tenants");
+                forbidden()
+            }
+            Match::Found(endpoint, params) => {
+                self.dispatch(endpoint, params, request, tenant.as_deref())
+                    .await
+            }
+            Match::MethodNotAllowed(allowed) => method_not_allowed(&allowed.join(", ")),
+            Match::NotFound => not_found(),
+        };
+        self.fingerprints.lock().await.save(&self.config);
+        response
+    }
+
+    /// Anyone may send webhooks unless `allowed_source_cidrs` is set.
+    fn allows_source(&self, remote: Option<SocketAddr>) -> bool {
+        let cidrs = self.config.allowed_source_cidrs();
+        if cidrs.is_empty() {
+            return true;
+        }
+        remote.is_some_and(|remote| cidrs.iter().any(|x| x.contains(&remote.ip())))
+    }
+
+    /// The fingerprints a request may see, a tenant only its own.
+    async fn visible_fingerprints(&self, tenant: Option<&str>) -> Arc<Mutex<Fingerprints>> {
+        match tenant {
+            Some(tenant) => Arc::new(Mutex::new(self.fingerprints.lock().await.of_tenant(tenant))),
+            None => self.fingerprints.clone(),
+        }
+    }
+
+    /// A `tenant` only sees and changes its own alerts and silences.
+    async fn dispatch(
+        &mut self,
+        endpoint: Endpoint,
+        params: Params,
+        request: http::Request,
+        tenant: Option<&str>,
+    ) -> http::Response {
+        match endpoint {
+            Endpoint::SourceWebhook(parser) => {
+                let signature = self.config.signature_for(parser);
+                self.receive_webhook(request, parser, &[], signature, None)
+                    .await
+            }
+            Endpoint::Dashboard => {
+                display_fingerprints(
+                    request,
+                    &self.visible_fingerprints(tenant).await,
+                    &self.silences,
+                    tenant,
+                    self.clock.as_ref(),
+                    *self.config.read_only(),
+                    *self.config.dashboard_page_size(),
+                    *self.config.dashboard_refresh_secs(),
+                )
+                .await
+            }
+            Endpoint::DeleteFingerprint => {
+                delete_fingerprint(request, &mut self.fingerprints, tenant).await
+            }
+            Endpoint::ListFingerprints => {
+                list_fingerprints(request, &self.visible_fingerprints(tenant).await).await
+            }
+            Endpoint::Export => {
+                let fingerprints = self.visible_fingerprints(tenant).await;
+                export_fingerprints(request, &fingerprints, self.clock.as_ref()).await
+            }
+            Endpoint::GetFingerprint => {
+                let fingerprints = self.fingerprints.lock().await;
+                match params.get("fingerprint").and_then(|x| fingerprints.get(x)) {
+                    Some(fingerprint) if fingerprint.visible_to(tenant) => {
+                        json_response(fingerprint)
+                    }
+                    _ => not_found(),
+                }
+            }
+            Endpoint::RemoveFingerprint => {
+                let mut fingerprints = self.fingerprints.lock().await;
+                let fingerprint = params.get("fingerprint").unwrap_or_default().to_string();
+                if !owns(&fingerprints, &fingerprint, tenant) {
+                    return not_found();
+                }
+                match fingerprints.remove(&fingerprint) {
+                    Some(_) => no_content(),
+                    None => not_found(),
+                }
+            }
+            Endpoint::AckFingerprint => {
+                let mut fingerprints = self.fingerprints.lock().await;
+                let fingerprint = params.get("fingerprint").unwrap_or_default();
+                if !owns(&fingerprints, fingerprint, tenant) {
+                    return not_found();
+                }
+                ack_fingerprint(request, fingerprint, &mut fingerprints, self.clock.as_ref())
+            }
+            Endpoint::ResendFingerprint => {
+                let mut fingerprints = self.fingerprints.lock().await;
+                let fingerprint = params.get("fingerprint").unwrap_or_default();
+                if !owns(&fingerprints, fingerprint, tenant) {
+                    return not_found();
+                }
+                resend_fingerprint(
+                    fingerprint,
+                    &self.config,
+                    &self.sender,
+                    &mut fingerprints,
+                    self.clock.as_ref(),
+                )
+            }
+            Endpoint::QueueStatus => json_response(&self.sender.status(self.clock.now())),
+            Endpoint::QueueItems => list_queue_items(&self.sender),
+            Endpoint::RemoveQueueItem => update_queue_item(&params, |id| self.sender.remove(id)),
+            Endpoint::RetryQueueItem => update_queue_item(&params, |id| self.sender.retry_now(id)),
+            Endpoint::Groups => {
+                list_groups(request, &self.visible_fingerprints(tenant).await).await
+            }
+            Endpoint::Incidents => {
+                let fingerprints = self.visible_fingerprints(tenant).await;
+                let fingerprints = fingerprints.lock().await;
+                json_response(&fingerprints.incidents(self.clock.now()))
+            }
+            Endpoint::Cardinality => {
+                let fingerprints = self.fingerprints.lock().await;
+                json_response(&fingerprints.cardinality(self.config.label_limits()))
+            }
+            Endpoint::BulkUpdate => {
+                // always lock fingerprints before silences
+                let mut fingerprints = self.fingerprints.lock().await;
+                let mut silences = self.silences.lock().await;
+                let response = bulk_update(
+                    request,
+                    &mut fingerprints,
+                    &mut silences,
+                    self.clock.as_ref(),
+                    tenant,
+                );
+                silences.save(&self.config);
+                response
+            }
+            Endpoint::Changes => {
+                list_changes(request, &self.visible_fingerprints(tenant).await).await
+            }
+            Endpoint::Deliveries => list_deliveries(request, &self.sender),
+            Endpoint::Backends => json_response(&self.sender.breakers().statuses()),
+            Endpoint::Metrics => show_metrics(&*self.metrics.lock().await),
+            Endpoint::Volume => {
+                let volume = self.volume.lock().await;
+                volume_status(&volume, self.clock.as_ref())
+            }
+            Endpoint::ExportSilences => {
+                let silences = self.silences.lock().await;
+                export_silences(&silences, self.clock.as_ref(), tenant)
+            }
+            Endpoint::CreateSilence => {
+                let mut silences = self.silences.lock().await;
+                let response = create_silence(request, &mut silences, self.clock.as_ref(), tenant);
+                silences.save(&self.config);
+                response
+            }
+            Endpoint::RemoveSilence => {
+                let mut silences = self.silences.lock().await;
+                let id = params.get("id").unwrap_or_default();
+                let visible = silences
+                    .get(id)
+                    .is_some_and(|x| tenant.is_none() || x.tenant() == tenant);
+                if !visible || !silences.remove(id) {
+                    return not_found();
+                }
+                log::info!("Removed silence {id}");
+                silences.save(&self.config);
+                no_content()
+            }
+            Endpoint::ImportSilences => {
+                let mut silences = self.silences.lock().await;
+                let response = import_silences(request, &mut silences, self.clock.as_ref());
+                silences.save(&self.config);
+                response
+            }
+            Endpoint::TestNotification => send_test(&self.config, &self.sender),
+            Endpoint::Reload => match self.shared_config.reload() {
+                Ok(reloaded) => {
+                    self.refresh_config();
+                    json_response(&reloaded)
+                }
+                Err(e) => {
+                    log::error!("Kept the current config. {e}");
+                    let status_line = "HTTP/1.1 500 Internal Server Error".to_string();
+                    let headers = vec!["Content-Type: text/plain".to_string()];
+                    http::Response::new(status_line, headers, Some(e.to_string()))
+                }
+            },
+            Endpoint::Version => json_response(&Version::new(
+                self.config.file().clone(),
+                self.started_at,
+                self.clock.now(),
+            )),
+            Endpoint::Webhook(index) => {
+                let webhook = self.config.webhooks()[index].clone();
+                let signature = webhook.signature();
+                self.receive_webhook(
+                    request,
+                    *webhook.parser(),
+                    webhook.receivers(),
+                    signature,
+                    None,
+                )
+                .await
+            }
+            Endpoint::TenantWebhook => {
+                // only known tenants get past auth, but the config may have been reloaded since
+                let tenant = params.get("tenant").unwrap_or_default();
+                match self.config.tenants().contains_key(tenant) {
+                    true => {
+                        self.receive_webhook(request, Parser::Grafana, &[], None, Some(tenant))
+                            .await
+                    }
+                    false => not_found(),
+                }
+            }
+        }
+    }
+}
+
+fn not_found() -> http::Response {
+    let body = "Not found".to_string();
+    let status_line = "HTTP/1.1 404 Not Found".to_string();
+    let headers = vec!["Content-Type: text/plain".to_string()];
+    http::Response::new(status_line, headers, Some(body))
+}
+
+fn no_content() -> http::Response {
+    let status_line = "HTTP/1.1 204 No Content".to_string();
+    http::Response::new(status_line, vec![], None)
+}
+
+fn forbidden() -> http::Response {
+    let status_line = "HTTP/1.1 403 Forbidden".to_string();
+    http::Response::new(status_line, vec![], None)
+}
+
+fn unauthorized(auth: &Auth) -> http::Response {
+    match auth.challenge() {
+        Some(challenge) => {
+            let status_line = "HTTP/1.1 401 Unauthorized".to_string();
+            let headers = vec![format!("WWW-Authenticate: {challenge}")];
+            http::Response::new(status_line, headers, None)
+        }
+        None => forbidden(),
+    }
+}
+
+fn bad_signature() -> http::Response {
+    let status_line = "HTTP/1.1 401 Unauthorized".to_string();
+    let headers = vec!["Content-Type: text/plain".to_string()];
+    http::Response::new(status_line, headers, Some("Invalid signature".to_string()))
+}
+
+fn read_only() -> http::Response {
+    let body = "Read only, changes are turned off".to_string();
+    let status_line = "HTTP/1.1 403 Forbidden".to_string();
+    let headers = vec!["Content-Type: text/plain".to_string()];
+    http::Response::new(status_line, headers, Some(body))
+}
+
+fn bad_request(body: String) -> http::Response {
+    let status_line = "HTTP/1.1 400 Bad Request".to_string();
+    let headers = vec!["Content-Type: text/plain".to_string()];
+    http::Response::new(status_line, headers, Some(body))
+}
+
+fn method_not_allowed(allow: &str) -> http::Response {
+    let status_line = "HTTP/1.1 405 Method Not Allowed".to_string();
+    let headers = vec![format!("Allow: {allow}")];
+    http::Response::new(status_line, headers, None)
+}
+
+/// Weak, because the same revision is sent both gzipped and not.
+fn etag(fingerprints: &Fingerprints) -> String {
+    format!("W/\"{}\"", fingerprints.revision())
+}
+
+/// For pages that depend on more than the fingerprints.
+fn hashed_etag(state: impl Hash) -> String {
+    let mut hasher = DefaultHasher::new();
+    state.hash(&mut hasher);
+    format!("W/\"{:x}\"", hasher.finish())
+}
+
+/// A 304 when the client's `If-None-Match` has the current ETag.
+fn not_modified(request: &http::Request, etag: &str) -> Option<http::Response> {
+    let if_none_match = request.header("If-None-Match")?;
+    let matched = if_none_match
+        .split(',')
+        .map(|x| x.trim())
+        .any(|x| x == "*" || x.trim_start_matches("W/") == etag.trim_start_matches("W/"));
+    if !matched {
+        return None;
+    }
+    let status_line = "HTTP/1.1 304 Not Modified".to_string();
+    Some(http::Response::new(
+        status_line,
+        vec![format!("ETag: {etag}")],
+        None,
+    ))
+}
+
+fn with_revision(mut response: http::Response, fingerprints: &Fingerprints) -> http::Response {
+    response.add_header(format!("X-Revision: {}", fingerprints.revision()));
+    response
+}
+
+/// Lets polling clients revalidate with `If-None-Match` instead of re-downloading.
+fn with_etag(mut response: http::Response, etag: String) -> http::Response {
+    if !response.status_line().ends_with(" 200 OK") {
+        return response;
+    }
+    response.add_header(format!("ETag: {etag}"));
+    response.add_header("Cache-Control: no-cache".to_string());
+    response
+}
+
+fn text_response(body: String) -> http::Response {
+    let status_line = "HTTP/1.1 200 OK".to_string();
+    let headers = vec!["Content-Type: text/plain".to_string()];
+    http::Response::new(status_line, headers, Some(body))
+}
+
+fn json_response<T: Serialize>(value: &T) -> http::Response {
+    match serde_json::to_string(value) {
+        Ok(body) => {
+            let status_line = "HTTP/1.1 200 OK".to_string();
+            let headers = vec!["Content-Type: application/json".to_string()];
+            http::Response::new(status_line, headers, Some(body))
+        }
+        Err(e) => {
+            log::error!("Failed to serialize response, {e}");
+            let status_line = "HTTP/1.1 500 Internal Server Error".to_string();
+            http::Response::new(status_line, vec![], None)
+        }
+    }
+}
+
+/// Fetches the SubscribeURL in the background, SNS resends the confirmation if it fails.
+fn confirm_sns_subscription(url: &str) -> http::Response {
+    if !trusted_subscribe_url(url) {
+        log::warn!("Refused to confirm an SNS subscription at {url}");
+        return bad_request("SubscribeURL isn't an SNS URL.".to_string());
+    }
+    let url = url.to_string();
+    tokio::spawn(async move {
+        let confirmed = match reqwest::Client::new()
+            .get(&url)
+            .timeout(Duration::from_secs(30))
+            .send()
+            .await
+        {
+            Ok(x) => x.error_for_status().map(|_| ()),
+            Err(e) => Err(e),
+        };
+        match confirmed {
+            Ok(()) => log::info!("Confirmed the SNS subscription"),
+            // the URL has the subscription's token in it
+            Err(e) => log::error!(
+                "Failed to confirm the SNS subscription. {}",
+                e.without_url()
+            ),
+        }
+    });
+    no_content()
 }
 
 fn create_grafana_failure_response(error: GrafanaWebhookError) -> http::Response {
@@ -81,157 +833,779 @@ fn create_grafana_failure_response(error: GrafanaWebhookError) -> http::Response
     http::Response::new(status_line, headers, Some(body))
 }
 
-async fn grafana_webook(
-    config: &Config,
-    request: http::Request,
-    sender: &ProwlQueueSender,
-    fingerprints: &mut Arc<Mutex<Fingerprints>>,
-) -> http::Response {
-    log::trace!("Processing request");
-
-    if request.request_line().method() != "POST" {
-        return create_grafana_failure_response(GrafanaWebhookError::WrongMethod(
-            request.request_line().method().clone(),
-        ));
-    }
+impl Handler {
+    /// Notifies `receivers`, or those of the matching route when there are none.
+    /// With a `signature`, unsigned bodies are rejected before they are parsed.
+    /// A `tenant`'s alerts are kept apart and only notify the tenant.
+    async fn receive_webhook(
+        &self,
+        request: http::Request,
+        parser: Parser,
+        receivers: &[String],
+        signature: Option<Auth>,
+        tenant: Option<&str>,
+    ) -> http::Response {
+        log::trace!("Processing request");
+        if signature.is_some_and(|x| !x.authorize(&request)) {
+            log::warn!(
+                "Rejected a {} webhook without a valid signature",
+                parser.name()
+            );
+            return bad_signature();
+        }
+        if parser == Parser::Sns {
+            let envelope = serde_json::from_str::<SnsEnvelope>(request.body()).ok();
+            if let Some(url) = envelope.as_ref().and_then(SnsEnvelope::subscribe_url) {
+                return confirm_sns_subscription(url);
+            }
+        }
+        self.forwarder.forward(
+            self.config.forward_urls_for(tenant),
+            request.body(),
+            request.content_type(),
+        );
 
-    let request: Result<Message, GrafanaWebhookError> =
-        serde_json::from_str(request.body()).map_err(GrafanaWebhookError::BadJson);
-    let request = match request {
-        Ok(r) => r,
-        Err(e) => return create_grafana_failure_response(e),
-    };
-    let mut last_err = None;
+        let body_bytes = request.body().len();
+        let request: Result<Message, GrafanaWebhookError> =
+            parser
+                .parse(request.body())
+                .map_err(|e| match request.content_type() {
+                    Some(x) if x != "application/json" => GrafanaWebhookError::NotJson(x, e),
+                    _ => GrafanaWebhookError::BadJson(e),
+                });
+        let mut request = match request {
+            Ok(r) => r,
+            Err(e) => return create_grafana_failure_response(e),
+        };
+        if let Some(tenant) = tenant {
+            request.apply_tenant(tenant);
+        }
+        log::debug!("Received {}", request.describe());
+        if let Some(truncated) = request.truncated_alerts().filter(|x| *x > 0) {
+            log::warn!(
+                "{} left {truncated} alerts out of the webhook, raise its max alerts",
+                parser.name()
+            );
+        }
+        let mut last_err = None;
+        self.volume
+            .lock()
+            .await
+            .record(request.alerts().len(), self.clock.now());
+        self.check_size(body_bytes, request.alerts().len()).await;
+        let unknown_statuses = request.apply_unknown_status(self.config.unknown_status());
+        if !unknown_statuses.is_empty() {
+            let mut metrics = self.metrics.lock().await;
+            for status in unknown_statuses {
+                log::warn!("Received an alert with unknown status '{status}'");
+                metrics.count_unknown_status(&status);
+            }
+        }
+        for fingerprint in request.apply_datasource_alerts(self.config.datasource_alerts()) {
+            log::debug!("Suppressed data source alert {fingerprint}");
+        }
+        let trimmed = request.apply_label_limits(self.config.label_limits());
+        if !trimmed.is_empty() {
+            let mut metrics = self.metrics.lock().await;
+            for (fingerprint, limit) in trimmed {
+                log::warn!("Trimmed labels of {fingerprint} to fit {}", limit.name());
+                metrics.count_label_limit(limit.name());
+            }
+        }
 
-    let mut fingerprints = fingerprints.lock().await;
-    for event in request.alerts() {
-        // Even if an alert is resolved, Grafana may call again with the notification.
-        match fingerprints.changed(event) {
-            false => fingerprints.update_last_seen(event),
-            true => {
-                fingerprints.update_last_alerted(event);
-                if let Err(err) = add_notification(event, config, sender).await {
-                    log::error!("Error queueing notification {:?}", err);
-                    last_err = Some(err);
+        // always lock fingerprints before silences
+        let mut fingerprints = self.fingerprints.lock().await;
+        let silences = self.silences.lock().await;
+        let now = self.clock.now();
+        for event in request.latest_alerts() {
+            // Even if an alert is resolved, Grafana may call again with the notification.
+            match fingerprints.changed(event) {
+                false => fingerprints.update_last_seen(event, now),
+                true if silences.is_silenced(event, now) => {
+                    log::debug!("Silenced notification for {}", event.fingerprint());
+                    fingerprints.update_last_alerted(event, now);
                 }
-            }
-        };
+                true if event.status() == "resolved"
+                    && event.tenant().is_none()
+                    && self.config.resolved_digest().is_some() =>
+                {
+                    fingerprints.update_last_alerted(event, now);
+                    let mut digest = self.digest.lock().await;
+                    digest.add(event.rule_name(), event.summary(), event.duration());
+                    log::debug!("Added {} to the resolved digest", event.fingerprint());
+                }
+                true => {
+                    fingerprints.update_last_alerted(event, now);
+                    let result = add_notification(event, &self.config, receivers, &self.sender);
+                    if let Err(err) = result.await {
+                        log::error!("Error queueing notification {:?}", err);
+                        last_err = Some(err);
+                    }
+                }
+            };
+        }
+
+        if let Some(e) = last_err {
+            create_grafana_failure_response(GrafanaWebhookError::QueueError(e))
+        } else {
+            let body = "Accepted";
+            let status_line = "HTTP/1.1 200 OK".to_string();
+            let headers = vec!["Content-Type: text/plain".to_string()];
+            http::Response::new(status_line, headers, Some(body.to_string()))
+        }
     }
 
-    if let Some(e) = last_err {
-        create_grafana_failure_response(GrafanaWebhookError::QueueError(e))
-    } else {
-        let body = "Accepted";
-        let status_line = "HTTP/1.1 200 OK".to_string();
-        let headers = vec!["Content-Type: text/plain".to_string()];
-        http::Response::new(status_line, headers, Some(body.to_string()))
+    /// Records the webhook's size, warning when it is over a `large_webhook_*` threshold.
+    /// Huge groups usually mean a notification policy upstream is grouping by too little.
+    async fn check_size(&self, body_bytes: usize, alerts: usize) {
+        let mut metrics = self.metrics.lock().await;
+        metrics.observe_webhook(body_bytes, alerts);
+        let mut over = vec![];
+        if let Some(max) = self
+            .config
+            .large_webhook_bytes()
+            .filter(|x| body_bytes > *x)
+        {
+            over.push(("body_bytes", format!("{body_bytes} bytes, over {max}")));
+        }
+        if let Some(max) = self.config.large_webhook_alerts().filter(|x| alerts > *x) {
+            over.push(("alerts", format!("{alerts} alerts, over {max}")));
+        }
+        let mut notify = false;
+        for (threshold, _) in &over {
+            notify |= metrics.count_large_webhook(threshold, self.clock.now());
+        }
+        drop(metrics);
+        if over.is_empty() {
+            return;
+        }
+        let sizes: Vec<String> = over.into_iter().map(|(_, x)| x).collect();
+        let sizes = sizes.join(" and ");
+        log::warn!("Received a large webhook, {sizes}");
+        if notify {
+            let description = format!(
+                "Grafana sent a webhook with {sizes}. Check the notification policy's group by."
+            );
+            self_notification::queue(
+                &self.config,
+                &self.sender,
+                Priority::Moderate,
+                "[📦] Large alert group",
+                description,
+            );
+        }
     }
 }
 
 async fn add_notification(
     alert: &Alert,
     config: &Config,
-    sender: &ProwlQueueSender,
+    receivers: &[String],
+    sender: &QueueSender,
 ) -> Result<(), AddNotificationError> {
-    let status = match alert.status().as_str() {
-        "firing" => "🔥",
-        "resolved" => "✅",
-        _ => alert.status(),
+    let problem = alert
+        .datasource_problem()
+        .filter(|_| alert.status() == "firing");
+    let status = match (alert.status().as_str(), config.unknown_status()) {
+        ("firing", _) => match problem {
+            Some(x) => config.datasource_alerts().emoji(x),
+            None => "🔥",
+        },
+        ("resolved", _) => "✅",
+        (
+            _,
+            UnknownStatus::Notify {
+                emoji: Some(emoji), ..
+            },
+        ) => emoji,
+        (status, _) => status,
     };
-    let event = format!("[{status}] {}", &alert.labels().alertname());
+    let event = format!("[{status}] {}", alert.rule_name());
 
-    let description = format!("{}: {}", alert.status(), alert.annotations().summary());
+    let status_text = match (problem, alert.duration()) {
+        (Some(x), _) => x.text().to_string(),
+        (None, Some(x)) => format!("{} after {}", alert.status(), duration_text(x)),
+        (None, None) => alert.status().clone(),
+    };
+    let summary = alert.summary();
+    let mut description = format!("{status_text}: {summary}");
+    if let Some(values) = alert.value_text() {
+        description = format!("{description}\n{values}");
+    }
+    for line in alert.annotations().lines(config.notification_annotations()) {
+        description = format!("{description}\n{line}");
+    }
+    if let Some(labels) = label_line(alert, config.notification_labels()) {
+        description = format!("{description}\n{labels}");
+    }
 
-    let notification = Notification::new(
-        config.prowl_api_keys().to_owned(),
+    let content = NotificationContent::new(
         Some(alert.get_priority()),
-        Some(alert.generator_url().clone()),
-        config.app_name().to_string(),
+        config.notification_url(&Links::of(alert)),
+        config.app_name_for(alert).to_string(),
         event.clone(),
         description,
-    )?;
-    log::trace!("Built = {:?}", notification);
-    sender.add(notification)?;
+    )
+    .with_field("Status", status_text)
+    .with_field("Summary", summary.to_string())
+    .with_time(alert.ended_at().or(alert.started_at()));
+    log::trace!("Built = {:?}", content);
+    for receiver in config.receivers_for(receivers, alert) {
+        sender.add_for(&receiver, &content)?;
+    }
     log::debug!("Queued notification for {}", event);
 
     Ok(())
 }
 
-// TODO: just move to a template lol
-async fn display_fingerprints(
+/// Sections of the dashboard, in the order they are shown.
+const SECTIONS: [&str; 6] = [
+    "Firing – Emergency",
+    "Firing – High",
+    "Firing – Normal",
+    "Firing – Low",
+    "Silenced / Snoozed",
+    "Recently Resolved",
+];
+
+/// Which of `SECTIONS` a fingerprint is shown in.
+fn section(fingerprint: &PreviousEvent, silences: &Silences, now: DateTime<Utc>) -> usize {
+    if fingerprint.last_status() != "firing" {
+        return 5;
+    }
+    if fingerprint.is_acked(now) || silences.is_silenced(fingerprint, now) {
+        return 4;
+    }
+    match fingerprint.priority() {
+        Some(Priority::Emergency) => 0,
+        Some(Priority::High) => 1,
+        Some(Priority::Normal) | None => 2,
+        Some(Priority::Moderate | Priority::VeryLow) => 3,
+    }
+}
+
+// TODO: just move to a template lol
+#[allow(clippy::too_many_arguments)]
+async fn display_fingerprints(
+    request: http::Request,
+    fingerprints: &Arc<Mutex<Fingerprints>>,
+    silences: &Arc<Mutex<Silences>>,
+    tenant: Option<&str>,
+    clock: &dyn Clock,
+    read_only: bool,
+    page_size: usize,
+    refresh_secs: Option<u64>,
+) -> http::Response {
+    let fingerprints = fingerprints.lock().await;
+    let silences = silences.lock().await;
+    let now = clock.now();
+    // acks, silences and open incidents change the page as time passes, not just revisions
+    let next_change = fingerprints
+        .next_change(now)
+        .into_iter()
+        .chain(silences.next_change(now))
+        .min();
+    let etag = hashed_etag((
+        fingerprints.revision(),
+        silences.revision(),
+        next_change,
+        tenant,
+        request.request_line().query_params(),
+        (read_only, page_size, refresh_secs),
+    ));
+    if let Some(response) = not_modified(&request, &etag) {
+        return response;
+    }
+    let query = DashboardQuery::from_request(&request);
+    let banner = banner(fingerprints.iter().map(|(_, x)| x));
+    let mut sections: Vec<Vec<&PreviousEvent>> = vec![vec![]; SECTIONS.len()];
+    for (_, fingerprint) in fingerprints.iter() {
+        if !query.matches(fingerprint) {
+            continue;
+        }
+        sections[section(fingerprint, &silences, now)].push(fingerprint);
+    }
+    drop(silences);
+
+    let total = sections.iter().map(Vec::len).sum();
+    let page = query.page(total, page_size);
+    let range = page.range();
+    let mut tables = query.pager(&page);
+    let mut offset = 0;
+    for (index, mut section) in sections.into_iter().enumerate() {
+        // pages run through the sections in order, so each shows its part of the page
+        let start = range.start.clamp(offset, offset + section.len()) - offset;
+        let end = range.end.clamp(offset, offset + section.len()) - offset;
+        offset += section.len();
+        if start == end {
+            continue;
+        }
+        query.sort(&mut section);
+        // resolved and silenced alerts don't need attention, so start collapsed
+        let open = match index < 4 {
+            true => " open",
+            false => "",
+        };
+        let title = SECTIONS[index];
+        let count = section.len();
+        let section = &section[start..end];
+        let mut table = format!("<details{open}><summary><b>{title}</b> ({count})</summary><table border='1px solid black'>");
+        table += match read_only {
+            true => "<tr>",
+            false => "<tr><th>Actions</th>",
+        };
+        for (column, title) in COLUMNS {
+            table = format!(
+                "{table}<th><a href='{}'>{title}</a></th>",
+                query.sort_link(column)
+            );
+        }
+        table += "<th>Labels</th></tr>";
+        for fingerprint in section {
+            // everything but the times comes from webhooks, so is escaped
+            let id = escape(fingerprint.fingerprint());
+            let buttons = match read_only {
+                true => String::new(),
+                false => format!("<td>{}</td>", actions(fingerprint)),
+            };
+            let name = match fingerprint.name() {
+                Some(x) => escape(x),
+                None => "Unknown".to_string(),
+            };
+            let name = link(fingerprint.links().generator_url().as_deref(), &name);
+            let priority = match fingerprint.priority() {
+                Some(x) => format!("{:?}", x),
+                None => "Unknown".to_string(),
+            };
+            let status = escape(fingerprint.last_status());
+            let last_alert = format!("{}", fingerprint.last_alerted().format("%d/%m/%y %H:%M"));
+            let first_alert = match fingerprint.first_alerted() {
+                Some(x) => format!("{}", x.format("%d/%m/%Y %H:%M")),
+                None => "Unknown".to_string(),
+            };
+            // Grafana's own times, which can be earlier than it told us
+            let grafana_time = |x: &Option<DateTime<Utc>>| {
+                x.map(|x| x.format("%d/%m/%y %H:%M").to_string())
+                    .unwrap_or_default()
+            };
+            let started = grafana_time(fingerprint.started_at());
+            let ended = grafana_time(fingerprint.ended_at());
+            let group = escape(&fingerprint.group_key().clone().unwrap_or_default());
+            let labels = labels(fingerprint);
+            let class = row_class(fingerprint);
+            table = format!("{table}<tr class='{class}'>{buttons}<td>{id}</td><td>{name}</td><td>{priority}</td><td>{status}</td><td>{last_alert}</td><td>{first_alert}</td><td>{started}</td><td>{ended}</td><td>{group}</td><td>{labels}</td></tr>");
+        }
+        tables += &table;
+        tables += "</table></details>";
+    }
+
+    let mut incidents = "<h2>Incidents</h2><table border='1px solid black'>".to_string();
+    incidents += "<tr><th>ID</th><th>Name</th><th>Started</th><th>Duration</th><th>Cycles</th><th>Notifications</th><th>Status</th></tr>";
+    for summary in fingerprints.incidents(now) {
+        let incident = summary.incident();
+        let id = escape(incident.id());
+        let name = summary
+            .name()
+            .as_deref()
+            .map_or_else(|| "Unknown".to_string(), escape);
+        let started = incident.started_at().format("%d/%m/%y %H:%M");
+        let secs = summary.duration_secs();
+        let duration = format!("{}h {}m", secs / 3600, secs % 3600 / 60);
+        let cycles = incident.cycles();
+        let notifications = incident.notifications();
+        let status = match incident.resolved_at() {
+            Some(_) => "resolved",
+            None => "open",
+        };
+        incidents = format!("{incidents}<tr><td>{id}</td><td>{name}</td><td>{started}</td><td>{duration}</td><td>{cycles}</td><td>{notifications}</td><td>{status}</td></tr>");
+    }
+    incidents += "</table>";
+    drop(fingerprints);
+    let form = query.form();
+    // a meta refresh keeps the query, so a wall monitor stays on its filters
+    let refresh = refresh_secs
+        .map(|x| format!("<meta http-equiv='refresh' content='{x}'>"))
+        .unwrap_or_default();
+    let body = format!(
+        "<html><head><meta charset='utf-8'>{refresh}{STYLE}{SCRIPT}</head><body>{banner}{form}{tables}{incidents}</body></html>"
+    );
+    let status_line = "HTTP/1.1 200 OK".to_string();
+    let headers = vec!["Content-Type: text/html".to_string()];
+    with_etag(http::Response::new(status_line, headers, Some(body)), etag)
+}
+
+/// Tenants may only change their own fingerprints.
+fn owns(fingerprints: &Fingerprints, fingerprint: &str, tenant: Option<&str>) -> bool {
+    fingerprints
+        .get(fingerprint)
+        .is_some_and(|x| x.visible_to(tenant))
+}
+
+async fn delete_fingerprint(
+    request: http::Request,
+    fingerprints: &mut Arc<Mutex<Fingerprints>>,
+    tenant: Option<&str>,
+) -> http::Response {
+    let mut fingerprints = fingerprints.lock().await;
+    let owned = owns(&fingerprints, request.body(), tenant);
+    let status_line = match owned.then(|| fingerprints.remove(request.body())).flatten() {
+        Some(_) => "HTTP/1.1 200 OK".to_string(),
+        None => "HTTP/1.1 404 Not Found".to_string(),
+    };
+    http::Response::new(status_line, vec![], None)
+}
+
+/// Every fingerprint matching the dashboard's filters, in its order, as a download.
+async fn export_fingerprints(
+    request: http::Request,
+    fingerprints: &Arc<Mutex<Fingerprints>>,
+    clock: &dyn Clock,
+) -> http::Response {
+    let format = match ExportFormat::parse(request.query("format").as_deref()) {
+        Some(x) => x,
+        None => return bad_request("format must be csv or json".to_string()),
+    };
+    let query = DashboardQuery::from_request(&request);
+    let fingerprints = fingerprints.lock().await;
+    let mut matched: Vec<&PreviousEvent> = fingerprints
+        .iter()
+        .map(|(_, x)| x)
+        .filter(|x| query.matches(x))
+        .collect();
+    query.sort(&mut matched);
+    let body = match format {
+        ExportFormat::Csv => export::to_csv(&matched),
+        ExportFormat::Json => match serde_json::to_string(&matched) {
+            Ok(x) => x,
+            Err(e) => {
+                log::error!("Failed to serialize the export, {e}");
+                let status_line = "HTTP/1.1 500 Internal Server Error".to_string();
+                return http::Response::new(status_line, vec![], None);
+            }
+        },
+    };
+    let status_line = "HTTP/1.1 200 OK".to_string();
+    let headers = vec![
+        format!("Content-Type: {}", format.content_type()),
+        format!(
+            "Content-Disposition: attachment; filename=\"{}\"",
+            format.file_name(clock.now())
+        ),
+    ];
+    http::Response::new(status_line, headers, Some(body))
+}
+
+fn list_queue_items(sender: &QueueSender) -> http::Response {
+    json_response(&sender.items())
+}
+
+/// `DELETE /api/queue/items/{id}` and `POST /api/queue/items/{id}/retry-now`,
+/// `update` returns false if the item is not in the queue.
+fn update_queue_item(params: &Params, update: impl FnOnce(u64) -> bool) -> http::Response {
+    let id = match params.get("id").and_then(|x| x.parse::<u64>().ok()) {
+        Some(id) => id,
+        None => return not_found(),
+    };
+    match update(id) {
+        true => no_content(),
+        false => not_found(),
+    }
+}
+
+/// `GET /api/fingerprints`
+async fn list_fingerprints(
+    request: http::Request,
+    fingerprints: &Arc<Mutex<Fingerprints>>,
+) -> http::Response {
+    let fingerprints = fingerprints.lock().await;
+    let etag = etag(&fingerprints);
+    if let Some(response) = not_modified(&request, &etag) {
+        return response;
+    }
+    let response = with_etag(json_response(&fingerprints.all()), etag);
+    with_revision(response, &fingerprints)
+}
+
+async fn list_groups(
+    request: http::Request,
+    fingerprints: &Arc<Mutex<Fingerprints>>,
+) -> http::Response {
+    let fingerprints = fingerprints.lock().await;
+    let etag = etag(&fingerprints);
+    if let Some(response) = not_modified(&request, &etag) {
+        return response;
+    }
+    let response = with_etag(json_response(&fingerprints.groups()), etag);
+    with_revision(response, &fingerprints)
+}
+
+/// `GET /api/changes?since={revision}`
+async fn list_changes(
+    request: http::Request,
+    fingerprints: &Arc<Mutex<Fingerprints>>,
+) -> http::Response {
+    let since = match since(&request) {
+        Ok(since) => since,
+        Err(response) => return response,
+    };
+    let fingerprints = fingerprints.lock().await;
+    with_revision(
+        json_response(&fingerprints.changes_since(since)),
+        &fingerprints,
+    )
+}
+
+/// `GET /api/deliveries?since={id}`
+fn list_deliveries(request: http::Request, sender: &QueueSender) -> http::Response {
+    match since(&request) {
+        Ok(since) => json_response(&sender.deliveries_since(since)),
+        Err(response) => response,
+    }
+}
+
+/// The `since` query parameter, or a 400 if it isn't a number.
+fn since(request: &http::Request) -> Result<u64, http::Response> {
+    match request.query("since").map(|x| x.parse::<u64>()) {
+        None => Ok(0),
+        Some(Ok(since)) => Ok(since),
+        Some(Err(_)) => Err(bad_request("since must be a number".to_string())),
+    }
+}
+
+/// `POST /api/test`, queues a low priority notification for the `prowl_api_keys`.
+/// The queue ID is the `item` of its events in `/api/deliveries`.
+fn send_test(config: &Config, sender: &QueueSender) -> http::Response {
+    let notification = match self_notification::create(
+        config,
+        Priority::Moderate,
+        "Test notification",
+        "Sent from POST /api/test.".to_string(),
+    ) {
+        Some(x) => x,
+        None => {
+            let status_line = "HTTP/1.1 500 Internal Server Error".to_string();
+            let headers = vec!["Content-Type: text/plain".to_string()];
+            let body = "Failed to create the test notification".to_string();
+            return http::Response::new(status_line, headers, Some(body));
+        }
+    };
+    match sender.add(notification) {
+        Ok(id) => json_response(&serde_json::json!({ "item": id })),
+        Err(e) => {
+            log::error!("Failed to queue the test notification, {e}");
+            let status_line = "HTTP/1.1 503 Service Unavailable".to_string();
+            let headers = vec!["Content-Type: text/plain".to_string()];
+            http::Response::new(status_line, headers, Some(e.to_string()))
+        }
+    }
+}
+
+/// `GET /readyz`, 503 with what failed when the fingerprints can't be
+/// saved or the queue isn't sending.
+fn readiness(config: &Config, sender: &QueueSender) -> http::Response {
+    let mut failures = vec![];
+    if let Err(e) = writable(Path::new(config.fingerprints_file())) {
+        failures.push(format!(
+            "fingerprints file {} is not writable: {e}",
+            config.fingerprints_file()
+        ));
+    }
+    if !sender.is_running() {
+        failures.push("notification queue has stopped".to_string());
+    }
+    if failures.is_empty() {
+        return text_response("OK".to_string());
+    }
+    log::warn!("Not ready: {}", failures.join(", "));
+    let status_line = "HTTP/1.1 503 Service Unavailable".to_string();
+    let headers = vec!["Content-Type: text/plain".to_string()];
+    http::Response::new(status_line, headers, Some(failures.join("\n")))
+}
+
+/// Opens the file for appending without changing it, or if it doesn't exist yet,
+/// creates and removes a file next to it.
+fn writable(path: &Path) -> std::io::Result<()> {
+    if path.exists() {
+        return OpenOptions::new().append(true).open(path).map(|_| ());
+    }
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let probe = path.with_file_name(format!(".{name}.readyz"));
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe)?;
+    std::fs::remove_file(probe)
+}
+
+fn show_metrics(metrics: &Metrics) -> http::Response {
+    let status_line = "HTTP/1.1 200 OK".to_string();
+    let headers = vec!["Content-Type: text/plain; version=0.0.4".to_string()];
+    http::Response::new(status_line, headers, Some(metrics.render()))
+}
+
+fn volume_status(volume: &Volume, clock: &dyn Clock) -> http::Response {
+    json_response(&volume.status(clock.now()))
+}
+
+/// Silences in Alertmanager's format, so they can be moved to another instance.
+/// A tenant only gets the silences it made.
+fn export_silences(silences: &Silences, clock: &dyn Clock, tenant: Option<&str>) -> http::Response {
+    match tenant {
+        Some(tenant) => json_response(&silences.export_for(tenant, clock.now())),
+        None => json_response(&silences.export(clock.now())),
+    }
+}
+
+/// `POST /api/silences`, a tenant's only silence its own alerts.
+fn create_silence(
+    request: http::Request,
+    silences: &mut Silences,
+    clock: &dyn Clock,
+    tenant: Option<&str>,
+) -> http::Response {
+    let silence = serde_json::from_str::<SilenceRequest>(request.body())
+        .map_err(|e| format!("Silence could not be parsed. {e}"))
+        .and_then(|x| match tenant {
+            Some(tenant) => x.for_tenant(tenant).map_err(|e| e.to_string()),
+            None => Ok(x),
+        })
+        .and_then(|x| x.into_silence(clock.now()).map_err(|e| e.to_string()));
+    let silence = match silence {
+        Ok(silence) => silence,
+        Err(e) => return bad_request(e),
+    };
+    let id = silence.id().clone();
+    log::info!("Added silence {silence:?}");
+    silences.add(silence);
+    match silences.export_one(&id, clock.now()) {
+        Some(exported) => json_response(&exported),
+        None => not_found(),
+    }
+}
+
+/// Accepts the output of Alertmanager's `GET /api/v2/silences` or `amtool silence query -o json`.
+fn import_silences(
+    request: http::Request,
+    silences: &mut Silences,
+    clock: &dyn Clock,
+) -> http::Response {
+    match serde_json::from_str::<Vec<Silence>>(request.body()) {
+        Ok(imported) => {
+            let count = silences.import(imported, clock.now());
+            log::info!("Imported {count} silences");
+            json_response(&serde_json::json!({ "imported": count }))
+        }
+        Err(e) => bad_request(format!("Silences could not be parsed. {e}")),
+    }
+}
+
+/// `POST /api/fingerprints/bulk`, a tenant's only reaches its own fingerprints.
+fn bulk_update(
     request: http::Request,
-    fingerprints: &Arc<Mutex<Fingerprints>>,
+    fingerprints: &mut Fingerprints,
+    silences: &mut Silences,
+    clock: &dyn Clock,
+    tenant: Option<&str>,
 ) -> http::Response {
-    if request.request_line().method() != "GET" {
-        let status_line = "HTTP/1.1 302 Found".to_string();
-        let headers = vec!["Location: /".to_string()];
-        return http::Response::new(status_line, headers, None);
+    let bulk = serde_json::from_str::<BulkRequest>(request.body())
+        .map_err(|e| format!("Bulk request could not be parsed. {e}"))
+        .and_then(|x| match tenant {
+            Some(tenant) => x.for_tenant(tenant).map_err(|e| e.to_string()),
+            None => Ok(x),
+        });
+    let bulk = match bulk {
+        Ok(bulk) => bulk,
+        Err(e) => return bad_request(e),
+    };
+    match bulk.apply(fingerprints, silences, clock.now()) {
+        Ok(result) => {
+            log::info!("Applied bulk request {bulk:?}");
+            json_response(&result)
+        }
+        Err(e) => bad_request(e.to_string()),
     }
+}
 
-    let js = "<script> window.delete_fp = function(id) { fetch('/delete/fingerprint', { method: 'DELETE', body: id}).then(() => window.location.reload())}</script>";
-
-    let mut table = "<table border='1px solid black'>".to_string();
-    table +=
-        "<tr><th>Delete</th><th>ID</th><th>Name</th><th>Priority</th><th>Status</th><th>Last Alert</th><th>First Alert</th></tr>";
-    let fingerprints = fingerprints.lock().await;
-    for (_, fingerprint) in fingerprints.iter() {
-        let id = fingerprint.fingerprint();
-        let delete = format!("<a href='javascript:delete_fp(\"{id}\")'>X</a>");
-        let name = match fingerprint.name() {
-            Some(x) => x.clone(),
-            None => "Unknown".to_string(),
-        };
-        let priority = match fingerprint.priority() {
-            Some(x) => format!("{:?}", x),
-            None => "Unknown".to_string(),
-        };
-        let status = fingerprint.last_status();
-        let last_alert = format!("{}", fingerprint.last_alerted().format("%d/%m/%y %H:%M"));
-        let first_alert = match fingerprint.first_alerted() {
-            Some(x) => format!("{}", x.format("%d/%m/%Y %H:%M")),
-            None => "Unknown".to_string(),
-        };
-        table = format!("{table}<tr><td>{delete}</td><td>{id}</td><td>{name}</td><td>{priority}</td><td>{status}</td><td>{last_alert}</td><td>{first_alert}</td></tr>");
+/// `POST /api/fingerprints/{fingerprint}/ack`
+fn ack_fingerprint(
+    request: http::Request,
+    fingerprint: &str,
+    fingerprints: &mut Fingerprints,
+    clock: &dyn Clock,
+) -> http::Response {
+    let ack = match AckRequest::parse(request.body()) {
+        Ok(ack) => ack,
+        Err(e) => return bad_request(format!("Ack could not be parsed. {e}")),
+    };
+    let until = match ack.until(clock.now()) {
+        Some(until) => until,
+        None => return bad_request("Ack lasts too long.".to_string()),
+    };
+    if !fingerprints.ack(fingerprint, until) {
+        return not_found();
+    }
+    log::info!("Acked {fingerprint} with {ack:?}");
+    match fingerprints.get(fingerprint) {
+        Some(event) => json_response(event),
+        None => not_found(),
     }
-    table += "</table>";
-    let body = format!("<html><head>{js}</head><body>{table}</body></html>");
-    let status_line = "HTTP/1.1 200 OK".to_string();
-    let headers = vec!["Content-Type: text/html".to_string()];
-    http::Response::new(status_line, headers, Some(body))
 }
 
-async fn delete_fingerprint(
-    request: http::Request,
-    fingerprints: &mut Arc<Mutex<Fingerprints>>,
+/// Queues a fingerprint's re-alert now, even if it is silenced or acked, since someone asked for it.
+fn resend_fingerprint(
+    fingerprint: &str,
+    config: &Config,
+    sender: &QueueSender,
+    fingerprints: &mut Fingerprints,
+    clock: &dyn Clock,
 ) -> http::Response {
-    let mut fingerprints = fingerprints.lock().await;
-    let status_line = match fingerprints.remove(request.body()) {
-        Some(_) => "HTTP/1.1 200 OK".to_string(),
-        None => "HTTP/1.1 404 Not Found".to_string(),
+    let event = match fingerprints.get(fingerprint) {
+        Some(x) => x.clone(),
+        None => return not_found(),
     };
-    http::Response::new(status_line, vec![], None)
+    if event.last_status() == "resolved" {
+        let status_line = "HTTP/1.1 409 Conflict".to_string();
+        let headers = vec!["Content-Type: text/plain".to_string()];
+        let body = format!("{fingerprint} is resolved, there is nothing to re-send");
+        return http::Response::new(status_line, headers, Some(body));
+    }
+    let content = realert::still_firing(config, &event);
+    if let Err(e) = sender.add_for_each(&config.receivers_for_item(&event), &content) {
+        log::error!("Failed to queue the re-send of {fingerprint}, {e}");
+        let status_line = "HTTP/1.1 503 Service Unavailable".to_string();
+        let headers = vec!["Content-Type: text/plain".to_string()];
+        return http::Response::new(status_line, headers, Some(e.to_string()));
+    }
+    log::info!("Re-sent {fingerprint}");
+    fingerprints.update_last_alerted_from_previous_event(&event, clock.now());
+    match fingerprints.get(fingerprint) {
+        Some(event) => json_response(event),
+        None => not_found(),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::test::TestStream;
-    use prowl_queue::ProwlQueue;
+    use crate::clock::SystemClock;
+    use crate::subsystems::queue::NotificationQueue;
+    use crate::test::{MockClock, TestStream};
+    use chrono::Utc;
 
     #[tokio::test]
     async fn test_add_notification() {
         let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
         let alert: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
             .expect("Failed to load default, firing alert");
-        let (sender, reciever) = ProwlQueue::default().into_parts();
+        let (sender, reciever) = NotificationQueue::default().into_parts();
 
-        add_notification(&alert, &config, &sender)
+        add_notification(&alert, &config, &[], &sender)
             .await
             .expect("Failed to add notification");
         drop(sender);
-        let mut reciever = reciever.to_unbound_receiver();
-        let notification = reciever.recv().await.expect("Failed to get first result");
-        assert!(reciever.recv().await.is_none());
+        let notification = reciever.pop().expect("Failed to get first result");
+        assert!(reciever.pop().is_none());
 
         assert_eq!(notification.priority(), &Some(prowl::Priority::Normal));
         assert_eq!(
@@ -250,19 +1624,18 @@ mod test {
         let firing_alert: Alert = serde_json::from_str(&json).expect("Failed to load alert");
         let json = crate::test::consts::create_resolved_alert_with_prefix("[high] ");
         let resolved_alert: Alert = serde_json::from_str(&json).expect("Failed to load alert");
-        let (sender, reciever) = ProwlQueue::default().into_parts();
+        let (sender, reciever) = NotificationQueue::default().into_parts();
 
-        add_notification(&firing_alert, &config, &sender)
+        add_notification(&firing_alert, &config, &[], &sender)
             .await
             .expect("Failed to add notification");
-        add_notification(&resolved_alert, &config, &sender)
+        add_notification(&resolved_alert, &config, &[], &sender)
             .await
             .expect("Failed to add notification");
         drop(sender);
-        let mut reciever = reciever.to_unbound_receiver();
-        let firing_notification = reciever.recv().await.expect("Failed to get first result");
-        let resolved_notification = reciever.recv().await.expect("Failed to get first result");
-        assert!(reciever.recv().await.is_none());
+        let firing_notification = reciever.pop().expect("Failed to get first result");
+        let resolved_notification = reciever.pop().expect("Failed to get first result");
+        assert!(reciever.pop().is_none());
 
         assert_eq!(firing_notification.event(), "[🔥] [high] Alert Name");
         assert_eq!(firing_notification.priority(), &Some(prowl::Priority::High));
@@ -299,19 +1672,18 @@ mod test {
         let firing_alert: Alert = serde_json::from_str(&json).expect("Failed to load alert");
         let json = crate::test::consts::create_resolved_alert_with_prefix("[critical] ");
         let resolved_alert: Alert = serde_json::from_str(&json).expect("Failed to load alert");
-        let (sender, reciever) = ProwlQueue::default().into_parts();
+        let (sender, reciever) = NotificationQueue::default().into_parts();
 
-        add_notification(&firing_alert, &config, &sender)
+        add_notification(&firing_alert, &config, &[], &sender)
             .await
             .expect("Failed to add notification");
-        add_notification(&resolved_alert, &config, &sender)
+        add_notification(&resolved_alert, &config, &[], &sender)
             .await
             .expect("Failed to add notification");
         drop(sender);
-        let mut reciever = reciever.to_unbound_receiver();
-        let firing_notification = reciever.recv().await.expect("Failed to get first result");
-        let resolved_notification = reciever.recv().await.expect("Failed to get first result");
-        assert!(reciever.recv().await.is_none());
+        let firing_notification = reciever.pop().expect("Failed to get first result");
+        let resolved_notification = reciever.pop().expect("Failed to get first result");
+        assert!(reciever.pop().is_none());
 
         assert_eq!(
             firing_notification.priority(),
@@ -345,6 +1717,7 @@ mod test {
     }
 
     #[tokio::test]
+    #[allow(clippy::useless_vec)]
     async fn test_grafana_webook() {
         // firing
         let body = format!(
@@ -361,11 +1734,13 @@ mod test {
         .join("\r\n");
         let request = format!("{headers}\r\n\r\n{body}");
         let mut firing_stream = TestStream::new(request.as_bytes());
-        let firing_request =
-            http::Request::from_stream(&mut firing_stream).expect("Failed to build request");
+        let firing_request = http::Request::from_stream(&mut firing_stream)
+            .await
+            .expect("Failed to build request");
         let mut firing_stream2 = TestStream::new(request.as_bytes());
-        let firing_request2 =
-            http::Request::from_stream(&mut firing_stream2).expect("Failed to build request");
+        let firing_request2 = http::Request::from_stream(&mut firing_stream2)
+            .await
+            .expect("Failed to build request");
 
         // resolved
         let body = format!(
@@ -382,29 +1757,34 @@ mod test {
         .join("\r\n");
         let request = format!("{headers}\r\n\r\n{body}");
         let mut resolved_stream = TestStream::new(request.as_bytes());
-        let resolved_request =
-            http::Request::from_stream(&mut resolved_stream).expect("Failed to build request");
+        let resolved_request = http::Request::from_stream(&mut resolved_stream)
+            .await
+            .expect("Failed to build request");
 
         // others
         let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
-        let fingerprints = Fingerprints::load_or_default(&config);
-        let mut fingerprints = Arc::new(Mutex::new(fingerprints));
-        let (sender, reciever) = ProwlQueue::default().into_parts();
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
 
-        let response = grafana_webook(&config, firing_request, &sender, &mut fingerprints).await;
+        let response = handler
+            .receive_webhook(firing_request, Parser::Grafana, &[], None, None)
+            .await;
         assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
 
-        let response = grafana_webook(&config, firing_request2, &sender, &mut fingerprints).await;
+        let response = handler
+            .receive_webhook(firing_request2, Parser::Grafana, &[], None, None)
+            .await;
         assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
 
-        let response = grafana_webook(&config, resolved_request, &sender, &mut fingerprints).await;
+        let response = handler
+            .receive_webhook(resolved_request, Parser::Grafana, &[], None, None)
+            .await;
         assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
 
-        drop(sender);
-        let mut reciever = reciever.to_unbound_receiver();
-        let firing_notification = reciever.recv().await.expect("Failed to get first result");
-        let resolved_notification = reciever.recv().await.expect("Failed to get second result");
-        assert!(reciever.recv().await.is_none());
+        drop(handler);
+        let firing_notification = reciever.pop().expect("Failed to get first result");
+        let resolved_notification = reciever.pop().expect("Failed to get second result");
+        assert!(reciever.pop().is_none());
 
         assert_eq!(
             firing_notification.priority(),
@@ -436,4 +1816,982 @@ mod test {
             "resolved: Annotation Summary"
         );
     }
+
+    #[tokio::test]
+    async fn test_unknown_status() {
+        let body = format!(
+            "{{\"alerts\": [{}]}}",
+            crate::test::consts::create_firing_alert().replace("\"firing\"", "\"pending\"")
+        );
+        let message = format!(
+            "POST /webhooks/grafana HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let mut stream = TestStream::new(message.as_bytes());
+        let webhook = http::Request::from_stream(&mut stream)
+            .await
+            .expect("Failed to build request");
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+
+        let response = handler
+            .receive_webhook(webhook, Parser::Grafana, &[], None, None)
+            .await;
+        assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
+        let metrics = show_metrics(&*handler.metrics.lock().await);
+        let metrics = metrics.body().clone().expect("Missing body");
+        assert!(metrics.contains("{status=\"pending\"} 1"));
+
+        drop(handler);
+        let notification = reciever.pop().expect("Failed to get notification");
+        assert_eq!(notification.event(), "[pending] Alert Name");
+        assert_eq!(notification.priority(), &Some(prowl::Priority::VeryLow));
+    }
+
+    #[tokio::test]
+    async fn test_large_webhook() {
+        let body = format!(
+            "{{\"alerts\": [{}, {}]}}",
+            crate::test::consts::create_firing_alert(),
+            crate::test::consts::create_firing_alert_with_prefix("[critical] ")
+        );
+        let message = format!(
+            "POST /webhooks/grafana HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let mut stream = TestStream::new(message.as_bytes());
+        let webhook = http::Request::from_stream(&mut stream)
+            .await
+            .expect("Failed to build request");
+        let config: Config = serde_json::from_str(
+            r#"{"prowl_api_keys": ["key"], "fingerprints_file": "/dev/null", "large_webhook_alerts": 1}"#,
+        )
+        .expect("Failed to parse config");
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+
+        let response = handler
+            .receive_webhook(webhook, Parser::Grafana, &[], None, None)
+            .await;
+        assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
+        let metrics = handler.metrics.lock().await.render();
+        assert!(metrics.contains("grafana_prowl_webhook_alerts_bucket{le=\"2\"} 1\n"));
+        assert!(metrics.contains(&format!(
+            "grafana_prowl_webhook_body_bytes_sum {}\n",
+            body.len()
+        )));
+        assert!(metrics.contains("grafana_prowl_large_webhooks_total{threshold=\"alerts\"} 1\n"));
+
+        drop(handler);
+        let notification = reciever.pop().expect("Failed to get notification");
+        assert_eq!(notification.event(), "[📦] Large alert group");
+        assert_eq!(
+            notification.description(),
+            "Grafana sent a webhook with 2 alerts, over 1. Check the notification policy's group by."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bulk_update() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let alert: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        fingerprints.update_last_alerted(&alert, Utc::now());
+        let mut silences = Silences::default();
+        let post = |body: &str| {
+            let message = format!(
+                "POST /api/fingerprints/bulk HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            async move {
+                let mut stream = TestStream::new(message.as_bytes());
+                http::Request::from_stream(&mut stream)
+                    .await
+                    .expect("Failed to build request")
+            }
+        };
+
+        let response = bulk_update(
+            post(r#"{"matchers": ["alertname=Alert Name"], "action": "snooze"}"#).await,
+            &mut fingerprints,
+            &mut silences,
+            &SystemClock,
+            None,
+        );
+        assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
+        let body = response.body().clone().expect("Missing body");
+        assert!(body.contains("\"fingerprints\":[\"581dd91e73c77248\"]"));
+        assert!(silences.is_silenced(&alert, Utc::now()));
+
+        let response = bulk_update(
+            post(r#"{"matchers": ["alertname=Alert Name"], "action": "shred"}"#).await,
+            &mut fingerprints,
+            &mut silences,
+            &SystemClock,
+            None,
+        );
+        assert_eq!(response.status_line(), "HTTP/1.1 400 Bad Request");
+        assert_eq!(fingerprints.len(), 1);
+    }
+
+    fn test_handler(config: Config, sender: QueueSender) -> Handler {
+        Handler::new(
+            SharedConfig::new(config.clone()),
+            sender,
+            Arc::new(Mutex::new(Fingerprints::load_or_default(&config))),
+            Arc::new(Mutex::new(Silences::default())),
+            Arc::new(Mutex::new(Volume::new(Utc::now()))),
+            Arc::new(Mutex::new(Digest::default())),
+            Arc::new(SystemClock),
+        )
+    }
+
+    async fn request(method: &str, path: &str) -> http::Request {
+        let message = format!("{method} {path} HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+        let mut stream = TestStream::new(message.as_bytes());
+        http::Request::from_stream(&mut stream)
+            .await
+            .expect("Failed to build request")
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized() {
+        let config = Config::load(Some("src/resources/test-max-config.json".to_string()));
+        for (message, expected) in [
+            (
+                "GET / HTTP/1.1\r\n\r\n",
+                "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic",
+            ),
+            ("GET /api/groups HTTP/1.1\r\n\r\n", "HTTP/1.1 403 Forbidden"),
+            (
+                "POST /webhooks/grafana HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Bearer",
+            ),
+        ] {
+            let (sender, _reciever) = NotificationQueue::default().into_parts();
+            let handler = test_handler(config.clone(), sender);
+            let mut stream = TestStream::new(message.as_bytes());
+            let remote = "10.0.0.7:41234".parse().ok();
+            handler.handle(&mut stream, remote).await;
+            assert!(stream.sent().starts_with(expected), "{}", stream.sent());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probes() {
+        let get = |config: &Config, sender: QueueSender, path: &str| {
+            let handler = test_handler(config.clone(), sender);
+            let message = format!("GET {path} HTTP/1.1\r\nConnection: close\r\n\r\n");
+            async move {
+                let mut stream = TestStream::new(message.as_bytes());
+                handler.handle(&mut stream, None).await;
+                stream.sent()
+            }
+        };
+        // no auth needed, though the max config protects everything else
+        let config = Config::load(Some("src/resources/test-max-config.json".to_string()));
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let sent = get(&config, sender, "/healthz").await;
+        assert!(sent.starts_with("HTTP/1.1 200 OK"), "{sent}");
+
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let sent = get(&config, sender.clone(), "/readyz").await;
+        assert!(sent.starts_with("HTTP/1.1 200 OK"), "{sent}");
+        drop(reciever);
+        let sent = get(&config, sender, "/readyz").await;
+        assert!(
+            sent.starts_with("HTTP/1.1 503 Service Unavailable"),
+            "{sent}"
+        );
+        assert!(sent.ends_with("notification queue has stopped"), "{sent}");
+
+        let config: Config = serde_json::from_str(
+            r#"{"prowl_api_keys": [], "fingerprints_file": "/nonexistent/fingerprints.json"}"#,
+        )
+        .expect("Failed to parse config");
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let sent = get(&config, sender, "/readyz").await;
+        assert!(
+            sent.contains("fingerprints file /nonexistent/fingerprints.json is not writable"),
+            "{sent}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_allowed_source_cidrs() {
+        let config = Config::load(Some("src/resources/test-max-config.json".to_string()));
+        let webhook = "POST /webhooks/grafana HTTP/1.1\r\nContent-Length: 0\r\n\r\n";
+        for (remote, expected) in [
+            (Some("10.0.0.7:41234"), "HTTP/1.1 401 Unauthorized"),
+            (Some("192.168.1.20:41234"), "HTTP/1.1 401 Unauthorized"),
+            (
+                Some("[::ffff:192.168.1.20]:41234"),
+                "HTTP/1.1 401 Unauthorized",
+            ),
+            (Some("10.0.0.8:41234"), "HTTP/1.1 403 Forbidden"),
+            (None, "HTTP/1.1 403 Forbidden"),
+        ] {
+            let (sender, _reciever) = NotificationQueue::default().into_parts();
+            let handler = test_handler(config.clone(), sender);
+            let mut stream = TestStream::new(webhook.as_bytes());
+            let remote = remote.map(|x| x.parse().expect("Failed to parse address"));
+            handler.handle(&mut stream, remote).await;
+            assert!(stream.sent().starts_with(expected), "{remote:?}");
+        }
+
+        // only webhooks are limited
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+        let mut stream = TestStream::new(b"GET / HTTP/1.1\r\n\r\n");
+        handler
+            .handle(&mut stream, "10.0.0.8:41234".parse().ok())
+            .await;
+        assert!(stream.sent().starts_with("HTTP/1.1 401 Unauthorized"));
+    }
+
+    #[tokio::test]
+    async fn test_read_only() {
+        let config: Config = serde_json::from_str(
+            r#"{"prowl_api_keys": [], "fingerprints_file": "/dev/null", "read_only": true}"#,
+        )
+        .expect("Failed to parse config");
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+        let alert: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        handler
+            .fingerprints
+            .lock()
+            .await
+            .update_last_alerted(&alert, Utc::now());
+        for (message, expected) in [
+            (
+                "DELETE /delete/fingerprint HTTP/1.1\r\nContent-Length: 1\r\n\r\na",
+                "HTTP/1.1 403 Forbidden",
+            ),
+            (
+                "POST /api/queue/items/1/retry-now HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 403 Forbidden",
+            ),
+            (
+                "POST /api/fingerprints/bulk HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 403 Forbidden",
+            ),
+            (
+                "POST /api/test HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 403 Forbidden",
+            ),
+            (
+                "POST /api/reload HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 403 Forbidden",
+            ),
+            ("GET /api/queue/items HTTP/1.1\r\n\r\n", "HTTP/1.1 200 OK"),
+            ("GET / HTTP/1.1\r\n\r\n", "HTTP/1.1 200 OK"),
+        ] {
+            let mut stream = TestStream::new(message.as_bytes());
+            handler.clone().handle(&mut stream, None).await;
+            assert!(stream.sent().starts_with(expected), "{}", stream.sent());
+            assert!(!stream.sent().contains("<th>Actions</th>"));
+        }
+        assert_eq!(handler.fingerprints.lock().await.len(), 1);
+        assert!(handler.sender.items().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_listener_groups() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let listener: Listener =
+            serde_json::from_str(r#"{"bind_host": "127.0.0.1:0", "groups": ["api"]}"#)
+                .expect("Failed to parse listener");
+        let handler = test_handler(config, sender).for_listener(listener);
+        for (message, expected) in [
+            ("GET /api/groups HTTP/1.1\r\n\r\n", "HTTP/1.1 200 OK"),
+            ("GET / HTTP/1.1\r\n\r\n", "HTTP/1.1 404 Not Found"),
+            (
+                "POST /webhooks/grafana HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 404 Not Found",
+            ),
+        ] {
+            let mut stream = TestStream::new(message.as_bytes());
+            handler.clone().handle(&mut stream, None).await;
+            assert!(stream.sent().starts_with(expected), "{}", stream.sent());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_method_not_allowed() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+        for (message, expected) in [
+            (
+                "GET /webhooks/grafana HTTP/1.1\r\n\r\n",
+                "HTTP/1.1 405 Method Not Allowed\r\nAllow: POST\r\n",
+            ),
+            (
+                "POST / HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 405 Method Not Allowed\r\nAllow: GET\r\n",
+            ),
+            (
+                "GET /api/fingerprints/bulk HTTP/1.1\r\n\r\n",
+                "HTTP/1.1 405 Method Not Allowed\r\nAllow: POST\r\n",
+            ),
+            ("GET /api/nope HTTP/1.1\r\n\r\n", "HTTP/1.1 404 Not Found"),
+        ] {
+            let mut stream = TestStream::new(message.as_bytes());
+            handler.clone().handle(&mut stream, None).await;
+            assert!(stream.sent().starts_with(expected), "{}", stream.sent());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+        let message = "GET /api/groups HTTP/1.1\r\n\r\nGET /nope HTTP/1.1\r\nConnection: close\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+        let mut stream = TestStream::new(message.as_bytes());
+        handler.handle(&mut stream, None).await;
+        let sent = stream.sent();
+        let (first, second) = sent.split_once("[]").expect("Missing groups response");
+        assert!(first.starts_with("HTTP/1.1 200 OK"));
+        assert!(first.contains("Connection: keep-alive"));
+        assert!(second.starts_with("HTTP/1.1 404 Not Found"));
+        assert!(second.contains("Connection: close"));
+        assert!(!second.contains("<html>"));
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let config = Config::load(Some("src/resources/test-timeout-config.json".to_string()));
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+        let fingerprints = handler.fingerprints.clone();
+
+        let _held = fingerprints.lock().await;
+        let message = "GET / HTTP/1.1\r\n\r\n";
+        let mut stream = TestStream::new(message.as_bytes());
+        handler.handle(&mut stream, None).await;
+        assert!(stream.sent().starts_with("HTTP/1.1 504 Gateway Timeout"));
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_sections() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let fingerprints = Arc::new(Mutex::new(Fingerprints::load_or_default(&config)));
+        let silences = Arc::new(Mutex::new(Silences::default()));
+        for (fingerprint, name, status) in [
+            ("a", "[critical]Disk full", "firing"),
+            ("b", "[high]CPU hot", "firing"),
+            ("c", "Memory low", "firing"),
+            ("d", "Backup failed", "resolved"),
+        ] {
+            let alert: Alert = serde_json::from_str(&format!("{{\"status\": \"{status}\", \"generatorURL\": \"http://something/this\", \"fingerprint\": \"{fingerprint}\", \"labels\": {{ \"alertname\": \"{name}\" }}, \"annotations\": {{ \"summary\": \"Summary\" }}}}"))
+                .expect("Failed to parse alert");
+            fingerprints
+                .lock()
+                .await
+                .update_last_alerted(&alert, Utc::now());
+        }
+        let later = Utc::now() + chrono::Duration::hours(1);
+        assert!(fingerprints.lock().await.ack("c", later));
+
+        let response = display_fingerprints(
+            request("GET", "/").await,
+            &fingerprints,
+            &silences,
+            None,
+            &SystemClock,
+            false,
+            100,
+            Some(15),
+        )
+        .await;
+        let body = response.body().clone().expect("Missing body");
+        let position = |text: &str| body.find(text).expect(text);
+        assert!(position("Firing – Emergency") < position("Disk full"));
+        assert!(position("Disk full") < position("Firing – High"));
+        assert!(position("Firing – High") < position("CPU hot"));
+        assert!(position("CPU hot") < position("Silenced / Snoozed"));
+        assert!(position("Silenced / Snoozed") < position("Memory low"));
+        assert!(position("Memory low") < position("Recently Resolved"));
+        assert!(position("Recently Resolved") < position("Backup failed"));
+        assert!(!body.contains("Firing – Normal"));
+        assert!(body.contains("<details open><summary><b>Firing – High</b> (1)"));
+        assert!(body.contains("<details><summary><b>Recently Resolved</b> (1)"));
+        assert!(body.contains("<td><a href='http://something/this'>[high]CPU hot</a></td>"));
+        assert!(body.contains("<meta http-equiv='refresh' content='15'>"));
+        assert!(body.contains(
+            "<div class='banner firing'><b>3 firing</b>, highest Emergency, last alert "
+        ));
+        assert!(body.contains("<tr class='firing high'><td><button onclick='ack_fp(&quot;b&quot;)'>Ack</button><button onclick='silence_fp(&quot;b&quot;)'>Silence 1h</button><button onclick='resend_fp(&quot;b&quot;)'>Re-send</button><button onclick='delete_fp(&quot;b&quot;)'>Delete</button></td>"));
+        assert!(!body.contains("resend_fp(&quot;d&quot;)"));
+        assert!(body.contains("<tr class='resolved "));
+
+        let filtered = display_fingerprints(
+            request("GET", "/?status=firing&q=d&sort=name&order=desc").await,
+            &fingerprints,
+            &silences,
+            None,
+            &SystemClock,
+            false,
+            100,
+            None,
+        )
+        .await;
+        let body = filtered.body().clone().expect("Missing body");
+        // the incidents below the tables aren't filtered
+        assert!(body.contains("this'>[critical]Disk full</a>"));
+        assert!(!body.contains("this'>[high]CPU hot</a>"));
+        assert!(!body.contains("this'>Backup failed</a>"));
+        assert!(body.contains("<a href='?sort=name&order=asc&status=firing&q=d'>Name</a>"));
+        assert!(body.contains("<option value='firing' selected>Firing</option>"));
+
+        let etag = response
+            .headers()
+            .iter()
+            .find_map(|x| x.strip_prefix("ETag: "))
+            .expect("Missing ETag")
+            .to_string();
+        let message = format!("GET / HTTP/1.1\r\nIf-None-Match: {etag}\r\n\r\n");
+        let mut stream = TestStream::new(message.as_bytes());
+        let cached = http::Request::from_stream(&mut stream)
+            .await
+            .expect("Failed to build request");
+        let response = display_fingerprints(
+            cached,
+            &fingerprints,
+            &silences,
+            None,
+            &SystemClock,
+            false,
+            100,
+            Some(15),
+        )
+        .await;
+        assert_eq!(response.status_line(), "HTTP/1.1 304 Not Modified");
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_escapes() {
+        let alert = r#"{"status": "firing", "generatorURL": "javascript:alert(1)", "fingerprint": "<b>fp</b>", "labels": {"alertname": "<script>alert(1)</script>"}, "annotations": {"summary": "Summary"}}"#;
+        let body = format!("{{\"groupKey\": \"<i>group</i>\", \"alerts\": [{alert}]}}");
+        let message = format!(
+            "POST /webhooks/grafana HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let mut stream = TestStream::new(message.as_bytes());
+        let webhook = http::Request::from_stream(&mut stream)
+            .await
+            .expect("Failed to build request");
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+        let response = handler
+            .receive_webhook(webhook, Parser::Grafana, &[], None, None)
+            .await;
+        assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
+
+        let response = display_fingerprints(
+            request("GET", "/").await,
+            &handler.fingerprints,
+            &handler.silences,
+            None,
+            &SystemClock,
+            false,
+            100,
+            None,
+        )
+        .await;
+        let page = response.body().clone().expect("Missing body");
+        assert!(!page.contains("<script>alert(1)"), "{page}");
+        assert!(page.contains("<td>&lt;script&gt;alert(1)&lt;/script&gt;</td>"));
+        assert!(page.contains("<td>&lt;b&gt;fp&lt;/b&gt;</td>"));
+        assert!(page.contains("<td>&lt;i&gt;group&lt;/i&gt;</td>"));
+        assert!(!page.contains("javascript:"));
+        assert!(!page.contains("<b>fp</b>") && !page.contains("<i>group</i>"));
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_pages() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let fingerprints = Arc::new(Mutex::new(Fingerprints::load_or_default(&config)));
+        let silences = Arc::new(Mutex::new(Silences::default()));
+        let start = Utc::now();
+        for (minutes, fingerprint, status) in [
+            (1, "a", "firing"),
+            (2, "b", "firing"),
+            (3, "c", "resolved"),
+            (4, "d", "resolved"),
+            (5, "e", "resolved"),
+        ] {
+            let alert: Alert = serde_json::from_str(&format!("{{\"status\": \"{status}\", \"generatorURL\": \"\", \"fingerprint\": \"{fingerprint}\", \"labels\": {{ \"alertname\": \"Alert {fingerprint}\" }}, \"annotations\": {{ \"summary\": \"Summary\" }}}}"))
+                .expect("Failed to parse alert");
+            fingerprints
+                .lock()
+                .await
+                .update_last_alerted(&alert, start + chrono::Duration::minutes(minutes));
+        }
+        let page = |path: &'static str| {
+            let fingerprints = fingerprints.clone();
+            let silences = silences.clone();
+            async move {
+                let response = display_fingerprints(
+                    request("GET", path).await,
+                    &fingerprints,
+                    &silences,
+                    None,
+                    &SystemClock,
+                    true,
+                    2,
+                    None,
+                )
+                .await;
+                response.body().clone().expect("Missing body")
+            }
+        };
+
+        // firing comes before resolved, newest first within each
+        let body = page("/").await;
+        assert!(body.contains("<p>Showing 1–2 of 5, page 1 of 3 <a href='?page=2'>Next</a></p>"));
+        assert!(body.contains("<td>b</td>") && body.contains("<td>a</td>"));
+        assert!(!body.contains("<td>e</td>"));
+
+        let body = page("/?page=2&order=asc&sort=id").await;
+        assert!(body.contains("<p>Showing 3–4 of 5, page 2 of 3 <a href='?sort=id&order=asc&page=1'>Prev</a> <a href='?sort=id&order=asc&page=3'>Next</a></p>"));
+        assert!(body.contains("<td>c</td>") && body.contains("<td>d</td>"));
+        assert!(body.contains("<b>Recently Resolved</b> (3)"));
+        assert!(!body.contains("<b>Firing"));
+
+        // past the end shows the last page, and per_page overrides the default
+        let body = page("/?page=9&per_page=3&status=resolved").await;
+        assert!(body.contains("<p>Showing 1–3 of 3, page 1 of 1</p>"));
+        let body = page("/?page=9").await;
+        assert!(body.contains("Showing 5–5 of 5, page 3 of 3"));
+        assert!(body.contains("<td>c</td>"));
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_etag() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let fingerprints = Arc::new(Mutex::new(Fingerprints::load_or_default(&config)));
+        let silences = Arc::new(Mutex::new(Silences::default()));
+        let clock = MockClock::new("2022-09-09T10:00:00Z".parse().expect("Bad time"));
+        let alert: Alert = serde_json::from_str(r#"{"status": "resolved", "generatorURL": "", "fingerprint": "a", "labels": {"alertname": "Disk full"}, "annotations": {"summary": "Summary"}}"#)
+            .expect("Failed to parse alert");
+        fingerprints
+            .lock()
+            .await
+            .update_last_alerted(&alert, clock.now());
+        let until = clock.now() + chrono::Duration::minutes(30);
+        assert!(fingerprints.lock().await.ack("a", until));
+        let page = |etag: &str| {
+            let message = format!("GET / HTTP/1.1\r\nIf-None-Match: {etag}\r\n\r\n");
+            let fingerprints = fingerprints.clone();
+            let silences = silences.clone();
+            let clock = &clock;
+            async move {
+                let mut stream = TestStream::new(message.as_bytes());
+                let request = http::Request::from_stream(&mut stream)
+                    .await
+                    .expect("Failed to build request");
+                let response = display_fingerprints(
+                    request,
+                    &fingerprints,
+                    &silences,
+                    None,
+                    clock,
+                    false,
+                    100,
+                    None,
+                )
+                .await;
+                let etag = response
+                    .headers()
+                    .iter()
+                    .find_map(|x| x.strip_prefix("ETag: "))
+                    .expect("Missing ETag")
+                    .to_string();
+                (response.status_line().to_string(), etag)
+            }
+        };
+
+        let (status, etag) = page("\"none\"").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(page(&etag).await.0, "HTTP/1.1 304 Not Modified");
+
+        // a new silence changes the page
+        let silence = serde_json::from_str::<SilenceRequest>(r#"{"alertname": "CPU hot"}"#)
+            .expect("Failed to parse silence")
+            .into_silence(clock.now())
+            .expect("Failed to create silence");
+        silences.lock().await.add(silence);
+        let (status, etag) = page(&etag).await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(page(&etag).await.0, "HTTP/1.1 304 Not Modified");
+
+        // and so does the ack expiring, without anything else changing
+        clock.advance(chrono::Duration::minutes(31));
+        let (status, etag) = page(&etag).await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(page(&etag).await.0, "HTTP/1.1 304 Not Modified");
+    }
+
+    #[tokio::test]
+    async fn test_export_headers() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let fingerprints = Arc::new(Mutex::new(Fingerprints::load_or_default(&config)));
+        let clock = MockClock::new("2022-09-09T10:05:00Z".parse().expect("Bad time"));
+        let response = export_fingerprints(
+            request("GET", "/export?format=csv").await,
+            &fingerprints,
+            &clock,
+        )
+        .await;
+        assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
+        assert_eq!(
+            response.headers(),
+            &vec![
+                "Content-Type: text/csv; charset=utf-8".to_string(),
+                "Content-Disposition: attachment; filename=\"fingerprints-20220909T100500Z.csv\""
+                    .to_string()
+            ]
+        );
+        let response =
+            export_fingerprints(request("GET", "/export").await, &fingerprints, &clock).await;
+        assert_eq!(response.body(), &Some("[]".to_string()));
+        assert!(response
+            .headers()
+            .contains(&"Content-Type: application/json".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_etag() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let fingerprints = Arc::new(Mutex::new(Fingerprints::load_or_default(&config)));
+        let if_none_match = |etag: &str| {
+            let message =
+                format!("GET /api/groups HTTP/1.1\r\nIf-None-Match: \"x\", {etag}\r\n\r\n");
+            async move {
+                let mut stream = TestStream::new(message.as_bytes());
+                http::Request::from_stream(&mut stream)
+                    .await
+                    .expect("Failed to build request")
+            }
+        };
+
+        let response = list_groups(request("GET", "/api/groups").await, &fingerprints).await;
+        assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
+        let etag = response
+            .headers()
+            .iter()
+            .find_map(|x| x.strip_prefix("ETag: "))
+            .expect("Missing ETag")
+            .to_string();
+        let response = list_groups(if_none_match(&etag).await, &fingerprints).await;
+        assert_eq!(response.status_line(), "HTTP/1.1 304 Not Modified");
+        assert!(response.body().is_none());
+
+        let alert: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        fingerprints
+            .lock()
+            .await
+            .update_last_seen(&alert, Utc::now());
+        let response = list_groups(if_none_match(&etag).await, &fingerprints).await;
+        assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
+    }
+
+    #[tokio::test]
+    async fn test_fingerprints_api() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+        let alert: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        handler
+            .fingerprints
+            .lock()
+            .await
+            .update_last_alerted(&alert, Utc::now());
+        let send = |method: &str, path: String| {
+            let handler = handler.clone();
+            let message = format!(
+                "{method} {path} HTTP/1.1\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+            );
+            async move {
+                let mut stream = TestStream::new(message.as_bytes());
+                handler.handle(&mut stream, None).await;
+                let sent = stream.sent();
+                let (head, body) = sent.split_once("\r\n\r\n").unwrap_or((&sent, ""));
+                (head.to_string(), body.to_string())
+            }
+        };
+
+        let (head, body) = send("GET", "/api/fingerprints".to_string()).await;
+        assert!(head.starts_with("HTTP/1.1 200 OK"), "{head}");
+        let all: serde_json::Value = serde_json::from_str(&body).expect("Not JSON");
+        let id = alert.fingerprint();
+        assert_eq!(all[id]["last_status"], "firing");
+        assert_eq!(all.as_object().map(|x| x.len()), Some(1));
+
+        let (head, body) = send("GET", format!("/api/fingerprints/{id}")).await;
+        assert!(head.starts_with("HTTP/1.1 200 OK"), "{head}");
+        let one: serde_json::Value = serde_json::from_str(&body).expect("Not JSON");
+        assert_eq!(one, all[id]);
+
+        let (head, _) = send("GET", "/api/fingerprints/missing".to_string()).await;
+        assert!(head.starts_with("HTTP/1.1 404 Not Found"), "{head}");
+
+        let (head, _) = send("DELETE", format!("/api/fingerprints/{id}")).await;
+        assert!(head.starts_with("HTTP/1.1 204 No Content"), "{head}");
+        assert_eq!(handler.fingerprints.lock().await.len(), 0);
+        let (head, _) = send("DELETE", format!("/api/fingerprints/{id}")).await;
+        assert!(head.starts_with("HTTP/1.1 404 Not Found"), "{head}");
+    }
+
+    #[tokio::test]
+    async fn test_ack_fingerprint() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let mut fingerprints = Fingerprints::load_or_default(&config);
+        let alert: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        fingerprints.update_last_alerted(&alert, Utc::now());
+        let id = alert.fingerprint();
+        let now = Utc::now();
+        let clock = MockClock::new(now);
+        let ack = |body: &str| {
+            let message = format!(
+                "POST /api/fingerprints/{id}/ack HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            async move {
+                let mut stream = TestStream::new(message.as_bytes());
+                http::Request::from_stream(&mut stream)
+                    .await
+                    .expect("Failed to build request")
+            }
+        };
+
+        let response = ack_fingerprint(ack("").await, id, &mut fingerprints, &clock);
+        assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
+        let acked_until = fingerprints.get(id).and_then(|x| *x.acked_until());
+        assert_eq!(acked_until, Some(now + chrono::Duration::hours(1)));
+
+        let response = ack_fingerprint(
+            ack(r#"{"minutes": "2h30m"}"#).await,
+            id,
+            &mut fingerprints,
+            &clock,
+        );
+        let body: serde_json::Value =
+            serde_json::from_str(&response.body().clone().expect("Missing body"))
+                .expect("Not JSON");
+        let expected = now + chrono::Duration::minutes(150);
+        assert_eq!(body["acked_until"], serde_json::json!(expected));
+
+        let response = ack_fingerprint(
+            ack(r#"{"minutes": "soon"}"#).await,
+            id,
+            &mut fingerprints,
+            &clock,
+        );
+        assert_eq!(response.status_line(), "HTTP/1.1 400 Bad Request");
+        let response = ack_fingerprint(ack("").await, "missing", &mut fingerprints, &clock);
+        assert_eq!(response.status_line(), "HTTP/1.1 404 Not Found");
+    }
+
+    #[tokio::test]
+    async fn test_silences_api() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+        let send = |method: &str, path: &str, body: &str| {
+            let handler = handler.clone();
+            let message = format!(
+                "{method} {path} HTTP/1.1\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            async move {
+                let mut stream = TestStream::new(message.as_bytes());
+                handler.handle(&mut stream, None).await;
+                stream.sent()
+            }
+        };
+
+        let sent = send("POST", "/api/silences", r#"{"alertname": "Alert Name"}"#).await;
+        assert!(sent.starts_with("HTTP/1.1 200 OK"), "{sent}");
+        let body = sent.split_once("\r\n\r\n").map(|(_, x)| x).unwrap_or("");
+        let silence: serde_json::Value = serde_json::from_str(body).expect("Not JSON");
+        assert_eq!(silence["status"]["state"], "active");
+        let id = silence["id"].as_str().expect("Missing ID").to_string();
+        let alert: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        assert!(handler
+            .silences
+            .lock()
+            .await
+            .is_silenced(&alert, Utc::now()));
+
+        let sent = send("POST", "/api/silences", "{}").await;
+        assert!(sent.starts_with("HTTP/1.1 400 Bad Request"), "{sent}");
+
+        let path = format!("/api/silences/{id}");
+        let sent = send("DELETE", &path, "").await;
+        assert!(sent.starts_with("HTTP/1.1 204 No Content"), "{sent}");
+        assert!(!handler
+            .silences
+            .lock()
+            .await
+            .is_silenced(&alert, Utc::now()));
+        let sent = send("DELETE", &path, "").await;
+        assert!(sent.starts_with("HTTP/1.1 404 Not Found"), "{sent}");
+    }
+
+    #[tokio::test]
+    async fn test_custom_webhooks() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "prowl_api_keys": ["default_key"],
+            "fingerprints_file": "/dev/null",
+            "receivers": { "team": { "api_keys": ["team_key"] } },
+            "webhooks": [{ "path": "/webhooks/teams/*", "receivers": ["team"] }]
+        }))
+        .expect("Failed to parse config");
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+        let send = |path: &str, body: String| {
+            let handler = handler.clone();
+            let message = format!(
+                "POST {path} HTTP/1.1\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            async move {
+                let mut stream = TestStream::new(message.as_bytes());
+                handler.handle(&mut stream, None).await;
+                stream.sent()
+            }
+        };
+
+        let alert = crate::test::consts::create_firing_alert();
+        let sent = send("/webhooks/teams/a/b", format!("{{\"alerts\": [{alert}]}}")).await;
+        assert!(sent.starts_with("HTTP/1.1 200 OK"), "{sent}");
+        let notification = format!("{:?}", reciever.pop().expect("Missing notification"));
+        assert!(notification.contains("[\"team_key\"]"), "{notification}");
+
+        let alert = alert.replace("581dd91e73c77248", "aaaa");
+        let sent = send("/webhooks/grafana", format!("{{\"alerts\": [{alert}]}}")).await;
+        assert!(sent.starts_with("HTTP/1.1 200 OK"), "{sent}");
+        let notification = format!("{:?}", reciever.pop().expect("Missing notification"));
+        assert!(notification.contains("[\"default_key\"]"), "{notification}");
+
+        let sent = send("/webhooks/teams/", String::new()).await;
+        assert!(sent.starts_with("HTTP/1.1 404 Not Found"), "{sent}");
+    }
+
+    #[tokio::test]
+    async fn test_version() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+        let response = handler
+            .clone()
+            .route(request("GET", "/version").await, None)
+            .await;
+        let body = response.body().clone().expect("Missing body");
+        let version: serde_json::Value = serde_json::from_str(&body).expect("Not JSON");
+        assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(version["config_file"], "src/resources/test-dev-null.json");
+        assert_eq!(version["uptime_secs"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_test_notification() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let handler = test_handler(config, sender);
+        let send = || {
+            let handler = handler.clone();
+            let message =
+                "POST /api/test HTTP/1.1\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+            async move {
+                let mut stream = TestStream::new(message.as_bytes());
+                handler.handle(&mut stream, None).await;
+                stream.sent()
+            }
+        };
+
+        let sent = send().await;
+        assert!(sent.starts_with("HTTP/1.1 200 OK"), "{sent}");
+        assert!(sent.ends_with("{\"item\":1}"), "{sent}");
+        let notification = reciever.pop().expect("Missing test notification");
+        assert_eq!(notification.event(), "Test notification");
+        assert_eq!(notification.priority(), &Some(Priority::Moderate));
+
+        drop(reciever);
+        let sent = send().await;
+        assert!(
+            sent.starts_with("HTTP/1.1 503 Service Unavailable"),
+            "{sent}"
+        );
+        assert!(sent.ends_with("The notification queue is no longer running."));
+    }
+
+    #[tokio::test]
+    async fn test_queue_items() {
+        let config = Config::load(Some("src/resources/test-dev-null.json".to_string()));
+        let alert: Alert = serde_json::from_str(&crate::test::consts::create_firing_alert())
+            .expect("Failed to load default, firing alert");
+        let (sender, _reciever) = NotificationQueue::default().into_parts();
+        let notification = NotificationContent::new(
+            Some(alert.get_priority()),
+            None,
+            config.app_name().to_string(),
+            "first".to_string(),
+            "description".to_string(),
+        )
+        .to_prowl(config.prowl_api_keys().to_owned())
+        .expect("Failed to build notification");
+        let id = sender.add(notification).expect("Failed to queue");
+
+        let response = list_queue_items(&sender);
+        assert_eq!(response.status_line(), "HTTP/1.1 200 OK");
+        let body = response.body().clone().expect("Missing body");
+        assert!(body.contains("\"event\":\"first\""));
+        assert!(!body.contains("default_key1"));
+
+        let mut handler = test_handler(config, sender.clone());
+        let retry_now = format!("/api/queue/items/{id}/retry-now");
+        let item = format!("/api/queue/items/{id}");
+        for (method, path, expected) in [
+            ("POST", retry_now.as_str(), "HTTP/1.1 204 No Content"),
+            ("GET", &retry_now, "HTTP/1.1 405 Method Not Allowed"),
+            ("DELETE", &item, "HTTP/1.1 204 No Content"),
+            ("DELETE", &item, "HTTP/1.1 404 Not Found"),
+            ("DELETE", "/api/queue/items/abc", "HTTP/1.1 404 Not Found"),
+            ("POST", &format!("{item}/other"), "HTTP/1.1 404 Not Found"),
+        ] {
+            let response = handler.respond(request(method, path).await, None).await;
+            assert_eq!(response.status_line(), expected, "{method} {path}");
+        }
+        assert!(sender.items().is_empty());
+
+        let response = list_deliveries(request("GET", "/api/deliveries").await, &sender);
+        let body = response.body().clone().expect("Missing body");
+        let events: Vec<serde_json::Value> =
+            serde_json::from_str(&body).expect("Failed to parse deliveries");
+        let statuses: Vec<_> = events.iter().map(|x| x["status"].as_str()).collect();
+        assert_eq!(statuses, vec![Some("queued"), Some("removed")]);
+        assert_eq!(events[1]["event"], "first");
+
+        let path = format!("/api/deliveries?since={}", events[0]["id"]);
+        let response = list_deliveries(request("GET", &path).await, &sender);
+        let body = response.body().clone().expect("Missing body");
+        assert!(!body.contains("queued"));
+        let response = list_deliveries(request("GET", "/api/deliveries?since=x").await, &sender);
+        assert_eq!(response.status_line(), "HTTP/1.1 400 Bad Request");
+    }
 }
@@ -0,0 +1,12 @@
+use tokio::{
+    sync::broadcast::Receiver,
+    time::{sleep, Duration},
+};
+
+/// Sleeps for the duration, returning early with `true` if shutdown was requested.
+pub(crate) async fn sleep_or_shutdown(duration: Duration, shutdown: &mut Receiver<()>) -> bool {
+    tokio::select! {
+        _ = sleep(duration) => false,
+        _ = shutdown.recv() => true,
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{
+    errors::DeliveryError,
+    models::{config::Config, notification::Addressed},
+    subsystems::sinks::{answered, http_client, reachable, truncate, NotificationSink},
+};
+use prowl::Priority;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+use tokio::time::Duration;
+
+/// Discord's limits for an embed.
+const MAX_TITLE_CHARS: usize = 256;
+const MAX_DESCRIPTION_CHARS: usize = 4096;
+const MAX_FIELD_CHARS: usize = 1024;
+
+/// Posts to Discord webhooks, with the same timeouts as Prowl.
+#[derive(Clone)]
+pub(crate) struct DiscordClient {
+    client: reqwest::Client,
+}
+
+impl DiscordClient {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self::build(
+            Duration::from_secs(*config.prowl_timeout_secs()),
+            Duration::from_secs(*config.prowl_connect_timeout_secs()),
+            Duration::from_secs(*config.prowl_keep_alive_secs()),
+        )
+    }
+
+    fn build(timeout: Duration, connect_timeout: Duration, keep_alive: Duration) -> Self {
+        Self {
+            client: http_client("Discord", timeout, connect_timeout, keep_alive),
+        }
+    }
+
+    /// Discord describes a webhook when its URL is fetched.
+    pub(crate) async fn verify(&self, webhook: &str, timeout: Duration) -> Result<(), String> {
+        reachable("Discord", self.client.get(webhook), timeout).await
+    }
+}
+
+impl Default for DiscordClient {
+    fn default() -> Self {
+        Self::build(
+            Duration::from_secs(30),
+            Duration::from_secs(10),
+            Duration::from_secs(90),
+        )
+    }
+}
+
+/// The embed's stripe, red for `Emergency` down to grey for `VeryLow`.
+fn colour(priority: &Option<Priority>) -> u32 {
+    match priority {
+        Some(Priority::Emergency) => 0xE74C3C,
+        Some(Priority::High) => 0xE67E22,
+        Some(Priority::Normal) | None => 0xF1C40F,
+        Some(Priority::Moderate) => 0x3498DB,
+        Some(Priority::VeryLow) => 0x95A5A6,
+    }
+}
+
+/// One embed for the notification. With fields, the description's first line is
+/// shown as them, and the time in each reader's own timezone.
+fn embed(addressed: &Addressed) -> Value {
+    let notification = addressed.notification();
+    let mut fields: Vec<Value> = addressed
+        .fields()
+        .iter()
+        .map(|(name, value)| {
+            json!({ "name": name, "value": truncate(value, MAX_FIELD_CHARS), "inline": name != "Summary" })
+        })
+        .collect();
+    let description = match fields.is_empty() {
+        true => notification.description().clone(),
+        false => notification
+            .description()
+            .lines()
+            .skip(1)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    let mut embed = json!({
+        "title": truncate(notification.event(), MAX_TITLE_CHARS),
+        "color": colour(notification.priority()),
+    });
+    if !description.is_empty() {
+        embed["description"] = json!(truncate(&description, MAX_DESCRIPTION_CHARS));
+    }
+    if let Some(time) = addressed.time() {
+        fields.push(json!({ "name": "Time", "value": format!("<t:{}:f>", time.timestamp()), "inline": true }));
+        embed["timestamp"] = json!(time.to_rfc3339());
+    }
+    if !fields.is_empty() {
+        embed["fields"] = json!(fields);
+    }
+    // Discord rejects links that aren't HTTP
+    if let Some(url) = notification
+        .url()
+        .as_ref()
+        .filter(|x| x.starts_with("https://") || x.starts_with("http://"))
+    {
+        embed["url"] = json!(url);
+    }
+    embed
+}
+
+impl NotificationSink for DiscordClient {
+    /// A request per webhook URL. Discord answers 429 when a webhook is used too fast,
+    /// which is retried.
+    async fn send(&self, addressed: &Addressed) -> Result<(), DeliveryError> {
+        let body = json!({
+            "username": addressed.notification().application(),
+            "embeds": [embed(addressed)],
+        })
+        .to_string();
+        for url in addressed.api_keys() {
+            let response = self
+                .client
+                .post(url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await
+                // the URL has the webhook's token in it
+                .map_err(|e| DeliveryError::Send("Discord", e.without_url()))?;
+            answered("Discord", response).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        models::{notification::NotificationContent, receiver::Receiver},
+        subsystems::sinks::test::{answer, fake_server},
+    };
+    use tokio::net::TcpListener;
+
+    fn addressed(webhook: &str) -> Addressed {
+        let receiver: Receiver = serde_json::from_value(json!({
+            "api_keys": [webhook],
+            "sink": "discord"
+        }))
+        .expect("Failed to parse receiver");
+        NotificationContent::new(
+            Some(Priority::High),
+            Some("https://grafana.example.com/d/1".to_string()),
+            "Grafana".to_string(),
+            "[🔥] Disk full".to_string(),
+            "firing: 95% used\nrunbook_url: https://wiki.example.com/disk".to_string(),
+        )
+        .with_field("Status", "firing".to_string())
+        .with_field("Summary", "95% used".to_string())
+        .with_time("2022-09-09T10:00:00Z".parse().ok())
+        .to_prowl_for(receiver.api_keys().clone(), &receiver)
+        .expect("Failed to create notification")
+    }
+
+    #[test]
+    fn embeds() {
+        assert_eq!(
+            embed(&addressed("https://discord.com/api/webhooks/1/token")),
+            json!({
+                "title": "[🔥] Disk full",
+                "color": 0xE67E22,
+                "description": "runbook_url: https://wiki.example.com/disk",
+                "url": "https://grafana.example.com/d/1",
+                "timestamp": "2022-09-09T10:00:00+00:00",
+                "fields": [
+                    { "name": "Status", "value": "firing", "inline": true },
+                    { "name": "Summary", "value": "95% used", "inline": false },
+                    { "name": "Time", "value": "<t:1662717600:f>", "inline": true }
+                ]
+            })
+        );
+
+        let plain = NotificationContent::new(
+            None,
+            None,
+            "Grafana".to_string(),
+            "[🟢] Notifier started".to_string(),
+            "Started.".to_string(),
+        )
+        .to_prowl(vec!["https://discord.com/api/webhooks/1/token".to_string()])
+        .expect("Failed to create notification");
+        assert_eq!(
+            embed(&plain),
+            json!({ "title": "[🟢] Notifier started", "color": 0xF1C40F, "description": "Started." })
+        );
+    }
+
+    #[tokio::test]
+    async fn posts_to_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let webhook = format!(
+            "http://{}/api/webhooks/1/token",
+            listener.local_addr().expect("No address")
+        );
+        let server = tokio::spawn(fake_server(
+            listener,
+            vec![answer("204 No Content", "", b"")],
+        ));
+        DiscordClient::default()
+            .send(&addressed(&webhook))
+            .await
+            .expect("Failed to send");
+
+        let requests = server.await.expect("Fake Discord failed");
+        assert!(requests[0].starts_with("POST /api/webhooks/1/token HTTP/1.1"));
+        let body = requests[0].split("\r\n\r\n").nth(1).expect("No body");
+        let body: Value = serde_json::from_str(body).expect("Body isn't JSON");
+        assert_eq!(body["username"], "Grafana");
+        assert_eq!(body["embeds"][0]["title"], "[🔥] Disk full");
+    }
+}
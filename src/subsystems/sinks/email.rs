@@ -0,0 +1,305 @@
+use crate::{
+    errors::DeliveryError,
+    models::{
+        config::Config,
+        notification::Addressed,
+        sink::{SmtpSettings, SmtpTls},
+    },
+    subsystems::sinks::NotificationSink,
+};
+use lettre::{
+    message::{
+        header::{ContentTransferEncoding, ContentType},
+        Body,
+    },
+    transport::smtp::{
+        authentication::Credentials,
+        client::{Tls, TlsParameters},
+        extension::ClientId,
+        Error as SmtpError,
+    },
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::error::Error;
+use tokio::time::Duration;
+
+/// What the notifier calls itself in `EHLO`.
+const CLIENT_NAME: &str = "grafana-prowl-notifier";
+
+/// Sends email with the `smtp` server, a connection per notification.
+#[derive(Clone)]
+pub(crate) struct EmailClient {
+    smtp: Option<SmtpSettings>,
+    timeout: Duration,
+}
+
+impl EmailClient {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            smtp: config.smtp().clone(),
+            timeout: Duration::from_secs(*config.prowl_timeout_secs()),
+        }
+    }
+
+    /// Connects, greets and signs in to the `smtp` server without sending anything.
+    pub(crate) async fn verify(&self) -> Result<(), String> {
+        let smtp = self.smtp.as_ref().ok_or("No smtp server is configured")?;
+        let transport = transport(smtp, self.timeout).map_err(|e| e.to_string())?;
+        match transport.test_connection().await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err("The mail server didn't answer NOOP".to_string()),
+            Err(e) => Err(delivery_error(e).to_string()),
+        }
+    }
+}
+
+impl Default for EmailClient {
+    fn default() -> Self {
+        Self {
+            smtp: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A plain text email, base64 encoded so long lines and any character survive.
+fn email(addressed: &Addressed, from: &str) -> Result<Message, DeliveryError> {
+    let notification = addressed.notification();
+    let mut body = notification.description().clone();
+    if let Some(url) = notification.url() {
+        body = format!("{body}\n\n{url}");
+    }
+    let bad_address = |e: lettre::address::AddressError| DeliveryError::Email(e.to_string());
+    let mut builder = Message::builder()
+        .from(from.parse().map_err(bad_address)?)
+        .subject(notification.event())
+        .header(ContentType::TEXT_PLAIN);
+    for recipient in addressed.api_keys() {
+        builder = builder.to(recipient.parse().map_err(bad_address)?);
+    }
+    let body = Body::new_with_encoding(body, ContentTransferEncoding::Base64)
+        .map_err(|_| DeliveryError::Email("The body can't be base64 encoded".to_string()))?;
+    builder
+        .body(body)
+        .map_err(|e| DeliveryError::Email(e.to_string()))
+}
+
+fn transport(
+    smtp: &SmtpSettings,
+    timeout: Duration,
+) -> Result<AsyncSmtpTransport<Tokio1Executor>, DeliveryError> {
+    let tls = || {
+        TlsParameters::new(smtp.host().clone())
+            .map_err(|e| DeliveryError::MailConnection(e.to_string()))
+    };
+    let tls = match smtp.tls() {
+        SmtpTls::Starttls => Tls::Required(tls()?),
+        SmtpTls::Tls => Tls::Wrapper(tls()?),
+        SmtpTls::None => Tls::None,
+    };
+    let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(smtp.host())
+        .port(*smtp.port())
+        .tls(tls)
+        .hello_name(ClientId::Domain(CLIENT_NAME.to_string()))
+        .timeout(Some(timeout));
+    if let (Some(username), Some(password)) = (smtp.username(), smtp.password()) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    Ok(builder.build())
+}
+
+/// 4xx replies are temporary and 5xx ones aren't, anything else is the connection's fault.
+fn delivery_error(e: SmtpError) -> DeliveryError {
+    match e.status() {
+        Some(code) => DeliveryError::Mail(
+            code.into(),
+            e.source().map(ToString::to_string).unwrap_or_default(),
+        ),
+        None => DeliveryError::MailConnection(e.to_string()),
+    }
+}
+
+impl NotificationSink for EmailClient {
+    /// One email to all of the notification's addresses.
+    async fn send(&self, addressed: &Addressed) -> Result<(), DeliveryError> {
+        let smtp = match &self.smtp {
+            Some(smtp) => smtp,
+            None => {
+                return Err(DeliveryError::Mail(
+                    554,
+                    "No smtp server is configured".to_string(),
+                ))
+            }
+        };
+        let email = email(addressed, smtp.from())?;
+        transport(smtp, self.timeout)?
+            .send(email)
+            .await
+            .map(|_| ())
+            .map_err(delivery_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::{notification::NotificationContent, receiver::Receiver};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    /// Answers each command with the next reply, and the email with the
+    /// reply after `354`. Returns everything the client sent.
+    async fn fake_smtp(listener: TcpListener, replies: Vec<&'static str>) -> String {
+        let (stream, _) = listener.accept().await.expect("Failed to accept");
+        let mut stream = BufReader::new(stream);
+        let mut replies = replies.into_iter();
+        let greeting = replies.next().expect("No greeting");
+        let greeting = greeting.as_bytes();
+        stream.write_all(greeting).await.expect("Failed to write");
+        let mut received = String::new();
+        let mut in_data = false;
+        for reply in replies {
+            loop {
+                let mut line = String::new();
+                let read = stream.read_line(&mut line).await.expect("Failed to read");
+                assert!(read > 0, "Client closed the connection");
+                received.push_str(&line);
+                if !in_data || line == ".\r\n" {
+                    break;
+                }
+            }
+            in_data = reply.starts_with("354");
+            stream
+                .write_all(reply.as_bytes())
+                .await
+                .expect("Failed to write");
+        }
+        received
+    }
+
+    fn addressed() -> Addressed {
+        let receiver: Receiver = serde_json::from_str(
+            r#"{"api_keys": ["me@example.com", "you@example.com"], "delivery": "together", "sink": "email"}"#,
+        )
+        .expect("Failed to parse receiver");
+        NotificationContent::new(
+            None,
+            Some("https://grafana.example.com/d/1".to_string()),
+            "Grafana".to_string(),
+            "[✅] Disk full".to_string(),
+            "resolved after 2h 5m: 95% used".to_string(),
+        )
+        .to_prowl_for(receiver.api_keys().clone(), &receiver)
+        .expect("Failed to create notification")
+    }
+
+    #[test]
+    fn sender_address() {
+        let message = email(&addressed(), "Grafana <grafana@example.com>").expect("Bad email");
+        let envelope = message.envelope();
+        assert_eq!(
+            envelope.from().map(ToString::to_string).as_deref(),
+            Some("grafana@example.com")
+        );
+        assert_eq!(envelope.to().len(), 2);
+        assert!(matches!(
+            email(&addressed(), "not an address"),
+            Err(DeliveryError::Email(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn sends_email() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let port = listener.local_addr().expect("No address").port();
+        let server = tokio::spawn(fake_smtp(
+            listener,
+            vec![
+                "220 mail.example.com ESMTP\r\n",
+                "250-mail.example.com\r\n250 AUTH PLAIN\r\n",
+                "235 Authenticated\r\n",
+                "250 OK\r\n",
+                "250 OK\r\n",
+                "250 OK\r\n",
+                "354 Go ahead\r\n",
+                "250 Queued\r\n",
+                "221 Bye\r\n",
+            ],
+        ));
+        let smtp: SmtpSettings = serde_json::from_value(serde_json::json!({
+            "host": "127.0.0.1",
+            "port": port,
+            "tls": "none",
+            "username": "grafana",
+            "password": "hunter2",
+            "from": "grafana@example.com"
+        }))
+        .expect("Failed to parse smtp");
+        let client = EmailClient {
+            smtp: Some(smtp),
+            timeout: Duration::from_secs(5),
+        };
+        client.send(&addressed()).await.expect("Failed to send");
+
+        let received = server.await.expect("Fake SMTP failed");
+        let lines: Vec<&str> = received.lines().take(6).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "EHLO grafana-prowl-notifier",
+                "AUTH PLAIN AGdyYWZhbmEAaHVudGVyMg==",
+                "MAIL FROM:<grafana@example.com>",
+                "RCPT TO:<me@example.com>",
+                "RCPT TO:<you@example.com>",
+                "DATA",
+            ]
+        );
+        assert!(received.contains("To: me@example.com, you@example.com\r\n"));
+        assert!(received.contains(&format!(
+            "Subject: =?utf-8?b?{}?= Disk full\r\n",
+            base64::encode("[✅]")
+        )));
+        let body =
+            base64::encode("resolved after 2h 5m: 95% used\r\n\r\nhttps://grafana.example.com/d/1");
+        assert!(received.contains(&body[..76]), "{received}");
+        assert!(received.ends_with("QUIT\r\n"));
+    }
+
+    #[tokio::test]
+    async fn rejected_recipients() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let port = listener.local_addr().expect("No address").port();
+        let server = tokio::spawn(fake_smtp(
+            listener,
+            vec![
+                "220 mail.example.com ESMTP\r\n",
+                "250 mail.example.com\r\n",
+                "250 OK\r\n",
+                "550 No such user\r\n",
+            ],
+        ));
+        let client = EmailClient {
+            smtp: serde_json::from_value(serde_json::json!({
+                "host": "127.0.0.1", "port": port, "tls": "none", "from": "grafana@example.com"
+            }))
+            .ok(),
+            timeout: Duration::from_secs(5),
+        };
+        let error = client
+            .send(&addressed())
+            .await
+            .expect_err("Bad recipient sent");
+        assert_eq!(
+            error.to_string(),
+            "The mail server answered 550. No such user"
+        );
+        assert!(!error.is_retryable());
+        server.await.expect("Fake SMTP failed");
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    errors::DeliveryError,
+    models::{config::Config, forward_url::ForwardUrl},
+    subsystems::sinks::{answered, http_client},
+};
+use chrono::Utc;
+use reqwest::header::CONTENT_TYPE;
+use tokio::{task::JoinHandle, time::Duration};
+
+/// Tries per URL before a webhook is given up on.
+const ATTEMPTS: u32 = 5;
+/// When the body was signed, in seconds since the epoch, for URLs with a `secret`.
+pub(crate) const TIMESTAMP_HEADER: &str = "X-Signature-Timestamp";
+/// `sha256=` and the hex HMAC-SHA256 of `{timestamp}.{body}`.
+pub(crate) const SIGNATURE_HEADER: &str = "X-Signature";
+
+/// Relays webhooks as they arrived to the `forward_urls`, with the same timeouts as Prowl.
+#[derive(Clone)]
+pub(crate) struct Forwarder {
+    client: reqwest::Client,
+    retry: Duration,
+}
+
+impl Forwarder {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self::build(
+            Duration::from_secs(*config.prowl_timeout_secs()),
+            Duration::from_secs(*config.prowl_connect_timeout_secs()),
+            Duration::from_secs(*config.prowl_keep_alive_secs()),
+            Duration::from_secs(*config.linear_retry_secs()),
+        )
+    }
+
+    fn build(
+        timeout: Duration,
+        connect_timeout: Duration,
+        keep_alive: Duration,
+        retry: Duration,
+    ) -> Self {
+        Self {
+            client: http_client("forwarding", timeout, connect_timeout, keep_alive),
+            retry,
+        }
+    }
+
+    /// Posts `body` to each URL in the background, so notifications never wait on it.
+    pub(crate) fn forward(
+        &self,
+        urls: &[ForwardUrl],
+        body: &str,
+        content_type: Option<String>,
+    ) -> Vec<JoinHandle<()>> {
+        urls.iter()
+            .map(|url| {
+                let forwarder = self.clone();
+                let url = url.clone();
+                let body = body.to_string();
+                let content_type = content_type
+                    .clone()
+                    .unwrap_or_else(|| "application/json".to_string());
+                tokio::spawn(async move { forwarder.post(&url, body, &content_type).await })
+            })
+            .collect()
+    }
+
+    /// Tries every `linear_retry_secs` while the URL can't be reached or answers
+    /// 5xx or 429, up to `ATTEMPTS` times.
+    /// Signed again each try, so receivers can reject old timestamps.
+    async fn post(&self, url: &ForwardUrl, body: String, content_type: &str) {
+        // the URL may have a token in it
+        let host = reqwest::Url::parse(url.url())
+            .ok()
+            .and_then(|x| x.host_str().map(str::to_string))
+            .unwrap_or_default();
+        for attempt in 1..=ATTEMPTS {
+            let mut request = self
+                .client
+                .post(url.url())
+                .header(CONTENT_TYPE, content_type);
+            let timestamp = Utc::now().timestamp();
+            if let Some(signature) = url.signature(timestamp, &body) {
+                request = request
+                    .header(TIMESTAMP_HEADER, timestamp)
+                    .header(SIGNATURE_HEADER, format!("sha256={signature}"));
+            }
+            let sent = match request.body(body.clone()).send().await {
+                Ok(response) => answered("Forwarding", response).await,
+                Err(e) => Err(DeliveryError::Send("Forwarding", e.without_url())),
+            };
+            match sent {
+                Ok(()) => {
+                    log::debug!("Forwarded a webhook to {host}");
+                    return;
+                }
+                Err(e) if e.is_retryable() && attempt < ATTEMPTS => {
+                    log::warn!("Failed to forward a webhook to {host}, attempt {attempt}. {e}");
+                    tokio::time::sleep(self.retry).await;
+                }
+                Err(e) => {
+                    log::error!("Gave up forwarding a webhook to {host}. {e}");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::subsystems::sinks::test::{answer, fake_server};
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+    use tokio::net::TcpListener;
+
+    fn forwarder() -> Forwarder {
+        Forwarder::build(
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+            Duration::from_secs(90),
+            Duration::ZERO,
+        )
+    }
+
+    #[tokio::test]
+    async fn retries_until_accepted() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let url = format!(
+            "http://{}/ingest",
+            listener.local_addr().expect("No address")
+        );
+        let server = tokio::spawn(fake_server(
+            listener,
+            vec![
+                answer("503 Service Unavailable", "", b""),
+                answer("200 OK", "", b""),
+            ],
+        ));
+        let body = r#"{"status":"firing","alerts":[]}"#;
+        for task in forwarder().forward(&[ForwardUrl::Plain(url)], body, None) {
+            task.await.expect("Forwarding panicked");
+        }
+
+        let requests = server.await.expect("Fake server failed");
+        assert_eq!(requests.len(), 2);
+        for request in requests {
+            assert!(request.starts_with("POST /ingest HTTP/1.1"));
+            assert!(request.contains("content-type: application/json"));
+            assert!(request.ends_with(body));
+            assert!(!request.contains("x-signature"));
+        }
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let url = format!(
+            "http://{}/ingest",
+            listener.local_addr().expect("No address")
+        );
+        let server = tokio::spawn(fake_server(
+            listener,
+            vec![answer("400 Bad Request", "", b"")],
+        ));
+        let tasks = forwarder().forward(
+            &[ForwardUrl::Plain(url)],
+            "status=firing",
+            Some("text/plain".to_string()),
+        );
+        for task in tasks {
+            task.await.expect("Forwarding panicked");
+        }
+        let requests = server.await.expect("Fake server failed");
+        assert!(requests[0].contains("content-type: text/plain"));
+    }
+
+    #[tokio::test]
+    async fn signs_with_the_secret() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let url = ForwardUrl::Signed {
+            url: format!(
+                "http://{}/ingest",
+                listener.local_addr().expect("No address")
+            ),
+            secret: "forward-secret".to_string(),
+        };
+        let server = tokio::spawn(fake_server(listener, vec![answer("200 OK", "", b"")]));
+        let body = r#"{"status":"firing","alerts":[]}"#;
+        for task in forwarder().forward(&[url], body, None) {
+            task.await.expect("Forwarding panicked");
+        }
+
+        let requests = server.await.expect("Fake server failed");
+        let header = |name: &str| {
+            requests[0]
+                .lines()
+                .find_map(|x| x.strip_prefix(&format!("{name}: ")))
+                .map(str::to_string)
+                .expect("Missing header")
+        };
+        let timestamp = header("x-signature-timestamp");
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(b"forward-secret").expect("Failed to create HMAC");
+        mac.update(format!("{timestamp}.{body}").as_bytes());
+        let signature = header("x-signature");
+        let signature = signature
+            .strip_prefix("sha256=")
+            .expect("No sha256= prefix");
+        mac.verify_slice(&hex::decode(signature).expect("Signature isn't hex"))
+            .expect("Signature doesn't match");
+        let age = Utc::now().timestamp() - timestamp.parse::<i64>().expect("Bad timestamp");
+        assert!((0..60).contains(&age));
+    }
+}
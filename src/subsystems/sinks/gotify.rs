@@ -0,0 +1,205 @@
+use crate::{
+    errors::DeliveryError,
+    models::{
+        config::Config,
+        notification::Addressed,
+        sink::{GotifySink, Sink},
+    },
+    subsystems::sinks::{answered, http_client, reachable, NotificationSink},
+};
+use prowl::Priority;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+use tokio::time::Duration;
+
+/// Sends to self-hosted Gotify servers, with the same timeouts as Prowl.
+#[derive(Clone)]
+pub(crate) struct GotifyClient {
+    client: reqwest::Client,
+}
+
+impl GotifyClient {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self::build(
+            Duration::from_secs(*config.prowl_timeout_secs()),
+            Duration::from_secs(*config.prowl_connect_timeout_secs()),
+            Duration::from_secs(*config.prowl_keep_alive_secs()),
+        )
+    }
+
+    fn build(timeout: Duration, connect_timeout: Duration, keep_alive: Duration) -> Self {
+        Self {
+            client: http_client("Gotify", timeout, connect_timeout, keep_alive),
+        }
+    }
+
+    /// Gotify's health endpoint, which needs no token.
+    pub(crate) async fn verify(
+        &self,
+        settings: &GotifySink,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        let url = format!("{}/health", settings.server().trim_end_matches('/'));
+        reachable("Gotify", self.client.get(url), timeout).await
+    }
+}
+
+impl Default for GotifyClient {
+    fn default() -> Self {
+        Self::build(
+            Duration::from_secs(30),
+            Duration::from_secs(10),
+            Duration::from_secs(90),
+        )
+    }
+}
+
+/// Gotify's priorities are 0 to 10. Its Android app is silent below 4 and
+/// vibrates from 8.
+fn priority(priority: &Option<Priority>) -> u8 {
+    match priority {
+        Some(Priority::VeryLow) => 1,
+        Some(Priority::Moderate) => 3,
+        Some(Priority::Normal) | None => 5,
+        Some(Priority::High) => 8,
+        Some(Priority::Emergency) => 10,
+    }
+}
+
+/// The description as markdown, with its line breaks kept and a link to the alert.
+fn markdown(description: &str, url: &Option<String>) -> String {
+    let mut lines: Vec<String> = description.lines().map(escape).collect();
+    if let Some(url) = url {
+        lines.push(format!(
+            "[Open]({})",
+            url.replace(' ', "%20").replace(')', "%29")
+        ));
+    }
+    lines.join("  \n")
+}
+
+fn escape(line: &str) -> String {
+    let mut escaped = String::with_capacity(line.len());
+    for c in line.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn message(addressed: &Addressed) -> Value {
+    let notification = addressed.notification();
+    let mut extras = json!({
+        "client::display": { "contentType": "text/markdown" }
+    });
+    if let Some(url) = notification.url() {
+        extras["client::notification"] = json!({ "click": { "url": url } });
+    }
+    json!({
+        "title": notification.event(),
+        "message": markdown(notification.description(), notification.url()),
+        "priority": priority(notification.priority()),
+        "extras": extras,
+    })
+}
+
+impl NotificationSink for GotifyClient {
+    /// A request per application token, each token is its own app in Gotify.
+    async fn send(&self, addressed: &Addressed) -> Result<(), DeliveryError> {
+        let settings: &GotifySink = match addressed.sink() {
+            Sink::Gotify(settings) => settings,
+            other => unreachable!("{} notification sent to Gotify", other.name()),
+        };
+        let body = message(addressed).to_string();
+        for token in addressed.api_keys() {
+            let response = self
+                .client
+                .post(format!(
+                    "{}/message",
+                    settings.server().trim_end_matches('/')
+                ))
+                .header("X-Gotify-Key", token)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await
+                .map_err(|e| DeliveryError::Send("Gotify", e.without_url()))?;
+            answered("Gotify", response).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        models::{notification::NotificationContent, receiver::Receiver},
+        subsystems::sinks::test::{answer, fake_server},
+    };
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn formats_markdown() {
+        assert_eq!(
+            markdown(
+                "disk_used > 95%\nhost=db_1",
+                &Some("https://grafana.example.com/d/a b".to_string())
+            ),
+            "disk\\_used \\> 95%  \nhost=db\\_1  \n[Open](https://grafana.example.com/d/a%20b)"
+        );
+        assert_eq!(markdown("Backups are late", &None), "Backups are late");
+        assert_eq!(priority(&Some(Priority::Emergency)), 10);
+        assert_eq!(priority(&None), 5);
+    }
+
+    #[tokio::test]
+    async fn sends() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let server = format!("http://{}", listener.local_addr().expect("No address"));
+        let fake = tokio::spawn(fake_server(
+            listener,
+            vec![answer(
+                "401 Unauthorized",
+                "",
+                br#"{"error":"Unauthorized"}"#,
+            )],
+        ));
+        let receiver: Receiver = serde_json::from_value(json!({
+            "api_keys": ["app_token"],
+            "sink": {"gotify": {"server": server}}
+        }))
+        .expect("Failed to parse receiver");
+        let addressed = NotificationContent::new(
+            Some(Priority::High),
+            None,
+            "Grafana".to_string(),
+            "Disk full".to_string(),
+            "95% full".to_string(),
+        )
+        .to_prowl_for(receiver.api_keys().clone(), &receiver)
+        .expect("Failed to create notification");
+
+        let error = GotifyClient::default()
+            .send(&addressed)
+            .await
+            .expect_err("Bad token sent");
+        assert!(!error.is_retryable());
+
+        let requests = fake.await.expect("Fake Gotify failed");
+        assert!(requests[0].starts_with("POST /message HTTP/1.1"));
+        assert!(requests[0].contains("x-gotify-key: app_token"));
+        let body = requests[0].split("\r\n\r\n").nth(1).expect("No body");
+        let body: Value = serde_json::from_str(body).expect("Body isn't JSON");
+        assert_eq!(body["priority"], 8);
+        assert_eq!(body["message"], "95% full");
+        assert_eq!(
+            body["extras"]["client::display"]["contentType"],
+            "text/markdown"
+        );
+    }
+}
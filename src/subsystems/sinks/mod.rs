@@ -0,0 +1,230 @@
+pub(crate) mod discord;
+pub(crate) mod email;
+pub(crate) mod forward;
+pub(crate) mod gotify;
+pub(crate) mod ntfy;
+pub(crate) mod prowl;
+pub(crate) mod pushover;
+pub(crate) mod slack;
+pub(crate) mod telegram;
+
+use self::{
+    discord::DiscordClient, email::EmailClient, gotify::GotifyClient, ntfy::NtfyClient,
+    prowl::ProwlClient, pushover::PushoverClient, slack::SlackClient, telegram::TelegramClient,
+};
+use crate::{
+    errors::DeliveryError,
+    models::{config::Config, notification::Addressed, sink::Sink},
+};
+use reqwest::{Response, StatusCode};
+use tokio::time::Duration;
+
+/// A service notifications can be delivered with.
+pub(crate) trait NotificationSink {
+    /// Delivers to every one of the notification's keys. Errors that are
+    /// `is_retryable` are tried again later.
+    async fn send(&self, addressed: &Addressed) -> Result<(), DeliveryError>;
+}
+
+/// Connections are kept open between deliveries, so an alert storm doesn't pay
+/// for a TLS handshake per alert.
+fn http_client(
+    name: &str,
+    timeout: Duration,
+    connect_timeout: Duration,
+    keep_alive: Duration,
+) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .connect_timeout(connect_timeout)
+        .pool_idle_timeout(keep_alive)
+        .tcp_keepalive(keep_alive)
+        .build()
+        .unwrap_or_else(|e| {
+            log::error!("Failed to build the {name} client, using the defaults. {e}");
+            reqwest::Client::new()
+        })
+}
+
+/// At most `max` characters, ending in … when it is cut short.
+fn truncate(text: &str, max: usize) -> String {
+    match text.chars().count() > max {
+        true => text.chars().take(max - 1).chain(['…']).collect(),
+        false => text.to_string(),
+    }
+}
+
+/// Whether the service accepted a notification. As Pushover and ntfy ask, 4xx answers
+/// aren't retried, but 5xx and 429 are.
+async fn answered(name: &'static str, response: Response) -> Result<(), DeliveryError> {
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| DeliveryError::Send(name, e.without_url()))?;
+    checked(name, status, body.trim().to_string())
+}
+
+/// 5xx and 429 answers are `Unavailable`, so they are retried and count against the
+/// service's circuit, other errors are final.
+fn checked(name: &'static str, status: StatusCode, body: String) -> Result<(), DeliveryError> {
+    match status {
+        _ if status.is_success() => Ok(()),
+        _ if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS => {
+            Err(DeliveryError::Unavailable(name, status, body))
+        }
+        _ => Err(DeliveryError::Api(name, status, body)),
+    }
+}
+
+/// Whether a service answered a health check. Any answer but a server error means
+/// it is reachable, a bad key is reported when a notification fails instead.
+async fn reachable(
+    name: &str,
+    request: reqwest::RequestBuilder,
+    timeout: Duration,
+) -> Result<(), String> {
+    let response = request
+        .timeout(timeout)
+        .send()
+        .await
+        // the URL may have a key or token in it
+        .map_err(|e| format!("Failed to reach {name}. {}", e.without_url()))?;
+    let status = response.status();
+    // lets the connection go back to the pool
+    let _ = response.bytes().await;
+    match status.is_server_error() {
+        true => Err(format!("{name} answered {status}")),
+        false => Ok(()),
+    }
+}
+
+/// A client for each service, picked by the notification's receiver.
+#[derive(Clone, Default)]
+pub(crate) struct Sinks {
+    prowl: ProwlClient,
+    pushover: PushoverClient,
+    ntfy: NtfyClient,
+    gotify: GotifyClient,
+    telegram: TelegramClient,
+    discord: DiscordClient,
+    slack: SlackClient,
+    email: EmailClient,
+}
+
+impl Sinks {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            prowl: ProwlClient::new(config),
+            pushover: PushoverClient::new(config),
+            ntfy: NtfyClient::new(config),
+            gotify: GotifyClient::new(config),
+            telegram: TelegramClient::new(config),
+            discord: DiscordClient::new(config),
+            slack: SlackClient::new(config),
+            email: EmailClient::new(config),
+        }
+    }
+
+    pub(crate) fn prowl(&self) -> &ProwlClient {
+        &self.prowl
+    }
+
+    /// Checks the service can be reached with one of a receiver's keys, without
+    /// sending a notification.
+    pub(crate) async fn verify(
+        &self,
+        sink: &Sink,
+        key: &str,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        match sink {
+            Sink::Prowl => self.prowl.verify(key, timeout).await,
+            Sink::Pushover(settings) => self.pushover.verify(settings, timeout).await,
+            Sink::Ntfy(settings) => self.ntfy.verify(settings, key, timeout).await,
+            Sink::Gotify(settings) => self.gotify.verify(settings, timeout).await,
+            Sink::Telegram(settings) => self.telegram.verify(settings, timeout).await,
+            Sink::Discord => self.discord.verify(key, timeout).await,
+            Sink::Slack => self.slack.verify(key, timeout).await,
+            Sink::Email => match tokio::time::timeout(timeout, self.email.verify()).await {
+                Ok(result) => result,
+                Err(_) => Err(format!("The mail server didn't answer within {timeout:?}")),
+            },
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_prowl(prowl: ProwlClient) -> Self {
+        Self {
+            prowl,
+            ..Self::default()
+        }
+    }
+}
+
+impl NotificationSink for Sinks {
+    async fn send(&self, addressed: &Addressed) -> Result<(), DeliveryError> {
+        match addressed.sink() {
+            Sink::Prowl => self.prowl.send(addressed).await,
+            Sink::Pushover(_) => self.pushover.send(addressed).await,
+            Sink::Ntfy(_) => self.ntfy.send(addressed).await,
+            Sink::Gotify(_) => self.gotify.send(addressed).await,
+            Sink::Telegram(_) => self.telegram.send(addressed).await,
+            Sink::Discord => self.discord.send(addressed).await,
+            Sink::Slack => self.slack.send(addressed).await,
+            Sink::Email => self.email.send(addressed).await,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Answers requests on the one connection it accepts, so a second
+    /// connection would leave the client waiting. Returns each request with its body.
+    pub(crate) async fn fake_server(listener: TcpListener, answers: Vec<Vec<u8>>) -> Vec<String> {
+        let (mut stream, _) = listener.accept().await.expect("Failed to accept");
+        let mut requests = vec![];
+        let mut buffer = [0; 4096];
+        for answer in answers {
+            let mut request = vec![];
+            let complete = |request: &[u8]| {
+                let text = String::from_utf8_lossy(request);
+                let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                    return false;
+                };
+                let length = head
+                    .lines()
+                    .find_map(|x| {
+                        let (name, value) = x.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().ok())?
+                    })
+                    .unwrap_or(0);
+                body.len() >= length
+            };
+            while !complete(&request) {
+                let read = stream.read(&mut buffer).await.expect("Failed to read");
+                assert!(read > 0, "Client closed the connection");
+                request.extend_from_slice(&buffer[..read]);
+            }
+            requests.push(String::from_utf8_lossy(&request).to_string());
+            stream.write_all(&answer).await.expect("Failed to write");
+        }
+        requests
+    }
+
+    pub(crate) fn answer(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut answer = format!(
+            "HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        answer.extend_from_slice(body);
+        answer
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{
+    errors::DeliveryError,
+    models::{
+        config::Config,
+        notification::{priority_number, Addressed},
+        sink::{NtfySink, Sink},
+    },
+    subsystems::sinks::{answered, http_client, reachable, NotificationSink},
+};
+use prowl::Priority;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde_json::{json, Value};
+use tokio::time::Duration;
+
+/// Publishes to ntfy servers, with the same timeouts as Prowl.
+#[derive(Clone)]
+pub(crate) struct NtfyClient {
+    client: reqwest::Client,
+}
+
+impl NtfyClient {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self::build(
+            Duration::from_secs(*config.prowl_timeout_secs()),
+            Duration::from_secs(*config.prowl_connect_timeout_secs()),
+            Duration::from_secs(*config.prowl_keep_alive_secs()),
+        )
+    }
+
+    fn build(timeout: Duration, connect_timeout: Duration, keep_alive: Duration) -> Self {
+        Self {
+            client: http_client("ntfy", timeout, connect_timeout, keep_alive),
+        }
+    }
+
+    /// Polls the topic for its latest message, which is how a subscriber checks it.
+    pub(crate) async fn verify(
+        &self,
+        settings: &NtfySink,
+        topic: &str,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        let mut request = self
+            .client
+            .get(format!(
+                "{}/{topic}/json",
+                settings.server().trim_end_matches('/')
+            ))
+            .query(&[("poll", "1"), ("since", "latest")]);
+        if let Some(token) = settings.token() {
+            request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        reachable("ntfy", request, timeout).await
+    }
+}
+
+impl Default for NtfyClient {
+    fn default() -> Self {
+        Self::build(
+            Duration::from_secs(30),
+            Duration::from_secs(10),
+            Duration::from_secs(90),
+        )
+    }
+}
+
+/// ntfy's tags that show as emoji before the title.
+fn tags(priority: &Priority) -> Vec<&'static str> {
+    match priority {
+        Priority::Emergency => vec!["rotating_light"],
+        Priority::High => vec!["warning"],
+        _ => vec![],
+    }
+}
+
+/// The JSON ntfy publishes for a topic. Its priorities are 1 to 5, Prowl's
+/// are -2 to 2.
+fn message(addressed: &Addressed, topic: &str) -> Value {
+    let notification = addressed.notification();
+    let priority = notification.priority().clone().unwrap_or(Priority::Normal);
+    let mut message = json!({
+        "topic": topic,
+        "title": notification.event(),
+        "message": notification.description(),
+        "priority": priority_number(&priority) + 3,
+        "tags": tags(&priority),
+    });
+    if let Some(url) = notification.url() {
+        message["click"] = json!(url);
+    }
+    message
+}
+
+impl NotificationSink for NtfyClient {
+    /// A request per topic, as ntfy publishes JSON to one topic at a time.
+    async fn send(&self, addressed: &Addressed) -> Result<(), DeliveryError> {
+        let settings: &NtfySink = match addressed.sink() {
+            Sink::Ntfy(settings) => settings,
+            other => unreachable!("{} notification sent to ntfy", other.name()),
+        };
+        for topic in addressed.api_keys() {
+            let mut request = self
+                .client
+                .post(settings.server().trim_end_matches('/'))
+                .header(CONTENT_TYPE, "application/json")
+                .body(message(addressed, topic).to_string());
+            if let Some(token) = settings.token() {
+                request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+            }
+            let response = request
+                .send()
+                .await
+                .map_err(|e| DeliveryError::Send("ntfy", e.without_url()))?;
+            answered("ntfy", response).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        models::{notification::NotificationContent, receiver::Receiver},
+        subsystems::sinks::test::{answer, fake_server},
+    };
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn publishes() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let server = format!("http://{}/", listener.local_addr().expect("No address"));
+        let fake = tokio::spawn(fake_server(
+            listener,
+            vec![
+                answer("200 OK", "", br#"{"id":"a"}"#),
+                answer("429 Too Many Requests", "", br#"{"code":42901}"#),
+            ],
+        ));
+        let receiver: Receiver = serde_json::from_value(json!({
+            "api_keys": ["alerts", "family"],
+            "delivery": "together",
+            "sink": {"ntfy": {"server": server, "token": "tk_secret"}}
+        }))
+        .expect("Failed to parse receiver");
+        let addressed = NotificationContent::new(
+            Some(Priority::Emergency),
+            Some("https://grafana.example.com/d/1".to_string()),
+            "Grafana".to_string(),
+            "[🔥] Disk full".to_string(),
+            "95% & rising".to_string(),
+        )
+        .to_prowl_for(receiver.api_keys().clone(), &receiver)
+        .expect("Failed to create notification");
+
+        let error = NtfyClient::default()
+            .send(&addressed)
+            .await
+            .expect_err("Rate limit ignored");
+        assert!(error.is_retryable());
+
+        let requests = fake.await.expect("Fake ntfy failed");
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].starts_with("POST / HTTP/1.1"));
+        assert!(requests[0].contains("authorization: Bearer tk_secret"));
+        let body = requests[0].split("\r\n\r\n").nth(1).expect("No body");
+        let body: Value = serde_json::from_str(body).expect("Body isn't JSON");
+        assert_eq!(
+            body,
+            json!({
+                "topic": "alerts",
+                "title": "[🔥] Disk full",
+                "message": "95% & rising",
+                "priority": 5,
+                "tags": ["rotating_light"],
+                "click": "https://grafana.example.com/d/1"
+            })
+        );
+        assert!(requests[1].contains(r#""topic":"family""#));
+    }
+}
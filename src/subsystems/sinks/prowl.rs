@@ -0,0 +1,200 @@
+use crate::{
+    errors::DeliveryError,
+    models::config::Config,
+    models::notification::{priority_number, Addressed},
+    subsystems::sinks::{checked, http_client, NotificationSink},
+};
+use flate2::read::GzDecoder;
+use reqwest::{
+    header::{ACCEPT_ENCODING, CONTENT_ENCODING},
+    Response,
+};
+use std::io::Read;
+use tokio::time::Duration;
+
+const API_BASE: &str = "https://api.prowlapp.com/publicapi";
+
+/// One HTTPS client for everything sent to Prowl. Connections are kept open
+/// between deliveries, so an alert storm doesn't pay for a TLS handshake per alert.
+#[derive(Clone)]
+pub(crate) struct ProwlClient {
+    client: reqwest::Client,
+    base: String,
+}
+
+impl ProwlClient {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self::build(
+            Duration::from_secs(*config.prowl_timeout_secs()),
+            Duration::from_secs(*config.prowl_connect_timeout_secs()),
+            Duration::from_secs(*config.prowl_keep_alive_secs()),
+            API_BASE,
+        )
+    }
+
+    #[cfg(test)]
+    pub(crate) fn at(base: &str) -> Self {
+        Self::build(
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+            Duration::from_secs(90),
+            base,
+        )
+    }
+
+    fn build(
+        timeout: Duration,
+        connect_timeout: Duration,
+        keep_alive: Duration,
+        base: &str,
+    ) -> Self {
+        Self {
+            client: http_client("Prowl", timeout, connect_timeout, keep_alive),
+            base: base.to_string(),
+        }
+    }
+
+    /// Prowl's verify call, which only needs an API key.
+    pub(crate) async fn verify(&self, api_key: &str, timeout: Duration) -> Result<(), String> {
+        let response = self
+            .client
+            .get(format!("{}/verify", self.base))
+            .query(&[("apikey", api_key)])
+            .header(ACCEPT_ENCODING, "gzip")
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(|e| e.without_url().to_string())?;
+        let status = response.status();
+        read_body(response).await.map_err(|e| e.to_string())?;
+        if status.is_server_error() {
+            return Err(format!("Prowl answered {status}"));
+        }
+        Ok(())
+    }
+}
+
+impl NotificationSink for ProwlClient {
+    /// Prowl's add call, one request for all of the notification's API keys.
+    async fn send(&self, addressed: &Addressed) -> Result<(), DeliveryError> {
+        let notification = addressed.notification();
+        let mut query = vec![
+            ("apikey", addressed.api_keys().join(",")),
+            ("application", notification.application().clone()),
+            ("event", notification.event().clone()),
+            ("description", notification.description().clone()),
+        ];
+        if let Some(url) = notification.url() {
+            query.push(("url", url.clone()));
+        }
+        if let Some(priority) = notification.priority() {
+            query.push(("priority", priority_number(priority).to_string()));
+        }
+        if let Some(provider_key) = addressed.provider_key() {
+            query.push(("providerkey", provider_key.clone()));
+        }
+        let response = self
+            .client
+            .post(format!("{}/add", self.base))
+            .query(&query)
+            .header(ACCEPT_ENCODING, "gzip")
+            .send()
+            .await
+            // the URL has the API keys in it
+            .map_err(|e| DeliveryError::Send("Prowl", e.without_url()))?;
+        let status = response.status();
+        let body = read_body(response).await?;
+        checked("Prowl", status, body)
+    }
+}
+
+impl Default for ProwlClient {
+    fn default() -> Self {
+        Self::build(
+            Duration::from_secs(30),
+            Duration::from_secs(10),
+            Duration::from_secs(90),
+            API_BASE,
+        )
+    }
+}
+
+/// Reads the whole body, which lets the connection go back to the pool.
+/// Prowl's answers are small XML documents, gzipped when it chooses to.
+async fn read_body(response: Response) -> Result<String, DeliveryError> {
+    let gzipped = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .is_some_and(|x| x.as_bytes().eq_ignore_ascii_case(b"gzip"));
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| DeliveryError::Send("Prowl", e.without_url()))?;
+    if !gzipped {
+        return Ok(String::from_utf8_lossy(&bytes).trim().to_string());
+    }
+    let mut body = String::new();
+    if let Err(e) = GzDecoder::new(&bytes[..]).read_to_string(&mut body) {
+        log::warn!("Failed to decompress Prowl's answer. {e}");
+    }
+    Ok(body.trim().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::notification::NotificationContent;
+    use crate::subsystems::sinks::test::{answer, fake_server};
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn reuses_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let base = format!("http://{}", listener.local_addr().expect("No address"));
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder
+            .write_all(b"<error code=\"401\">Invalid API key</error>")
+            .expect("Failed to compress");
+        let gzipped = encoder.finish().expect("Failed to compress");
+        let server = tokio::spawn(fake_server(
+            listener,
+            vec![
+                answer("200 OK", "", b"<success code=\"200\"/>"),
+                answer("401 Unauthorized", "Content-Encoding: gzip\r\n", &gzipped),
+            ],
+        ));
+
+        let client = ProwlClient::build(
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+            Duration::from_secs(90),
+            &base,
+        );
+        let addressed = NotificationContent::new(
+            Some(prowl::Priority::High),
+            None,
+            "Grafana".to_string(),
+            "Disk full".to_string(),
+            "95% & rising".to_string(),
+        )
+        .to_prowl(vec!["key1".to_string(), "key2".to_string()])
+        .expect("Failed to create notification");
+        client.send(&addressed).await.expect("Failed to send");
+        let error = client.send(&addressed).await.expect_err("Bad key was sent");
+        assert_eq!(
+            error.to_string(),
+            "Prowl answered 401 Unauthorized. <error code=\"401\">Invalid API key</error>"
+        );
+
+        let requests = server.await.expect("Fake Prowl failed");
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].starts_with(
+            "POST /add?apikey=key1%2Ckey2&application=Grafana&event=Disk+full&description=95%25+%26+rising&priority=1 HTTP/1.1"
+        ), "{}", requests[0]);
+        assert!(requests[0].contains("accept-encoding: gzip"));
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{
+    errors::DeliveryError,
+    models::{
+        config::Config,
+        notification::{priority_number, Addressed},
+        sink::{PushoverSink, Sink},
+    },
+    subsystems::sinks::{answered, http_client, reachable, truncate, NotificationSink},
+};
+use prowl::Priority;
+use tokio::time::Duration;
+
+const API_BASE: &str = "https://api.pushover.net/1";
+/// Pushover's limits, longer text is rejected.
+const MAX_TITLE_CHARS: usize = 250;
+const MAX_MESSAGE_CHARS: usize = 1024;
+const MAX_URL_CHARS: usize = 512;
+
+/// Sends to Pushover's messages API, with the same timeouts as Prowl.
+#[derive(Clone)]
+pub(crate) struct PushoverClient {
+    client: reqwest::Client,
+    base: String,
+}
+
+impl PushoverClient {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self::build(
+            Duration::from_secs(*config.prowl_timeout_secs()),
+            Duration::from_secs(*config.prowl_connect_timeout_secs()),
+            Duration::from_secs(*config.prowl_keep_alive_secs()),
+            API_BASE,
+        )
+    }
+
+    fn build(
+        timeout: Duration,
+        connect_timeout: Duration,
+        keep_alive: Duration,
+        base: &str,
+    ) -> Self {
+        Self {
+            client: http_client("Pushover", timeout, connect_timeout, keep_alive),
+            base: base.to_string(),
+        }
+    }
+
+    /// The application's message limits, which only need its token.
+    pub(crate) async fn verify(
+        &self,
+        settings: &PushoverSink,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        let request = self
+            .client
+            .get(format!("{}/apps/limits.json", self.base))
+            .query(&[("token", settings.token())]);
+        reachable("Pushover", request, timeout).await
+    }
+}
+
+impl Default for PushoverClient {
+    fn default() -> Self {
+        Self::build(
+            Duration::from_secs(30),
+            Duration::from_secs(10),
+            Duration::from_secs(90),
+            API_BASE,
+        )
+    }
+}
+
+/// The form Pushover wants. Its priorities are Prowl's, -2 to 2, and `Emergency`
+/// repeats until it is acknowledged, which needs `retry` and `expire`.
+fn form(addressed: &Addressed, settings: &PushoverSink) -> Vec<(&'static str, String)> {
+    let notification = addressed.notification();
+    let mut form = vec![
+        ("token", settings.token().clone()),
+        ("user", addressed.api_keys().join(",")),
+        ("title", truncate(notification.event(), MAX_TITLE_CHARS)),
+        (
+            "message",
+            truncate(notification.description(), MAX_MESSAGE_CHARS),
+        ),
+    ];
+    if let Some(url) = notification
+        .url()
+        .as_ref()
+        .filter(|x| x.len() <= MAX_URL_CHARS)
+    {
+        form.push(("url", url.clone()));
+    }
+    if let Some(priority) = notification.priority() {
+        form.push(("priority", priority_number(priority).to_string()));
+        if *priority == Priority::Emergency {
+            // the least and most Pushover allows
+            let retry = (*settings.emergency_retry_secs()).max(30);
+            let expire = (*settings.emergency_expire_secs()).min(10800);
+            form.push(("retry", retry.to_string()));
+            form.push(("expire", expire.to_string()));
+        }
+    }
+    form
+}
+
+impl NotificationSink for PushoverClient {
+    /// One request for all of the notification's user keys.
+    async fn send(&self, addressed: &Addressed) -> Result<(), DeliveryError> {
+        let settings = match addressed.sink() {
+            Sink::Pushover(settings) => settings,
+            other => unreachable!("{} notification sent to Pushover", other.name()),
+        };
+        let response = self
+            .client
+            .post(format!("{}/messages.json", self.base))
+            .form(&form(addressed, settings))
+            .send()
+            .await
+            .map_err(|e| DeliveryError::Send("Pushover", e.without_url()))?;
+        answered("Pushover", response).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        models::{notification::NotificationContent, receiver::Receiver},
+        subsystems::sinks::test::{answer, fake_server},
+    };
+    use tokio::net::TcpListener;
+
+    fn addressed(priority: Priority, description: &str) -> Addressed {
+        let receiver: Receiver = serde_json::from_str(
+            r#"{"api_keys": ["user1", "user2"], "delivery": "together",
+                "sink": {"pushover": {"token": "app", "emergency_expire_secs": 86400}}}"#,
+        )
+        .expect("Failed to parse receiver");
+        NotificationContent::new(
+            Some(priority),
+            Some("https://grafana.example.com/d/1".to_string()),
+            "Grafana".to_string(),
+            "Disk full".to_string(),
+            description.to_string(),
+        )
+        .to_prowl_for(receiver.api_keys().clone(), &receiver)
+        .expect("Failed to create notification")
+    }
+
+    #[test]
+    fn priorities() {
+        let settings = |x: &Addressed| match x.sink() {
+            Sink::Pushover(settings) => settings.clone(),
+            other => panic!("Unexpected {other:?}"),
+        };
+        let emergency = addressed(Priority::Emergency, &"x".repeat(2000));
+        let fields = form(&emergency, &settings(&emergency));
+        let field = |name| {
+            fields
+                .iter()
+                .find(|(x, _)| *x == name)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(field("user").as_deref(), Some("user1,user2"));
+        assert_eq!(field("priority").as_deref(), Some("2"));
+        assert_eq!(field("retry").as_deref(), Some("60"));
+        // capped at Pushover's three hours
+        assert_eq!(field("expire").as_deref(), Some("10800"));
+        let message = field("message").expect("No message");
+        assert_eq!(message.chars().count(), MAX_MESSAGE_CHARS);
+        assert!(message.ends_with('…'));
+
+        let moderate = addressed(Priority::Moderate, "95% & rising");
+        let fields = form(&moderate, &settings(&moderate));
+        assert!(fields.contains(&("priority", "-1".to_string())));
+        assert!(!fields.iter().any(|(x, _)| *x == "retry"));
+    }
+
+    #[tokio::test]
+    async fn retries_when_unavailable() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let base = format!("http://{}", listener.local_addr().expect("No address"));
+        let server = tokio::spawn(fake_server(
+            listener,
+            vec![
+                answer("200 OK", "", br#"{"status":1,"request":"a"}"#),
+                answer("503 Service Unavailable", "", b"down"),
+                answer("400 Bad Request", "", br#"{"user":"invalid","status":0}"#),
+            ],
+        ));
+        let client = PushoverClient::build(
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+            Duration::from_secs(90),
+            &base,
+        );
+        let addressed = addressed(Priority::High, "95% & rising");
+        client.send(&addressed).await.expect("Failed to send");
+        let error = client.send(&addressed).await.expect_err("Outage ignored");
+        assert!(error.is_retryable());
+        let error = client.send(&addressed).await.expect_err("Bad user sent");
+        assert!(!error.is_retryable());
+        assert_eq!(
+            error.to_string(),
+            r#"Pushover answered 400 Bad Request. {"user":"invalid","status":0}"#
+        );
+
+        let requests = server.await.expect("Fake Pushover failed");
+        assert!(requests[0].starts_with("POST /messages.json HTTP/1.1"));
+        assert!(requests[0].ends_with(
+            "token=app&user=user1%2Cuser2&title=Disk+full&message=95%25+%26+rising&url=https%3A%2F%2Fgrafana.example.com%2Fd%2F1&priority=1"
+        ), "{}", requests[0]);
+    }
+}
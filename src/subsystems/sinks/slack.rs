@@ -0,0 +1,201 @@
+use crate::{
+    errors::DeliveryError,
+    models::{config::Config, notification::Addressed},
+    subsystems::sinks::{answered, http_client, reachable, truncate, NotificationSink},
+};
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+use tokio::time::Duration;
+
+/// Slack's limit for a section's text.
+const MAX_SECTION_CHARS: usize = 3000;
+
+/// Posts to Slack incoming webhooks, with the same timeouts as Prowl.
+#[derive(Clone)]
+pub(crate) struct SlackClient {
+    client: reqwest::Client,
+}
+
+impl SlackClient {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self::build(
+            Duration::from_secs(*config.prowl_timeout_secs()),
+            Duration::from_secs(*config.prowl_connect_timeout_secs()),
+            Duration::from_secs(*config.prowl_keep_alive_secs()),
+        )
+    }
+
+    fn build(timeout: Duration, connect_timeout: Duration, keep_alive: Duration) -> Self {
+        Self {
+            client: http_client("Slack", timeout, connect_timeout, keep_alive),
+        }
+    }
+
+    /// Slack only takes posts to webhooks, but answers anything else without a server error.
+    pub(crate) async fn verify(&self, webhook: &str, timeout: Duration) -> Result<(), String> {
+        reachable("Slack", self.client.get(webhook), timeout).await
+    }
+}
+
+impl Default for SlackClient {
+    fn default() -> Self {
+        Self::build(
+            Duration::from_secs(30),
+            Duration::from_secs(10),
+            Duration::from_secs(90),
+        )
+    }
+}
+
+/// Slack's mrkdwn only needs these escaped.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Block Kit for the notification: the event, which starts with the status emoji, in
+/// bold above the description, a button to the alert, and when it happened in each
+/// reader's timezone. `text` is what Slack shows in push notifications.
+fn message(addressed: &Addressed) -> Value {
+    let notification = addressed.notification();
+    let text = format!(
+        "*{}*\n{}",
+        escape(notification.event()),
+        escape(notification.description())
+    );
+    let mut section = json!({
+        "type": "section",
+        "text": { "type": "mrkdwn", "text": truncate(&text, MAX_SECTION_CHARS) },
+    });
+    if let Some(url) = notification
+        .url()
+        .as_ref()
+        .filter(|x| x.starts_with("https://") || x.starts_with("http://"))
+    {
+        section["accessory"] = json!({
+            "type": "button",
+            "text": { "type": "plain_text", "text": "Open" },
+            "url": url,
+        });
+    }
+    let mut context = escape(notification.application());
+    if let Some(time) = addressed.time() {
+        let fallback = time.format("%Y-%m-%d %H:%M UTC");
+        context = format!(
+            "{context} · <!date^{}^{{date_short_pretty}} {{time}}|{fallback}>",
+            time.timestamp()
+        );
+    }
+    json!({
+        "text": notification.event(),
+        "blocks": [
+            section,
+            { "type": "context", "elements": [{ "type": "mrkdwn", "text": context }] },
+        ],
+    })
+}
+
+impl NotificationSink for SlackClient {
+    /// A request per webhook URL. Slack answers 429 when a webhook is used too fast,
+    /// which is retried.
+    async fn send(&self, addressed: &Addressed) -> Result<(), DeliveryError> {
+        let body = message(addressed).to_string();
+        for url in addressed.api_keys() {
+            let response = self
+                .client
+                .post(url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await
+                // the URL is the webhook's secret
+                .map_err(|e| DeliveryError::Send("Slack", e.without_url()))?;
+            answered("Slack", response).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        models::{notification::NotificationContent, receiver::Receiver},
+        subsystems::sinks::test::{answer, fake_server},
+    };
+    use prowl::Priority;
+    use tokio::net::TcpListener;
+
+    fn addressed(webhook: &str) -> Addressed {
+        let receiver: Receiver = serde_json::from_value(json!({
+            "api_keys": [webhook],
+            "sink": "slack"
+        }))
+        .expect("Failed to parse receiver");
+        NotificationContent::new(
+            Some(Priority::Normal),
+            Some("https://grafana.example.com/d/1".to_string()),
+            "Grafana".to_string(),
+            "[🔥] Disk full".to_string(),
+            "firing: used > 95%".to_string(),
+        )
+        .with_time("2022-09-09T10:00:00Z".parse().ok())
+        .to_prowl_for(receiver.api_keys().clone(), &receiver)
+        .expect("Failed to create notification")
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(
+            message(&addressed("https://hooks.slack.com/services/T0/B0/x")),
+            json!({
+                "text": "[🔥] Disk full",
+                "blocks": [
+                    {
+                        "type": "section",
+                        "text": { "type": "mrkdwn", "text": "*[🔥] Disk full*\nfiring: used &gt; 95%" },
+                        "accessory": {
+                            "type": "button",
+                            "text": { "type": "plain_text", "text": "Open" },
+                            "url": "https://grafana.example.com/d/1"
+                        }
+                    },
+                    {
+                        "type": "context",
+                        "elements": [{
+                            "type": "mrkdwn",
+                            "text": "Grafana · <!date^1662717600^{date_short_pretty} {time}|2022-09-09 10:00 UTC>"
+                        }]
+                    }
+                ]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn posts_to_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let webhook = format!(
+            "http://{}/services/T0/B0/x",
+            listener.local_addr().expect("No address")
+        );
+        let server = tokio::spawn(fake_server(
+            listener,
+            vec![answer("404 Not Found", "", b"no_service")],
+        ));
+        let error = SlackClient::default()
+            .send(&addressed(&webhook))
+            .await
+            .expect_err("Removed webhook was sent");
+        assert_eq!(
+            error.to_string(),
+            "Slack answered 404 Not Found. no_service"
+        );
+
+        let requests = server.await.expect("Fake Slack failed");
+        assert!(requests[0].starts_with("POST /services/T0/B0/x HTTP/1.1"));
+    }
+}
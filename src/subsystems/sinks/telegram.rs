@@ -0,0 +1,215 @@
+use crate::{
+    errors::DeliveryError,
+    models::{
+        config::Config,
+        notification::Addressed,
+        sink::{Sink, TelegramSink},
+    },
+    subsystems::sinks::{answered, http_client, reachable, truncate, NotificationSink},
+};
+use prowl::Priority;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+use tokio::time::Duration;
+
+const API_BASE: &str = "https://api.telegram.org";
+/// Telegram allows 4096 characters, this leaves room for the title and escaping.
+const MAX_DESCRIPTION_CHARS: usize = 3000;
+
+/// Sends with the Telegram Bot API, with the same timeouts as Prowl.
+#[derive(Clone)]
+pub(crate) struct TelegramClient {
+    client: reqwest::Client,
+    base: String,
+}
+
+impl TelegramClient {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self::build(
+            Duration::from_secs(*config.prowl_timeout_secs()),
+            Duration::from_secs(*config.prowl_connect_timeout_secs()),
+            Duration::from_secs(*config.prowl_keep_alive_secs()),
+            API_BASE,
+        )
+    }
+
+    fn build(
+        timeout: Duration,
+        connect_timeout: Duration,
+        keep_alive: Duration,
+        base: &str,
+    ) -> Self {
+        Self {
+            client: http_client("Telegram", timeout, connect_timeout, keep_alive),
+            base: base.to_string(),
+        }
+    }
+
+    /// The Bot API's `getMe`, which only needs the bot's token.
+    pub(crate) async fn verify(
+        &self,
+        settings: &TelegramSink,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        let url = format!("{}/bot{}/getMe", self.base, settings.bot_token());
+        reachable("Telegram", self.client.get(url), timeout).await
+    }
+}
+
+impl Default for TelegramClient {
+    fn default() -> Self {
+        Self::build(
+            Duration::from_secs(30),
+            Duration::from_secs(10),
+            Duration::from_secs(90),
+            API_BASE,
+        )
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// The event, which starts with the status emoji, in bold above the description.
+/// The URL is a button, as Telegram only allows HTTP links there.
+fn message(addressed: &Addressed, chat_id: &str) -> Value {
+    let notification = addressed.notification();
+    let text = format!(
+        "<b>{}</b>\n{}",
+        escape(notification.event()),
+        escape(&truncate(notification.description(), MAX_DESCRIPTION_CHARS))
+    );
+    let quiet = matches!(
+        notification.priority(),
+        Some(Priority::VeryLow | Priority::Moderate)
+    );
+    let mut message = json!({
+        "chat_id": chat_id,
+        "text": text,
+        "parse_mode": "HTML",
+        "disable_notification": quiet,
+    });
+    if let Some(url) = notification
+        .url()
+        .as_ref()
+        .filter(|x| x.starts_with("https://") || x.starts_with("http://"))
+    {
+        message["reply_markup"] = json!({
+            "inline_keyboard": [[{ "text": "Open", "url": url }]]
+        });
+    }
+    message
+}
+
+impl NotificationSink for TelegramClient {
+    /// A request per chat. Telegram answers 429 when the bot sends too fast, which is retried.
+    async fn send(&self, addressed: &Addressed) -> Result<(), DeliveryError> {
+        let settings: &TelegramSink = match addressed.sink() {
+            Sink::Telegram(settings) => settings,
+            other => unreachable!("{} notification sent to Telegram", other.name()),
+        };
+        for chat_id in addressed.api_keys() {
+            let response = self
+                .client
+                .post(format!(
+                    "{}/bot{}/sendMessage",
+                    self.base,
+                    settings.bot_token()
+                ))
+                .header(CONTENT_TYPE, "application/json")
+                .body(message(addressed, chat_id).to_string())
+                .send()
+                .await
+                // the URL has the bot token in it
+                .map_err(|e| DeliveryError::Send("Telegram", e.without_url()))?;
+            answered("Telegram", response).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        models::{notification::NotificationContent, receiver::Receiver},
+        subsystems::sinks::test::{answer, fake_server},
+    };
+    use tokio::net::TcpListener;
+
+    fn addressed(priority: Priority, url: Option<&str>) -> Addressed {
+        let receiver: Receiver = serde_json::from_value(json!({
+            "api_keys": ["-1001234567890", "42"],
+            "delivery": "together",
+            "sink": {"telegram": {"bot_token": "123456:ABC"}}
+        }))
+        .expect("Failed to parse receiver");
+        NotificationContent::new(
+            Some(priority),
+            url.map(str::to_string),
+            "Grafana".to_string(),
+            "[🔥] Disk <root>".to_string(),
+            "firing: 95% & rising".to_string(),
+        )
+        .to_prowl_for(receiver.api_keys().clone(), &receiver)
+        .expect("Failed to create notification")
+    }
+
+    #[test]
+    fn formats_html() {
+        let urgent = message(
+            &addressed(Priority::High, Some("https://grafana.example.com/d/1")),
+            "42",
+        );
+        assert_eq!(
+            urgent["text"],
+            "<b>[🔥] Disk &lt;root&gt;</b>\nfiring: 95% &amp; rising"
+        );
+        assert_eq!(urgent["disable_notification"], false);
+        assert_eq!(
+            urgent["reply_markup"]["inline_keyboard"][0][0]["url"],
+            "https://grafana.example.com/d/1"
+        );
+
+        // not an HTTP link, so there's no button
+        let quiet = message(
+            &addressed(Priority::Moderate, Some("grafana.local/d/1")),
+            "42",
+        );
+        assert_eq!(quiet["disable_notification"], true);
+        assert!(quiet.get("reply_markup").is_none());
+    }
+
+    #[tokio::test]
+    async fn sends_to_each_chat() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let base = format!("http://{}", listener.local_addr().expect("No address"));
+        let server = tokio::spawn(fake_server(
+            listener,
+            vec![
+                answer("200 OK", "", br#"{"ok":true}"#),
+                answer("200 OK", "", br#"{"ok":true}"#),
+            ],
+        ));
+        let client = TelegramClient::build(
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+            Duration::from_secs(90),
+            &base,
+        );
+        client
+            .send(&addressed(Priority::Normal, None))
+            .await
+            .expect("Failed to send");
+
+        let requests = server.await.expect("Fake Telegram failed");
+        assert!(requests[0].starts_with("POST /bot123456:ABC/sendMessage HTTP/1.1"));
+        assert!(requests[0].contains(r#""chat_id":"-1001234567890""#));
+        assert!(requests[1].contains(r#""chat_id":"42""#));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::{
+    clock::Clock,
+    models::{
+        config::Config,
+        volume::{Anomaly, Volume},
+    },
+    subsystems::{queue::QueueSender, self_notification, shutdown::sleep_or_shutdown},
+};
+use prowl::Priority;
+use std::sync::Arc;
+use tokio::{
+    sync::{broadcast::Receiver, Mutex},
+    time::Duration,
+};
+
+const CHECK_EVERY: Duration = Duration::from_secs(60);
+
+pub(crate) async fn main_loop(
+    config: Config,
+    sender: QueueSender,
+    volume: Arc<Mutex<Volume>>,
+    clock: Arc<dyn Clock>,
+    mut shutdown: Receiver<()>,
+) {
+    if !*config.volume_anomalies() {
+        log::trace!("Volume anomalies not enabled. Exiting volume loop.");
+        return;
+    }
+    loop {
+        let anomaly = volume.lock().await.check(clock.now());
+        match anomaly {
+            Some(Anomaly::Spike { count, baseline }) => {
+                log::warn!("{count} alerts this hour, usually {baseline:.1}");
+                let description =
+                    format!("{count} alerts this hour, usually there are {baseline:.1} an hour.");
+                self_notification::queue(
+                    &config,
+                    &sender,
+                    Priority::High,
+                    "[📈] Alert storm",
+                    description,
+                );
+            }
+            Some(Anomaly::Quiet { hours, baseline }) => {
+                log::warn!("No alerts for {hours} hours, usually {baseline:.1} an hour");
+                let description = format!(
+                    "No alerts for {hours} hours, usually there are {baseline:.1} an hour. Is Grafana still sending them?"
+                );
+                self_notification::queue(
+                    &config,
+                    &sender,
+                    Priority::High,
+                    "[📉] Alerts stopped",
+                    description,
+                );
+            }
+            None => {}
+        }
+        if sleep_or_shutdown(CHECK_EVERY, &mut shutdown).await {
+            log::debug!("Volume loop stopped.");
+            return;
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{
+    clock::Clock,
+    models::{
+        config::Config, digest::Digest, fingerprint::Fingerprints, silence::Silences,
+        volume::Volume,
+    },
+    subsystems::{
+        digest::send_digest,
+        queue::{NotificationQueue, QueueReceiver, QueueSender},
+        realert_every::realert_expired,
+        reload::SharedConfig,
+        server::Handler,
+    },
+    test::{MockClock, TestStream},
+};
+use chrono::{DateTime, Duration, Utc};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::Mutex;
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// The server, fingerprints, silences, routes and re-alerts wired together like `main`,
+/// with a mock clock and a queue that nothing is sent from. Files go in a temporary
+/// directory, so a scenario can restart and carry on from what was saved.
+pub(crate) struct Harness {
+    config: SharedConfig,
+    handler: Handler,
+    sender: QueueSender,
+    reciever: QueueReceiver,
+    clock: Arc<MockClock>,
+    fingerprints: Arc<Mutex<Fingerprints>>,
+    silences: Arc<Mutex<Silences>>,
+    digest: Arc<Mutex<Digest>>,
+    dir: Arc<TempDir>,
+}
+
+/// Removed with everything in it once the last harness using it is dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn write_config(&self, config: serde_json::Value) -> String {
+        let mut merged = serde_json::json!({
+            "prowl_api_keys": ["default"],
+            "fingerprints_file": self.0.join("fingerprints.json"),
+        });
+        if let (Some(merged), Some(config)) = (merged.as_object_mut(), config.as_object()) {
+            merged.extend(config.clone());
+        }
+        let path = self.0.join("config.json");
+        std::fs::write(&path, merged.to_string()).expect("Failed to write config");
+        path.to_string_lossy().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// What was queued for Prowl, without the API keys' order mattering.
+#[derive(Debug)]
+pub(crate) struct Delivered {
+    pub(crate) event: String,
+    pub(crate) description: String,
+    pub(crate) url: Option<String>,
+    pub(crate) api_keys: String,
+    pub(crate) application: String,
+}
+
+impl Harness {
+    /// `config` is merged over a minimal config with `prowl_api_keys` of `["default"]`,
+    /// and written to `config.json` in the directory so it can be reloaded.
+    pub(crate) fn new(config: serde_json::Value) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "harness-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).expect("Failed to create harness directory");
+        let dir = TempDir(dir);
+        let path = dir.write_config(config);
+        let config = SharedConfig::new(Config::load(Some(path)));
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        Self::start(config, clock, Arc::new(dir))
+    }
+
+    fn start(config: SharedConfig, clock: Arc<MockClock>, dir: Arc<TempDir>) -> Self {
+        let (sender, reciever) = NotificationQueue::default().into_parts();
+        let current = config.current();
+        let fingerprints = Arc::new(Mutex::new(Fingerprints::load_or_default(&current)));
+        let silences = Arc::new(Mutex::new(Silences::load_or_default(&current)));
+        let digest = Arc::new(Mutex::new(Digest::default()));
+        let handler = Handler::new(
+            config.clone(),
+            sender.clone(),
+            fingerprints.clone(),
+            silences.clone(),
+            Arc::new(Mutex::new(Volume::new(clock.now()))),
+            digest.clone(),
+            clock.clone(),
+        );
+        Harness {
+            config,
+            handler,
+            sender,
+            reciever,
+            clock,
+            fingerprints,
+            silences,
+            digest,
+            dir,
+        }
+    }
+
+    /// Starts again from the saved files, anything still queued is lost.
+    pub(crate) fn restart(self) -> Self {
+        Self::start(self.config, self.clock, self.dir)
+    }
+
+    /// Replaces `config.json` like `new` writes it, for a reload to pick up.
+    pub(crate) fn write_config(&self, config: serde_json::Value) {
+        self.dir.write_config(config);
+    }
+
+    pub(crate) fn advance(&self, minutes: i64) {
+        self.clock.advance(Duration::minutes(minutes));
+    }
+
+    /// Sends a request through the router, returning the status line and body.
+    pub(crate) async fn send(&self, method: &str, path: &str, body: &str) -> (String, String) {
+        self.send_with(method, path, &[], body).await
+    }
+
+    /// Like `send` with extra headers. Without a body there is no `Content-Length`
+    /// either, like `curl -X POST` sends.
+    pub(crate) async fn send_with(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> (String, String) {
+        let mut headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}\r\n"))
+            .collect();
+        if !body.is_empty() {
+            headers.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        let message =
+            format!("{method} {path} HTTP/1.1\r\nConnection: close\r\n{headers}\r\n{body}");
+        let mut stream = TestStream::new(message.as_bytes());
+        self.handler.clone().handle(&mut stream, None).await;
+        let sent = stream.sent();
+        let (head, body) = sent.split_once("\r\n\r\n").unwrap_or((&sent, ""));
+        let status = head.lines().next().unwrap_or_default().to_string();
+        (status, body.to_string())
+    }
+
+    pub(crate) fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// Sends Grafana's webhook for one alert, `labels` are added to its `alertname`.
+    pub(crate) async fn webhook(
+        &self,
+        status: &str,
+        fingerprint: &str,
+        labels: serde_json::Value,
+    ) -> String {
+        self.webhook_with(status, fingerprint, serde_json::json!({ "labels": labels }))
+            .await
+    }
+
+    /// Like `webhook` with `fields` set on the alert, objects are added to what it has.
+    pub(crate) async fn webhook_with(
+        &self,
+        status: &str,
+        fingerprint: &str,
+        fields: serde_json::Value,
+    ) -> String {
+        let mut alert = serde_json::json!({
+            "status": status,
+            "generatorURL": "http://grafana/alerting",
+            "fingerprint": fingerprint,
+            "labels": { "alertname": format!("Alert {fingerprint}") },
+            "annotations": { "summary": "Summary" },
+        });
+        for (name, value) in fields.as_object().into_iter().flatten() {
+            if let (Some(merged), Some(value)) = (alert[name].as_object_mut(), value.as_object()) {
+                merged.extend(value.clone());
+                continue;
+            }
+            alert[name] = value.clone();
+        }
+        let body = serde_json::json!({ "alerts": [alert] }).to_string();
+        let (status, _) = self.send("POST", "/webhooks/grafana", &body).await;
+        status
+    }
+
+    /// Runs the `alert_every_minutes` re-alert once, returning how many were queued.
+    pub(crate) async fn realert_every(&self) -> usize {
+        let config = self.config.current();
+        let ttl = Duration::minutes(config.alert_every_minutes().unwrap_or(60));
+        let mut fingerprints = self.fingerprints.lock().await;
+        let silences = self.silences.lock().await;
+        realert_expired(
+            &config,
+            &self.sender,
+            &mut fingerprints,
+            &silences,
+            self.clock.as_ref(),
+            ttl,
+        )
+    }
+
+    /// Sends the `resolved_digest` once, returning how many alerts it listed.
+    pub(crate) async fn send_digest(&self) -> usize {
+        let config = self.config.current();
+        let mut digest = self.digest.lock().await;
+        send_digest(&config, &self.sender, &mut digest).expect("Failed to queue digest")
+    }
+
+    /// Takes everything queued so far, oldest first.
+    pub(crate) fn delivered(&self) -> Vec<Delivered> {
+        std::iter::from_fn(|| self.reciever.pop())
+            .map(|x| {
+                let debug = format!("{x:?}");
+                Delivered {
+                    event: x.event().clone(),
+                    description: x.description().clone(),
+                    url: x.url().clone(),
+                    application: x.application().clone(),
+                    api_keys: debug
+                        .split_once("api_keys: ")
+                        .and_then(|(_, x)| x.split_once(']'))
+                        .map(|(keys, _)| format!("{keys}]"))
+                        .unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn events(&self) -> Vec<String> {
+        self.delivered().into_iter().map(|x| x.event).collect()
+    }
+}
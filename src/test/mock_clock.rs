@@ -0,0 +1,26 @@
+use crate::clock::Clock;
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Mutex;
+
+pub(crate) struct MockClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl MockClock {
+    pub(crate) fn new(now: DateTime<Utc>) -> Self {
+        MockClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub(crate) fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("MockClock lock poisoned");
+        *now += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().expect("MockClock lock poisoned")
+    }
+}
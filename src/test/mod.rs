@@ -1,4 +1,9 @@
 pub(crate) mod consts;
+mod harness;
+mod mock_clock;
+mod scenarios;
 mod test_stream;
 
+pub(crate) use harness::Harness;
+pub(crate) use mock_clock::MockClock;
 pub(crate) use test_stream::TestStream;
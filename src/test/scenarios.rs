@@ -0,0 +1,1027 @@
+//! Alerts followed through the webhook, the API and re-alerts, see `Harness`.
+
+use crate::test::Harness;
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+
+/// What a sender signing with `secret` puts in its signature header.
+fn signature(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("Failed to create HMAC");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[tokio::test]
+async fn fire_silence_realert_resolve() {
+    let harness = Harness::new(json!({ "alert_every_minutes": 30 }));
+    assert_eq!(
+        harness.webhook("firing", "a", json!({})).await,
+        "HTTP/1.1 200 OK"
+    );
+    assert_eq!(harness.events(), vec!["[🔥] Alert a"]);
+
+    harness.advance(30);
+    assert_eq!(harness.realert_every().await, 1);
+    assert_eq!(harness.events(), vec!["[🕓] Alert a"]);
+
+    let (status, body) = harness
+        .send(
+            "POST",
+            "/api/silences",
+            r#"{"fingerprint": "a", "minutes": 90}"#,
+        )
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let silence: serde_json::Value = serde_json::from_str(&body).expect("Not JSON");
+    let id = silence["id"].as_str().expect("Missing ID");
+
+    // silenced, and Grafana sending the same status again is not news
+    harness.advance(30);
+    assert_eq!(harness.realert_every().await, 0);
+    harness.webhook("firing", "a", json!({})).await;
+    assert!(harness.events().is_empty());
+
+    // skipped re-alerts are due as soon as the silence is gone
+    let (status, _) = harness
+        .send("DELETE", &format!("/api/silences/{id}"), "")
+        .await;
+    assert_eq!(status, "HTTP/1.1 204 No Content");
+    assert_eq!(harness.realert_every().await, 1);
+    assert_eq!(harness.events(), vec!["[🕓] Alert a"]);
+
+    harness.advance(5);
+    harness.webhook("resolved", "a", json!({})).await;
+    assert_eq!(harness.events(), vec!["[✅] Alert a"]);
+    harness.advance(60);
+    assert_eq!(harness.realert_every().await, 0);
+
+    let harness = harness.restart();
+    let (status, body) = harness.send("GET", "/api/fingerprints/a", "").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let fingerprint: serde_json::Value = serde_json::from_str(&body).expect("Not JSON");
+    assert_eq!(fingerprint["last_status"], "resolved");
+    let statuses: Vec<&str> = fingerprint["history"]
+        .as_array()
+        .expect("Missing history")
+        .iter()
+        .filter_map(|x| x["status"].as_str())
+        .collect();
+    assert_eq!(statuses, vec!["firing", "resolved"]);
+}
+
+#[tokio::test]
+async fn ack_and_routes() {
+    let harness = Harness::new(json!({
+        "alert_every_minutes": 30,
+        "receivers": { "oncall": { "api_keys": ["oncall"] } },
+        // re-alerts only know the kept labels
+        "fingerprint_labels": ["team"],
+        "notification_labels": ["team", "instance"],
+        "routes": [{ "matchers": ["team=db"], "receiver": "oncall" }]
+    }));
+    harness
+        .webhook("firing", "db", json!({ "team": "db" }))
+        .await;
+    harness
+        .webhook("firing", "web", json!({ "team": "web" }))
+        .await;
+    let delivered = harness.delivered();
+    assert_eq!(delivered.len(), 2);
+    assert_eq!(delivered[0].api_keys, r#"["oncall"]"#);
+    assert_eq!(delivered[0].description, "firing: Summary\nteam=db");
+    assert_eq!(delivered[1].api_keys, r#"["default"]"#);
+
+    let (status, _) = harness
+        .send("POST", "/api/fingerprints/db/ack", r#"{"minutes": 45}"#)
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    harness.advance(30);
+    assert_eq!(harness.realert_every().await, 1);
+    assert_eq!(harness.events(), vec!["[🕓] Alert web"]);
+
+    // the ack ended at 45 minutes, re-alerts go where the alert did
+    harness.advance(30);
+    assert_eq!(harness.realert_every().await, 2);
+    let delivered = harness.delivered();
+    let db = delivered
+        .iter()
+        .find(|x| x.event == "[🕓] Alert db")
+        .expect("Missing re-alert");
+    assert_eq!(db.api_keys, r#"["oncall"]"#);
+    assert!(db
+        .description
+        .starts_with("Alert db is still firing.\nteam=db\nSince"));
+
+    let (_, body) = harness.send("GET", "/?q=web", "").await;
+    assert!(body.contains("<td>team=web</td>"), "{body}");
+    assert!(!body.contains("team=db"), "{body}");
+}
+
+#[tokio::test]
+async fn silence_survives_restart() {
+    let harness = Harness::new(json!({}));
+    let (status, _) = harness
+        .send(
+            "POST",
+            "/api/silences",
+            r#"{"alertname": "Alert .*", "regex": true, "minutes": "2h"}"#,
+        )
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+
+    let harness = harness.restart();
+    harness.webhook("firing", "a", json!({})).await;
+    assert!(harness.events().is_empty());
+    let (_, body) = harness.send("GET", "/api/fingerprints/a", "").await;
+    assert!(body.contains("\"last_status\":\"firing\""), "{body}");
+
+    // the silence ends on the harness' clock, not the real one
+    harness.advance(121);
+    harness.webhook("resolved", "a", json!({})).await;
+    assert_eq!(harness.events(), vec!["[✅] Alert a"]);
+}
+
+#[tokio::test]
+async fn dashboard_actions() {
+    let harness = Harness::new(json!({ "alert_every_minutes": 30 }));
+    harness.webhook("firing", "a", json!({})).await;
+    assert_eq!(harness.events(), vec!["[🔥] Alert a"]);
+    let (_, page) = harness.send("GET", "/", "").await;
+    assert!(page.contains("<button onclick='resend_fp(&quot;a&quot;)'>Re-send</button>"));
+
+    // what the Ack and Silence 1h buttons send
+    let (status, _) = harness.send("POST", "/api/fingerprints/a/ack", "").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let (status, _) = harness
+        .send(
+            "POST",
+            "/api/silences",
+            r#"{"fingerprint": "a", "minutes": 60, "created_by": "dashboard"}"#,
+        )
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    harness.advance(30);
+    assert_eq!(harness.realert_every().await, 0);
+
+    // asking for it sends it anyway, and counts as the last alert
+    let (status, body) = harness.send("POST", "/api/fingerprints/a/resend", "").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let fingerprint: serde_json::Value = serde_json::from_str(&body).expect("Not JSON");
+    assert_eq!(fingerprint["fingerprint"], "a");
+    assert_eq!(harness.events(), vec!["[🕓] Alert a"]);
+
+    harness.webhook("resolved", "a", json!({})).await;
+    harness.events();
+    let (status, _) = harness.send("POST", "/api/fingerprints/a/resend", "").await;
+    assert_eq!(status, "HTTP/1.1 409 Conflict");
+    let (status, _) = harness
+        .send("POST", "/api/fingerprints/missing/resend", "")
+        .await;
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+    assert!(harness.events().is_empty());
+
+    let (status, _) = harness.send("DELETE", "/api/fingerprints/a", "").await;
+    assert_eq!(status, "HTTP/1.1 204 No Content");
+}
+
+#[tokio::test]
+async fn export() {
+    let harness = Harness::new(json!({}));
+    harness.webhook("firing", "a", json!({})).await;
+    harness.advance(5);
+    harness.webhook("firing", "b", json!({})).await;
+    harness.webhook("resolved", "a", json!({})).await;
+
+    let (status, csv) = harness
+        .send("GET", "/export?format=csv&sort=id&order=asc", "")
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("fingerprint,name,priority,status,"));
+    assert!(lines[1].starts_with("a,Alert a,"), "{}", lines[1]);
+    assert!(lines[1].contains(",resolved,"));
+    assert!(lines[2].starts_with("b,Alert b,"), "{}", lines[2]);
+
+    let (status, json) = harness
+        .send("GET", "/export?format=json&status=firing", "")
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let exported: serde_json::Value = serde_json::from_str(&json).expect("Not JSON");
+    let exported = exported.as_array().expect("Not an array");
+    assert_eq!(exported.len(), 1);
+    assert_eq!(exported[0]["fingerprint"], "b");
+    assert!(exported[0]["history"].is_array());
+
+    let (status, _) = harness.send("GET", "/export?format=xml", "").await;
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+}
+
+#[tokio::test]
+async fn reload_config() {
+    let harness = Harness::new(json!({ "alert_every_minutes": 30 }));
+    harness.webhook("firing", "a", json!({})).await;
+    harness.events();
+
+    harness.write_config(json!({ "alert_every_minutes": 10, "delivery_workers": 2 }));
+    let (status, body) = harness.send("POST", "/api/reload", "").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let reloaded: serde_json::Value = serde_json::from_str(&body).expect("Not JSON");
+    assert_eq!(reloaded["restart_needed"], json!(["delivery_workers"]));
+    harness.advance(10);
+    assert_eq!(harness.realert_every().await, 1);
+    assert_eq!(harness.events(), vec!["[🕓] Alert a"]);
+
+    // a bad file keeps what is running
+    harness.write_config(json!({ "max_connections": 0 }));
+    let (status, body) = harness.send("POST", "/api/reload", "").await;
+    assert_eq!(status, "HTTP/1.1 500 Internal Server Error");
+    assert_eq!(body, "max_connections must be at least 1.");
+
+    // the server picks up new settings, like read_only, on its next request
+    harness.write_config(json!({ "read_only": true }));
+    let (status, _) = harness.send("POST", "/api/reload", "").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let (status, _) = harness.send("POST", "/api/fingerprints/a/ack", "").await;
+    assert_eq!(status, "HTTP/1.1 403 Forbidden");
+
+    // after which only SIGHUP reloads
+    harness.write_config(json!({ "read_only": false }));
+    let (status, _) = harness.send("POST", "/api/reload", "").await;
+    assert_eq!(status, "HTTP/1.1 403 Forbidden");
+    let (status, _) = harness.send("POST", "/api/fingerprints/a/ack", "").await;
+    assert_eq!(status, "HTTP/1.1 403 Forbidden");
+}
+
+#[tokio::test]
+async fn grafana_times() {
+    let harness = Harness::new(json!({}));
+    let started = harness.now() - chrono::Duration::minutes(5);
+    harness
+        .webhook_with(
+            "firing",
+            "a",
+            json!({ "startsAt": started, "endsAt": "0001-01-01T00:00:00Z" }),
+        )
+        .await;
+    let description = |harness: &Harness| {
+        let delivered = harness.delivered();
+        assert_eq!(delivered.len(), 1);
+        delivered[0].description.clone()
+    };
+    assert_eq!(description(&harness), "firing: Summary");
+    let time = |x: &serde_json::Value| -> chrono::DateTime<chrono::Utc> {
+        serde_json::from_value(x.clone()).expect("Not a time")
+    };
+    let (_, body) = harness.send("GET", "/api/fingerprints/a", "").await;
+    let fingerprint: serde_json::Value = serde_json::from_str(&body).expect("Not JSON");
+    // Grafana waited for the pending period, the alert started before it was sent
+    assert_eq!(time(&fingerprint["first_alerted"]), started);
+    assert_eq!(time(&fingerprint["started_at"]), started);
+    assert!(fingerprint["ended_at"].is_null());
+
+    harness.advance(133);
+    let ended = harness.now();
+    harness
+        .webhook_with(
+            "resolved",
+            "a",
+            json!({ "startsAt": started, "endsAt": ended }),
+        )
+        .await;
+    assert_eq!(description(&harness), "resolved after 2h 18m: Summary");
+    let (_, body) = harness.send("GET", "/?status=resolved", "").await;
+    let ended = ended.format("%d/%m/%y %H:%M").to_string();
+    assert!(body.contains(&format!("<td>{ended}</td>")), "{body}");
+    assert!(body.contains("?sort=ended"), "{body}");
+
+    // without times nothing is made up
+    harness.webhook("firing", "b", json!({})).await;
+    assert_eq!(description(&harness), "firing: Summary");
+    harness.advance(10);
+    harness.webhook("resolved", "b", json!({})).await;
+    assert_eq!(description(&harness), "resolved: Summary");
+}
+
+#[tokio::test]
+async fn notification_links() {
+    let harness = Harness::new(json!({
+        "alert_every_minutes": 30,
+        "notification_url_preference": ["panel", "dashboard"],
+        "url_rewrite": [{ "from": "http://grafana/", "to": "https://grafana.example.com/" }]
+    }));
+    harness
+        .webhook_with(
+            "firing",
+            "a",
+            json!({ "panelURL": "http://grafana/d/1?viewPanel=2", "dashboardURL": "http://grafana/d/1" }),
+        )
+        .await;
+    harness
+        .webhook_with("firing", "b", json!({ "panelURL": "" }))
+        .await;
+    let urls: Vec<Option<String>> = harness.delivered().into_iter().map(|x| x.url).collect();
+    assert_eq!(
+        urls,
+        vec![
+            Some("https://grafana.example.com/d/1?viewPanel=2".to_string()),
+            None
+        ]
+    );
+
+    // re-alerts open the same link, rewritten the same way
+    harness.advance(30);
+    assert_eq!(harness.realert_every().await, 2);
+    let delivered = harness.delivered();
+    let a = delivered
+        .iter()
+        .find(|x| x.event == "[🕓] Alert a")
+        .expect("Missing re-alert");
+    assert_eq!(
+        a.url.as_deref(),
+        Some("https://grafana.example.com/d/1?viewPanel=2")
+    );
+}
+
+#[tokio::test]
+async fn datasource_alerts() {
+    let harness = Harness::new(json!({
+        "datasource_alerts": { "no_data": { "priority": "Moderate" }, "error": { "suppress": true } }
+    }));
+    let labels = |alertname: &str| json!({ "alertname": alertname, "rulename": "[critical] Disk" });
+    harness
+        .webhook("firing", "a", labels("DatasourceNoData"))
+        .await;
+    harness
+        .webhook("firing", "b", labels("DatasourceError"))
+        .await;
+    let delivered = harness.delivered();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].event, "[❓] [critical] Disk");
+    assert_eq!(delivered[0].description, "no data: Summary");
+    let (_, body) = harness.send("GET", "/api/fingerprints/a", "").await;
+    assert!(body.contains("\"priority\":\"Moderate\""), "{body}");
+    let (status, _) = harness.send("GET", "/api/fingerprints/b", "").await;
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+
+    harness
+        .webhook("resolved", "a", labels("DatasourceNoData"))
+        .await;
+    assert_eq!(harness.events(), vec!["[✅] [critical] Disk"]);
+}
+
+#[tokio::test]
+async fn alertmanager() {
+    let harness = Harness::new(json!({}));
+    let body = json!({
+        "version": "4",
+        "groupKey": "{}:{alertname=\"Disk full\"}",
+        "status": "firing",
+        "receiver": "prowl",
+        "externalURL": "http://alertmanager:9093",
+        "alerts": [{
+            "status": "firing",
+            "labels": { "alertname": "Disk full", "instance": "db1", "severity": "critical" },
+            "annotations": { "summary": "95% used" },
+            "startsAt": "2022-09-09T10:00:00Z",
+            "endsAt": "0001-01-01T00:00:00Z",
+            "generatorURL": "http://prometheus:9090/graph"
+        }]
+    });
+    let (status, _) = harness
+        .send("POST", "/webhooks/alertmanager", &body.to_string())
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let delivered = harness.delivered();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].event, "[🔥] Disk full");
+    assert_eq!(
+        delivered[0].url.as_deref(),
+        Some("http://prometheus:9090/graph")
+    );
+    let (status, body) = harness
+        .send("GET", "/api/fingerprints/b8967b8b4685ff54", "")
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK", "{body}");
+}
+
+#[tokio::test]
+async fn legacy_grafana() {
+    let harness = Harness::new(json!({}));
+    let legacy = |state: &str| {
+        json!({
+            "ruleId": 3,
+            "ruleName": "Disk full",
+            "ruleUrl": "http://grafana/d/abc?viewPanel=2",
+            "state": state,
+            "message": "95% used",
+            "evalMatches": [{ "metric": "used", "value": 95.0 }]
+        })
+        .to_string()
+    };
+    let (status, _) = harness
+        .send("POST", "/webhooks/grafana", &legacy("alerting"))
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let delivered = harness.delivered();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].event, "[🔥] Disk full");
+    assert_eq!(delivered[0].description, "firing: 95% used\nvalue: 95");
+
+    harness
+        .send("POST", "/webhooks/grafana", &legacy("ok"))
+        .await;
+    assert_eq!(harness.events(), vec!["[✅] Disk full"]);
+    let (status, _) = harness
+        .send("GET", "/api/fingerprints/legacy-1-3", "")
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+}
+
+#[tokio::test]
+async fn healthchecks() {
+    let harness = Harness::new(json!({}));
+    let event = |status: &str| {
+        json!({ "code": "5f8e", "name": "Backups", "status": status, "desc": "", "tags": "" })
+            .to_string()
+    };
+    let (status, _) = harness
+        .send("POST", "/webhooks/healthchecks", &event("down"))
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let delivered = harness.delivered();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].event, "[🔥] Backups");
+    assert_eq!(delivered[0].description, "firing: Backups is down");
+
+    // still down when it is re-alerted
+    harness.advance(61);
+    assert_eq!(harness.realert_every().await, 1);
+    harness.delivered();
+    harness
+        .send("POST", "/webhooks/healthchecks", &event("up"))
+        .await;
+    assert_eq!(harness.events(), vec!["[✅] Backups"]);
+}
+
+#[tokio::test]
+async fn sns() {
+    let harness = Harness::new(json!({}));
+    let notification = |state: &str| {
+        let alarm = json!({
+            "AlarmName": "High CPU",
+            "NewStateValue": state,
+            "NewStateReason": "Threshold Crossed",
+            "AlarmArn": "arn:aws:cloudwatch:us-east-1:123456789012:alarm:High CPU"
+        });
+        json!({ "Type": "Notification", "Message": alarm.to_string() }).to_string()
+    };
+    let (status, _) = harness
+        .send("POST", "/webhooks/sns", &notification("ALARM"))
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let delivered = harness.delivered();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].event, "[🔥] High CPU");
+    harness
+        .send("POST", "/webhooks/sns", &notification("OK"))
+        .await;
+    assert_eq!(harness.events(), vec!["[✅] High CPU"]);
+
+    // confirmations are only fetched from SNS
+    let confirmation = json!({
+        "Type": "SubscriptionConfirmation",
+        "Message": "You have chosen to subscribe",
+        "SubscribeURL": "http://10.0.0.1/internal"
+    });
+    let (status, _) = harness
+        .send("POST", "/webhooks/sns", &confirmation.to_string())
+        .await;
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+    assert!(harness.delivered().is_empty());
+}
+
+#[tokio::test]
+async fn sentry() {
+    let harness = Harness::new(json!({}));
+    let issue = json!({
+        "id": "1117540176",
+        "level": "error",
+        "culprit": "app.payments in charge",
+        "url": "https://sentry.io/organizations/shop/issues/1117540176/",
+        "event": { "title": "ZeroDivisionError: division by zero" }
+    })
+    .to_string();
+    for _ in 0..3 {
+        let (status, _) = harness.send("POST", "/webhooks/sentry", &issue).await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+    }
+    let delivered = harness.delivered();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(
+        delivered[0].event,
+        "[🔥] ZeroDivisionError: division by zero"
+    );
+    assert_eq!(
+        delivered[0].url.as_deref(),
+        Some("https://sentry.io/organizations/shop/issues/1117540176/")
+    );
+    let (_, body) = harness
+        .send("GET", "/api/fingerprints/sentry-1117540176", "")
+        .await;
+    assert!(body.contains("\"priority\":\"High\""), "{body}");
+}
+
+#[tokio::test]
+async fn github() {
+    let harness = Harness::new(json!({ "github_webhook_secret": "octocat" }));
+    let run = |conclusion: &str| {
+        json!({
+            "action": "completed",
+            "workflow_run": {
+                "name": "CI",
+                "head_branch": "main",
+                "run_number": 7,
+                "conclusion": conclusion,
+                "html_url": "https://github.com/octo/notifier/actions/runs/1"
+            },
+            "repository": { "full_name": "octo/notifier" }
+        })
+        .to_string()
+    };
+    async fn send(harness: &Harness, body: String) -> String {
+        let signature = signature("octocat", &body);
+        let headers = [("X-Hub-Signature-256", signature.as_str())];
+        harness
+            .send_with("POST", "/webhooks/github", &headers, &body)
+            .await
+            .0
+    }
+
+    let (status, _) = harness
+        .send("POST", "/webhooks/github", &run("failure"))
+        .await;
+    assert_eq!(status, "HTTP/1.1 401 Unauthorized");
+    assert_eq!(send(&harness, run("failure")).await, "HTTP/1.1 200 OK");
+    let delivered = harness.delivered();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].event, "[🔥] octo/notifier CI");
+    assert_eq!(delivered[0].description, "firing: Run #7 on main failure");
+    assert_eq!(send(&harness, run("success")).await, "HTTP/1.1 200 OK");
+    assert_eq!(harness.events(), vec!["[✅] octo/notifier CI"]);
+}
+
+#[tokio::test]
+async fn signed_webhooks() {
+    let harness = Harness::new(json!({
+        "hmac_secrets": { "alertmanager": "am-secret" },
+        "webhooks": [{ "path": "/webhooks/team", "hmac_secret": "team-secret", "hmac_header": "X-Team-Signature" }]
+    }));
+    let body = json!({
+        "version": "4",
+        "alerts": [{ "status": "firing", "labels": { "alertname": "Disk full" } }]
+    })
+    .to_string();
+    let send = |path: &'static str, header: &'static str, secret: &str| {
+        let signature = signature(secret, &body);
+        let body = body.clone();
+        let harness = &harness;
+        async move {
+            harness
+                .send_with("POST", path, &[(header, signature.as_str())], &body)
+                .await
+                .0
+        }
+    };
+
+    let unauthorized = "HTTP/1.1 401 Unauthorized";
+    let (status, _) = harness.send("POST", "/webhooks/alertmanager", &body).await;
+    assert_eq!(status, unauthorized);
+    assert_eq!(
+        send("/webhooks/alertmanager", "X-Signature", "wrong").await,
+        unauthorized
+    );
+    assert_eq!(
+        send("/webhooks/team", "X-Signature", "team-secret").await,
+        unauthorized
+    );
+    assert!(harness.delivered().is_empty());
+
+    assert_eq!(
+        send("/webhooks/alertmanager", "X-Signature", "am-secret").await,
+        "HTTP/1.1 200 OK"
+    );
+    assert_eq!(
+        send("/webhooks/team", "X-Team-Signature", "team-secret").await,
+        "HTTP/1.1 200 OK"
+    );
+    // both are the same alert
+    assert_eq!(harness.events(), vec!["[🔥] Disk full"]);
+    // other webhooks don't need one
+    let (status, _) = harness.send("POST", "/webhooks/grafana", &body).await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+}
+
+#[tokio::test]
+async fn zabbix() {
+    let harness = Harness::new(json!({}));
+    let event = |value: &str| {
+        json!({
+            "event_id": "4321",
+            "event_value": value,
+            "event_nseverity": "4",
+            "event_name": "High CPU on db1",
+            "host": "db1",
+            "message": "CPU over 90%"
+        })
+        .to_string()
+    };
+    let (status, _) = harness.send("POST", "/webhooks/zabbix", &event("1")).await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    harness.send("POST", "/webhooks/zabbix", &event("1")).await;
+    assert_eq!(harness.events(), vec!["[🔥] High CPU on db1"]);
+    let (_, body) = harness
+        .send("GET", "/api/fingerprints/zabbix-4321", "")
+        .await;
+    assert!(body.contains("\"priority\":\"High\""), "{body}");
+    harness.send("POST", "/webhooks/zabbix", &event("0")).await;
+    assert_eq!(harness.events(), vec!["[✅] High CPU on db1"]);
+}
+
+#[tokio::test]
+async fn batched_messages() {
+    let harness = Harness::new(json!({}));
+    let message = |alerts: serde_json::Value, truncated: u64| json!({ "alerts": alerts, "truncatedAlerts": truncated });
+    let alert = |fingerprint: &str, status: &str| json!({ "status": status, "fingerprint": fingerprint, "labels": { "alertname": format!("Alert {fingerprint}") } });
+    let body = json!([
+        message(json!([alert("a", "firing"), alert("b", "firing")]), 1),
+        message(json!([alert("a", "resolved")]), 0),
+    ]);
+    let (status, _) = harness
+        .send("POST", "/webhooks/grafana", &body.to_string())
+        .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    // the last state of each fingerprint wins
+    assert_eq!(harness.events(), vec!["[✅] Alert a", "[🔥] Alert b"]);
+
+    let (status, _) = harness.send("POST", "/webhooks/alertmanager", "[]").await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let (status, _) = harness
+        .send("POST", "/webhooks/grafana", "[{\"alerts\": 1}]")
+        .await;
+    assert_eq!(status, "HTTP/1.1 500 Internal Server Error");
+}
+
+#[tokio::test]
+async fn resolved_digest() {
+    let harness = Harness::new(json!({
+        "smtp": { "host": "mail.example.com", "from": "grafana@example.com" },
+        "receivers": {
+            "mail": { "api_keys": ["team@example.com"], "sink": "email" }
+        },
+        "resolved_digest": { "receiver": "mail", "every_minutes": 30 }
+    }));
+    assert_eq!(harness.send_digest().await, 0);
+    harness.webhook("firing", "a", json!({})).await;
+    harness.webhook("firing", "b", json!({})).await;
+    assert_eq!(harness.events(), vec!["[🔥] Alert a", "[🔥] Alert b"]);
+
+    // resolved alerts wait for the digest instead of a push each
+    harness.webhook("resolved", "a", json!({})).await;
+    harness.webhook("resolved", "b", json!({})).await;
+    harness.webhook("resolved", "b", json!({})).await;
+    assert!(harness.events().is_empty());
+
+    assert_eq!(harness.send_digest().await, 2);
+    let delivered = harness.delivered();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].event, "[✅] 2 alerts resolved");
+    assert_eq!(
+        delivered[0].description,
+        "Alert a: Summary\nAlert b: Summary"
+    );
+    assert_eq!(delivered[0].api_keys, r#"["team@example.com"]"#);
+    assert_eq!(harness.send_digest().await, 0);
+}
+
+#[tokio::test]
+async fn priority_destinations() {
+    let harness = Harness::new(json!({
+        "alert_every_minutes": 30,
+        "receivers": {
+            "family": { "api_keys": ["mine", "partner", "kid"], "delivery": "together" }
+        },
+        "priority_destinations": { "Emergency": ["family"] }
+    }));
+    harness
+        .webhook(
+            "firing",
+            "flood",
+            json!({ "alertname": "[critical] Flood" }),
+        )
+        .await;
+    harness.webhook("firing", "disk", json!({})).await;
+    let delivered = harness.delivered();
+    assert_eq!(delivered[0].api_keys, r#"["mine", "partner", "kid"]"#);
+    assert_eq!(delivered[1].api_keys, r#"["default"]"#);
+
+    // re-alerts keep the priority, so they go to the same keys
+    harness.advance(30);
+    assert_eq!(harness.realert_every().await, 2);
+    let delivered = harness.delivered();
+    let flood = delivered
+        .iter()
+        .find(|x| x.event.contains("Flood"))
+        .expect("Missing re-alert");
+    assert_eq!(flood.api_keys, r#"["mine", "partner", "kid"]"#);
+}
+
+#[tokio::test]
+async fn routing_to_api_keys() {
+    let harness = Harness::new(json!({
+        "routing": [
+            { "matchers": ["grafana_folder=Network"], "api_keys": ["netops1", "netops2"] },
+            { "matchers": ["team=db"], "api_keys": ["dba"] }
+        ]
+    }));
+    harness
+        .webhook("firing", "uplink", json!({ "grafana_folder": "Network" }))
+        .await;
+    harness
+        .webhook("firing", "replica", json!({ "team": "db" }))
+        .await;
+    harness
+        .webhook("firing", "disk", json!({ "grafana_folder": "Storage" }))
+        .await;
+    let keys: Vec<String> = harness
+        .delivered()
+        .into_iter()
+        .map(|x| x.api_keys)
+        .collect();
+    assert_eq!(
+        keys,
+        vec![
+            r#"["netops1"]"#,
+            r#"["netops2"]"#,
+            r#"["dba"]"#,
+            r#"["default"]"#
+        ]
+    );
+}
+
+#[tokio::test]
+async fn tenants() {
+    let harness = Harness::new(json!({
+        "alert_every_minutes": 30,
+        "auth_token": "mine",
+        "tenants": {
+            "alice": { "auth_token": "alice-token", "prowl_api_keys": ["alice"], "app_name": "Alice's Grafana" },
+            "bob": { "auth_token": "bob-token", "prowl_api_keys": ["bob"] }
+        }
+    }));
+    let body = |status: &str| {
+        json!({ "alerts": [{
+            "status": status,
+            "fingerprint": "a",
+            "labels": { "alertname": "Disk full" },
+            "annotations": { "summary": "Summary" }
+        }]})
+        .to_string()
+    };
+    let send = |path: &'static str, token: &'static str, body: String| {
+        let harness = &harness;
+        async move {
+            let bearer = format!("Bearer {token}");
+            harness
+                .send_with("POST", path, &[("Authorization", bearer.as_str())], &body)
+                .await
+                .0
+        }
+    };
+
+    // each tenant only gets in with its own token
+    let unauthorized = "HTTP/1.1 401 Unauthorized";
+    assert_eq!(
+        send("/webhooks/grafana/alice", "bob-token", body("firing")).await,
+        unauthorized
+    );
+    assert_eq!(
+        send("/webhooks/grafana/alice", "mine", body("firing")).await,
+        unauthorized
+    );
+    assert_eq!(
+        send("/webhooks/grafana", "alice-token", body("firing")).await,
+        unauthorized
+    );
+    assert_eq!(
+        send("/webhooks/grafana/carol", "mine", body("firing")).await,
+        "HTTP/1.1 404 Not Found"
+    );
+
+    // the same fingerprint from everyone is three separate alerts
+    let ok = "HTTP/1.1 200 OK";
+    assert_eq!(
+        send("/webhooks/grafana/alice", "alice-token", body("firing")).await,
+        ok
+    );
+    assert_eq!(
+        send("/webhooks/grafana/bob", "bob-token", body("firing")).await,
+        ok
+    );
+    assert_eq!(send("/webhooks/grafana", "mine", body("firing")).await, ok);
+    let delivered = harness.delivered();
+    let sent: Vec<(&str, &str)> = delivered
+        .iter()
+        .map(|x| (x.api_keys.as_str(), x.application.as_str()))
+        .collect();
+    assert_eq!(
+        sent,
+        vec![
+            (r#"["alice"]"#, "Alice's Grafana"),
+            (r#"["bob"]"#, "Grafana"),
+            (r#"["default"]"#, "Grafana")
+        ]
+    );
+    let (_, fingerprints) = harness.send("GET", "/api/fingerprints", "").await;
+    for fingerprint in [r#""alice:a""#, r#""bob:a""#, r#""a""#] {
+        assert!(fingerprints.contains(fingerprint), "{fingerprints}");
+    }
+    let (status, _) = harness.send("GET", "/api/fingerprints/alice:a", "").await;
+    assert_eq!(status, ok);
+
+    // Bob resolving doesn't resolve Alice's alert, and re-alerts stay with their tenant
+    assert_eq!(
+        send("/webhooks/grafana/bob", "bob-token", body("resolved")).await,
+        ok
+    );
+    assert_eq!(harness.events(), vec!["[✅] Disk full"]);
+    harness.advance(30);
+    assert_eq!(harness.realert_every().await, 2);
+    let mut keys: Vec<String> = harness
+        .delivered()
+        .into_iter()
+        .map(|x| x.api_keys)
+        .collect();
+    keys.sort();
+    assert_eq!(keys, vec![r#"["alice"]"#, r#"["default"]"#]);
+}
+
+#[tokio::test]
+async fn tenant_isolation() {
+    let harness = Harness::new(json!({
+        "alert_every_minutes": 30,
+        "dashboard_user": "me",
+        "dashboard_password": "mine",
+        "tenants": {
+            "alice": { "auth_token": "alice-token", "prowl_api_keys": ["alice"] },
+            "bob": { "auth_token": "bob-token", "prowl_api_keys": ["bob"] }
+        }
+    }));
+    let basic = |user: &str, password: &str| {
+        format!("Basic {}", base64::encode(format!("{user}:{password}")))
+    };
+    let operator = basic("me", "mine");
+    let alice = "Bearer alice-token".to_string();
+    let bob = "Bearer bob-token".to_string();
+    let send = |method: &'static str, path: String, authorization: &str, body: String| {
+        let harness = &harness;
+        let authorization = authorization.to_string();
+        async move {
+            harness
+                .send_with(method, &path, &[("Authorization", &authorization)], &body)
+                .await
+        }
+    };
+    let firing = json!({ "alerts": [{
+        "status": "firing",
+        "fingerprint": "a",
+        "labels": { "alertname": "Disk full" },
+        "annotations": { "summary": "Summary" }
+    }]})
+    .to_string();
+    for (path, token) in [
+        ("/webhooks/grafana/alice", &alice),
+        ("/webhooks/grafana/bob", &bob),
+    ] {
+        let (status, _) = send("POST", path.to_string(), token, firing.clone()).await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+    }
+    assert_eq!(
+        harness.webhook("firing", "a", json!({})).await,
+        "HTTP/1.1 200 OK"
+    );
+    harness.events();
+
+    // a tenant's token only shows it its own fingerprints
+    let keys = |body: &str| -> Vec<String> {
+        let fingerprints: serde_json::Value = serde_json::from_str(body).expect("Not JSON");
+        let fingerprints = fingerprints.as_object().expect("Not an object");
+        fingerprints.keys().cloned().collect()
+    };
+    let (_, body) = send("GET", "/api/fingerprints".into(), &alice, String::new()).await;
+    assert_eq!(keys(&body), vec!["alice:a"]);
+    let (_, body) = send("GET", "/api/fingerprints".into(), &operator, String::new()).await;
+    assert_eq!(keys(&body), vec!["a", "alice:a", "bob:a"]);
+    let (_, body) = send(
+        "GET",
+        "/".into(),
+        &basic("alice", "alice-token"),
+        String::new(),
+    )
+    .await;
+    assert!(
+        body.contains("alice:a") && !body.contains("bob:a"),
+        "{body}"
+    );
+    let (status, _) = send(
+        "GET",
+        "/".into(),
+        &basic("alice", "bob-token"),
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, "HTTP/1.1 401 Unauthorized");
+
+    // and can't see or change anyone else's
+    let not_found = "HTTP/1.1 404 Not Found";
+    for (method, path) in [
+        ("GET", "/api/fingerprints/bob:a"),
+        ("POST", "/api/fingerprints/bob:a/ack"),
+        ("POST", "/api/fingerprints/a/resend"),
+        ("DELETE", "/api/fingerprints/bob:a"),
+    ] {
+        let (status, _) = send(method, path.to_string(), &alice, String::new()).await;
+        assert_eq!(status, not_found, "{method} {path}");
+    }
+    let (status, _) = send(
+        "DELETE",
+        "/delete/fingerprint".into(),
+        &alice,
+        "bob:a".into(),
+    )
+    .await;
+    assert_eq!(status, not_found);
+    let (status, _) = send(
+        "GET",
+        "/api/fingerprints/alice:a".into(),
+        &alice,
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+
+    // the queue, config and imports are the operator's
+    for (method, path) in [
+        ("GET", "/api/queue/items"),
+        ("GET", "/api/deliveries"),
+        ("GET", "/metrics"),
+        ("POST", "/api/silences/import"),
+        ("POST", "/api/test"),
+    ] {
+        let (status, _) = send(method, path.to_string(), &alice, String::new()).await;
+        assert_eq!(status, "HTTP/1.1 403 Forbidden", "{method} {path}");
+    }
+
+    // a tenant's silence only silences its alerts and only it can see or remove it
+    let silence = json!({ "alertname": "Disk full", "matchers": ["@tenant=bob"] }).to_string();
+    let (status, body) = send("POST", "/api/silences".into(), &alice, silence).await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let id = serde_json::from_str::<serde_json::Value>(&body).expect("Not JSON")["id"]
+        .as_str()
+        .expect("Missing ID")
+        .to_string();
+    let count = |body: &str| {
+        serde_json::from_str::<Vec<serde_json::Value>>(body)
+            .expect("Not a list")
+            .len()
+    };
+    let (_, body) = send("GET", "/api/silences".into(), &bob, String::new()).await;
+    assert_eq!(count(&body), 0);
+    let (_, body) = send("GET", "/api/silences".into(), &alice, String::new()).await;
+    assert_eq!(count(&body), 1);
+    let (_, body) = send("GET", "/api/silences".into(), &operator, String::new()).await;
+    assert_eq!(count(&body), 1);
+    let path = format!("/api/silences/{id}");
+    let (status, _) = send("DELETE", path.clone(), &bob, String::new()).await;
+    assert_eq!(status, not_found);
+    harness.advance(30);
+    assert_eq!(harness.realert_every().await, 2);
+    let mut keys_sent: Vec<String> = harness
+        .delivered()
+        .into_iter()
+        .map(|x| x.api_keys)
+        .collect();
+    keys_sent.sort();
+    assert_eq!(keys_sent, vec![r#"["bob"]"#, r#"["default"]"#]);
+    let (status, _) = send("DELETE", path, &alice, String::new()).await;
+    assert_eq!(status, "HTTP/1.1 204 No Content");
+
+    // bulk updates only reach the tenant's own fingerprints
+    let bulk = json!({ "matchers": ["alertname=Disk full"], "action": "delete" }).to_string();
+    let (_, body) = send("POST", "/api/fingerprints/bulk".into(), &bob, bulk).await;
+    let result: serde_json::Value = serde_json::from_str(&body).expect("Not JSON");
+    assert_eq!(result["fingerprints"], json!(["bob:a"]));
+    let (_, body) = send("GET", "/api/fingerprints".into(), &operator, String::new()).await;
+    assert_eq!(keys(&body), vec!["a", "alice:a"]);
+}
@@ -1,4 +1,9 @@
-use std::io::{BufReader, Error, Read, Write};
+use std::{
+    io::{BufReader, Error, Read},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
 pub(crate) struct TestStream<'a> {
     to_send: BufReader<&'a [u8]>,
@@ -12,21 +17,39 @@ impl<'a> TestStream<'a> {
             sent: vec![],
         }
     }
+
+    pub(crate) fn sent(&self) -> String {
+        String::from_utf8_lossy(&self.sent).to_string()
+    }
 }
 
-impl<'a> Read for TestStream<'a> {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        self.to_send.read(buf)
+impl<'a> AsyncRead for TestStream<'a> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), Error>> {
+        let read = self.to_send.read(buf.initialize_unfilled())?;
+        buf.advance(read);
+        Poll::Ready(Ok(()))
     }
 }
 
-impl<'a> Write for TestStream<'a> {
-    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+impl<'a> AsyncWrite for TestStream<'a> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Error>> {
         self.sent.extend_from_slice(buf);
-        Ok(buf.len())
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
     }
 
-    fn flush(&mut self) -> Result<(), Error> {
-        Ok(())
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
     }
 }